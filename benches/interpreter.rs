@@ -0,0 +1,57 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use sage::{lir::*, parse::*, vm::*};
+use std::fs::read_to_string;
+
+const CALL_STACK_SIZE: usize = 8192;
+
+/// Compile a frontend example which only uses the core variant of the virtual machine.
+fn compile_core_file(filename: &str) -> CoreProgram {
+    let src = read_to_string(filename).unwrap();
+    match parse_frontend(src, Some(filename))
+        .unwrap()
+        .compile()
+        .unwrap()
+    {
+        Ok(asm_code) => asm_code.assemble(CALL_STACK_SIZE).unwrap(),
+        Err(_) => panic!("{filename} is not a core variant program"),
+    }
+}
+
+fn bench_interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Core Interpreters");
+    group.sample_size(10);
+
+    for (name, filename) in [
+        ("ChaCha20", "examples/frontend/chacha20.sg"),
+        ("RNG", "examples/frontend/rng.sg"),
+    ] {
+        let program = compile_core_file(filename);
+        let bytecode = Bytecode::new(&program).unwrap();
+
+        group.bench_function(format!("{name} (core interpreter)"), |b| {
+            b.iter(|| {
+                CoreInterpreter::new(TestingDevice::new("hello world!"))
+                    .run(&program)
+                    .unwrap();
+            })
+        });
+        group.bench_function(format!("{name} (fast interpreter)"), |b| {
+            b.iter(|| {
+                FastInterpreter::new(TestingDevice::new("hello world!"))
+                    .run(&program)
+                    .unwrap();
+            })
+        });
+        group.bench_function(format!("{name} (fast interpreter, precompiled)"), |b| {
+            b.iter(|| {
+                FastInterpreter::new(TestingDevice::new("hello world!"))
+                    .run_bytecode(&bytecode)
+                    .unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_interpreter);
+criterion_main!(benches);
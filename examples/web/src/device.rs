@@ -91,26 +91,26 @@ impl WasmDevice {
         self.ffi.insert(ffi, f);
     }
 
-    fn put_char(&mut self, ch: char) -> Result<(), String> {
+    fn put_char(&mut self, ch: char) -> Result<(), RuntimeError> {
         self.output.push(ch as usize as i64);
         Ok(())
     }
 
-    fn put_int(&mut self, val: i64) -> Result<(), String> {
+    fn put_int(&mut self, val: i64) -> Result<(), RuntimeError> {
         for ch in val.to_string().chars() {
             self.put_char(ch)?
         }
         Ok(())
     }
 
-    fn put_float(&mut self, val: f32) -> Result<(), String> {
+    fn put_float(&mut self, val: f32) -> Result<(), RuntimeError> {
         for ch in format!("{val}").chars() {
             self.put_char(ch)?
         }
         Ok(())
     }
 
-    fn get_char(&mut self) -> Result<char, String> {
+    fn get_char(&mut self) -> Result<char, RuntimeError> {
         Ok(self
             .input
             .pop_front()
@@ -118,7 +118,7 @@ impl WasmDevice {
             .unwrap_or('\0'))
     }
 
-    fn get_int(&mut self) -> Result<i64, String> {
+    fn get_int(&mut self) -> Result<i64, RuntimeError> {
         let mut result: i64 = 0;
         loop {
             if self.input.is_empty() {
@@ -150,7 +150,7 @@ impl WasmDevice {
         Ok(result)
     }
 
-    fn get_float(&mut self) -> Result<f32, String> {
+    fn get_float(&mut self) -> Result<f32, RuntimeError> {
         let whole_part = self.get_int()? as f32;
 
         if self.input.is_empty() {
@@ -177,7 +177,7 @@ impl WasmDevice {
 
 /// Make the testing device work with the interpreter.
 impl Device for WasmDevice {
-    fn get(&mut self, src: Input) -> Result<i64, String> {
+    fn get(&mut self, src: Input) -> Result<i64, RuntimeError> {
         match src.mode {
             InputMode::StdinChar => Ok(if let Some(n) = self.input.pop_front() {
                 n
@@ -190,7 +190,7 @@ impl Device for WasmDevice {
         }
     }
 
-    fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), RuntimeError> {
         match dst.mode {
             OutputMode::StdoutInt => self.put_int(val),
             OutputMode::StdoutFloat => self.put_float(as_float(val)),
@@ -203,27 +203,35 @@ impl Device for WasmDevice {
         }
     }
 
-    fn peek(&mut self) -> Result<i64, String> {
+    fn peek(&mut self) -> Result<i64, RuntimeError> {
         // println!("peeking");
         // Ok(0)
         if let Some(n) = self.ffi_channel.pop_front() {
             Ok(n)
         } else {
-            Err("ffi channel is empty".to_string())
+            Err(RuntimeError::EmptyFFIChannel)
         }
     }
 
-    fn poke(&mut self, val: i64) -> Result<(), String> {
+    fn poke(&mut self, val: i64) -> Result<(), RuntimeError> {
         self.ffi_channel.push_back(val);
         Ok(())
     }
 
-    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String> {
+    fn ffi_channel(&self) -> &VecDeque<i64> {
+        &self.ffi_channel
+    }
+
+    fn ffi_channel_mut(&mut self) -> &mut VecDeque<i64> {
+        &mut self.ffi_channel
+    }
+
+    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), RuntimeError> {
         if let Some(f) = self.ffi.get(ffi) {
             f(&mut self.ffi_channel, tape);
             Ok(())
         } else {
-            Err(format!("ffi call not found: {:?}", ffi))
+            Err(RuntimeError::FFIUnbound(ffi.clone()))
         }
     }
 }
@@ -4,7 +4,10 @@
 //! variant.
 
 use super::{as_float, as_int};
-use sage::vm::{CoreOp, Device, StandardDevice, CoreProgram, StandardOp, StandardProgram};
+use sage::vm::{
+    CoreOp, Device, StandardDevice, CoreProgram, RuntimeError, StandardOp, StandardProgram,
+    StateSnapshot,
+};
 
 impl Default for WasmInterpreter<StandardDevice> {
     fn default() -> Self {
@@ -82,19 +85,20 @@ impl<T> WasmInterpreter<T> where T: Device {
     }
 
     /// Dereference the current pointer on the tape.
-    fn deref(&mut self) {
-        // Add the old pointer to the dereference stack.
-        self.refs.push(self.pointer);
+    fn deref(&mut self) -> Result<(), RuntimeError> {
         let cell = *self.get_cell();
         if cell < 0 {
-            panic!("Dereferencing negative cell with value {cell:?}");
+            return Err(RuntimeError::DerefOutOfBounds(cell));
         }
+        // Add the old pointer to the dereference stack.
+        self.refs.push(self.pointer);
         // Set the pointer to the address on the tape.
-        self.pointer = *self.get_cell() as usize;
+        self.pointer = cell as usize;
+        Ok(())
     }
 
     /// Undo a dereference.
-    fn refer(&mut self) -> Result<(), String> {
+    fn refer(&mut self) -> Result<(), RuntimeError> {
         // Get the previous value of the pointer before
         // the last dereference instruction.
         if let Some(old) = self.refs.pop() {
@@ -102,12 +106,12 @@ impl<T> WasmInterpreter<T> where T: Device {
             Ok(())
         } else {
             // There was no previous dereference, throw an error
-            Err(String::from("cannot Refer due to empty Deref stack"))
+            Err(RuntimeError::EmptyDerefStack)
         }
     }
 
     /// Call the Nth function defined in the program, where N is the value of the register.
-    fn call(&mut self, code: &StandardProgram) -> Result<(), String> {
+    fn call(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
         // If the function has been defined
         if self.functions.len() > self.reg_scalar() as usize {
             // Push the current instruction pointer to the call stack
@@ -129,7 +133,11 @@ impl<T> WasmInterpreter<T> where T: Device {
                         count += 1;
                     }
                     Some(_) => {}
-                    None => return Err(format!("function {} not defined", self.reg_scalar())),
+                    None => {
+                        // Return to the caller, so the error points at the call.
+                        self.i = self.calls.pop().unwrap_or_default();
+                        return Err(RuntimeError::InvalidCall(self.reg_scalar()));
+                    }
                 }
                 // If `count` hasn't reached the function we want,
                 // keep going.
@@ -249,7 +257,7 @@ impl<T> WasmInterpreter<T> where T: Device {
     }
 
     /// Run a core program using this interpreter and its device.
-    pub fn run(mut self, code: &StandardProgram) -> Result<T, String> {
+    pub fn run(mut self, code: &StandardProgram) -> Result<T, RuntimeError> {
         while !self.done {
             self.step(code)?
         }
@@ -257,8 +265,28 @@ impl<T> WasmInterpreter<T> where T: Device {
         Ok(self.device)
     }
 
+    /// Take a snapshot of the state of the interpreter, to attach to an error.
+    fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            instruction: self.i,
+            pointer: self.pointer,
+            register: self.register.clone(),
+            tape: self.cells.clone(),
+            call_stack: self.calls.clone(),
+            deref_stack: self.refs.clone(),
+        }
+    }
+
     /// Run a single step of the interpreter.
-    fn step(&mut self, code: &StandardProgram) -> Result<(), String> {
+    fn step(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
+        self.execute(code).map_err(|err| match err {
+            RuntimeError::WithState(..) => err,
+            _ => RuntimeError::WithState(Box::new(err), Box::new(self.snapshot())),
+        })
+    }
+
+    /// Execute the current instruction.
+    fn execute(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
         if let Some(op) = self.fetch(code) {
             match op {
                 StandardOp::CoreOp(core_op) => match core_op {
@@ -324,10 +352,7 @@ impl<T> WasmInterpreter<T> where T: Device {
                             self.pointer += *n as usize
                         } else {
                             if self.pointer < -*n as usize {
-                                return Err(format!(
-                                    "Instruction #{} tried to move the pointer to a negative index.",
-                                    self.i
-                                ));
+                                return Err(RuntimeError::NegativePointer(self.i));
                             }
                             self.pointer -= -*n as usize
                         }
@@ -339,7 +364,7 @@ impl<T> WasmInterpreter<T> where T: Device {
                             self.reg_mut_vector()[i] += *n as i64;
                         }
                     }
-                    CoreOp::Deref => self.deref(),
+                    CoreOp::Deref => self.deref()?,
                     CoreOp::Refer => self.refer()?,
 
                     CoreOp::Index(n) => {
@@ -416,17 +441,19 @@ impl<T> WasmInterpreter<T> where T: Device {
                     CoreOp::Div(n) => {
                         for i in 0..*n {
                             let val = self.cells[self.pointer + i];
-                            if val != 0 {
-                                self.reg_mut_vector()[i] /= val;
+                            if val == 0 {
+                                return Err(RuntimeError::DivideByZero);
                             }
+                            self.reg_mut_vector()[i] /= val;
                         }
                     }
                     CoreOp::Rem(n) => {
                         for i in 0..*n {
                             let val = self.cells[self.pointer + i];
-                            if val != 0 {
-                                self.reg_mut_vector()[i] %= val;
+                            if val == 0 {
+                                return Err(RuntimeError::DivideByZero);
                             }
+                            self.reg_mut_vector()[i] %= val;
                         }
                     }
                     CoreOp::Neg(n) => {
@@ -626,6 +653,9 @@ impl<T> WasmInterpreter<T> where T: Device {
                 }
 
                 StandardOp::Alloc => {
+                    if self.reg_scalar() < 0 {
+                        return Err(RuntimeError::NegativeAlloc(self.reg_scalar()));
+                    }
                     // If the virtual machine doesn't have a thousand cells,
                     // allocate some.
                     if self.cells.len() < 30000 {
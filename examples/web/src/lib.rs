@@ -76,7 +76,7 @@ enum BetterError {
     /// Error generated when assembling input code.
     AsmError(asm::Error),
     /// Error generated by the interpreter executing input code.
-    InterpreterError(String),
+    InterpreterError(RuntimeError),
     /// Error when building the virtual machine code for a given target.
     BuildError(String),
     /// Invalid source code (expected core but got standard).
@@ -211,18 +211,23 @@ pub fn compile_and_run() -> Result<(), JsValue> {
                     console_log!("successfully compiled to asm {asm_code:?}");
                     match target.as_str() {
                         "run" => {
-                            let device = WasmInterpreter::new(device)
-                                .run(
-                                    &match asm_code {
-                                        Ok(core) => core.into(),
-                                        Err(std) => std,
-                                    }
-                                    .assemble(8192)
-                                    .unwrap(),
+                            match WasmInterpreter::new(device).run(
+                                &match asm_code {
+                                    Ok(core) => core.into(),
+                                    Err(std) => std,
+                                }
+                                .assemble(8192)
+                                .unwrap(),
+                            ) {
+                                Ok(device) => String::from_utf8(
+                                    device.output.into_iter().map(|n| n as u8).collect(),
                                 )
-                                .unwrap();
-                            String::from_utf8(device.output.into_iter().map(|n| n as u8).collect())
-                                .unwrap()
+                                .unwrap(),
+                                Err(e) => {
+                                    console_log!("error running program {e:?}");
+                                    format!("{:?}", BetterError::InterpreterError(e))
+                                }
+                            }
                         }
                         "lir" => lir_code.to_string(),
                         "asm" => {
@@ -285,3 +290,23 @@ pub fn compile_and_run() -> Result<(), JsValue> {
 
     Ok(())
 }
+
+/// Run a precompiled binary program (a `.sgb` file) with the given input,
+/// and return its output. This skips compiling the program in the browser.
+#[wasm_bindgen(js_name = run_binary)]
+pub fn run_binary(program: &[u8], input: String) -> String {
+    let code = match from_bytes(program) {
+        Ok(Ok(core)) => core.into(),
+        Ok(Err(std)) => std,
+        Err(e) => return format!("{:?}", BetterError::Parse(e)),
+    };
+    match WasmInterpreter::new(WasmDevice::new(input)).run(&code) {
+        Ok(device) => {
+            String::from_utf8(device.output.into_iter().map(|n| n as u8).collect()).unwrap()
+        }
+        Err(e) => {
+            console_log!("error running program {e:?}");
+            format!("{:?}", BetterError::InterpreterError(e))
+        }
+    }
+}
@@ -5,15 +5,23 @@
 //! the supported targets provided by the compiler.
 use clap::*;
 use sage::{
+    debugger::Debugger,
+    frontend::stdlib,
     lir::*,
     parse::*,
+    profiler::Profiler,
     targets::{self, CompiledTarget},
     vm::*,
     LOGO_WITH_COLOR, *,
 };
 use std::{
+    collections::BTreeSet,
     fmt,
-    fs::{read_to_string, write},
+    fs::{read, read_to_string, write, File},
+    io::{stdin, stdout, BufWriter},
+    ops::RangeInclusive,
+    process::ExitCode,
+    time::Duration,
 };
 
 use log::error;
@@ -45,6 +53,8 @@ enum LogLevel {
 enum TargetType {
     /// Execute the source code in the interpreter.
     Run,
+    /// Debug the source code interactively in the interpreter.
+    Debug,
     /// Compile to the core variant of the assembly language.
     CoreASM,
     /// Compile to the standard variant of the assembly language.
@@ -53,12 +63,20 @@ enum TargetType {
     CoreVM,
     /// Compile to the standard variant of the virtual machine.
     StdVM,
+    /// Compile to a binary program of the core variant of the virtual machine.
+    CoreBin,
+    /// Compile to a binary program of the standard variant of the virtual machine.
+    StdBin,
     // /// Compile to My OS source code (GCC only).
     // SageOS,
-    /// Compile to C source code (GCC only).
+    /// Compile to C99 source code.
     C,
-    // /// Compile to x86 assembly code.
-    // X86,
+    /// Compile to x86-64 assembly code (GNU assembler, Linux only).
+    X86,
+    /// Compile to a WebAssembly module (text format).
+    Wasm,
+    /// Compile to LLVM IR (text format).
+    Llvm,
 }
 
 /// The source language options to compile.
@@ -76,15 +94,54 @@ enum SourceType {
     CoreVM,
     /// Compile standard variant virtual machine code.
     StdVM,
+    /// Compile a core variant binary program.
+    CoreBin,
+    /// Compile a standard variant binary program.
+    StdBin,
+}
+
+/// The interpreters to run programs with.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum InterpreterType {
+    /// Run programs in the interpreter for their variant of the virtual machine.
+    Standard,
+    /// Compile core variant programs to bytecode before running them.
+    /// Standard variant programs still run in the standard interpreter.
+    Fast,
+}
+
+/// The optimization passes to run over LIR code.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum LirPass {
+    /// Evaluate operations on literals ahead of time.
+    Fold,
+    /// Replace calls to small procedures with their bodies.
+    Inline,
+    /// Remove branches which are never taken.
+    DeadBranches,
+    /// Remove variables which are never used.
+    UnusedVars,
+    /// Run every pass.
+    All,
 }
 
 /// The argument parser for the CLI.
 #[derive(Parser, Debug)]
 #[clap(author, version, about = Some(LOGO_WITH_COLOR), long_about = Some(LOGO_WITH_COLOR), max_term_width=90)]
+#[clap(
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true,
+    disable_help_subcommand = true
+)]
 struct Args {
-    /// The input file to compiler.
-    #[clap(value_parser)]
-    input: String,
+    /// A command to run instead of compiling a file.
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// The input file to compile. A file with the same name as a command
+    /// can be compiled by writing `--` before it.
+    #[clap(value_parser, required = true)]
+    input: Option<String>,
 
     /// The file to write the output of the compiler to.
     #[clap(short, long, value_parser, default_value = "out")]
@@ -110,6 +167,226 @@ struct Args {
     /// also enable debug logging.
     #[clap(short, long, value_parser)]
     debug: Option<String>,
+
+    /// The source map of the program. When compiling to virtual machine or C code,
+    /// the source map is written to this file. When running virtual machine code,
+    /// the source map is read from this file to report runtime errors.
+    #[clap(long, value_parser)]
+    source_map: Option<String>,
+
+    /// The optimization level for the virtual machine code, from 0 (no optimizations) to 3.
+    #[clap(short = 'O', value_parser = value_parser!(u8).range(0..=MAX_OPTIMIZATION_LEVEL as i64), default_value = "0")]
+    opt_level: u8,
+
+    /// The optimization passes to run over the LIR code before compiling it,
+    /// separated by commas. No passes are run by default.
+    #[clap(long, value_parser, value_delimiter = ',')]
+    lir_passes: Vec<LirPass>,
+
+    /// The interpreter to run the program with, when the target is `run`.
+    #[clap(long, value_parser, default_value = "standard")]
+    interpreter: InterpreterType,
+
+    /// The maximum number of instructions to execute, when the target is `run`.
+    #[clap(long, value_parser)]
+    max_instructions: Option<u64>,
+
+    /// The maximum number of cells on the tape, when the target is `run`.
+    #[clap(long, value_parser)]
+    max_tape_size: Option<usize>,
+
+    /// The maximum depth of the call stack, when the target is `run`.
+    #[clap(long, value_parser)]
+    max_call_depth: Option<usize>,
+
+    /// The maximum depth of the dereference stack, when the target is `run`.
+    #[clap(long, value_parser)]
+    max_deref_depth: Option<usize>,
+
+    /// The maximum number of seconds to run the program for, when the target is `run`.
+    #[clap(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    time_limit: Option<Duration>,
+
+    /// The file to save the state of the interpreter to, if the program stops
+    /// with an error (such as exceeding a limit), or periodically with
+    /// `--checkpoint-every`, when the target is `run`.
+    #[clap(long, value_name = "FILE", value_parser)]
+    checkpoint: Option<String>,
+
+    /// Save the state of the interpreter to the checkpoint file while the program runs,
+    /// every N instructions (like `1000000`) or every N seconds (like `30s`).
+    #[clap(long, value_name = "N", value_parser = parse_interval, requires = "checkpoint")]
+    checkpoint_every: Option<CheckpointInterval>,
+
+    /// The file to restore the state of the interpreter from before running
+    /// the program, when the target is `run`. The program must be the same
+    /// one the checkpoint was saved from.
+    #[clap(long, value_name = "FILE", value_parser)]
+    resume: Option<String>,
+
+    /// Count the instructions executed in each function, when the target is `run`.
+    /// The report is printed to stderr, and the collapsed call stacks for flamegraph
+    /// tools are written to the output file with the `.folded` extension.
+    #[clap(long, value_parser)]
+    profile: bool,
+
+    /// The file to write a trace of the instructions executed to, when the target is `run`.
+    /// The fast interpreter can't record traces, so it isn't used for traced programs.
+    #[clap(long, value_name = "FILE", value_parser)]
+    trace: Option<String>,
+
+    /// Only trace the instructions executed in the functions with these indices,
+    /// given as a single index like `3` or a range like `2-5`.
+    #[clap(long, value_name = "INDICES", value_parser = parse_functions)]
+    trace_functions: Option<RangeInclusive<usize>>,
+
+    /// Only trace the given number of instructions from the start of the program.
+    #[clap(long, value_name = "N", value_parser)]
+    trace_steps: Option<u64>,
+
+    /// Warn about a lint, even one which is allowed by default (like `shadowing`).
+    /// `warnings` enables every lint.
+    #[clap(short = 'W', long = "warn", value_name = "LINT", value_parser = parse_lint)]
+    warn: Vec<String>,
+
+    /// Don't warn about a lint. `warnings` allows every lint.
+    #[clap(short = 'A', long = "allow", value_name = "LINT", value_parser = parse_lint)]
+    allow: Vec<String>,
+
+    /// Stop compiling the program if there are any warnings.
+    #[clap(long, value_parser)]
+    deny_warnings: bool,
+
+    /// The most errors to report for a program, or 0 to report every error.
+    #[clap(long, value_name = "N", value_parser, default_value = "20")]
+    max_errors: usize,
+}
+
+/// The commands which the CLI runs instead of compiling a file.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show a trace of a program, or compare two traces.
+    TraceView(TraceViewArgs),
+}
+
+/// The arguments to the `sage trace-view` command.
+#[derive(Parser, Debug)]
+struct TraceViewArgs {
+    /// The trace to show.
+    #[clap(value_parser)]
+    trace: String,
+
+    /// The trace to compare it with. The first step where they differ is shown.
+    #[clap(value_parser)]
+    other: Option<String>,
+
+    /// The number of steps to show before the first difference.
+    #[clap(short, long, value_parser, default_value = "3")]
+    context: usize,
+}
+
+/// Parse a (possibly fractional) number of seconds.
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .map_err(|e| e.to_string())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).map_err(|e| e.to_string()))
+}
+
+/// Parse a number of instructions (like `1000`), or of seconds (like `2.5s`).
+fn parse_interval(s: &str) -> Result<CheckpointInterval, String> {
+    match s.strip_suffix('s') {
+        Some(secs) => parse_seconds(secs).map(CheckpointInterval::Time),
+        None => s
+            .parse::<u64>()
+            .map(CheckpointInterval::Instructions)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Parse a function index (like `3`) or an inclusive range of them (like `2-5`).
+fn parse_functions(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let index = |s: &str| s.trim().parse::<usize>().map_err(|e| e.to_string());
+    match s.split_once('-') {
+        Some((start, end)) => Ok(index(start)?..=index(end)?),
+        None => index(s).map(|i| i..=i),
+    }
+}
+
+/// Check that a lint name given on the command line names a lint.
+fn parse_lint(s: &str) -> Result<String, String> {
+    match Lint::named(s) {
+        Some(_) => Ok(s.to_string()),
+        None => Err(format!(
+            "unknown lint `{s}`, expected `{ALL_LINTS}` or one of: {}",
+            Lint::ALL.map(|lint| lint.name()).join(", ")
+        )),
+    }
+}
+
+/// Get the warnings to report, given by the arguments.
+/// Lints are enabled with `-W` and then allowed with `-A`.
+fn warnings(args: &Args) -> Warnings {
+    let named = |names: &[String]| {
+        names
+            .iter()
+            .flat_map(|name| Lint::named(name).unwrap_or_default())
+            .collect::<BTreeSet<_>>()
+    };
+    let mut lints = Lint::defaults();
+    lints.extend(named(&args.warn));
+    Warnings {
+        lints: lints.difference(&named(&args.allow)).copied().collect(),
+        deny: args.deny_warnings,
+    }
+}
+
+/// Get the interpreter limits given by the arguments.
+fn limits(args: &Args) -> Limits {
+    Limits {
+        instructions: args.max_instructions,
+        tape_size: args.max_tape_size,
+        call_depth: args.max_call_depth,
+        deref_depth: args.max_deref_depth,
+        time: args.time_limit,
+    }
+}
+
+/// Get the LIR optimizations which run the given passes.
+fn lir_optimizations(passes: &[LirPass]) -> Optimizations {
+    let enabled = |pass| passes.contains(&pass) || passes.contains(&LirPass::All);
+    Optimizations {
+        fold_constants: enabled(LirPass::Fold),
+        inline_procedures: enabled(LirPass::Inline),
+        remove_dead_branches: enabled(LirPass::DeadBranches),
+        remove_unused_variables: enabled(LirPass::UnusedVars),
+    }
+}
+
+/// The warnings to report for Sage and LIR code.
+struct Warnings {
+    /// The lints to check.
+    lints: BTreeSet<Lint>,
+    /// Stop compiling the program if there are any warnings.
+    deny: bool,
+}
+
+impl Warnings {
+    /// Check a program for warnings, and print them to stderr, pointing at the source code.
+    fn check(&self, expr: &Expr, code: &str) -> Result<(), Error> {
+        use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+
+        let warnings = expr.lint(&self.lints);
+        let writer = StandardStream::stderr(ColorChoice::Always);
+        for warning in &warnings {
+            warning
+                .emit(&mut writer.lock(), |loc| Some(Error::source_of(loc, code)))
+                .map_err(Error::IO)?;
+        }
+        if self.deny && !warnings.is_empty() {
+            return Err(Error::DeniedWarnings(warnings.len()));
+        }
+        Ok(())
+    }
 }
 
 /// The types of errors returned by the CLI.
@@ -129,21 +406,40 @@ enum Error {
     /// Error generated when assembling input code.
     AsmError(asm::Error),
     /// Error generated by the interpreter executing input code.
-    InterpreterError(String),
+    InterpreterError(RuntimeError),
+    /// Error generated by the interpreter, with the location in the source
+    /// code where it happened and the backtrace (innermost frame first).
+    RuntimeError {
+        message: String,
+        backtrace: Vec<SourceSpan>,
+        source_code: String,
+    },
     /// Error when building the virtual machine code for a given target.
     BuildError(String),
     /// Invalid source code (expected core but got standard).
     InvalidSource(String),
+    /// The program has warnings, and `--deny-warnings` was given.
+    DeniedWarnings(usize),
+    /// Several independent errors, in the order they were found.
+    Many(Vec<Self>),
+    /// The number of errors which weren't reported, because of `--max-errors`.
+    Omitted(usize),
 }
 
 impl Error {
     pub fn annotate_with_source(self, code: &str) -> Self {
         match self {
+            Self::LirError(lir::Error::Many(errors)) => Self::Many(
+                errors
+                    .into_iter()
+                    .map(|err| Self::LirError(err).annotate_with_source(code))
+                    .collect(),
+            ),
             Self::LirError(lir::Error::Annotated(ref err, ref metadata)) => {
                 if let Some(loc) = metadata.location().cloned() {
                     Self::WithSourceCode {
+                        source_code: Self::source_of(&loc, code),
                         loc,
-                        source_code: code.to_owned(),
                         err: Box::new(Error::LirError(*err.clone())),
                     }
                 } else {
@@ -153,6 +449,54 @@ impl Error {
             _ => self,
         }
     }
+
+    /// Create an error from a frontend parse error, annotated with the source
+    /// code of the file (or included module) which caused it.
+    fn from_frontend(err: frontend::Error, code: &str) -> Self {
+        if let frontend::Error::Many(errors) = err {
+            return Self::Many(
+                errors
+                    .into_iter()
+                    .map(|err| Self::from_frontend(err, code))
+                    .collect(),
+            );
+        }
+        match err.location().cloned() {
+            Some(loc) => Self::WithSourceCode {
+                source_code: Self::source_of(&loc, code),
+                loc,
+                err: Box::new(Error::Parse(err.to_string())),
+            },
+            None => Self::Parse(err.to_string()),
+        }
+    }
+
+    /// Only keep the first `max` errors, or every error if `max` is zero.
+    fn limit(self, max: usize) -> Self {
+        match self {
+            Self::Many(mut errors) if max > 0 && errors.len() > max => {
+                let omitted = errors.len() - max;
+                errors.truncate(max);
+                errors.push(Self::Omitted(omitted));
+                Self::Many(errors)
+            }
+            _ => self,
+        }
+    }
+
+    /// Get the source code of the file containing the given location.
+    /// Locations inside of included modules refer to other files than the
+    /// input file, so those are read from disk (or from the bundled standard library).
+    fn source_of(loc: &SourceCodeLocation, code: &str) -> String {
+        loc.filename
+            .as_ref()
+            .and_then(|filename| {
+                stdlib::source(filename)
+                    .map(str::to_owned)
+                    .or_else(|| read_to_string(filename).ok())
+            })
+            .unwrap_or_else(|| code.to_owned())
+    }
 }
 
 impl fmt::Debug for Error {
@@ -217,101 +561,322 @@ impl fmt::Debug for Error {
                 Ok(())
             }
             Error::InterpreterError(e) => write!(f, "Interpreter error: {}", e),
+            Error::RuntimeError {
+                message,
+                backtrace,
+                source_code,
+            } => {
+                use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+
+                let writer = StandardStream::stderr(ColorChoice::Always);
+                let result = SourceMap::emit_error(
+                    &mut writer.lock(),
+                    message,
+                    &backtrace.iter().collect::<Vec<_>>(),
+                    |loc| Some(Self::source_of(loc, source_code)),
+                );
+                result.map_err(|_| fmt::Error)
+            }
             Error::BuildError(e) => write!(f, "Build error: {}", e),
             Error::InvalidSource(e) => write!(f, "Invalid source: {}", e),
+            Error::DeniedWarnings(n) => {
+                write!(f, "Denied warnings: {n} warning(s) with --deny-warnings")
+            }
+            Error::Many(errors) => {
+                for err in errors {
+                    writeln!(f, "{err:?}")?;
+                }
+                Ok(())
+            }
+            Error::Omitted(n) => write!(
+                f,
+                "... and {n} more error(s), use --max-errors to show them"
+            ),
         }
     }
 }
 
-/// Compile a given source language to virtual machine code.
+/// Compile a given source language to virtual machine code, along with the
+/// debug information recorded while compiling it. Only Sage and LIR code
+/// carry source code locations, so other source languages have no debug information.
+///
+/// Sage and LIR code is checked for warnings, and optimized with the given passes
+/// before it's compiled. The virtual machine code is optimized at the given optimization level.
+/// If the target only supports the core variant, `core_only` compiles the program with the
+/// core fallbacks for standard instructions.
 fn compile_source_to_vm(
     filename: Option<&str>,
     src: String,
     src_type: SourceType,
     call_stack_size: usize,
-) -> Result<Result<sage::vm::CoreProgram, sage::vm::StandardProgram>, Error> {
-    match src_type {
+    opt_level: u8,
+    lir_optimizations: Optimizations,
+    warnings: &Warnings,
+    core_only: bool,
+) -> Result<
+    (
+        Result<sage::vm::CoreProgram, sage::vm::StandardProgram>,
+        DebugInfo,
+    ),
+    Error,
+> {
+    let (asm_code, debug_info) = match src_type {
         SourceType::StdVM => {
             // Simply parse the virtual machine code
-            parse_vm(src).map_err(Error::Parse)
+            return Ok((
+                optimize(parse_vm(src).map_err(Error::Parse)?, opt_level),
+                DebugInfo::default(),
+            ));
         }
         SourceType::CoreVM => {
             // Parse the virtual machine code
-            match parse_vm(src).map_err(Error::Parse)? {
+            return match parse_vm(src).map_err(Error::Parse)? {
                 // If we got a core program back, return it.
-                Ok(prog) => Ok(Ok(prog)),
+                Ok(prog) => Ok((Ok(prog.optimize(opt_level)), DebugInfo::default())),
                 // Otherwise, our core program was actually a standard program. Throw an error.
                 Err(_) => Err(Error::InvalidSource(
                     "expected core VM program, got standard VM program".to_string(),
                 )),
-            }
+            };
+        }
+        // Binary programs are read straight from the input file.
+        SourceType::StdBin => {
+            return Ok((
+                optimize(read_binary(filename)?, opt_level),
+                DebugInfo::default(),
+            ));
+        }
+        SourceType::CoreBin => {
+            return match read_binary(filename)? {
+                Ok(prog) => Ok((Ok(prog.optimize(opt_level)), DebugInfo::default())),
+                Err(_) => Err(Error::InvalidSource(
+                    "expected core binary program, got standard binary program".to_string(),
+                )),
+            };
         }
         SourceType::StdASM => {
             // Parse the assembly code.
-            // Then, assembly the program with the given recursion depth,
-            // and return the virtual machine output.
-            match parse_asm(src).map_err(Error::Parse)? {
-                Ok(prog) => Ok(Ok(prog
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-                Err(prog) => Ok(Err(prog
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-            }
+            (parse_asm(src).map_err(Error::Parse)?, DebugInfo::default())
         }
         SourceType::CoreASM => {
             // Parse the assembly code.
             match parse_asm(src).map_err(Error::Parse)? {
-                // If we got back a core program, assembly it and return the virtual machine code.
-                Ok(prog) => Ok(Ok(prog
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
+                // If we got back a core program, assemble it.
+                Ok(prog) => (Ok(prog), DebugInfo::default()),
                 // Otherwise, our core program was actually a standard program. Throw an error.
-                Err(_) => Err(Error::InvalidSource(
-                    "expected core assembly program, got standard assembly program".to_string(),
-                )),
+                Err(_) => {
+                    return Err(Error::InvalidSource(
+                        "expected core assembly program, got standard assembly program".to_string(),
+                    ))
+                }
             }
         }
+        // Parse the lower intermediate representation code, and compile it.
         SourceType::LowIR => {
-            // Parse the lower intermediate representation code.
-            match parse_lir(src)
-                .map_err(Error::Parse)?
-                .compile()
+            let expr = parse_lir(src.clone()).map_err(Error::Parse)?;
+            warnings.check(&expr, &src)?;
+            expr.optimize(lir_optimizations)
+                .and_then(|expr| {
+                    if core_only {
+                        expr.compile_core_only_with_debug_info()
+                    } else {
+                        expr.compile_with_debug_info()
+                    }
+                })
                 .map_err(Error::LirError)?
-            {
-                // If we got back a valid program, assemble it and return the result.
-                Ok(asm_code) => Ok(Ok(asm_code
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-                Err(asm_code) => Ok(Err(asm_code
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-            }
         }
         SourceType::Sage => {
-            match parse_frontend(&src, filename)
-                .map_err(Error::Parse)?
-                .compile()
+            let expr = parse_frontend(&src, filename).map_err(|e| Error::from_frontend(e, &src))?;
+            warnings.check(&expr, &src)?;
+            expr.optimize(lir_optimizations)
+                .and_then(|expr| {
+                    if core_only {
+                        expr.compile_core_only_with_debug_info()
+                    } else {
+                        expr.compile_with_debug_info()
+                    }
+                })
                 .map_err(Error::LirError)
                 .map_err(|e| e.annotate_with_source(&src))?
-            {
-                // If we got back a valid program, assemble it and return the result.
-                Ok(asm_code) => Ok(Ok(asm_code
+        }
+    };
+    // Assemble the program with the given recursion depth,
+    // and return the optimized virtual machine output.
+    Ok((
+        optimize(
+            match asm_code {
+                Ok(asm_code) => Ok(asm_code
                     .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-                Err(asm_code) => Ok(Err(asm_code
+                    .map_err(Error::AsmError)?),
+                Err(asm_code) => Err(asm_code
                     .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
+                    .map_err(Error::AsmError)?),
+            },
+            opt_level,
+        ),
+        debug_info,
+    ))
+}
+
+/// Optimize virtual machine code of either variant at the given optimization level.
+fn optimize(
+    code: Result<sage::vm::CoreProgram, sage::vm::StandardProgram>,
+    opt_level: u8,
+) -> Result<sage::vm::CoreProgram, sage::vm::StandardProgram> {
+    match code {
+        Ok(code) => Ok(code.optimize(opt_level)),
+        Err(code) => Err(code.optimize(opt_level)),
+    }
+}
+
+/// Run a virtual machine program in an interpreter. Runtime errors are reported
+/// with the location in the source code and the backtrace, using the source map.
+/// If the program is profiled, the profile is reported even if the program fails.
+fn run_program<I: Debuggable>(
+    interpreter: &mut I,
+    code: &I::Program,
+    source_map: &SourceMap,
+    src: &str,
+    mut profiler: Option<(Profiler, &str)>,
+) -> Result<(), Error> {
+    let result = run_steps(interpreter, code, source_map, src, profiler.as_mut());
+    if let Some((profiler, folded)) = profiler {
+        eprint!("{}", profiler.report::<I>(code));
+        write_file(folded.to_string(), profiler.collapsed_stacks())?;
+    }
+    result
+}
+
+/// Step through a program until it's done, counting the instructions if it's profiled.
+fn run_steps<I: Debuggable>(
+    interpreter: &mut I,
+    code: &I::Program,
+    source_map: &SourceMap,
+    src: &str,
+    mut profiler: Option<&mut (Profiler, &str)>,
+) -> Result<(), Error> {
+    while !interpreter.is_done() {
+        let step = match &mut profiler {
+            Some((profiler, _)) => profiler.step(interpreter, code),
+            None => interpreter.step(code),
+        };
+        if let Err(err) = step {
+            // Running out of resources isn't the fault of any one line of the program.
+            if let RuntimeError::LimitExceeded(_) = err.kind() {
+                return Err(Error::InterpreterError(err));
+            }
+            let backtrace = source_map
+                .backtrace(interpreter.instruction_pointer(), interpreter.call_stack())
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            if backtrace.is_empty() {
+                return Err(Error::InterpreterError(err));
             }
+            return Err(Error::RuntimeError {
+                message: err.to_string(),
+                backtrace,
+                source_code: src.to_string(),
+            });
         }
     }
+    Ok(())
+}
+
+/// Flush the trace of a program to its file, if the program was traced.
+fn finish_trace(tracer: Option<Tracer>) -> Result<(), Error> {
+    match tracer {
+        Some(tracer) => tracer.finish().map_err(Error::IO),
+        None => Ok(()),
+    }
+}
+
+/// Format a step of a trace for reading.
+fn show_step(step: &TraceStep) -> String {
+    let mut result = format!(
+        "{:>8} {:>6}  {:<24} pointer={} register={}",
+        step.step, step.instruction, step.op, step.pointer, step.register
+    );
+    for (addr, value) in &step.writes {
+        result += &format!(" [{addr}]={value}");
+    }
+    result
+}
+
+/// Show a trace, or the first step where two traces differ.
+fn trace_view(args: TraceViewArgs) -> Result<(), Error> {
+    let read_trace = |file: &str| parse_trace(&read_file(file)?).map_err(Error::InvalidSource);
+    let trace = read_trace(&args.trace)?;
+    let Some(other_file) = args.other else {
+        for step in &trace {
+            println!("{}", show_step(step));
+        }
+        return Ok(());
+    };
+
+    let other = read_trace(&other_file)?;
+    let Some(i) = diff_traces(&trace, &other) else {
+        println!("The traces are the same ({} steps)", trace.len());
+        return Ok(());
+    };
+    for step in &trace[i.saturating_sub(args.context)..i] {
+        println!("  {}", show_step(step));
+    }
+    let show = |step: Option<&TraceStep>| step.map_or("(end of trace)".to_string(), show_step);
+    println!("- {}", show(trace.get(i)));
+    println!("+ {}", show(other.get(i)));
+    println!(
+        "The traces are the same for {i} steps, then differ: `-` is {}, `+` is {other_file}",
+        args.trace
+    );
+    Ok(())
+}
+
+/// Get the source map of the compiled program. Virtual machine code doesn't
+/// carry its own debug information, so its source map is read from the given file.
+fn get_source_map(
+    code: &Result<sage::vm::CoreProgram, sage::vm::StandardProgram>,
+    debug_info: &DebugInfo,
+    src_type: SourceType,
+    source_map: Option<&str>,
+) -> Result<SourceMap, Error> {
+    Ok(match (src_type, source_map) {
+        (
+            SourceType::CoreVM | SourceType::StdVM | SourceType::CoreBin | SourceType::StdBin,
+            Some(file),
+        ) => SourceMap::parse(&read_file(file)?).map_err(Error::InvalidSource)?,
+        (_, _) => match code {
+            Ok(vm_code) => vm_code.source_map(debug_info),
+            Err(vm_code) => vm_code.source_map(debug_info),
+        },
+    })
+}
+
+/// Write the source map of the compiled program to the given file, if any.
+/// Source maps are only written for programs compiled from code with debug information.
+fn write_source_map(
+    source_map: &SourceMap,
+    src_type: SourceType,
+    file: Option<&str>,
+) -> Result<(), Error> {
+    match (src_type, file) {
+        (SourceType::CoreVM | SourceType::StdVM | SourceType::CoreBin | SourceType::StdBin, _)
+        | (_, None) => Ok(()),
+        (_, Some(file)) => write_file(file.to_string(), source_map.to_string()),
+    }
 }
 
 /// Compile code in a given source language to assembly code.
+/// Sage and LIR code is checked for warnings, and optimized with the given passes before it's compiled.
+/// If the target only supports the core variant, `core_only` compiles the program with the
+/// core fallbacks for standard instructions.
 fn compile_source_to_asm(
     filename: Option<&str>,
     src: String,
     src_type: SourceType,
+    lir_optimizations: Optimizations,
+    warnings: &Warnings,
+    core_only: bool,
 ) -> Result<Result<sage::asm::CoreProgram, sage::asm::StandardProgram>, Error> {
     match src_type {
         // If the source language is standard assembly, then parse it and return it.
@@ -325,26 +890,48 @@ fn compile_source_to_asm(
             )),
         },
         // If the source language is LIR, parse it and compile it to assembly code.
-        SourceType::LowIR => parse_lir(src)
-            .map_err(Error::Parse)?
-            .compile()
-            .map_err(Error::LirError),
+        SourceType::LowIR => {
+            let expr = parse_lir(src.clone()).map_err(Error::Parse)?;
+            warnings.check(&expr, &src)?;
+            expr.optimize(lir_optimizations)
+                .and_then(|expr| {
+                    if core_only {
+                        expr.compile_core_only()
+                    } else {
+                        expr.compile()
+                    }
+                })
+                .map_err(Error::LirError)
+        }
 
         // If the source language is Sage, parse it and compile it to assembly code.
-        SourceType::Sage => parse_frontend(&src, filename)
-            .map_err(Error::Parse)?
-            .compile()
-            .map_err(Error::LirError)
-            .map_err(|e| e.annotate_with_source(&src)),
+        SourceType::Sage => {
+            let expr = parse_frontend(&src, filename).map_err(|e| Error::from_frontend(e, &src))?;
+            warnings.check(&expr, &src)?;
+            expr.optimize(lir_optimizations)
+                .and_then(|expr| {
+                    if core_only {
+                        expr.compile_core_only()
+                    } else {
+                        expr.compile()
+                    }
+                })
+                .map_err(Error::LirError)
+                .map_err(|e| e.annotate_with_source(&src))
+        }
         // If the source language is a virtual machine program,
         // then we cannot compile it to assembly. Throw an error.
         SourceType::CoreVM | SourceType::StdVM => Err(Error::InvalidSource(
             "cannot compile a core VM program to assembly".to_string(),
         )),
+        SourceType::CoreBin | SourceType::StdBin => Err(Error::InvalidSource(
+            "cannot compile a binary program to assembly".to_string(),
+        )),
     }
 }
 
 /// Compile code in a given source language to a given target language.
+#[allow(clippy::too_many_arguments)]
 fn compile(
     filename: Option<&str>,
     src: String,
@@ -353,75 +940,383 @@ fn compile(
     output: String,
     call_stack_size: usize,
     debug: bool,
+    source_map: Option<&str>,
+    opt_level: u8,
+    lir_optimizations: Optimizations,
+    warnings: &Warnings,
+    interpreter: InterpreterType,
+    limits: Limits,
+    checkpoint: Option<&str>,
+    checkpoint_every: Option<CheckpointInterval>,
+    resume: Option<&str>,
+    profile: bool,
+    tracer: Option<Tracer>,
 ) -> Result<(), Error> {
     match target {
         // If the target is `Run`, then compile the code and execute it with the interpreter.
-        TargetType::Run => match compile_source_to_vm(filename, src, src_type, call_stack_size)? {
-            // If the code is core variant virtual machine code
-            Ok(vm_code) => {
-                CoreInterpreter::new(StandardDevice::default())
-                    .run(&vm_code)
-                    .map_err(Error::InterpreterError)?;
+        TargetType::Run => {
+            // Virtual machine code read with a source map is run as is,
+            // so that the source map still points to the right instructions.
+            let opt_level = match (src_type, source_map) {
+                (
+                    SourceType::CoreVM
+                    | SourceType::StdVM
+                    | SourceType::CoreBin
+                    | SourceType::StdBin,
+                    Some(_),
+                ) => 0,
+                _ => opt_level,
+            };
+            let (vm_code, debug_info) = compile_source_to_vm(
+                filename,
+                src.clone(),
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )?;
+            let map = get_source_map(&vm_code, &debug_info, src_type, source_map)?;
+            let folded = format!("{output}.folded");
+            let profiler = || profile.then(|| (Profiler::new(&debug_info), folded.as_str()));
+            // Only the standard interpreter can save and restore its state,
+            // so core variant code is run with it when checkpointing.
+            let vm_code = match vm_code {
+                Ok(vm_code) if checkpoint.is_some() || resume.is_some() => Err(vm_code.into()),
+                vm_code => vm_code,
+            };
+            match vm_code {
+                // If the code is core variant virtual machine code,
+                // and the fast interpreter was chosen, compile it to bytecode.
+                // The fast interpreter can't record traces, so traced programs use the core one.
+                Ok(vm_code) if interpreter == InterpreterType::Fast && tracer.is_none() => {
+                    run_program(
+                        &mut FastInterpreter::new(StandardDevice::default()).with_limits(limits),
+                        &Bytecode::new(&vm_code.without_comments()).map_err(|e| {
+                            Error::InterpreterError(RuntimeError::InvalidProgram(e))
+                        })?,
+                        &map,
+                        &src,
+                        profiler(),
+                    )?
+                }
+                // If the code is core variant virtual machine code
+                Ok(vm_code) => {
+                    let mut interpreter = CoreInterpreter::new(StandardDevice::default())
+                        .with_limits(limits)
+                        .with_tracer(tracer);
+                    let result = run_program(
+                        &mut interpreter,
+                        &vm_code.without_comments(),
+                        &map,
+                        &src,
+                        profiler(),
+                    );
+                    finish_trace(interpreter.take_tracer())?;
+                    result?
+                }
+                // If the code is standard variant virtual machine code
+                Err(vm_code) => {
+                    let checkpointer = checkpoint
+                        .zip(checkpoint_every)
+                        .map(|(file, interval)| Checkpointer::new(file, interval));
+                    let mut interpreter = StandardInterpreter::new(StandardDevice::default())
+                        .with_limits(limits)
+                        .with_tracer(tracer)
+                        .with_checkpointer(checkpointer);
+                    let vm_code = vm_code.without_comments();
+                    if let Some(file) = resume {
+                        interpreter
+                            .restore(
+                                Checkpoint::parse(&read_file(file)?)
+                                    .map_err(Error::InvalidSource)?,
+                                &vm_code,
+                            )
+                            .map_err(Error::InvalidSource)?;
+                    }
+                    let result = run_program(&mut interpreter, &vm_code, &map, &src, profiler());
+                    // Save the state of the interpreter, to resume the program or reproduce the error.
+                    if let (Err(_), Some(file)) = (&result, checkpoint) {
+                        interpreter
+                            .snapshot(&vm_code)
+                            .save(file)
+                            .map_err(Error::IO)?;
+                    }
+                    if let Some(checkpointer) = interpreter.take_checkpointer() {
+                        checkpointer.finish().map_err(Error::IO)?;
+                    }
+                    finish_trace(interpreter.take_tracer())?;
+                    result?
+                }
             }
-            // If the code is standard variant virtual machine code
-            Err(vm_code) => {
-                StandardInterpreter::new(StandardDevice::default())
-                    .run(&vm_code) 
-                    .map_err(Error::InterpreterError)?;
+        }
+
+        // If the target is `Debug`, then compile the code with debug information,
+        // and run it in the interactive debugger.
+        TargetType::Debug => {
+            let name = filename.unwrap_or("program").to_string();
+            match compile_source_to_vm(
+                filename,
+                src.clone(),
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )? {
+                (Ok(vm_code), debug_info) => Debugger::new(
+                    CoreInterpreter::new(StandardDevice::default()),
+                    &vm_code,
+                    debug_info,
+                )
+                .with_source(name, src)
+                .run(stdin().lock(), stdout())
+                .map_err(Error::IO)?,
+                (Err(vm_code), debug_info) => Debugger::new(
+                    StandardInterpreter::new(StandardDevice::default()),
+                    &vm_code,
+                    debug_info,
+                )
+                .with_source(name, src)
+                .run(stdin().lock(), stdout())
+                .map_err(Error::IO)?,
             }
-        },
+        }
 
         // If the target is C source code, then compile the code to virtual machine code,
         // and then use the C target implementation to build the output source code.
-        TargetType::C => write_file(
-            format!("{output}.c"),
-            match compile_source_to_vm(filename, src, src_type, call_stack_size)? {
-                Ok(vm_code) => targets::C.build_core(&vm_code.flatten()),
-                Err(vm_code) => targets::C.build_std(&vm_code.flatten()),
-            }
-            .map_err(Error::BuildError)?,
-        )?,
+        // The C code reports runtime errors using the program's source map.
+        TargetType::C => {
+            let (vm_code, debug_info) = compile_source_to_vm(
+                filename,
+                src.clone(),
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )?;
+            let vm_code = match vm_code {
+                Ok(vm_code) => Ok(vm_code.flatten()),
+                Err(vm_code) => Err(vm_code.flatten()),
+            };
+            let map = get_source_map(&vm_code, &debug_info, src_type, None)?;
+            write_source_map(&map, src_type, source_map)?;
+            let mut c = targets::C::default()
+                .with_source_map(&map, |loc| Some(Error::source_of(loc, &src)));
+            write_file(
+                format!("{output}.c"),
+                match vm_code {
+                    Ok(vm_code) => c.build_core(&vm_code),
+                    Err(vm_code) => c.build_std(&vm_code),
+                }
+                .map_err(Error::BuildError)?,
+            )?
+        }
+
+        // If the target is x86-64 assembly code, then compile the code to virtual machine code,
+        // and then use the x86 target implementation to build the output assembly code.
+        TargetType::X86 => {
+            let (vm_code, _) = compile_source_to_vm(
+                filename,
+                src,
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )?;
+            let mut x86 = targets::X86::default();
+            write_file(
+                format!("{output}.s"),
+                match vm_code {
+                    Ok(vm_code) => x86.build_core(&vm_code.flatten()),
+                    Err(vm_code) => x86.build_std(&vm_code.flatten()),
+                }
+                .map_err(Error::BuildError)?,
+            )?
+        }
+
+        // If the target is WebAssembly, then compile the code to virtual machine code,
+        // and then use the wasm target implementation to build the output module.
+        TargetType::Wasm => {
+            let (vm_code, _) = compile_source_to_vm(
+                filename,
+                src,
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )?;
+            let mut wasm = targets::Wasm::default();
+            write_file(
+                format!("{output}.wat"),
+                match vm_code {
+                    Ok(vm_code) => wasm.build_core(&vm_code.flatten()),
+                    Err(vm_code) => wasm.build_std(&vm_code.flatten()),
+                }
+                .map_err(Error::BuildError)?,
+            )?
+        }
+
+        // If the target is LLVM IR, then compile the code to virtual machine code,
+        // and then use the LLVM target implementation to build the output IR.
+        TargetType::Llvm => {
+            let (vm_code, _) = compile_source_to_vm(
+                filename,
+                src,
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )?;
+            let mut llvm = targets::Llvm::default();
+            write_file(
+                format!("{output}.ll"),
+                match vm_code {
+                    Ok(vm_code) => llvm.build_core(&vm_code.flatten()),
+                    Err(vm_code) => llvm.build_std(&vm_code.flatten()),
+                }
+                .map_err(Error::BuildError)?,
+            )?
+        }
 
         // If the target is core virtual machine code, then try to compile the source to the core variant.
         // If not possible, throw an error.
-        TargetType::CoreVM => match compile_source_to_vm(filename, src, src_type, call_stack_size)?
-        {
-            Ok(vm_code) if debug => write_file(
-                format!("{output}.vm.sg"),
-                format!("{:#}", vm_code.flatten()),
-            ),
-            Ok(vm_code) => write_file(format!("{output}.vm.sg"), vm_code.flatten().to_string()),
-            Err(_) => Err(Error::InvalidSource(
+        TargetType::CoreVM => match compile_source_to_vm(
+            filename,
+            src,
+            src_type,
+            call_stack_size,
+            opt_level,
+            lir_optimizations,
+            warnings,
+            true,
+        )? {
+            (Ok(vm_code), debug_info) => {
+                let vm_code = vm_code.flatten();
+                write_source_map(&vm_code.source_map(&debug_info), src_type, source_map)?;
+                if debug {
+                    write_file(format!("{output}.vm.sg"), format!("{:#}", vm_code))
+                } else {
+                    write_file(format!("{output}.vm.sg"), vm_code.to_string())
+                }
+            }
+            (Err(_), _) => Err(Error::InvalidSource(
                 "expected core VM program, got standard VM program".to_string(),
             )),
         }?,
         // If the target is standard virtual machine code, the compile it to virtual machine code.
         // If the result is core variant, we don't care. Just return the generated code.
-        TargetType::StdVM => write_file(
-            format!("{output}.vm.sg"),
-            match compile_source_to_vm(filename, src, src_type, call_stack_size)? {
-                Ok(vm_code) if debug => format!("{:#}", vm_code.flatten()),
-                Err(vm_code) if debug => format!("{:#}", vm_code.flatten()),
-                Ok(vm_code) => vm_code.flatten().to_string(),
-                Err(vm_code) => vm_code.flatten().to_string(),
-            },
-        )?,
-        // If the target is core assembly code, then try to compile the source to the core variant.
+        TargetType::StdVM => {
+            let (vm_code, debug_info) = compile_source_to_vm(
+                filename,
+                src,
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )?;
+            let vm_code = match vm_code {
+                Ok(vm_code) => Ok(vm_code.flatten()),
+                Err(vm_code) => Err(vm_code.flatten()),
+            };
+            let map = get_source_map(&vm_code, &debug_info, src_type, None)?;
+            write_source_map(&map, src_type, source_map)?;
+            write_file(
+                format!("{output}.vm.sg"),
+                match vm_code {
+                    Ok(vm_code) if debug => format!("{:#}", vm_code),
+                    Err(vm_code) if debug => format!("{:#}", vm_code),
+                    Ok(vm_code) => vm_code.to_string(),
+                    Err(vm_code) => vm_code.to_string(),
+                },
+            )?
+        }
+        // If the target is a core binary program, then try to compile the source to the core variant.
         // If not possible, throw an error.
-        TargetType::CoreASM => match compile_source_to_asm(filename, src, src_type)? {
-            Ok(asm_code) if debug => {
-                write_file(format!("{output}.asm.sg"), format!("{:#}", asm_code))
+        TargetType::CoreBin => match compile_source_to_vm(
+            filename,
+            src,
+            src_type,
+            call_stack_size,
+            opt_level,
+            lir_optimizations,
+            warnings,
+            true,
+        )? {
+            (Ok(vm_code), debug_info) => {
+                let vm_code = vm_code.flatten();
+                write_source_map(&vm_code.source_map(&debug_info), src_type, source_map)?;
+                write_binary(format!("{output}.sgb"), vm_code.to_bytes())
             }
-            Ok(asm_code) => write_file(format!("{output}.asm.sg"), asm_code.to_string()),
-            Err(_) => Err(Error::InvalidSource(
-                "expected core assembly program, got standard assembly program".to_string(),
+            (Err(_), _) => Err(Error::InvalidSource(
+                "expected core VM program, got standard VM program".to_string(),
             )),
         }?,
+        // If the target is a standard binary program, then compile the source to virtual machine code.
+        // Core variant programs are written as they are.
+        TargetType::StdBin => {
+            let (vm_code, debug_info) = compile_source_to_vm(
+                filename,
+                src,
+                src_type,
+                call_stack_size,
+                opt_level,
+                lir_optimizations,
+                warnings,
+                false,
+            )?;
+            let vm_code = match vm_code {
+                Ok(vm_code) => Ok(vm_code.flatten()),
+                Err(vm_code) => Err(vm_code.flatten()),
+            };
+            let map = get_source_map(&vm_code, &debug_info, src_type, None)?;
+            write_source_map(&map, src_type, source_map)?;
+            write_binary(
+                format!("{output}.sgb"),
+                match vm_code {
+                    Ok(vm_code) => vm_code.to_bytes(),
+                    Err(vm_code) => vm_code.to_bytes(),
+                },
+            )?
+        }
+        // If the target is core assembly code, then try to compile the source to the core variant.
+        // If not possible, throw an error.
+        TargetType::CoreASM => {
+            match compile_source_to_asm(filename, src, src_type, lir_optimizations, warnings, true)?
+            {
+                Ok(asm_code) if debug => {
+                    write_file(format!("{output}.asm.sg"), format!("{:#}", asm_code))
+                }
+                Ok(asm_code) => write_file(format!("{output}.asm.sg"), asm_code.to_string()),
+                Err(_) => Err(Error::InvalidSource(
+                    "expected core assembly program, got standard assembly program".to_string(),
+                )),
+            }?
+        }
         // If the target is standard assembly code, then try to compile the source to the standard variant.
         // If the result is core variant, we don't care. Just return the generated code.
         TargetType::StdASM => write_file(
             format!("{output}.asm.sg"),
-            match compile_source_to_asm(filename, src, src_type)? {
+            match compile_source_to_asm(
+                filename,
+                src,
+                src_type,
+                lir_optimizations,
+                warnings,
+                false,
+            )? {
                 Ok(core_asm_code) if debug => format!("{:#}", core_asm_code),
                 Err(std_asm_code) if debug => format!("{:#}", std_asm_code),
                 Ok(core_asm_code) => core_asm_code.to_string(),
@@ -442,10 +1337,36 @@ fn read_file(name: &str) -> Result<String, Error> {
     read_to_string(name).map_err(Error::IO)
 }
 
-/// Run the CLI.
-fn cli() {
+/// Write a binary program to a file.
+fn write_binary(file: String, contents: Vec<u8>) -> Result<(), Error> {
+    write(file, contents).map_err(Error::IO)
+}
+
+/// Read a binary program of either variant from a file.
+fn read_binary(
+    name: Option<&str>,
+) -> Result<Result<sage::vm::CoreProgram, sage::vm::StandardProgram>, Error> {
+    let bytes = read(name.unwrap_or_default()).map_err(Error::IO)?;
+    from_bytes(&bytes).map_err(Error::Parse)
+}
+
+/// Run the CLI. This fails if there were any errors, so the
+/// process exits with an unsuccessful status.
+fn cli() -> ExitCode {
     // Parse the arguments to the CLI.
-    let args = Args::parse();
+    let mut args = Args::parse();
+    // Commands have their own arguments, and don't compile anything.
+    let input = match (args.command.take(), args.input.take()) {
+        (Some(Command::TraceView(trace_args)), _) => {
+            if let Err(e) = trace_view(trace_args) {
+                eprintln!("{e:#?}");
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        (None, Some(input)) => input,
+        (None, None) => unreachable!("the input file is required without a command"),
+    };
     let mut builder = env_logger::Builder::from_default_env();
     builder.format_timestamp(None);
 
@@ -466,30 +1387,63 @@ fn cli() {
 
     builder.init();
 
-    match read_file(&args.input) {
+    let limits = limits(&args);
+    let warnings = warnings(&args);
+    let tracer = match &args.trace {
+        Some(file) => match File::create(file) {
+            Ok(file) => Some(Tracer::new(BufWriter::new(file)).with_filter(TraceFilter {
+                functions: args.trace_functions.clone(),
+                steps: args.trace_steps,
+            })),
+            Err(e) => {
+                error!("Error creating trace file: {e:?}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    // Binary programs aren't text, so they're read when they're compiled.
+    let contents = match args.source_type {
+        SourceType::CoreBin | SourceType::StdBin => Ok(String::new()),
+        _ => read_file(&input),
+    };
+    match contents {
         Ok(file_contents) => {
             match compile(
-                Some(&args.input),
+                Some(&input),
                 file_contents,
                 args.source_type,
                 args.target_type,
                 args.output,
                 args.call_stack_size,
                 args.debug.is_some(),
+                args.source_map.as_deref(),
+                args.opt_level,
+                lir_optimizations(&args.lir_passes),
+                &warnings,
+                args.interpreter,
+                limits,
+                args.checkpoint.as_deref(),
+                args.checkpoint_every,
+                args.resume.as_deref(),
+                args.profile,
+                tracer,
             ) {
-                Ok(_) => {}
+                Ok(_) => ExitCode::SUCCESS,
                 Err(e) => {
-                    error!("{e:#?}");
+                    error!("{:#?}", e.limit(args.max_errors));
+                    ExitCode::FAILURE
                 }
             }
         }
         Err(e) => {
             error!("Error reading file: {e:?}");
+            ExitCode::FAILURE
         }
     }
 }
 
-fn main() {
+fn main() -> ExitCode {
     rayon::ThreadPoolBuilder::new()
         .num_threads(16)
         .stack_size(RELEASE_STACK_SIZE_MB * 1024 * 1024)
@@ -0,0 +1,743 @@
+//! # Debugger
+//!
+//! This module implements an interactive source-level debugger for the
+//! virtual machine interpreters.
+//!
+//! A program compiled with `Compile::compile_with_debug_info` is marked with
+//! comments referring to the debug scopes recorded by the LIR compiler. The
+//! assembler preserves these comments, so the debugger can map every marked
+//! instruction index in the virtual machine code back to a source code location,
+//! and to the variables defined at that point in the program (along with their
+//! offsets from the frame pointer).
+//!
+//! The debugger can drive either interpreter through the `Debuggable` trait.
+//!
+//! ## Commands
+//!
+//! | Command                         | Description                                              |
+//! |---------------------------------|----------------------------------------------------------|
+//! | `break [file:]line`             | Stop whenever the program reaches the given line.        |
+//! | `delete [n]`                    | Delete breakpoint `n`, or all breakpoints.               |
+//! | `breakpoints`                   | List the breakpoints.                                    |
+//! | `continue`                      | Run until a breakpoint is hit or the program exits.      |
+//! | `step`                          | Run until the next line, entering procedure calls.       |
+//! | `next`                          | Run until the next line, stepping over procedure calls.  |
+//! | `finish`                        | Run until the current procedure returns.                 |
+//! | `stepi [n]`                     | Execute `n` virtual machine instructions.                |
+//! | `print var`                     | Print the value of a variable.                           |
+//! | `locals`                        | Print all the variables in scope.                        |
+//! | `register`                      | Print the contents of the register.                      |
+//! | `tape [address] [count]`        | Print the cells of the tape.                             |
+//! | `derefs`                        | Print the stack of dereferenced pointers.                |
+//! | `backtrace`                     | Print the call stack.                                    |
+//! | `where`                         | Print the current location and instruction.              |
+//! | `list`                          | Print the source code around the current line.           |
+//! | `disassemble [n]`               | Print the next `n` virtual machine instructions.         |
+//! | `quit`                          | Stop debugging.                                          |
+use crate::{
+    asm::{Location, FP},
+    frontend::stdlib,
+    lir::{DebugInfo, DebugScope, DebugVar, IntType, Type},
+    parse::SourceCodeLocation,
+    vm::{as_float, Debuggable, RuntimeError},
+};
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    io::{BufRead, Result as IoResult, Write},
+    path::Path,
+};
+
+/// The help message printed by the `help` command.
+const HELP: &str = "\
+break [file:]line      stop whenever the program reaches the given line (alias: b)
+delete [n]             delete breakpoint n, or all breakpoints (alias: d)
+breakpoints            list the breakpoints
+continue               run until a breakpoint is hit or the program exits (alias: c, run)
+step                   run until the next line, entering procedure calls (alias: s)
+next                   run until the next line, stepping over procedure calls (alias: n)
+finish                 run until the current procedure returns (alias: f)
+stepi [n]              execute n virtual machine instructions (alias: si)
+print var              print the value of a variable (alias: p)
+locals                 print all the variables in scope
+register               print the contents of the register (alias: reg)
+tape [address] [count] print the cells of the tape (alias: t)
+derefs                 print the stack of dereferenced pointers
+backtrace              print the call stack (alias: bt)
+where                  print the current location and instruction (alias: w)
+list                   print the source code around the current line (alias: l)
+disassemble [n]        print the next n virtual machine instructions (alias: x)
+quit                   stop debugging (alias: q)";
+
+/// How to resume the program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until a breakpoint is hit.
+    Continue,
+    /// Run until the next line, entering procedure calls.
+    Step,
+    /// Run until the next line in the current procedure, stepping over calls.
+    Next,
+    /// Run until the current procedure returns.
+    Finish,
+}
+
+/// Why the program stopped after being resumed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The program reached the breakpoint with the given ID.
+    Breakpoint(usize),
+    /// The program reached the next line.
+    Stepped,
+    /// The current procedure returned.
+    Returned,
+    /// The program finished running.
+    Exited,
+    /// The program stopped with a runtime error.
+    Error(RuntimeError),
+}
+
+/// A breakpoint on a line of source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// The file containing the line.
+    pub filename: Option<String>,
+    /// The line to stop at.
+    pub line: usize,
+    /// The instruction indices which belong to the line.
+    instructions: Vec<usize>,
+}
+
+/// An interactive debugger for a virtual machine program.
+pub struct Debugger<'a, I>
+where
+    I: Debuggable,
+{
+    /// The interpreter running the program.
+    interpreter: I,
+    /// The program being debugged.
+    code: &'a I::Program,
+    /// The debug information recorded when the program was compiled.
+    debug_info: DebugInfo,
+    /// The file being debugged.
+    filename: Option<String>,
+    /// The source code of the file being debugged.
+    source: Option<String>,
+    /// The debug scope marked at each instruction index.
+    markers: BTreeMap<usize, usize>,
+    /// The breakpoints, by ID.
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    /// The ID to give to the next breakpoint.
+    next_breakpoint: usize,
+    /// The last debug scope entered in each frame of the call stack.
+    /// The first frame is the code outside of any procedure.
+    frames: Vec<Option<usize>>,
+    /// The runtime error which stopped the program, if any.
+    error: Option<RuntimeError>,
+}
+
+impl<'a, I> Debugger<'a, I>
+where
+    I: Debuggable,
+{
+    /// Create a debugger for a program, using the debug information
+    /// recorded when the program was compiled.
+    pub fn new(interpreter: I, code: &'a I::Program, debug_info: DebugInfo) -> Self {
+        let markers = (0..I::program_len(code))
+            .filter_map(|i| Some((i, DebugInfo::parse_marker(I::comment(code, i)?)?)))
+            .filter(|(_, id)| debug_info.get_scope(*id).is_some())
+            .collect();
+
+        Self {
+            interpreter,
+            code,
+            debug_info,
+            filename: None,
+            source: None,
+            markers,
+            breakpoints: BTreeMap::new(),
+            next_breakpoint: 1,
+            frames: vec![None],
+            error: None,
+        }
+    }
+
+    /// Supply the name and source code of the file being debugged.
+    pub fn with_source(mut self, filename: impl ToString, source: impl ToString) -> Self {
+        self.filename = Some(filename.to_string());
+        self.source = Some(source.to_string());
+        self
+    }
+
+    /// Get the interpreter running the program.
+    pub fn interpreter(&self) -> &I {
+        &self.interpreter
+    }
+
+    /// Has the program finished running (or stopped with an error)?
+    pub fn is_done(&self) -> bool {
+        self.interpreter.is_done() || self.error.is_some()
+    }
+
+    /// The depth of the call stack.
+    fn depth(&self) -> usize {
+        self.interpreter.call_stack().len()
+    }
+
+    /// Get the debug scope of the current instruction.
+    pub fn current_scope(&self) -> Option<&DebugScope> {
+        let ip = self.interpreter.instruction_pointer();
+        let id = match self.markers.get(&ip) {
+            Some(id) => Some(*id),
+            None => self.frames.last().copied().flatten(),
+        };
+        self.debug_info.get_scope(id?)
+    }
+
+    /// Get the source code location of the current instruction.
+    pub fn location(&self) -> Option<&SourceCodeLocation> {
+        self.current_scope().map(|scope| &scope.location)
+    }
+
+    /// Are the two filenames referring to the same file? A missing
+    /// filename refers to the file being debugged.
+    fn same_file(&self, a: Option<&str>, b: Option<&str>) -> bool {
+        match (
+            a.or(self.filename.as_deref()),
+            b.or(self.filename.as_deref()),
+        ) {
+            (Some(a), Some(b)) => Path::new(a).ends_with(b) || Path::new(b).ends_with(a),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Add a breakpoint on a line of source code, and return its ID.
+    pub fn add_breakpoint(&mut self, filename: Option<&str>, line: usize) -> Result<usize, String> {
+        // A line can be marked several times in a row (a statement and its
+        // initial value, for instance), but only the first marker starts the line.
+        let mut instructions = vec![];
+        let mut previous = None;
+        for (i, id) in &self.markers {
+            let location = &self.debug_info.scopes()[*id].location;
+            let current = (location.filename.as_deref(), location.line);
+            if current.1 == line && previous != Some(current) && self.same_file(current.0, filename)
+            {
+                instructions.push(*i);
+            }
+            previous = Some(current);
+        }
+
+        if instructions.is_empty() {
+            return Err(match filename {
+                Some(filename) => format!("no code at {filename}:{line}"),
+                None => format!("no code at line {line}"),
+            });
+        }
+
+        let id = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.insert(
+            id,
+            Breakpoint {
+                filename: filename.map(str::to_string),
+                line,
+                instructions,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Remove the breakpoint with the given ID. Returns whether the breakpoint existed.
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some()
+    }
+
+    /// Get the breakpoints, by ID.
+    pub fn breakpoints(&self) -> &BTreeMap<usize, Breakpoint> {
+        &self.breakpoints
+    }
+
+    /// Get the ID of the breakpoint at the given instruction, if any.
+    fn breakpoint_at(&self, ip: usize) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| breakpoint.instructions.contains(&ip))
+            .map(|(id, _)| *id)
+    }
+
+    /// Execute a single virtual machine instruction.
+    pub fn step_instruction(&mut self) -> Result<(), RuntimeError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        if self.interpreter.is_done() {
+            return Ok(());
+        }
+
+        // Remember the scope we're entering in the current frame.
+        let ip = self.interpreter.instruction_pointer();
+        if let Some(id) = self.markers.get(&ip) {
+            if let Some(frame) = self.frames.last_mut() {
+                *frame = Some(*id);
+            }
+        }
+
+        if let Err(error) = self.interpreter.step(self.code) {
+            self.error = Some(error.clone());
+            return Err(error);
+        }
+
+        // Push a new frame for a call, or pop the frames we returned from.
+        let depth = self.depth();
+        self.frames.resize(depth + 1, None);
+        Ok(())
+    }
+
+    /// The line of source code a scope belongs to.
+    fn line_of(&self, id: usize) -> (Option<&str>, usize) {
+        let location = &self.debug_info.scopes()[id].location;
+        (location.filename.as_deref(), location.line)
+    }
+
+    /// Resume the program until it stops for the given reason.
+    pub fn resume(&mut self, mode: Resume) -> Stop {
+        let start_depth = self.depth();
+        let start_line = self
+            .current_scope()
+            .map(|scope| (scope.location.filename.clone(), scope.location.line));
+
+        loop {
+            if let Some(error) = &self.error {
+                return Stop::Error(error.clone());
+            }
+            if self.interpreter.is_done() {
+                return Stop::Exited;
+            }
+            if let Err(error) = self.step_instruction() {
+                return Stop::Error(error);
+            }
+            if self.interpreter.is_done() {
+                return Stop::Exited;
+            }
+
+            let depth = self.depth();
+            if mode == Resume::Finish && depth < start_depth {
+                return Stop::Returned;
+            }
+
+            let ip = self.interpreter.instruction_pointer();
+            if let Some(id) = self.markers.get(&ip).copied() {
+                if let Some(breakpoint) = self.breakpoint_at(ip) {
+                    return Stop::Breakpoint(breakpoint);
+                }
+
+                let (filename, line) = self.line_of(id);
+                let new_line = match &start_line {
+                    Some((start_filename, start_line)) => {
+                        *start_line != line || start_filename.as_deref() != filename
+                    }
+                    None => true,
+                };
+                match mode {
+                    Resume::Step if new_line || depth != start_depth => return Stop::Stepped,
+                    Resume::Next if depth < start_depth || (depth == start_depth && new_line) => {
+                        return Stop::Stepped
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Get the value of the frame pointer.
+    fn frame_pointer(&self) -> i64 {
+        match FP {
+            Location::Address(addr) => self.cell(addr as i64),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Get the value of a cell on the tape.
+    fn cell(&self, addr: i64) -> i64 {
+        usize::try_from(addr)
+            .ok()
+            .and_then(|addr| self.interpreter.tape().get(addr))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get the cells which store a variable in the current frame.
+    fn read_var(&self, var: &DebugVar) -> Vec<i64> {
+        let addr = self.frame_pointer() + var.offset as i64;
+        (0..var.size as i64).map(|i| self.cell(addr + i)).collect()
+    }
+
+    /// Get the type and value of a variable defined in the current scope.
+    pub fn get_var(&self, name: &str) -> Result<(Type, Vec<i64>), String> {
+        let var = self
+            .current_scope()
+            .and_then(|scope| scope.get_var(name))
+            .ok_or_else(|| format!("no variable {name} in the current scope"))?;
+        Ok((var.ty.clone(), self.read_var(var)))
+    }
+
+    /// Get the frames of the call stack, starting with the innermost frame.
+    /// Each frame is described by the scope it is currently in.
+    pub fn backtrace(&self) -> Vec<Option<&DebugScope>> {
+        let mut result = vec![self.current_scope()];
+        for id in self.frames.iter().rev().skip(1) {
+            result.push(id.and_then(|id| self.debug_info.get_scope(id)));
+        }
+        result
+    }
+
+    /// Get a line of source code from a file.
+    fn source_line(&self, filename: Option<&str>, line: usize) -> Option<String> {
+        let source = match filename {
+            Some(filename) if !self.same_file(Some(filename), None) => stdlib::source(filename)
+                .map(str::to_string)
+                .or_else(|| read_to_string(filename).ok())?,
+            _ => self.source.clone()?,
+        };
+        source.lines().nth(line.checked_sub(1)?).map(str::to_string)
+    }
+
+    /// Run the interactive debugger, reading commands from the input until
+    /// the user quits or the input ends.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> IoResult<()> {
+        writeln!(
+            output,
+            "Debugging {}. Type `help` for a list of commands.",
+            self.filename.as_deref().unwrap_or("program")
+        )?;
+        self.print_location(&mut output)?;
+
+        let mut last_command = String::new();
+        loop {
+            write!(output, "(sage) ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+            // An empty line repeats the last command.
+            let command = match line.trim() {
+                "" => last_command.clone(),
+                command => command.to_string(),
+            };
+            if !self.execute(&command, &mut output)? {
+                return Ok(());
+            }
+            last_command = command;
+        }
+    }
+
+    /// Execute a single debugger command. Returns `false` if the user quit.
+    pub fn execute(&mut self, command: &str, output: &mut impl Write) -> IoResult<bool> {
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(true);
+        };
+        let args = words.collect::<Vec<_>>();
+        let number = |i: usize, default: usize| -> Result<usize, String> {
+            match args.get(i) {
+                Some(arg) => arg.parse().map_err(|_| format!("invalid number {arg}")),
+                None => Ok(default),
+            }
+        };
+
+        match name {
+            "quit" | "q" | "exit" => return Ok(false),
+            "help" | "h" => writeln!(output, "{HELP}")?,
+
+            "break" | "b" => {
+                let Some(arg) = args.first() else {
+                    writeln!(output, "usage: break [file:]line")?;
+                    return Ok(true);
+                };
+                let (filename, line) = match arg.rsplit_once(':') {
+                    Some((filename, line)) => (Some(filename), line),
+                    None => (None, *arg),
+                };
+                match line.parse() {
+                    Ok(line) => match self.add_breakpoint(filename, line) {
+                        Ok(id) => match filename {
+                            Some(filename) => {
+                                writeln!(output, "Breakpoint {id} at {filename}:{line}")?
+                            }
+                            None => writeln!(output, "Breakpoint {id} at line {line}")?,
+                        },
+                        Err(e) => writeln!(output, "{e}")?,
+                    },
+                    Err(_) => writeln!(output, "invalid line number {line}")?,
+                }
+            }
+            "delete" | "d" => match args.first() {
+                Some(_) => match number(0, 0) {
+                    Ok(id) if self.remove_breakpoint(id) => {
+                        writeln!(output, "Deleted breakpoint {id}")?
+                    }
+                    Ok(id) => writeln!(output, "no breakpoint {id}")?,
+                    Err(e) => writeln!(output, "{e}")?,
+                },
+                None => {
+                    self.breakpoints.clear();
+                    writeln!(output, "Deleted all breakpoints")?
+                }
+            },
+            "breakpoints" | "info" => {
+                if self.breakpoints.is_empty() {
+                    writeln!(output, "No breakpoints")?;
+                }
+                for (id, breakpoint) in &self.breakpoints {
+                    match &breakpoint.filename {
+                        Some(filename) => writeln!(output, "{id}: {filename}:{}", breakpoint.line)?,
+                        None => writeln!(output, "{id}: line {}", breakpoint.line)?,
+                    }
+                }
+            }
+
+            "continue" | "c" | "run" | "r" => self.resume_and_report(Resume::Continue, output)?,
+            "step" | "s" => self.resume_and_report(Resume::Step, output)?,
+            "next" | "n" => self.resume_and_report(Resume::Next, output)?,
+            "finish" | "f" => self.resume_and_report(Resume::Finish, output)?,
+            "stepi" | "si" => match number(0, 1) {
+                Ok(count) => {
+                    for _ in 0..count {
+                        if self.is_done() {
+                            break;
+                        }
+                        if let Err(e) = self.step_instruction() {
+                            writeln!(output, "Program stopped with an error: {e}")?;
+                            break;
+                        }
+                    }
+                    if self.interpreter.is_done() {
+                        writeln!(output, "Program exited")?;
+                    } else {
+                        self.print_instruction(output)?;
+                    }
+                }
+                Err(e) => writeln!(output, "{e}")?,
+            },
+
+            "print" | "p" => match args.first() {
+                Some(name) => match self.get_var(name) {
+                    Ok((ty, cells)) => {
+                        writeln!(output, "{name}: {ty} = {}", format_value(&ty, &cells))?
+                    }
+                    Err(e) => writeln!(output, "{e}")?,
+                },
+                None => writeln!(output, "usage: print var")?,
+            },
+            "locals" => match self.current_scope() {
+                Some(scope) if !scope.vars.is_empty() => {
+                    for var in &scope.vars {
+                        let cells = self.read_var(var);
+                        writeln!(
+                            output,
+                            "{}{}: {} = {}",
+                            if var.mutability.is_mutable() {
+                                "mut "
+                            } else {
+                                ""
+                            },
+                            var.name,
+                            var.ty,
+                            format_value(&var.ty, &cells)
+                        )?;
+                    }
+                }
+                _ => writeln!(output, "No variables in scope")?,
+            },
+            "register" | "reg" => {
+                let register = self.interpreter.register();
+                if register.len() > 16 {
+                    writeln!(output, "register = {:?}...", &register[..16])?;
+                } else {
+                    writeln!(output, "register = {:?}", register)?;
+                }
+            }
+            "tape" | "t" => {
+                let pointer = self.interpreter.pointer();
+                match (number(0, pointer), number(1, 8)) {
+                    (Ok(addr), Ok(count)) => {
+                        for addr in addr..addr + count {
+                            write!(output, "{addr:6}: {}", self.cell(addr as i64))?;
+                            if addr == pointer {
+                                write!(output, " <- pointer")?;
+                            }
+                            writeln!(output)?;
+                        }
+                    }
+                    (Err(e), _) | (_, Err(e)) => writeln!(output, "{e}")?,
+                }
+            }
+            "derefs" => {
+                let refs = self.interpreter.deref_stack();
+                if refs.is_empty() {
+                    writeln!(output, "The deref stack is empty")?;
+                }
+                for (i, pointer) in refs.iter().enumerate().rev() {
+                    writeln!(output, "#{i}: {pointer}")?;
+                }
+            }
+            "backtrace" | "bt" => {
+                let calls = self.interpreter.call_stack();
+                for (i, scope) in self.backtrace().into_iter().enumerate() {
+                    write!(output, "#{i} ")?;
+                    match scope {
+                        Some(scope) => write!(
+                            output,
+                            "{} at {}",
+                            procedure_name(scope),
+                            format_location(&scope.location)
+                        )?,
+                        None => write!(output, "<unknown>")?,
+                    }
+                    // Every frame except the outermost was called from an instruction.
+                    if let Some(call) = calls.len().checked_sub(i + 1).map(|i| calls[i]) {
+                        write!(output, " (called from instruction #{call})")?;
+                    }
+                    writeln!(output)?;
+                }
+            }
+            "where" | "w" => {
+                self.print_location(output)?;
+                self.print_instruction(output)?;
+            }
+            "list" | "l" => match self.location() {
+                Some(location) => {
+                    let current = location.line;
+                    let filename = location.filename.clone();
+                    for line in current.saturating_sub(5).max(1)..=current + 5 {
+                        if let Some(text) = self.source_line(filename.as_deref(), line) {
+                            let marker = if line == current { ">" } else { " " };
+                            writeln!(output, "{marker}{line:4} | {text}")?;
+                        }
+                    }
+                }
+                None => writeln!(output, "No source code location")?,
+            },
+            "disassemble" | "x" => match number(0, 8) {
+                Ok(count) => {
+                    let ip = self.interpreter.instruction_pointer();
+                    for i in ip..ip + count {
+                        if let Some(op) = I::instruction(self.code, i) {
+                            let marker = if i == ip { "=>" } else { "  " };
+                            writeln!(output, "{marker} {i:6}: {op}")?;
+                        }
+                    }
+                }
+                Err(e) => writeln!(output, "{e}")?,
+            },
+
+            other => writeln!(output, "unknown command `{other}`, type `help` for help")?,
+        }
+        Ok(true)
+    }
+
+    /// Resume the program, and tell the user why it stopped.
+    fn resume_and_report(&mut self, mode: Resume, output: &mut impl Write) -> IoResult<()> {
+        if self.is_done() {
+            return writeln!(output, "The program is not running");
+        }
+        let procedure = self.current_scope().map(procedure_name);
+        output.flush()?;
+        match self.resume(mode) {
+            Stop::Breakpoint(id) => {
+                write!(output, "Breakpoint {id}, ")?;
+                self.print_location(output)?;
+            }
+            Stop::Stepped => self.print_location(output)?,
+            Stop::Returned => {
+                writeln!(
+                    output,
+                    "Returned from {}",
+                    procedure.unwrap_or_else(|| "<unknown>".to_string())
+                )?;
+                self.print_location(output)?;
+            }
+            Stop::Exited => writeln!(output, "Program exited")?,
+            Stop::Error(e) => {
+                writeln!(output, "Program stopped with an error: {e}")?;
+                self.print_location(output)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the current source code location, and its line of code.
+    fn print_location(&self, output: &mut impl Write) -> IoResult<()> {
+        match self.current_scope() {
+            Some(scope) => {
+                writeln!(
+                    output,
+                    "{} at {}",
+                    procedure_name(scope),
+                    format_location(&scope.location)
+                )?;
+                let location = &scope.location;
+                if let Some(text) = self.source_line(location.filename.as_deref(), location.line) {
+                    writeln!(output, "{:4} | {text}", location.line)?;
+                }
+            }
+            None => writeln!(
+                output,
+                "at instruction #{}",
+                self.interpreter.instruction_pointer()
+            )?,
+        }
+        Ok(())
+    }
+
+    /// Print the current virtual machine instruction.
+    fn print_instruction(&self, output: &mut impl Write) -> IoResult<()> {
+        let ip = self.interpreter.instruction_pointer();
+        match I::instruction(self.code, ip) {
+            Some(op) => writeln!(output, "instruction #{ip}: {op}"),
+            None => writeln!(output, "instruction #{ip}: <end of program>"),
+        }
+    }
+}
+
+/// The name of the procedure a scope belongs to.
+fn procedure_name(scope: &DebugScope) -> String {
+    scope
+        .procedure
+        .clone()
+        .unwrap_or_else(|| "<main>".to_string())
+}
+
+/// Format a source code location as `file:line:column`.
+fn format_location(location: &SourceCodeLocation) -> String {
+    format!(
+        "{}:{}:{}",
+        location.filename.as_deref().unwrap_or("<unknown>"),
+        location.line,
+        location.column
+    )
+}
+
+/// Format the cells of a value according to its type.
+fn format_value(ty: &Type, cells: &[i64]) -> String {
+    match (ty, cells) {
+        (Type::Int | Type::Cell, [n]) => n.to_string(),
+        (Type::FixedInt(IntType::U64), [n]) => (*n as u64).to_string(),
+        (Type::FixedInt(_), [n]) => n.to_string(),
+        (Type::Float, [n]) => format!("{:?}", as_float(*n)),
+        (Type::Bool, [n]) => if *n != 0 { "True" } else { "False" }.to_string(),
+        (Type::Char, [n]) => format!(
+            "{:?}",
+            u32::try_from(*n)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+        ),
+        (Type::Pointer(_, _), [n]) => format!("{n:#x}"),
+        (Type::None, []) => "None".to_string(),
+        _ => format!("{cells:?}"),
+    }
+}
@@ -0,0 +1,92 @@
+use crate::parse::SourceCodeLocation;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// An error produced while parsing a frontend program and the modules it includes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A syntax error in a source file.
+    Syntax(String),
+    /// A `mod` declaration whose source file could not be found or read.
+    ModuleNotFound(String, SourceCodeLocation),
+    /// A `use` declaration or a qualified symbol which does not name
+    /// a declaration exported by a module.
+    UnresolvedImport(String, SourceCodeLocation),
+    /// A module which (indirectly) includes itself. This contains the
+    /// chain of files which make up the cycle.
+    CyclicImport(Vec<String>, SourceCodeLocation),
+    /// An imported name which collides with another declaration in the same file.
+    ConflictingImport(String, SourceCodeLocation),
+    /// A statement at the top level of a module. Modules may only contain declarations.
+    StatementInModule(SourceCodeLocation),
+    /// An attribute which allows a lint that doesn't exist.
+    UnknownLint(String, SourceCodeLocation),
+    /// Several independent errors, in the order they were found.
+    Many(Vec<Self>),
+}
+
+impl Error {
+    /// Get the location in the source code which caused the error, if there is one.
+    pub fn location(&self) -> Option<&SourceCodeLocation> {
+        match self {
+            Self::Syntax(_) | Self::Many(_) => None,
+            Self::ModuleNotFound(_, loc)
+            | Self::UnresolvedImport(_, loc)
+            | Self::CyclicImport(_, loc)
+            | Self::ConflictingImport(_, loc)
+            | Self::StatementInModule(loc)
+            | Self::UnknownLint(_, loc) => Some(loc),
+        }
+    }
+
+    /// Combine several independent errors into one error.
+    pub(super) fn many(mut errors: Vec<Self>) -> Self {
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            Self::Many(errors)
+        }
+    }
+
+    /// Get the independent errors which make up this error, in the order they were found.
+    pub fn into_errors(self) -> Vec<Self> {
+        match self {
+            Self::Many(errors) => errors.into_iter().flat_map(Self::into_errors).collect(),
+            err => vec![err],
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Syntax(e) => write!(f, "{e}"),
+            Self::ModuleNotFound(name, _) => write!(f, "module {name} not found"),
+            Self::UnresolvedImport(path, _) => write!(f, "unresolved import {path}"),
+            Self::CyclicImport(files, _) => {
+                write!(f, "cyclic module import {}", files.join(" -> "))
+            }
+            Self::ConflictingImport(name, _) => {
+                write!(f, "imported name {name} conflicts with another declaration")
+            }
+            Self::StatementInModule(_) => {
+                write!(f, "modules may only contain declarations")
+            }
+            Self::UnknownLint(name, _) => write!(f, "unknown lint {name}"),
+            Self::Many(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{err}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<Box<pest::error::Error<super::parse::Rule>>> for Error {
+    fn from(e: Box<pest::error::Error<super::parse::Rule>>) -> Self {
+        Self::Syntax(e.to_string())
+    }
+}
@@ -3,12 +3,17 @@
 //! This module implements the frontend of the compiler, which is responsible for parsing
 //! the source code and converting it into the LIR.
 
+mod error;
+mod module;
 mod parse;
+pub mod stdlib;
+pub use error::Error;
+
 use crate::side_effects::Output;
 use no_comment::{languages, IntoWithoutComments};
 use parse::*;
 
-pub fn parse(code: impl ToString, filename: Option<&str>) -> Result<crate::lir::Expr, String> {
+pub fn parse(code: impl ToString, filename: Option<&str>) -> Result<crate::lir::Expr, Error> {
     let code = code
         .to_string()
         .chars()
@@ -350,6 +355,6 @@ pub fn parse(code: impl ToString, filename: Option<&str>) -> Result<crate::lir::
                 result,
             ))
         }
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(e),
     }
 }
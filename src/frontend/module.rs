@@ -0,0 +1,490 @@
+//! # Module System
+//!
+//! This module implements the `mod` and `use` declarations of the frontend.
+//!
+//! A `mod foo;` declaration includes the file `foo.sg` (or `foo/mod.sg`) found
+//! in the same directory as the file that declares it. Every top level declaration
+//! of the module is namespaced under the module's path, so a procedure `area`
+//! declared in `foo.sg` is referred to as `foo::area` by the including file.
+//! A `use foo::{Shape, area};` declaration lets the including file refer to
+//! these declarations by their short names instead.
+//!
+//! Namespacing is implemented by consistently renaming symbols while a file's
+//! syntax tree is converted to LIR. Every symbol declared at the top level of a
+//! module, and every imported symbol, is replaced with its qualified name wherever
+//! it is used as a variable, constant, or type. Struct fields, enum variants, and
+//! associated constants are never renamed.
+//!
+//! The bundled standard library is the module `std`. It is included by the first
+//! file which refers to it (or declares `mod std;`), unless there is a `std.sg`
+//! file to include instead. Once it has been included, every file can refer to
+//! its declarations by their `std::` paths.
+use super::{
+    parse::{parse_decl, Declaration, FrontendParser, Rule},
+    stdlib, Error,
+};
+use crate::{
+    lir::{Annotation, Lint},
+    parse::SourceCodeLocation,
+};
+use no_comment::{languages, IntoWithoutComments};
+use pest::{error::InputLocation, iterators::Pair, Parser};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+thread_local! {
+    /// The names visible to the file currently being converted to LIR.
+    static SCOPE: RefCell<Scope> = RefCell::new(Scope::default());
+}
+
+/// The names visible to a single source file.
+#[derive(Clone, Debug, Default)]
+struct Scope {
+    /// The short names of declarations mapped to their qualified names.
+    names: BTreeMap<String, String>,
+    /// The names of the modules declared by the file, mapped to their qualified paths.
+    children: BTreeMap<String, String>,
+}
+
+/// Get the qualified name of a symbol used in the current file.
+pub(super) fn qualify(name: &str) -> String {
+    SCOPE.with(|scope| {
+        let scope = scope.borrow();
+        if let Some(qualified) = scope.names.get(name) {
+            return qualified.clone();
+        }
+        match name.split_once("::") {
+            Some((module, rest)) if scope.children.contains_key(module) => {
+                format!("{}::{rest}", scope.children[module])
+            }
+            _ => name.to_string(),
+        }
+    })
+}
+
+/// Get the short name of a constant declared in an `impl` block.
+/// Associated constants are accessed as members, so they are never qualified.
+pub(super) fn associated_name(name: String) -> String {
+    SCOPE.with(|scope| {
+        scope
+            .borrow()
+            .names
+            .iter()
+            .find(|(_, qualified)| **qualified == name)
+            .map(|(short, _)| short.clone())
+            .unwrap_or(name)
+    })
+}
+
+/// Parse a program, and all of the modules it includes, into a list of declarations.
+pub(super) fn parse_program(code: &str, filename: Option<&str>) -> Result<Vec<Declaration>, Error> {
+    let mut loader = ModuleLoader::default();
+    if let Some(path) = filename.and_then(|name| Path::new(name).canonicalize().ok()) {
+        loader.stack.push((path, filename.unwrap().to_string()));
+    }
+    let dir = filename
+        .and_then(|name| Path::new(name).parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    Ok(loader.load(code, filename, &dir, "")?.declarations)
+}
+
+/// A module loaded from a source file.
+struct Module {
+    /// The qualified path of the module.
+    path: String,
+    /// The qualified names of every declaration in the module and its submodules.
+    exports: BTreeSet<String>,
+    /// The declarations of the module and its submodules, with qualified names.
+    declarations: Vec<Declaration>,
+}
+
+/// Loads the modules included by a program.
+#[derive(Default)]
+struct ModuleLoader {
+    /// The canonical paths (and the displayed names) of the files currently
+    /// being loaded. This is used to detect cyclic includes.
+    stack: Vec<(PathBuf, String)>,
+    /// The qualified names exported by the standard library modules loaded so far.
+    /// The standard library is only included once, and every file can refer to
+    /// it by its `std::` path after it has been included.
+    std_exports: Option<BTreeSet<String>>,
+}
+
+impl ModuleLoader {
+    /// Parse the source code of a module with the given qualified path.
+    fn load(
+        &mut self,
+        code: &str,
+        filename: Option<&str>,
+        dir: &Path,
+        prefix: &str,
+    ) -> Result<Module, Error> {
+        let program = parse_file(code, filename)?;
+
+        let mut declarations = vec![];
+        let mut exports = BTreeSet::new();
+        let mut children = BTreeMap::new();
+        let mut names = BTreeMap::new();
+        let mut uses = vec![];
+        let mut items = vec![];
+
+        if let Some(std_exports) = &self.std_exports {
+            children.insert(STD.to_string(), (STD.to_string(), std_exports.clone()));
+        }
+
+        for pair in program.into_inner() {
+            match pair.as_rule() {
+                Rule::decl_mod => {
+                    let name = pair.into_inner().nth(1).unwrap();
+                    let loc = location(&name, filename);
+                    let name = name.as_str().to_string();
+                    let module = self.load_file(&name, dir, &qualified(prefix, &name), loc)?;
+                    exports.extend(module.exports.iter().cloned());
+                    declarations.extend(module.declarations);
+                    children.insert(name, (module.path, module.exports));
+                }
+                Rule::decl_use => uses.push(pair),
+                Rule::EOI => {}
+                _ => {
+                    let declared = declared_names(&pair);
+                    if declared.is_none() && !prefix.is_empty() {
+                        return Err(Error::StatementInModule(location(&pair, filename)));
+                    }
+                    for name in declared.unwrap_or_default() {
+                        exports.insert(qualified(prefix, &name));
+                        names.insert(name.clone(), qualified(prefix, &name));
+                    }
+                    items.push(pair);
+                }
+            }
+        }
+
+        // The standard library is included by the first file which refers to it.
+        let refers_to_std = |pair: &Pair<Rule>| {
+            pair.as_rule() == Rule::path
+                && (pair.as_str() == STD || pair.as_str().starts_with("std::"))
+        };
+        let std_path = uses
+            .iter()
+            .map(|pair| pair.clone().into_inner().nth(1).unwrap())
+            .find(refers_to_std)
+            .or_else(|| {
+                items
+                    .iter()
+                    .flat_map(|pair| pair.clone().into_inner().flatten())
+                    .filter(|pair| pair.as_str() != STD)
+                    .find(refers_to_std)
+            });
+        if let (Some(pair), false) = (std_path, children.contains_key(STD)) {
+            let module = self.load_std(location(&pair, filename))?;
+            declarations.extend(module.declarations);
+            children.insert(STD.to_string(), (module.path, module.exports));
+        }
+
+        for pair in uses {
+            let mut inner_rules = pair.into_inner().skip(1);
+            let path_pair = inner_rules.next().unwrap();
+            let path = path_pair.as_str().to_string();
+            let mut imported = inner_rules
+                .map(|pair| {
+                    let loc = location(&pair, filename);
+                    (path.clone(), pair.as_str().to_string(), loc)
+                })
+                .collect::<Vec<_>>();
+            if imported.is_empty() {
+                let loc = location(&path_pair, filename);
+                match path.rsplit_once("::") {
+                    Some((module, name)) => {
+                        imported.push((module.to_string(), name.to_string(), loc))
+                    }
+                    None => return Err(Error::UnresolvedImport(path, loc)),
+                }
+            }
+
+            for (module, name, loc) in imported {
+                let path = format!("{module}::{name}");
+                let resolved = resolve(&children, &path)
+                    .ok_or_else(|| Error::UnresolvedImport(path, loc.clone()))?;
+                match names.get(&name) {
+                    Some(other) if *other != resolved => {
+                        return Err(Error::ConflictingImport(name, loc))
+                    }
+                    _ => names.insert(name, resolved),
+                };
+            }
+        }
+
+        // Confirm that every qualified symbol refers to a declaration in a submodule,
+        // and that every attribute names a lint.
+        let mut errors = vec![];
+        for item in &items {
+            for pair in item.clone().into_inner().flatten() {
+                if matches!(pair.as_rule(), Rule::path | Rule::const_symbol)
+                    && pair.as_str().contains("::")
+                    && resolve(&children, pair.as_str()).is_none()
+                {
+                    errors.push(Error::UnresolvedImport(
+                        pair.as_str().to_string(),
+                        location(&pair, filename),
+                    ));
+                }
+                if pair.as_rule() == Rule::lint_name && Lint::named(pair.as_str()).is_none() {
+                    errors.push(Error::UnknownLint(
+                        pair.as_str().to_string(),
+                        location(&pair, filename),
+                    ));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Error::many(errors));
+        }
+
+        let scope = Scope {
+            names,
+            children: children
+                .into_iter()
+                .map(|(name, (path, _))| (name, path))
+                .collect(),
+        };
+        let outer_scope = SCOPE.with(|current| current.replace(scope));
+        declarations.extend(items.into_iter().map(|pair| parse_decl(pair, filename)));
+        SCOPE.with(|current| current.replace(outer_scope));
+
+        Ok(Module {
+            path: prefix.to_string(),
+            exports,
+            declarations,
+        })
+    }
+
+    /// Find, read, and parse the file for the module declared with `mod name;`.
+    fn load_file(
+        &mut self,
+        name: &str,
+        dir: &Path,
+        prefix: &str,
+        loc: SourceCodeLocation,
+    ) -> Result<Module, Error> {
+        let path = [
+            dir.join(format!("{name}.sg")),
+            dir.join(name).join("mod.sg"),
+        ]
+        .into_iter()
+        .find(|path| stdlib::source(path).is_some() || path.is_file());
+        match path {
+            Some(path) => self.load_path(&path, name, prefix, loc),
+            // A local module named `std` takes precedence over the bundled library.
+            None if name == STD => self.load_std(loc),
+            None => Err(Error::ModuleNotFound(name.to_string(), loc)),
+        }
+    }
+
+    /// Include the bundled standard library, if it has not been included already.
+    fn load_std(&mut self, loc: SourceCodeLocation) -> Result<Module, Error> {
+        if let Some(exports) = &self.std_exports {
+            return Ok(Module {
+                path: STD.to_string(),
+                exports: exports.clone(),
+                declarations: vec![],
+            });
+        }
+        self.std_exports = Some(BTreeSet::new());
+        let mut module = self.load_path(&Path::new(stdlib::ROOT).join("mod.sg"), STD, STD, loc)?;
+        // Programs can't change the standard library, so it never warns about its own code.
+        let allowed = Annotation::allow(Lint::ALL);
+        module.declarations = module
+            .declarations
+            .into_iter()
+            .map(|decl| decl.allow(allowed.clone()))
+            .collect();
+        Ok(module)
+    }
+
+    /// Read and parse the source file of a module.
+    fn load_path(
+        &mut self,
+        path: &Path,
+        name: &str,
+        prefix: &str,
+        loc: SourceCodeLocation,
+    ) -> Result<Module, Error> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let filename = path.display().to_string();
+        if let Some(start) = self.stack.iter().position(|(file, _)| *file == canonical) {
+            let mut cycle = self.stack[start..]
+                .iter()
+                .map(|(_, name)| name.clone())
+                .collect::<Vec<_>>();
+            cycle.push(filename);
+            return Err(Error::CyclicImport(cycle, loc));
+        }
+
+        let bundled = stdlib::source(path);
+        let code = match bundled {
+            Some(code) => code.to_string(),
+            None => std::fs::read_to_string(path)
+                .map_err(|_| Error::ModuleNotFound(name.to_string(), loc))?,
+        }
+        .chars()
+        .without_comments(languages::rust())
+        .collect::<String>();
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        self.stack.push((canonical, filename.clone()));
+        let module = self.load(&code, Some(&filename), &dir, prefix);
+        self.stack.pop();
+        let module = module?;
+
+        if let (Some(_), Some(exports)) = (bundled, &mut self.std_exports) {
+            exports.extend(module.exports.iter().cloned());
+        }
+        Ok(module)
+    }
+}
+
+/// The name of the bundled standard library module.
+const STD: &str = "std";
+
+/// Join a module's qualified path with the name of one of its declarations.
+fn qualified(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}::{name}")
+    }
+}
+
+/// Resolve a path (relative to a file) to the qualified name of a declaration
+/// exported by one of the file's submodules.
+fn resolve(children: &BTreeMap<String, (String, BTreeSet<String>)>, path: &str) -> Option<String> {
+    let (module, rest) = path.split_once("::")?;
+    let (module_path, exports) = children.get(module)?;
+    let path = format!("{module_path}::{rest}");
+    exports.contains(&path).then_some(path)
+}
+
+/// Get the names of the symbols declared by a top level declaration.
+/// This returns `None` if the declaration is a statement.
+fn declared_names(pair: &Pair<Rule>) -> Option<Vec<String>> {
+    let symbols = |pair: Pair<Rule>| -> Vec<String> {
+        pair.into_inner()
+            .filter_map(|pair| match pair.as_rule() {
+                Rule::symbol => Some(pair.as_str().to_string()),
+                Rule::mut_symbol => Some(pair.into_inner().next()?.as_str().to_string()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    match pair.as_rule() {
+        Rule::decl | Rule::decl_proc | Rule::stmt | Rule::short_stmt => declared_names(
+            &pair
+                .clone()
+                .into_inner()
+                .find(|pair| pair.as_rule() != Rule::attribute)?,
+        ),
+        Rule::decl_proc_block
+        | Rule::decl_proc_expr
+        | Rule::decl_struct
+        | Rule::decl_enum
+        | Rule::decl_trait => Some(symbols(pair.clone()).into_iter().take(1).collect()),
+        Rule::decl_type | Rule::decl_unit | Rule::decl_const | Rule::stmt_let_static => {
+            Some(symbols(pair.clone()))
+        }
+        Rule::decl_impl | Rule::decl_impl_trait | Rule::decl_extern => Some(vec![]),
+        _ => None,
+    }
+}
+
+/// The most syntax errors to report for a single file.
+const MAX_SYNTAX_ERRORS: usize = 100;
+
+/// Parse the source code of a file into its syntax tree.
+///
+/// After a syntax error, the top level declaration containing the error is
+/// blanked out, and the file is parsed again to find the syntax errors in the
+/// rest of the file. Top level declarations are found by their indentation:
+/// they start at a line which doesn't begin with whitespace or a closing bracket.
+fn parse_file<'a>(code: &'a str, filename: Option<&str>) -> Result<Pair<'a, Rule>, Error> {
+    let syntax_error = |e: pest::error::Error<Rule>| match filename {
+        Some(filename) => Error::Syntax(e.with_path(filename).to_string()),
+        None => Error::Syntax(e.to_string()),
+    };
+    let mut err = match FrontendParser::parse(Rule::program, code) {
+        Ok(mut program) => return Ok(program.next().unwrap()),
+        Err(err) => err,
+    };
+
+    let mut errors = vec![];
+    let mut masked = code.to_string();
+    let mut positions = BTreeSet::new();
+    loop {
+        let pos = match err.location {
+            InputLocation::Pos(pos) | InputLocation::Span((pos, _)) => pos,
+        };
+        errors.push(syntax_error(err));
+        // Stop if the error couldn't be skipped over, since it would be found again.
+        if !positions.insert(pos) || errors.len() >= MAX_SYNTAX_ERRORS {
+            break;
+        }
+        let (start, end) = top_level_span(&masked, pos);
+        // Blank out every character with the same number of bytes, so that the
+        // positions of the later syntax errors are the same as in the original code.
+        let blank = masked[start..end]
+            .chars()
+            .map(|c| match c {
+                '\n' => "\n".to_string(),
+                c => " ".repeat(c.len_utf8()),
+            })
+            .collect::<String>();
+        masked.replace_range(start..end, &blank);
+        match FrontendParser::parse(Rule::program, &masked) {
+            Ok(_) => break,
+            Err(next) => err = next,
+        }
+    }
+    Err(Error::many(errors))
+}
+
+/// Find the span of the top level declaration containing a position in the source code.
+/// This is from the start of the line which begins the declaration to the start of
+/// the line which begins the next one (or the end of the file).
+fn top_level_span(code: &str, pos: usize) -> (usize, usize) {
+    let is_top_level = |line: &str| {
+        line.starts_with(|c: char| !c.is_whitespace() && !matches!(c, '}' | ')' | ']'))
+    };
+    let mut lines = vec![];
+    let mut offset = 0;
+    for line in code.split_inclusive('\n') {
+        lines.push((offset, line));
+        offset += line.len();
+    }
+    let start = lines
+        .iter()
+        .rev()
+        .find(|(offset, line)| *offset <= pos && is_top_level(line))
+        .map_or(0, |(offset, _)| *offset);
+    let end = lines
+        .iter()
+        .find(|(offset, line)| *offset > pos && is_top_level(line))
+        .map_or(code.len(), |(offset, _)| *offset);
+    (start, end)
+}
+
+/// Get the location of a syntax tree node in the source code.
+fn location(pair: &Pair<Rule>, filename: Option<&str>) -> SourceCodeLocation {
+    let span = pair.as_span();
+    let (line, column) = span.start_pos().line_col();
+    SourceCodeLocation {
+        filename: filename.map(|x| x.to_string()),
+        line,
+        column,
+        length: Some(span.end_pos().pos() - span.start_pos().pos()),
+        offset: span.start_pos().pos(),
+    }
+}
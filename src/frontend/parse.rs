@@ -1,11 +1,13 @@
+use super::module::{associated_name, qualify};
 use crate::{lir::*, parse::SourceCodeLocation};
-use pest::{error::Error, iterators::Pair, Parser};
+use pest::iterators::{Pair, Pairs};
 use pest_derive::Parser;
 use rayon::prelude::*;
+use std::collections::BTreeMap;
 
 #[derive(Parser)]
 #[grammar = "frontend/parse.pest"] // relative to src
-struct FrontendParser;
+pub(super) struct FrontendParser;
 
 #[derive(Clone, Debug)]
 pub enum Statement {
@@ -43,11 +45,49 @@ impl Statement {
         }
     }
 
+    /// Allow some lints in the statement. The variables declared by a `let` statement
+    /// are in scope for the statements after it, so only their initial values are annotated.
+    fn allow(self, annotation: Annotation) -> Self {
+        match self {
+            Self::AnnotatedWithSource { stmt, loc } => Self::AnnotatedWithSource {
+                stmt: Box::new(stmt.allow(annotation)),
+                loc,
+            },
+            Self::Let(defs) => Self::Let(
+                defs.into_iter()
+                    .map(|(name, mutability, ty, val)| {
+                        (name, mutability, ty, val.annotate(annotation.clone()))
+                    })
+                    .collect(),
+            ),
+            Self::LetPattern(defs) => Self::LetPattern(
+                defs.into_iter()
+                    .map(|(pattern, val)| (pattern, val.annotate(annotation.clone())))
+                    .collect(),
+            ),
+            Self::LetStatic(_) => self,
+            stmt => Self::Expr(stmt.to_expr(None).annotate(annotation)),
+        }
+    }
+
     fn to_expr(self, rest: Option<Expr>) -> Expr {
         let rest_expr = Box::new(rest.clone().unwrap_or(Expr::ConstExpr(ConstExpr::None)));
 
         let stmt = match (self, rest.clone()) {
             (Self::AnnotatedWithSource { stmt, loc }, _) => {
+                // The declarations of a `let` statement are merged into the
+                // statements after it, which takes over its location. Annotate
+                // the initial values so the statement keeps its own location.
+                let stmt = match *stmt {
+                    Self::Let(defs) => Self::Let(
+                        defs.into_iter()
+                            .map(|(name, mutability, ty, val)| {
+                                (name, mutability, ty, val.annotate(loc.clone()))
+                            })
+                            .collect(),
+                    ),
+                    stmt => stmt,
+                };
                 return stmt.to_expr(rest).annotate(loc);
             }
             (Self::Assign(lhs, op, rhs), _) => {
@@ -129,7 +169,19 @@ impl Statement {
             //     }
             //     return Expr::LetVars(vars, Box::new(Expr::Many(vec![body.to_expr(Some(*ret))])))
             // }
-            (Self::Let(defs), _) => return rest_expr.with(defs),
+            (Self::Let(defs), _) => {
+                return match *rest_expr {
+                    // Keep the location of the following statements inside the
+                    // declaration, so it refers to the code after the variables are defined.
+                    Expr::Annotated(inner, annotation) => match inner.with(defs) {
+                        Expr::Declare(decls, body) => {
+                            Expr::Declare(decls, Box::new(body.annotate(annotation)))
+                        }
+                        other => other.annotate(annotation),
+                    },
+                    rest => rest.with(defs),
+                };
+            }
             (Self::LetPattern(defs), _) => return rest_expr.with(defs),
             (Self::LetStatic(defs), _) => {
                 return rest_expr.with(
@@ -178,16 +230,60 @@ pub enum Declaration {
     ),
     PolyProc(
         String,
-        Vec<String>,
+        Vec<(String, Vec<String>)>,
         Vec<(String, Mutability, Type)>,
         Option<Type>,
         Box<Statement>,
     ),
+    Trait(String, BTreeMap<String, Type>),
+    ImplTrait(String, Type, Vec<(String, ConstExpr)>),
     Type(Vec<(String, Type)>),
     Statement(Statement),
 }
 
 impl Declaration {
+    /// Allow some lints in the code of the declaration.
+    pub(super) fn allow(self, annotation: Annotation) -> Self {
+        if annotation.is_none() {
+            return self;
+        }
+        let allow_methods = |methods: Vec<(String, ConstExpr)>| {
+            methods
+                .into_iter()
+                .map(|(name, method)| match method {
+                    ConstExpr::Proc(proc) => (
+                        name,
+                        ConstExpr::Proc(proc.annotate_body(annotation.clone())),
+                    ),
+                    ConstExpr::PolyProc(proc) => (
+                        name,
+                        ConstExpr::PolyProc(proc.annotate_body(annotation.clone())),
+                    ),
+                    method => (name, method),
+                })
+                .collect()
+        };
+        match self {
+            Self::Proc(name, params, ret, body) => {
+                Self::Proc(name, params, ret, Box::new(body.allow(annotation)))
+            }
+            Self::PolyProc(name, ty_params, params, ret, body) => Self::PolyProc(
+                name,
+                ty_params,
+                params,
+                ret,
+                Box::new(body.allow(annotation)),
+            ),
+            Self::Impl(ty, methods) => Self::Impl(ty, allow_methods(methods)),
+            Self::ImplTrait(trait_name, ty, methods) => {
+                Self::ImplTrait(trait_name, ty, allow_methods(methods))
+            }
+            Self::Statement(stmt) => Self::Statement(stmt.allow(annotation)),
+            // Types, constants, and external procedures have no code to warn about.
+            decl => decl,
+        }
+    }
+
     fn proc_to_expr(
         name: String,
         args: Vec<(String, Mutability, Type)>,
@@ -202,6 +298,31 @@ impl Declaration {
         )
     }
 
+    fn poly_proc_to_expr(
+        name: String,
+        ty_params: Vec<(String, Vec<String>)>,
+        args: Vec<(String, Mutability, Type)>,
+        ret: Option<Type>,
+        body: Statement,
+    ) -> PolyProcedure {
+        let bounds = ty_params
+            .iter()
+            .filter(|(_, bounds)| !bounds.is_empty())
+            .cloned()
+            .collect();
+        PolyProcedure::new(
+            name,
+            ty_params
+                .into_iter()
+                .map(|(ty_param, _)| ty_param)
+                .collect(),
+            args,
+            ret.unwrap_or(Type::None),
+            body.to_expr(None),
+        )
+        .with_bounds(bounds)
+    }
+
     fn to_expr(self, rest: Option<Expr>) -> Expr {
         let rest_expr = Box::new(rest.clone().unwrap_or(Expr::ConstExpr(ConstExpr::None)));
         match (self, rest) {
@@ -217,6 +338,13 @@ impl Declaration {
             (Self::Impl(ty, methods), _) => {
                 rest_expr.with(crate::lir::Declaration::Impl(ty, methods))
             }
+            (Self::Trait(name, methods), _) => rest_expr.with(crate::lir::Declaration::Trait(
+                name.clone(),
+                Trait::new(name, methods),
+            )),
+            (Self::ImplTrait(trait_name, ty, methods), _) => {
+                rest_expr.with(crate::lir::Declaration::ImplTrait(trait_name, ty, methods))
+            }
             (Self::Struct(name, fields), _) => {
                 rest_expr.with((name, Type::Struct(fields.into_iter().collect())))
             }
@@ -264,13 +392,7 @@ impl Declaration {
             }
             (Self::PolyProc(name, ty_params, params, ret, stmt), _) => rest_expr.with((
                 name.clone(),
-                ConstExpr::PolyProc(PolyProcedure::new(
-                    name,
-                    ty_params,
-                    params,
-                    ret.unwrap_or(Type::None),
-                    stmt.to_expr(None),
-                )),
+                ConstExpr::PolyProc(Self::poly_proc_to_expr(name, ty_params, params, ret, *stmt)),
             )),
             (Self::Type(types), _) => rest_expr.with(types),
             (Self::Statement(stmt), Some(rest)) => stmt.to_expr(Some(rest)),
@@ -297,29 +419,99 @@ impl Program {
     }
 }
 
-pub fn parse_frontend(code: &str, filename: Option<&str>) -> Result<Expr, Box<Error<Rule>>> {
-    let x = FrontendParser::parse(Rule::program, code)?;
-    Ok(parse_program(x.into_iter().next().unwrap(), filename).to_expr())
+pub fn parse_frontend(code: &str, filename: Option<&str>) -> Result<Expr, super::Error> {
+    Ok(Program(super::module::parse_program(code, filename)?).to_expr())
 }
 
 fn parse_symbol(pair: Pair<Rule>) -> (Mutability, String) {
     if pair.as_rule() == Rule::mut_symbol {
         (
             Mutability::Mutable,
-            pair.into_inner().next().unwrap().as_str().to_string(),
+            qualify(pair.into_inner().next().unwrap().as_str()),
         )
     } else {
-        (Mutability::Immutable, pair.as_str().to_string())
+        (Mutability::Immutable, qualify(pair.as_str()))
+    }
+}
+
+/// Parse the declarations in the body of an `impl` block into associated constants.
+fn parse_impl_body(
+    mut inner_rules: Pairs<Rule>,
+    filename: Option<&str>,
+) -> Vec<(String, ConstExpr)> {
+    let mut constants = vec![];
+    while inner_rules.peek().is_some() {
+        let decl = parse_decl(inner_rules.next().unwrap(), filename);
+        match decl {
+            Declaration::Const(mut decls) => constants.append(&mut decls),
+            Declaration::Proc(name, args, ret, body) => constants.push((
+                name.clone(),
+                ConstExpr::Proc(Procedure::new(
+                    Some(name),
+                    args,
+                    ret.unwrap_or(Type::None),
+                    body.to_expr(None),
+                )),
+            )),
+            Declaration::PolyProc(name, ty_params, args, ret, body) => constants.push((
+                name.clone(),
+                ConstExpr::PolyProc(Declaration::poly_proc_to_expr(
+                    name, ty_params, args, ret, *body,
+                )),
+            )),
+            Declaration::Type(types) => {
+                for (name, ty) in types {
+                    constants.push((name, ConstExpr::Type(ty)))
+                }
+            }
+            Declaration::Enum(name, variants) => constants.push((
+                name.clone(),
+                ConstExpr::Type(Type::EnumUnion(
+                    variants
+                        .into_iter()
+                        .map(|(a, x)| (a, x.unwrap_or(Type::None)))
+                        .collect(),
+                )),
+            )),
+            Declaration::Struct(name, fields) => constants.push((
+                name.clone(),
+                ConstExpr::Type(Type::Struct(fields.into_iter().collect())),
+            )),
+            _ => {
+                panic!("Unexpected declaration in impl: {:?}", decl)
+            }
+        }
     }
+    constants
+        .into_iter()
+        .map(|(name, constant)| (associated_name(name), constant))
+        .collect()
 }
 
-fn parse_program(pair: Pair<Rule>, filename: Option<&str>) -> Program {
-    Program(pair.into_inner().map(|x| parse_decl(x, filename)).collect())
+/// Parse an `@allow(...)` attribute into an annotation which allows its lints.
+/// The names of the lints are checked when the module is loaded.
+fn parse_attribute(pair: Pair<Rule>) -> Annotation {
+    Annotation::allow(
+        pair.into_inner()
+            .flat_map(|name| Lint::named(name.as_str()).unwrap_or_default()),
+    )
 }
 
-fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
+pub(super) fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
     match pair.as_rule() {
-        Rule::decl | Rule::decl_proc => pair
+        Rule::decl | Rule::decl_imp_child_decl => {
+            // The attributes of a declaration come before it.
+            let mut allowed = Annotation::None;
+            for pair in pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::attribute => allowed |= parse_attribute(pair),
+                    _ => return parse_decl(pair, filename).allow(allowed),
+                }
+            }
+            unreachable!("a declaration always follows its attributes")
+        }
+
+        Rule::decl_proc => pair
             .into_inner()
             .map(|x| parse_decl(x, filename))
             .next()
@@ -328,70 +520,58 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
         Rule::decl_impl => {
             let mut inner_rules = pair.into_inner();
             let ty = parse_type(inner_rules.next().unwrap());
-            let mut constants = vec![];
-            while inner_rules.peek().is_some() {
-                let decl = parse_decl(inner_rules.next().unwrap(), filename);
-                match decl {
-                    Declaration::Const(mut decls) => constants.append(&mut decls),
-                    Declaration::Proc(name, args, ret, body) => constants.push((
-                        name.clone(),
-                        ConstExpr::Proc(Procedure::new(
-                            Some(name),
-                            args,
-                            ret.unwrap_or(Type::None),
-                            body.to_expr(None),
-                        )),
-                    )),
-                    Declaration::PolyProc(name, ty_params, args, ret, body) => constants.push((
-                        name.clone(),
-                        ConstExpr::PolyProc(PolyProcedure::new(
-                            name,
-                            ty_params,
-                            args,
-                            ret.unwrap_or(Type::None),
-                            body.to_expr(None),
-                        )),
-                    )),
-                    Declaration::Type(types) => {
-                        for (name, ty) in types {
-                            constants.push((name, ConstExpr::Type(ty)))
+            Declaration::Impl(ty, parse_impl_body(inner_rules, filename))
+        }
+
+        Rule::decl_impl_trait => {
+            let mut inner_rules = pair.into_inner();
+            let trait_name = qualify(inner_rules.next().unwrap().as_str());
+            let ty = parse_type(inner_rules.next().unwrap());
+            Declaration::ImplTrait(trait_name, ty, parse_impl_body(inner_rules, filename))
+        }
+
+        Rule::decl_trait => {
+            let mut inner_rules = pair.into_inner();
+            let name = qualify(inner_rules.next().unwrap().as_str());
+            let mut methods = BTreeMap::new();
+            for method_pair in inner_rules {
+                let mut inner_rules = method_pair.into_inner();
+                let method_name = inner_rules.next().unwrap().as_str().to_string();
+                let mut args = vec![];
+                let mut ret = Type::None;
+                for pair in inner_rules {
+                    match pair.as_rule() {
+                        Rule::decl_proc_param => {
+                            let mut inner_rules = pair.into_inner();
+                            inner_rules.next();
+                            args.push(parse_type(inner_rules.next().unwrap()));
                         }
-                    }
-                    Declaration::Enum(name, variants) => constants.push((
-                        name.clone(),
-                        ConstExpr::Type(Type::EnumUnion(
-                            variants
-                                .into_iter()
-                                .map(|(a, x)| (a, x.unwrap_or(Type::None)))
-                                .collect(),
-                        )),
-                    )),
-                    Declaration::Struct(name, fields) => constants.push((
-                        name.clone(),
-                        ConstExpr::Type(Type::Struct(fields.into_iter().collect())),
-                    )),
-                    _ => {
-                        panic!("Unexpected declaration in impl: {:?}", decl)
+                        Rule::r#type => {
+                            ret = parse_type(pair);
+                        }
+                        other => panic!("unexpected rule {:?}", other),
                     }
                 }
+                methods.insert(method_name, Type::Proc(args, Box::new(ret)));
             }
-            Declaration::Impl(ty, constants)
+            Declaration::Trait(name, methods)
         }
 
-        Rule::decl_imp_child_decl => parse_decl(pair.into_inner().next().unwrap(), filename),
-
         Rule::decl_proc_block | Rule::decl_proc_expr => {
             let mut inner_rules = pair.into_inner();
-            let name = inner_rules.next().unwrap().as_str().to_string();
+            let name = qualify(inner_rules.next().unwrap().as_str());
 
             let mut ty_params = vec![];
             if let Some(ty_params_pair) = inner_rules.peek() {
-                if ty_params_pair.as_rule() == Rule::type_parameters
+                if ty_params_pair.as_rule() == Rule::bounded_type_parameters
                     && ty_params_pair.into_inner().count() > 0
                 {
                     let ty_params_pair = inner_rules.next().unwrap();
                     for ty_param_pair in ty_params_pair.into_inner() {
-                        ty_params.push(ty_param_pair.as_str().to_string());
+                        let mut inner_rules = ty_param_pair.into_inner();
+                        let ty_param = qualify(inner_rules.next().unwrap().as_str());
+                        let bounds = inner_rules.map(|bound| qualify(bound.as_str())).collect();
+                        ty_params.push((ty_param, bounds));
                     }
                 }
             }
@@ -429,7 +609,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
             let mut inner_rules = pair.into_inner();
             let mut types = Vec::new();
             while inner_rules.peek().is_some() {
-                let name = inner_rules.next().unwrap().as_str().to_string();
+                let name = qualify(inner_rules.next().unwrap().as_str());
 
                 let mut ty_params = vec![];
                 if let Some(ty_params_pair) = inner_rules.peek() {
@@ -438,7 +618,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
                     {
                         let ty_params_pair = inner_rules.next().unwrap();
                         for ty_param_pair in ty_params_pair.into_inner() {
-                            ty_params.push(ty_param_pair.as_str().to_string());
+                            ty_params.push(qualify(ty_param_pair.as_str()));
                         }
                     }
                 }
@@ -457,7 +637,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
             let mut inner_rules = pair.into_inner();
             let mut types = Vec::new();
             while inner_rules.peek().is_some() {
-                let name = inner_rules.next().unwrap().as_str().to_string();
+                let name = qualify(inner_rules.next().unwrap().as_str());
                 let ty = parse_type(inner_rules.next().unwrap());
                 types.push((name.clone(), Type::Unit(name, Box::new(ty))));
             }
@@ -466,7 +646,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
         }
         Rule::decl_struct => {
             let mut inner_rules = pair.into_inner();
-            let name = inner_rules.next().unwrap().as_str().to_string();
+            let name = qualify(inner_rules.next().unwrap().as_str());
 
             let mut ty_params = vec![];
             if let Some(ty_params_pair) = inner_rules.peek() {
@@ -475,7 +655,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
                 {
                     let ty_params_pair = inner_rules.next().unwrap();
                     for ty_param_pair in ty_params_pair.into_inner() {
-                        ty_params.push(ty_param_pair.as_str().to_string());
+                        ty_params.push(qualify(ty_param_pair.as_str()));
                     }
                 }
             }
@@ -501,7 +681,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
         }
         Rule::decl_enum => {
             let mut inner_rules = pair.into_inner();
-            let name = inner_rules.next().unwrap().as_str().to_string();
+            let name = qualify(inner_rules.next().unwrap().as_str());
 
             let mut ty_params = vec![];
             if let Some(ty_params_pair) = inner_rules.peek() {
@@ -510,7 +690,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
                 {
                     let ty_params_pair = inner_rules.next().unwrap();
                     for ty_param_pair in ty_params_pair.into_inner() {
-                        ty_params.push(ty_param_pair.as_str().to_string());
+                        ty_params.push(qualify(ty_param_pair.as_str()));
                     }
                 }
             }
@@ -592,7 +772,7 @@ fn parse_decl(pair: Pair<Rule>, filename: Option<&str>) -> Declaration {
             let mut inner_rules = pair.into_inner();
             let mut defs = Vec::new();
             while inner_rules.peek().is_some() {
-                let name = inner_rules.next().unwrap().as_str().to_string();
+                let name = qualify(inner_rules.next().unwrap().as_str());
                 let expr = parse_const(inner_rules.next().unwrap());
                 defs.push((name, expr));
             }
@@ -831,6 +1011,9 @@ fn parse_stmt(pair: Pair<Rule>, filename: Option<&str>) -> Statement {
                     "&=" => Some(Box::new(Assign::new(BitwiseAnd))),
                     "^=" => Some(Box::new(Assign::new(BitwiseXor))),
                     "|=" => Some(Box::new(Assign::new(BitwiseOr))),
+                    "<<=" => Some(Box::new(Assign::new(Shift::Left))),
+                    ">>=" => Some(Box::new(Assign::new(Shift::Right))),
+                    ">>>=" => Some(Box::new(Assign::new(Shift::LogicalRight))),
                     _ => unreachable!(),
                 },
                 rhs,
@@ -896,6 +1079,7 @@ pub fn parse_expr(pair: Pair<Rule>) -> Expr {
         Rule::expr_logic_factor
         | Rule::expr_logic_term
         | Rule::expr_comparison
+        | Rule::expr_shift
         | Rule::expr_sum
         | Rule::expr_index
         | Rule::expr_factor
@@ -972,6 +1156,43 @@ pub fn parse_expr(pair: Pair<Rule>) -> Expr {
         Rule::r#const | Rule::const_term | Rule::const_monomorph | Rule::const_atom => {
             Expr::ConstExpr(parse_const(pair))
         }
+        Rule::expr_closure => {
+            let mut captures = vec![];
+            let mut params = vec![];
+            let mut ret = Type::None;
+            let mut body = Expr::ConstExpr(ConstExpr::None);
+            for pair in pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::closure_captures => {
+                        for capture in pair.into_inner() {
+                            let capture = capture.into_inner().next().unwrap();
+                            let kind = match capture.as_rule() {
+                                Rule::closure_capture_mut_ref => {
+                                    Capture::Reference(Mutability::Mutable)
+                                }
+                                Rule::closure_capture_ref => {
+                                    Capture::Reference(Mutability::Immutable)
+                                }
+                                _ => Capture::Value,
+                            };
+                            let name = qualify(capture.into_inner().next().unwrap().as_str());
+                            captures.push((name, kind));
+                        }
+                    }
+                    Rule::decl_proc_param => {
+                        let mut inner_rules = pair.into_inner();
+                        let (mutability, name) = parse_symbol(inner_rules.next().unwrap());
+                        let ty = parse_type(inner_rules.next().unwrap());
+                        params.push((name, mutability, ty));
+                    }
+                    Rule::r#type => ret = parse_type(pair),
+                    Rule::stmt_block => body = parse_stmt(pair, None).to_expr(None),
+                    Rule::expr => body = parse_expr(pair),
+                    other => panic!("unexpected rule {:?}", other),
+                }
+            }
+            Expr::Closure(Closure::new(captures, params, ret, body))
+        }
         Rule::stmt_block => parse_stmt(pair, None).to_expr(None),
         other => panic!("Unexpected rule: {:?}: {:?}", other, pair),
     };
@@ -1069,6 +1290,9 @@ fn parse_binop(pair: Pair<Rule>) -> Expr {
             "^" => head.bitxor(tail),
             "~&" => head.bitnand(tail),
             "~|" => head.bitnor(tail),
+            "<<" => head.shl(tail),
+            ">>" => head.shr(tail),
+            ">>>" => head.logical_shr(tail),
             _ => unreachable!(),
         };
     }
@@ -1161,7 +1385,7 @@ fn parse_const(pair: Pair<Rule>) -> ConstExpr {
                 ConstExpr::Of(ty, symbol)
             }
         }
-        Rule::const_symbol => ConstExpr::Symbol(pair.as_str().to_string()),
+        Rule::const_symbol => ConstExpr::Symbol(qualify(pair.as_str())),
         Rule::const_int => {
             let s = pair.as_str();
             ConstExpr::Int(if s.len() > 2 && &s[..2] == "0b" {
@@ -1283,7 +1507,7 @@ fn parse_type(pair: Pair<Rule>) -> Type {
             for pair in inner_rules {
                 match pair.as_rule() {
                     Rule::symbol => {
-                        params.push(pair.as_str().to_string());
+                        params.push(qualify(pair.as_str()));
                     }
                     Rule::r#type => {
                         let ty = parse_type(pair);
@@ -1300,7 +1524,7 @@ fn parse_type(pair: Pair<Rule>) -> Type {
             // Get all but the last rule
             let mut result = vec![];
             while inner_rules.clone().count() > 2 {
-                let name = inner_rules.next().unwrap().as_str().to_string();
+                let name = qualify(inner_rules.next().unwrap().as_str());
                 let ty = parse_type(inner_rules.next().unwrap());
                 result.push((name, ty));
             }
@@ -1311,8 +1535,21 @@ fn parse_type(pair: Pair<Rule>) -> Type {
             ty
         }
 
-        Rule::type_symbol => Type::Symbol(pair.as_str().to_string()),
+        Rule::type_symbol => Type::Symbol(qualify(pair.as_str())),
+        Rule::type_dyn => Type::TraitObject(qualify(pair.into_inner().next().unwrap().as_str())),
         Rule::type_int => Type::Int,
+        Rule::type_str => Type::Str,
+        Rule::type_fixed_int => Type::FixedInt(match pair.as_str() {
+            "I8" => IntType::I8,
+            "I16" => IntType::I16,
+            "I32" => IntType::I32,
+            "I64" => IntType::I64,
+            "U8" => IntType::U8,
+            "U16" => IntType::U16,
+            "U32" => IntType::U32,
+            "U64" => IntType::U64,
+            _ => unreachable!(),
+        }),
         Rule::type_cell => Type::Cell,
         Rule::type_float => Type::Float,
         Rule::type_bool => Type::Bool,
@@ -1396,6 +1633,10 @@ fn parse_type(pair: Pair<Rule>) -> Type {
             let ty = parse_type(inner_rules.next().unwrap());
             Type::Pointer(Mutability::Mutable, Box::new(ty))
         }
+        Rule::type_closure => match parse_type(pair.into_inner().next().unwrap()) {
+            Type::Proc(args, ret) => Type::Closure(args, ret),
+            _ => unreachable!(),
+        },
         Rule::type_proc => {
             let mut inner_rules = pair.into_inner();
             let mut args_rules = inner_rules.next().unwrap().into_inner();
@@ -1457,7 +1698,7 @@ fn parse_pattern(pair: Pair<Rule>) -> Pattern {
                 if inner_rules.peek().is_none() {
                     fields.push((
                         symbol.clone(),
-                        Pattern::Symbol(Mutability::Immutable, symbol),
+                        Pattern::Symbol(Mutability::Immutable, qualify(&symbol)),
                     ));
                     continue;
                 }
@@ -1479,7 +1720,7 @@ fn parse_pattern(pair: Pair<Rule>) -> Pattern {
             if symbol == "_" {
                 Pattern::Wildcard
             } else {
-                Pattern::Symbol(Mutability::Mutable, symbol)
+                Pattern::Symbol(Mutability::Mutable, qualify(&symbol))
             }
         }
         Rule::pattern_symbol => {
@@ -1487,7 +1728,7 @@ fn parse_pattern(pair: Pair<Rule>) -> Pattern {
             if symbol == "_" {
                 Pattern::Wildcard
             } else {
-                Pattern::Symbol(Mutability::Immutable, symbol)
+                Pattern::Symbol(Mutability::Immutable, qualify(&symbol))
             }
         }
         Rule::pattern_alt => {
@@ -0,0 +1,45 @@
+//! # Standard Library
+//!
+//! The standard library is written in Sage and bundled with the compiler.
+//! A program includes it by referring to one of its declarations, like
+//! `use std::vec::Vec;`, or explicitly with `mod std;`. It is loaded at most
+//! once per program, and its declarations are always namespaced under `std`.
+//!
+//! Every module of the library works on both variants of the virtual machine.
+//! Procedures which need standard instructions (like `alloc` and `free`)
+//! check the `CORE_ONLY` constant, and fall back on a pure core implementation
+//! when the program is being compiled for a target which only supports the core variant.
+use std::path::Path;
+
+/// The version of the bundled standard library.
+/// This is also available to programs as `std::VERSION`.
+pub const VERSION: &str = "0.1.0";
+
+/// The directory that the bundled source files appear to be in.
+/// This is used to name the files in error messages.
+pub const ROOT: &str = "<std>";
+
+/// The source files of the standard library, relative to the library's root.
+const FILES: &[(&str, &str)] = &[
+    ("mod.sg", include_str!("std/mod.sg")),
+    ("mem.sg", include_str!("std/mem.sg")),
+    ("fmt.sg", include_str!("std/fmt.sg")),
+    ("process.sg", include_str!("std/process.sg")),
+    ("option.sg", include_str!("std/option.sg")),
+    ("result.sg", include_str!("std/result.sg")),
+    ("num.sg", include_str!("std/num.sg")),
+    ("sort.sg", include_str!("std/sort.sg")),
+    ("vec.sg", include_str!("std/vec.sg")),
+    ("string.sg", include_str!("std/string.sg")),
+    ("hash.sg", include_str!("std/hash.sg")),
+    ("hashmap.sg", include_str!("std/hashmap.sg")),
+];
+
+/// Get the source code of a bundled file by its path, like `<std>/vec.sg`.
+pub fn source(path: impl AsRef<Path>) -> Option<&'static str> {
+    let path = path.as_ref().strip_prefix(ROOT).ok()?;
+    FILES
+        .iter()
+        .find(|(name, _)| path == Path::new(name))
+        .map(|(_, code)| *code)
+}
@@ -88,9 +88,11 @@
 //! | Arithmetic             | `IsNonNegative?` | `Add`     | `Subtract`      | `Multiply` | `Divide`    | `Remainder`  |
 //! | Worldly                | `GetChar`        | `PutChar` | `GetInt`        | `PutInt`   | `GetFloat`  | `PutFloat`   |
 pub mod asm;
+pub mod debugger;
 pub mod frontend;
 pub mod lir;
 pub mod parse;
+pub mod profiler;
 pub mod side_effects;
 pub mod targets;
 pub mod vm;
@@ -1,3 +1,4 @@
+use super::Lint;
 use crate::parse::SourceCodeLocation;
 use core::ops::{BitOr, BitOrAssign};
 use std::collections::BTreeSet;
@@ -17,6 +18,8 @@ pub enum Annotation {
     CompilerGenerated(bool),
     /// Is this expression a temporary?
     Temporary(bool),
+    /// Don't warn about this lint in the expression.
+    Allow(Lint),
     /// Many annotations can be attached to an expression.
     /// This is a list of them.
     Many(BTreeSet<Annotation>),
@@ -110,6 +113,22 @@ impl Annotation {
         }
     }
 
+    /// Is this lint allowed?
+    pub fn allows(&self, lint: Lint) -> bool {
+        match self {
+            Annotation::Allow(allowed) => *allowed == lint,
+            Annotation::Many(annotations) => annotations.iter().any(|a| a.allows(lint)),
+            _ => false,
+        }
+    }
+
+    /// An annotation which allows all of the given lints.
+    pub fn allow(lints: impl IntoIterator<Item = Lint>) -> Self {
+        lints.into_iter().fold(Annotation::None, |result, lint| {
+            result | Annotation::Allow(lint)
+        })
+    }
+
     /// Remove any existing location from this annotation.
     fn purge_existing_location(&mut self) {
         match self {
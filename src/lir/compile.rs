@@ -9,6 +9,12 @@
 //! 1. First, type check the expression.
 //! 2. Then, attempt to compile the expression into a core assembly program.
 //! 3. If the expression cannot be compiled into a core assembly program, then compile it into a standard assembly program.
+//!
+//! While compiling, the constant `CORE_ONLY` is `True` when compiling for a target which
+//! only supports the core variant (with `Compile::compile_core_only`), and `False` otherwise.
+//! Programs can use it in a `when` expression to provide a pure core fallback for code
+//! which needs standard instructions. Other targets always use the standard instructions,
+//! so a program only compiles to the core variant if it doesn't need them.
 use super::*;
 use crate::asm::{
     AssemblyProgram, CoreOp, CoreProgram, StandardOp, StandardProgram, A, B, C, FP, SP,
@@ -16,10 +22,74 @@ use crate::asm::{
 use crate::NULL;
 use log::*;
 use rayon::prelude::*;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 
 use log::{error, info, trace, warn};
 
+/// The name of the constant which tells a program whether it is being compiled
+/// to the core variant of the assembly language.
+pub const CORE_ONLY: &str = "CORE_ONLY";
+
+/// Create the environment to compile a program in.
+pub(crate) fn root_env(core_only: bool, debug_info: Option<&Arc<RwLock<DebugInfo>>>) -> Env {
+    let mut env = Env::default();
+    env.define_const(CORE_ONLY, ConstExpr::Bool(core_only));
+    if let Some(debug_info) = debug_info {
+        env.record_debug_info(debug_info.clone());
+    }
+    env
+}
+
+/// Type check and compile an expression, recording debug information if requested.
+/// This first attempts to compile to the core variant, and then falls back on the
+/// standard variant. The core fallbacks guarded by `CORE_ONLY` are only used if
+/// the target only supports the core variant.
+fn compile_program<T: Compile + Clone>(
+    expr: T,
+    debug: bool,
+    core_only: bool,
+) -> Result<(Result<CoreProgram, StandardProgram>, DebugInfo), Error> {
+    // eprintln!("Compiling LIR expression {self}");
+    info!("Type checking...");
+    // First, type check the expression.
+    expr.type_check(&root_env(false, None))?;
+    // Then, attempt to compile the expression into a core assembly program.
+    let mut core_asm = CoreProgram::default();
+    // Each attempt at compiling the program records its own debug information.
+    let new_debug_info = || debug.then(|| Arc::new(RwLock::new(DebugInfo::default())));
+    let core_debug_info = new_debug_info();
+
+    info!("Compiling...");
+    // If the expression cannot be compiled into a core assembly program,
+    // then compile it into a standard assembly program.
+    let (program, debug_info) = if let Err(err) = expr
+        .clone()
+        // Compile the expression into the core assembly program.
+        .compile_expr(
+            &mut root_env(core_only, core_debug_info.as_ref()),
+            &mut core_asm,
+        )
+    {
+        warn!("Failed to compile into core assembly program: {err}, falling back on standard assembly");
+        let mut std_asm = StandardProgram::default();
+        let std_debug_info = new_debug_info();
+        // Compile the expression into the standard assembly program.
+        expr.compile_expr(&mut root_env(false, std_debug_info.as_ref()), &mut std_asm)?;
+        info!("Compiled to standard assembly successfully");
+        // Return the fallback standard assembly program.
+        (Err(std_asm), std_debug_info)
+    } else {
+        info!("Compiled to core assembly successfully");
+        // Return the successfully compiled core assembly program.
+        (Ok(core_asm), core_debug_info)
+    };
+
+    let debug_info = debug_info
+        .map(|debug_info| debug_info.read().unwrap().clone())
+        .unwrap_or_default();
+    Ok((program, debug_info))
+}
+
 /// A trait which allows an LIR expression to be compiled to one of the
 /// two variants of the assembly language.
 pub trait Compile: TypeCheck + std::fmt::Debug + std::fmt::Display {
@@ -33,34 +103,45 @@ pub trait Compile: TypeCheck + std::fmt::Debug + std::fmt::Display {
     where
         Self: Sized + Clone,
     {
-        // eprintln!("Compiling LIR expression {self}");
-        info!("Type checking...");
-        // First, type check the expression.
-        self.type_check(&Env::default())?;
-        // Then, attempt to compile the expression into a core assembly program.
-        let mut core_asm = CoreProgram::default();
-
-        info!("Compiling...");
-        // If the expression cannot be compiled into a core assembly program,
-        // then compile it into a standard assembly program.
-        if let Err(err) = self
-            .clone()
-            // Compile the expression into the core assembly program.
-            .compile_expr(&mut Env::default(), &mut core_asm)
-        {
-            warn!("Failed to compile into core assembly program: {err}, falling back on standard assembly");
-            let mut std_asm = StandardProgram::default();
-            // Compile the expression into the standard assembly program.
-            self.compile_expr(&mut Env::default(), &mut std_asm)?;
-            info!("Compiled to standard assembly successfully");
-            // Return the fallback standard assembly program.
-            Ok(Err(std_asm))
-        } else {
-            info!("Compiled to core assembly successfully");
-            // Return the successfully compiled core assembly program.
-            Ok(Ok(core_asm))
-        }
+        compile_program(self, false, false).map(|(program, _)| program)
+    }
+
+    /// Compile the expression into an assembly program for debugging.
+    ///
+    /// This is the same as `compile`, but the program is marked with the source
+    /// code locations of its expressions, and the debug information about each
+    /// location is returned alongside the program.
+    fn compile_with_debug_info(
+        self,
+    ) -> Result<(Result<CoreProgram, StandardProgram>, DebugInfo), Error>
+    where
+        Self: Sized + Clone,
+    {
+        compile_program(self, true, false)
+    }
+
+    /// Compile the expression for a target which only supports the core variant.
+    ///
+    /// This is the same as `compile`, but `CORE_ONLY` is `True` while compiling the
+    /// core variant, so the pure core fallbacks for standard instructions are used.
+    fn compile_core_only(self) -> Result<Result<CoreProgram, StandardProgram>, Error>
+    where
+        Self: Sized + Clone,
+    {
+        compile_program(self, false, true).map(|(program, _)| program)
+    }
+
+    /// Compile the expression for a target which only supports the core variant,
+    /// with debug information like `compile_with_debug_info`.
+    fn compile_core_only_with_debug_info(
+        self,
+    ) -> Result<(Result<CoreProgram, StandardProgram>, DebugInfo), Error>
+    where
+        Self: Sized + Clone,
+    {
+        compile_program(self, true, true)
     }
+
     // Compile a specific expression into an assembly program.
     fn compile_expr(self, env: &mut Env, output: &mut dyn AssemblyProgram) -> Result<(), Error>;
 }
@@ -81,6 +162,11 @@ impl Compile for Expr {
         // Compile the expression.
         match self {
             Self::Annotated(expr, metadata) => {
+                // Mark the location of the expression for the debugger.
+                if let Some(location) = metadata.location() {
+                    env.mark_location(location, output)
+                        .map_err(|e| e.annotate(metadata.clone()))?;
+                }
                 // Compile the expression.
                 expr.compile_expr(env, output)
                     .map_err(|e| e.annotate(metadata))?;
@@ -173,6 +259,54 @@ impl Compile for Expr {
 
             // Compile a type cast.
             Self::As(ref expr, ref t) => {
+                // Casting a pointer to a trait object creates the object with its vtable.
+                if let Type::TraitObject(name) = t {
+                    if let Type::Pointer(_, _) = expr.get_type(env)?.simplify_until_concrete(env)? {
+                        let trait_ = env
+                            .get_trait(name)
+                            .ok_or_else(|| Error::TraitNotDefined(name.clone()))?;
+                        return trait_.to_object(expr, env)?.compile_expr(env, output);
+                    }
+                }
+
+                // Casting a pointer to characters slices the characters before the null
+                // terminator, or the whole array of characters if there isn't one.
+                if let Type::Str = t {
+                    if let Type::Pointer(_, elem) =
+                        expr.get_type(env)?.simplify_until_concrete(env)?
+                    {
+                        let count = match elem.simplify_until_concrete(env)? {
+                            Type::Array(_, len) => vec![
+                                CoreOp::Set(B, len.as_int(env)?),
+                                CoreOp::While(B),
+                                CoreOp::If(A.deref()),
+                                CoreOp::Inc(C),
+                                CoreOp::Next(A, None),
+                                CoreOp::Dec(B),
+                                CoreOp::Else,
+                                CoreOp::Set(B, 0),
+                                CoreOp::End,
+                                CoreOp::End,
+                            ],
+                            _ => vec![
+                                CoreOp::While(A.deref()),
+                                CoreOp::Inc(C),
+                                CoreOp::Next(A, None),
+                                CoreOp::End,
+                            ],
+                        };
+                        expr.clone().compile_expr(env, output)?;
+                        output.op(CoreOp::Move {
+                            src: SP.deref(),
+                            dst: A,
+                        });
+                        output.op(CoreOp::Set(C, 0));
+                        output.op(CoreOp::Many(count));
+                        output.op(CoreOp::Push(C, 1));
+                        return Ok(());
+                    }
+                }
+
                 // Compile the expression.
                 expr.clone().compile_expr(env, output)?;
                 // Cast the expression to the specified type.
@@ -187,6 +321,18 @@ impl Compile for Expr {
                     (Type::Float, Type::Int) => {
                         output.std_op(StandardOp::ToInt(SP.deref()))?;
                     }
+                    (Type::FixedInt(_), Type::Float) => {
+                        output.std_op(StandardOp::ToFloat(SP.deref()))?;
+                    }
+                    // If the cast is to a fixed-width integer,
+                    // then wrap the value into its range.
+                    (Type::Float, Type::FixedInt(ty)) => {
+                        output.std_op(StandardOp::ToInt(SP.deref()))?;
+                        output.op(ty.wrap_op(SP.deref(), SP.deref().offset(1)));
+                    }
+                    (_, Type::FixedInt(ty)) => {
+                        output.op(ty.wrap_op(SP.deref(), SP.deref().offset(1)));
+                    }
                     // If the cast is to a type of the same size,
                     // we will trust the user.
                     (a, b) if a.get_size(env)? == b.get_size(env)? => {}
@@ -210,6 +356,13 @@ impl Compile for Expr {
                         });
                 }
 
+                // If the procedure is a closure, call its procedure with its environment.
+                if self_clone.is_closure_call(env)? {
+                    return self_clone
+                        .transform_closure_call()
+                        .compile_expr(env, output);
+                }
+
                 // if !matches!(*f, Expr::Member(_, _)) {
                 //     // Push the arguments to the procedure on the stack.
                 //     for arg in &args {
@@ -647,6 +800,9 @@ impl Compile for Expr {
                 }
             }
 
+            // Compile a closure by creating its environment and pairing it with its procedure.
+            Self::Closure(closure) => closure.compile_expr(env, output)?,
+
             // Compile a member access operation.
             Self::Member(ref val, ref member) => {
                 // If the value we're getting a field from is a pointer,
@@ -814,7 +970,7 @@ impl Compile for Expr {
                     // Push the address of the struct, tuple, or union onto the stack.
                     match val_type.simplify_until_has_members(env)? {
                         // If the value is a struct, tuple, or union:
-                        Type::Struct(_) | Type::Tuple(_) | Type::Union(_) => {
+                        Type::Struct(_) | Type::Tuple(_) | Type::Union(_) | Type::Str => {
                             // Compile a reference to the inner value with the expected mutability.
                             Self::Refer(expected_mutability, val.clone())
                                 .compile_expr(env, output)?;
@@ -1046,6 +1202,12 @@ impl Compile for ConstExpr {
                 }
             },
 
+            Self::As(expr, ty @ Type::FixedInt(_)) => {
+                // Fixed-width integers are converted like run time casts.
+                Expr::ConstExpr(*expr)
+                    .as_type(ty)
+                    .compile_expr(env, output)?;
+            }
             Self::As(expr, _ty) => {
                 // Compile a compile time type cast expression.
                 expr.compile_expr(env, output)?;
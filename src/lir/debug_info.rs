@@ -0,0 +1,120 @@
+//! # Debug Information
+//!
+//! When an LIR program is compiled for debugging, the compiler records a scope
+//! for every expression annotated with a source code location. A scope remembers
+//! the location, the procedure it belongs to, and every variable defined at that
+//! point along with its offset from the frame pointer.
+//!
+//! The compiler inserts a marker comment referring to each scope into the assembly
+//! code. The comments are preserved by the assembler, so they end up in the virtual
+//! machine code, where a debugger can use them to map instruction indices back to
+//! source locations and variables.
+//!
+//! The compiler also records the name of every procedure it defines, in the order
+//! they are defined. The assembler numbers the functions of the virtual machine code
+//! in the same order, so the Nth procedure is the function called with `N` in the register.
+use super::{Mutability, Type};
+use crate::parse::SourceCodeLocation;
+
+/// The prefix of the comments which mark a debug scope in the compiled code.
+const MARKER_PREFIX: &str = "@debug ";
+
+/// A variable defined in a debug scope.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugVar {
+    /// The name of the variable.
+    pub name: String,
+    /// Is the variable mutable?
+    pub mutability: Mutability,
+    /// The type of the variable.
+    pub ty: Type,
+    /// The offset of the variable from the frame pointer.
+    pub offset: isize,
+    /// The size of the variable in cells.
+    pub size: usize,
+}
+
+/// The information recorded about a single source code location in the compiled code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugScope {
+    /// The location of the expression in the source code.
+    pub location: SourceCodeLocation,
+    /// The name of the procedure the expression belongs to.
+    /// This is `None` for code outside of any procedure.
+    pub procedure: Option<String>,
+    /// The variables defined at this point in the program, ordered by offset.
+    pub vars: Vec<DebugVar>,
+}
+
+impl DebugScope {
+    /// Get a variable defined in this scope by name.
+    pub fn get_var(&self, name: &str) -> Option<&DebugVar> {
+        self.vars.iter().find(|var| var.name == name)
+    }
+}
+
+/// A procedure defined in the compiled code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugProcedure {
+    /// The mangled name of the procedure, which labels it in the assembly code.
+    pub mangled_name: String,
+    /// The name of the procedure known to the front-end, if it has one.
+    pub common_name: Option<String>,
+}
+
+impl DebugProcedure {
+    /// The name of the procedure to show to the user.
+    pub fn name(&self) -> &str {
+        self.common_name.as_deref().unwrap_or(&self.mangled_name)
+    }
+}
+
+/// The debug scopes recorded while compiling a program.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DebugInfo {
+    scopes: Vec<DebugScope>,
+    procedures: Vec<DebugProcedure>,
+}
+
+impl DebugInfo {
+    /// Record a new scope, and return its ID.
+    pub(super) fn add_scope(&mut self, scope: DebugScope) -> usize {
+        self.scopes.push(scope);
+        self.scopes.len() - 1
+    }
+
+    /// Get the scope with the given ID.
+    pub fn get_scope(&self, id: usize) -> Option<&DebugScope> {
+        self.scopes.get(id)
+    }
+
+    /// Get all of the recorded scopes, indexed by their IDs.
+    pub fn scopes(&self) -> &[DebugScope] {
+        &self.scopes
+    }
+
+    /// Record a procedure defined in the compiled code.
+    pub(super) fn add_procedure(&mut self, procedure: DebugProcedure) {
+        self.procedures.push(procedure);
+    }
+
+    /// Get the procedure compiled to the virtual machine function with the given index.
+    pub fn get_procedure(&self, index: usize) -> Option<&DebugProcedure> {
+        self.procedures.get(index)
+    }
+
+    /// Get all of the recorded procedures, indexed by their virtual machine function indices.
+    pub fn procedures(&self) -> &[DebugProcedure] {
+        &self.procedures
+    }
+
+    /// Get the comment which marks the scope with the given ID in the compiled code.
+    pub fn marker(id: usize) -> String {
+        format!("{MARKER_PREFIX}{id}")
+    }
+
+    /// If the comment is a scope marker, get the ID of the scope it marks.
+    pub fn parse_marker(comment: &str) -> Option<usize> {
+        comment.strip_prefix(MARKER_PREFIX)?.parse().ok()
+    }
+}
@@ -5,10 +5,11 @@
 //! with respect to the frame pointer.
 
 use super::{
-    Compile, ConstExpr, Declaration, Error, Expr, FFIProcedure, GetSize, GetType, Mutability,
-    PolyProcedure, Procedure, Type,
+    Compile, ConstExpr, DebugInfo, DebugProcedure, DebugScope, DebugVar, Declaration, Error, Expr,
+    FFIProcedure, GetSize, GetType, Mutability, PolyProcedure, Procedure, Trait, Type,
 };
 use crate::asm::{AssemblyProgram, Globals, Location};
+use crate::parse::SourceCodeLocation;
 use core::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
 use std::{
@@ -28,6 +29,10 @@ pub struct Env {
     consts: Arc<HashMap<String, ConstExpr>>,
     /// The procedures defined under the environment.
     procs: Arc<HashMap<String, Procedure>>,
+    /// The traits defined under the environment.
+    traits: Arc<HashMap<String, Trait>>,
+    /// The types which implement each trait.
+    trait_impls: Arc<HashMap<String, Vec<Type>>>,
     /// The variables defined under the environment.
     vars: Arc<HashMap<String, (Mutability, Type, isize)>>,
     /// The static variables defined under the environment.
@@ -53,6 +58,12 @@ pub struct Env {
 
     /// Memoized type sizes.
     type_sizes: Arc<HashMap<Type, usize>>,
+
+    /// The debug information recorded while compiling.
+    /// This is `None` unless we are compiling the program for debugging.
+    debug_info: Option<Arc<RwLock<DebugInfo>>>,
+    /// The name of the procedure being compiled, for the debug information.
+    procedure: Option<String>,
 }
 
 impl Default for Env {
@@ -64,6 +75,8 @@ impl Default for Env {
             type_sizes: Arc::new(HashMap::new()),
             consts: Arc::new(HashMap::new()),
             procs: Arc::new(HashMap::new()),
+            traits: Arc::new(HashMap::new()),
+            trait_impls: Arc::new(HashMap::new()),
             vars: Arc::new(HashMap::new()),
             static_vars: Arc::new(HashMap::new()),
             globals: Arc::new(RwLock::new(Globals::new())),
@@ -75,6 +88,9 @@ impl Default for Env {
             fp_offset: 1,
             args_size: 0,
             expected_ret: None,
+
+            debug_info: None,
+            procedure: None,
         }
     }
 }
@@ -87,6 +103,8 @@ impl Env {
             types: self.types.clone(),
             consts: self.consts.clone(),
             procs: self.procs.clone(),
+            traits: self.traits.clone(),
+            trait_impls: self.trait_impls.clone(),
             static_vars: self.static_vars.clone(),
             type_sizes: {
                 // Copy the data but not the lock.
@@ -107,6 +125,7 @@ impl Env {
                 // Arc::new(RwLock::new(type_checked_consts))
                 self.type_checked_consts.clone()
             },
+            debug_info: self.debug_info.clone(),
 
             // The rest are the same as a new environment.
             ..Env::default()
@@ -695,6 +714,13 @@ impl Env {
                     // })?;
                 }
             }
+            Declaration::Trait(name, trait_) => {
+                self.define_trait(name, trait_.clone())?;
+            }
+            Declaration::ImplTrait(trait_name, ty, impls) => {
+                self.add_compile_time_declaration(&Declaration::Impl(ty.clone(), impls.clone()))?;
+                self.add_trait_impl(trait_name, ty.clone());
+            }
             Declaration::Var(_, _, Some(_ty), _e) => {
                 // ty.add_monomorphized_associated_consts(self).ok();
                 // if let Ok(ty) = e.get_type(self) {
@@ -759,9 +785,12 @@ impl Env {
             Declaration::StaticVar(_, _, _, _) => {
                 // Static variables are not defined at runtime.
             }
-            Declaration::Impl(_, _) => {
+            Declaration::Impl(_, _) | Declaration::ImplTrait(_, _, _) => {
                 // Implementations are not defined at runtime.
             }
+            Declaration::Trait(_, _) => {
+                // Traits are not defined at runtime.
+            }
             Declaration::Var(name, mutability, ty, expr) => {
                 let ty = match ty {
                     Some(ty) => ty.clone(),
@@ -811,6 +840,52 @@ impl Env {
         self.static_vars.get(name)
     }
 
+    /// Define a trait with a given name under this environment.
+    /// The methods of the trait's object type are added as its associated constants.
+    pub(super) fn define_trait(&mut self, name: impl ToString, trait_: Trait) -> Result<(), Error> {
+        let name = name.to_string();
+        trace!("Defining trait {name} as {trait_}");
+        for (method, object_method) in trait_.get_object_methods() {
+            self.add_associated_const(
+                Type::TraitObject(name.clone()),
+                method,
+                object_method.clone(),
+            )?;
+        }
+        Arc::make_mut(&mut self.traits).insert(name, trait_);
+        Ok(())
+    }
+
+    /// Get a trait definition from this environment.
+    pub fn get_trait(&self, name: &str) -> Option<&Trait> {
+        self.traits.get(name)
+    }
+
+    /// Record that a type implements a trait. Implementations for templates
+    /// are recorded for the template, so that they apply to all of its monomorphs.
+    pub(super) fn add_trait_impl(&mut self, trait_name: impl ToString, ty: Type) {
+        let ty = match ty {
+            Type::Apply(template, _) => *template,
+            ty => ty,
+        };
+        Arc::make_mut(&mut self.trait_impls)
+            .entry(trait_name.to_string())
+            .or_default()
+            .push(ty);
+    }
+
+    /// Does a type implement a trait under this environment?
+    pub fn implements(&self, ty: &Type, trait_name: &str) -> Result<bool, Error> {
+        if let Some(impls) = self.trait_impls.get(trait_name) {
+            for impl_ty in impls {
+                if ty.equals(impl_ty, self)? || ty.is_monomorph_of(impl_ty, self)? {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
     /// Define a type with a given name under this environment.
     pub(super) fn define_type(&mut self, name: impl ToString, ty: Type) {
         let name = name.to_string();
@@ -1008,6 +1083,60 @@ impl Env {
         Ok(offset)
     }
 
+    /// Record debug information for the code compiled under this environment.
+    pub(super) fn record_debug_info(&mut self, debug_info: Arc<RwLock<DebugInfo>>) {
+        self.debug_info = Some(debug_info);
+    }
+
+    /// Set the name of the procedure being compiled under this environment.
+    pub(super) fn set_procedure_name(&mut self, name: Option<String>) {
+        self.procedure = name;
+    }
+
+    /// If we're recording debug information, remember the names of a procedure
+    /// whose function is being defined.
+    pub(super) fn record_procedure(&self, mangled_name: &str, common_name: Option<&str>) {
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.write().unwrap().add_procedure(DebugProcedure {
+                mangled_name: mangled_name.to_string(),
+                common_name: common_name.map(str::to_string),
+            });
+        }
+    }
+
+    /// If we're recording debug information, insert a marker for the given
+    /// source code location into the program. The marker remembers the variables
+    /// defined in the current scope, and their offsets from the frame pointer.
+    pub(super) fn mark_location(
+        &self,
+        location: &SourceCodeLocation,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let Some(debug_info) = &self.debug_info else {
+            return Ok(());
+        };
+
+        let mut vars = vec![];
+        for (name, (mutability, ty, offset)) in self.vars.iter() {
+            vars.push(DebugVar {
+                name: name.clone(),
+                mutability: *mutability,
+                ty: ty.clone(),
+                offset: *offset,
+                size: ty.get_size(self)?,
+            });
+        }
+        vars.sort_by_key(|var| var.offset);
+
+        let id = debug_info.write().unwrap().add_scope(DebugScope {
+            location: location.clone(),
+            procedure: self.procedure.clone(),
+            vars,
+        });
+        output.comment(DebugInfo::marker(id));
+        Ok(())
+    }
+
     /// Get the expected return type of the current function.
     /// This is used to check if the returned value of a function matches the expected return type.
     /// This method returns `None` if the current scope is not a function.
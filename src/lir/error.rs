@@ -15,6 +15,8 @@ pub enum Error {
         /// This is used for error reporting.
         Annotation,
     ),
+    /// Several independent errors, in the order they were found.
+    Many(Vec<Self>),
 
     /// An error caused by trying to assemble invalid code generated by the compiler.
     /// This should be taken seriously, unless the error is due to an invalid handwritten builtin.
@@ -95,6 +97,8 @@ pub enum Error {
 
     /// Invalid type casting expression.
     InvalidAs(Expr, Type, Type),
+    /// A constant integer is cast to a fixed-width integer type it doesn't fit in.
+    IntOutOfRange(ConstExpr, Type),
 
     /// Invalid constant expression.
     InvalidConstExpr(ConstExpr),
@@ -122,6 +126,19 @@ pub enum Error {
 
     /// Cannot monomorphize a constant expression.
     InvalidMonomorphize(ConstExpr),
+
+    /// A trait was used, but not defined.
+    TraitNotDefined(String),
+    /// An implementation of a trait (the first field) for a type does not define
+    /// one of the trait's methods.
+    MissingTraitMethod(String, Type, String),
+    /// An implementation of a trait (the first field) for a type defines a member
+    /// which is not one of the trait's methods.
+    UnknownTraitMethod(String, Type, String),
+    /// A type was used where a type implementing a trait was required.
+    UnsatisfiedTraitBound(Type, String),
+    /// Tried to create a trait object of a trait whose methods cannot be called through a vtable.
+    TraitNotObjectSafe(String),
 }
 
 impl Error {
@@ -135,9 +152,39 @@ impl Error {
                 *previous_annotation = result;
                 self
             }
+            // Annotate each of the errors, keeping their own locations.
+            Self::Many(errors) => Self::Many(
+                errors
+                    .drain(..)
+                    .map(|err| err.annotate(annotation.clone()))
+                    .collect(),
+            ),
             _ => Self::Annotated(Box::new(self), annotation),
         }
     }
+
+    /// Combine the results of checking independent parts of a program,
+    /// so that all of their errors are reported instead of only the first one.
+    pub fn all(results: impl IntoIterator<Item = Result<(), Self>>) -> Result<(), Self> {
+        let mut errors = results
+            .into_iter()
+            .filter_map(Result::err)
+            .flat_map(Self::into_errors)
+            .collect::<Vec<_>>();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Self::Many(errors)),
+        }
+    }
+
+    /// Get the independent errors which make up this error, in the order they were found.
+    pub fn into_errors(self) -> Vec<Self> {
+        match self {
+            Self::Many(errors) => errors.into_iter().flat_map(Self::into_errors).collect(),
+            err => vec![err],
+        }
+    }
 }
 
 /// Create an IR error from an assembly error.
@@ -153,6 +200,15 @@ impl Display for Error {
             Self::Annotated(err, _) => {
                 write!(f, "{err}")
             }
+            Self::Many(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{err}")?;
+                }
+                Ok(())
+            }
             Self::MismatchedTypes {
                 expected,
                 found,
@@ -291,6 +347,9 @@ impl Display for Error {
                     expr, ty1, ty2
                 )
             }
+            Self::IntOutOfRange(expr, ty) => {
+                write!(f, "integer {expr} is out of the range of {ty}")
+            }
             Self::InvalidConstExpr(expr) => {
                 write!(f, "invalid constant expression {}", expr)
             }
@@ -325,6 +384,29 @@ impl Display for Error {
                     expr
                 )
             }
+            Self::TraitNotDefined(name) => {
+                write!(f, "trait {} not defined", name)
+            }
+            Self::MissingTraitMethod(trait_name, ty, method) => {
+                write!(
+                    f,
+                    "implementation of trait {} for type {} is missing method {}",
+                    trait_name, ty, method
+                )
+            }
+            Self::UnknownTraitMethod(trait_name, ty, method) => {
+                write!(
+                    f,
+                    "method {} of implementation for type {} is not a member of trait {}",
+                    method, ty, trait_name
+                )
+            }
+            Self::UnsatisfiedTraitBound(ty, trait_name) => {
+                write!(f, "type {} does not implement trait {}", ty, trait_name)
+            }
+            Self::TraitNotObjectSafe(name) => {
+                write!(f, "trait {} cannot be used as a trait object", name)
+            }
         }
     }
 }
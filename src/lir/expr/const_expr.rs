@@ -368,7 +368,31 @@ impl ConstExpr {
                         ));
                     }
 
-                    expr.eval_checked(env, i)
+                    match (expr.eval_checked(env, i)?, cast_ty) {
+                        // Integers must be in the range of the fixed-width type they're cast to.
+                        (Self::Int(n), Type::FixedInt(ty)) if !ty.contains(n) => {
+                            Err(Error::IntOutOfRange(Self::Int(n), Type::FixedInt(ty)))
+                        }
+                        // Keep the cast on a fixed-width integer, so that it keeps its type.
+                        (Self::Int(n), cast_ty @ Type::FixedInt(_)) => {
+                            Ok(Self::As(Box::new(Self::Int(n)), cast_ty))
+                        }
+                        (Self::Char(ch), Type::FixedInt(ty)) => Ok(Self::As(
+                            Box::new(Self::Int(ty.wrap(ch as i64))),
+                            Type::FixedInt(ty),
+                        )),
+                        (Self::Bool(b), cast_ty @ Type::FixedInt(_)) => {
+                            Ok(Self::As(Box::new(Self::Int(b as i64)), cast_ty))
+                        }
+                        // Casting between fixed-width integers wraps the value.
+                        (Self::As(n, Type::FixedInt(_)), Type::FixedInt(ty)) => Ok(Self::As(
+                            Box::new(Self::Int(ty.wrap(n.as_int(env)?))),
+                            Type::FixedInt(ty),
+                        )),
+                        // Casting a fixed-width integer to another type drops the cast.
+                        (Self::As(n, Type::FixedInt(_)), _) => Ok(*n),
+                        (value, _) => Ok(value),
+                    }
                 }
 
                 Self::SizeOfType(t) => Ok(Self::Int(t.get_size(env)? as i64)),
@@ -3,7 +3,7 @@ use crate::{
     asm::{AssemblyProgram, CoreOp, Location, SP},
     lir::{
         Compile, ConstExpr, Env, Error, Expr, FFIProcedure, GetSize, GetType, Mutability, Pattern,
-        Type, TypeCheck,
+        Trait, Type, TypeCheck, SELF_TYPE,
     },
 };
 use core::{
@@ -36,6 +36,10 @@ pub enum Declaration {
     ExternProc(String, FFIProcedure),
     /// Declare associated constants and procedures for a type.
     Impl(Type, Vec<(String, ConstExpr)>),
+    /// A trait declaration.
+    Trait(String, Trait),
+    /// Implement a trait's methods for a type.
+    ImplTrait(String, Type, Vec<(String, ConstExpr)>),
     /// Many declarations.
     Many(Vec<Declaration>),
 }
@@ -85,6 +89,8 @@ impl Declaration {
             Self::PolyProc(..) => true,
             Self::ExternProc(..) => true,
             Self::Impl(..) => true,
+            Self::Trait(..) => true,
+            Self::ImplTrait(..) => true,
             Self::Many(decls) => decls
                 .par_iter()
                 .all(|decl| decl.is_compile_time_declaration()),
@@ -287,6 +293,15 @@ impl Declaration {
             Self::ExternProc(_name, proc) => {
                 proc.substitute(substitution_name, substitution_ty);
             }
+            Self::Trait(_name, _trait) => {
+                // Traits are only declared at the top level of a scope, so they
+                // never contain the type parameters of a template.
+            }
+            Self::ImplTrait(_trait_name, _ty, impls) => {
+                impls.par_iter_mut().for_each(|(_name, expr)| {
+                    expr.substitute(substitution_name, substitution_ty);
+                });
+            }
             Self::Impl(_name, impls) => {
                 // for (_name, expr) in impls {
                 //     expr.substitute(substitution_name, substitution_ty);
@@ -313,23 +328,33 @@ impl TypeCheck for Declaration {
             // Typecheck a variable declaration.
             Self::Var(_name, _mutability, expected_ty, expr) => {
                 // Get the type of the expression.
-                let found_ty = expr.get_type(env)?;
+                let found_ty = match expr.get_type(env) {
+                    Ok(found_ty) => found_ty,
+                    // Check the expression to find all of its errors, instead of only the first one.
+                    Err(err) => return expr.type_check(env).and(Err(err)),
+                };
+                // Errors in the declaration itself are reported at the location of its value,
+                // instead of the location of the block containing it.
+                let at_value = |err: Error| match expr {
+                    Expr::Annotated(_, annotation) => err.annotate(annotation.clone()),
+                    _ => err,
+                };
                 // If there is a type specified, then make sure the type of the expression
                 // can decay to the specified type.
                 if let Some(expected_ty) = expected_ty {
                     // Typecheck the specified type.
-                    expected_ty.type_check(env)?;
+                    expected_ty.type_check(env).map_err(at_value)?;
                     // Make sure the type of the expression can decay to the specified type.
                     if !found_ty.can_decay_to(expected_ty, env)? {
                         error!(
                             "Invalid declaration {}: {found_ty:?} != {expected_ty:?}",
                             self.clone()
                         );
-                        return Err(Error::MismatchedTypes {
+                        return Err(at_value(Error::MismatchedTypes {
                             expected: expected_ty.clone(),
                             found: found_ty.clone(),
                             expr: Expr::NONE.with(self.clone()),
-                        });
+                        }));
                     }
                 }
 
@@ -482,9 +507,12 @@ impl TypeCheck for Declaration {
                     //     templated_const.type_check(&new_env)?;
                     // }
 
-                    templated_consts
-                        .par_iter()
-                        .try_for_each(|templated_const| templated_const.type_check(&new_env))?;
+                    Error::all(
+                        templated_consts
+                            .par_iter()
+                            .map(|templated_const| templated_const.type_check(&new_env))
+                            .collect::<Vec<_>>(),
+                    )?;
                 } else {
                     // ty.add_monomorphized_associated_consts(env)?;
                     for (name, associated_const) in impls {
@@ -497,11 +525,42 @@ impl TypeCheck for Declaration {
                     //     associated_const.type_check(&new_env)?;
                     // }
 
-                    impls.par_iter().try_for_each(|(_name, associated_const)| {
-                        associated_const.type_check(&new_env)
-                    })?;
+                    Error::all(
+                        impls
+                            .par_iter()
+                            .map(|(_name, associated_const)| associated_const.type_check(&new_env))
+                            .collect::<Vec<_>>(),
+                    )?;
                 }
             }
+            // Typecheck a trait declaration.
+            Self::Trait(_name, trait_) => {
+                // The method signatures may use the `Self` type for the implementing type.
+                let mut new_env = env.clone();
+                new_env.define_type(
+                    SELF_TYPE,
+                    Type::Unit(SELF_TYPE.to_string(), Box::new(Type::None)),
+                );
+                for method_ty in trait_.get_methods().values() {
+                    method_ty.type_check(&new_env)?;
+                }
+                // Make sure the methods of the trait object can call through the vtable.
+                for object_method in trait_.get_object_methods().values() {
+                    object_method.type_check(env)?;
+                }
+            }
+            // Typecheck a trait implementation.
+            Self::ImplTrait(trait_name, ty, impls) => {
+                // Check the methods as a normal implementation.
+                Self::Impl(ty.clone(), impls.clone()).type_check(env)?;
+                // Then confirm they match the trait's signatures.
+                let mut new_env = env.clone();
+                new_env.add_compile_time_declaration(self)?;
+                let trait_ = env
+                    .get_trait(trait_name)
+                    .ok_or_else(|| Error::TraitNotDefined(trait_name.clone()))?;
+                trait_.check_impl(ty, impls, &new_env)?;
+            }
             // Typecheck a multi-declaration.
             Self::Many(decls) => {
                 let mut new_env = env.clone();
@@ -513,12 +572,12 @@ impl TypeCheck for Declaration {
                     .iter()
                     .partition(|decl| decl.is_compile_time_declaration());
 
-                if !comp_time_decls.is_empty() {
-                    // Type check all the compile time declarations in parallel.
-                    comp_time_decls
-                        .par_iter()
-                        .try_for_each(|decl| decl.type_check(&new_env))?;
-                }
+                // Type check all the compile time declarations in parallel.
+                // Each declaration is checked on its own, so that all of their errors are found.
+                let mut checked = comp_time_decls
+                    .par_iter()
+                    .map(|decl| decl.type_check(&new_env))
+                    .collect::<Vec<_>>();
 
                 /*
                 lazy_static! {
@@ -558,13 +617,18 @@ impl TypeCheck for Declaration {
                 }
                  */
 
-                run_time_decls
-                    .iter()
-                    // .map(|decl| decl.type_check(&new_env))
-                    .try_for_each(|decl| {
-                        decl.type_check(&new_env)?;
-                        new_env.add_declaration(decl)
-                    })?;
+                for decl in run_time_decls {
+                    // The later declarations can still be checked if this one has errors,
+                    // as long as it can be defined.
+                    let result = decl.type_check(&new_env);
+                    let defined = new_env.add_declaration(decl);
+                    let is_defined = defined.is_ok();
+                    checked.push(result.and(defined));
+                    if !is_defined {
+                        break;
+                    }
+                }
+                Error::all(checked)?;
 
                 // for decl in decls {
                 //     // Typecheck any variable declarations in the old scope
@@ -620,6 +684,16 @@ impl Display for Declaration {
                 }
                 write!(f, "}}")?;
             }
+            Self::Trait(_name, trait_) => {
+                write!(f, "{}", trait_)?;
+            }
+            Self::ImplTrait(trait_name, ty, impls) => {
+                write!(f, "impl {trait_name} for {ty} {{")?;
+                for (name, expr) in impls {
+                    write!(f, "{} = {}", name, expr)?;
+                }
+                write!(f, "}}")?;
+            }
             Self::Many(decls) => {
                 for decl in decls {
                     writeln!(f, "{}", decl)?;
@@ -844,6 +918,17 @@ impl Hash for Declaration {
                 state.write_u8(9);
                 decls.hash(state);
             }
+            Self::Trait(name, trait_) => {
+                state.write_u8(10);
+                name.hash(state);
+                trait_.hash(state);
+            }
+            Self::ImplTrait(trait_name, ty, impls) => {
+                state.write_u8(11);
+                trait_name.hash(state);
+                ty.hash(state);
+                impls.hash(state);
+            }
         }
     }
 }
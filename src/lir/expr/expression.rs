@@ -7,7 +7,8 @@
 
 use super::ops::*;
 use crate::lir::{
-    Annotation, ConstExpr, Declaration, Env, Error, GetType, Mutability, Pattern, Procedure, Type,
+    Annotation, Closure, ConstExpr, Declaration, Env, Error, GetType, Mutability, Pattern,
+    Procedure, Type,
 };
 use core::fmt;
 use std::collections::BTreeMap;
@@ -107,6 +108,9 @@ pub enum Expr {
     Member(Box<Self>, ConstExpr),
     /// Index an array or pointer with an expression that evaluates to an `Int` at runtime.
     Index(Box<Self>, Box<Self>),
+
+    /// A procedure which captures variables from the scope it is created in.
+    Closure(Closure),
 }
 
 impl From<ConstExpr> for Expr {
@@ -425,6 +429,76 @@ impl Expr {
         Ok(result)
     }
 
+    /// Is this expression an application of a closure?
+    pub fn is_closure_call(&self, env: &Env) -> Result<bool, Error> {
+        match self {
+            Self::Annotated(inner, annotation) => inner
+                .is_closure_call(env)
+                .map_err(|e| e.annotate(annotation.clone())),
+
+            Self::Apply(fun, args) => {
+                if let Self::Annotated(inner, annotation) = &**fun {
+                    return Self::Apply(inner.clone(), args.clone())
+                        .is_closure_call(env)
+                        .map_err(|e| e.annotate(annotation.clone()));
+                }
+                if self.is_method_call(env)? {
+                    return Ok(false);
+                }
+                // If the type of the function can't be determined, this isn't a closure call.
+                // The error will be reported when the application is checked as usual.
+                Ok(matches!(
+                    fun.get_type(env)
+                        .and_then(|ty| ty.simplify_until_concrete(env)),
+                    Ok(Type::Closure(_, _))
+                ))
+            }
+
+            _ => Ok(false),
+        }
+    }
+
+    /// Transform the application of a closure into an application of its procedure
+    /// to its environment pointer, followed by the rest of the arguments.
+    pub fn transform_closure_call(&self) -> Self {
+        match self {
+            Self::Annotated(inner, annotation) => {
+                inner.transform_closure_call().annotate(annotation.clone())
+            }
+
+            Self::Apply(fun, args) => {
+                let fun = match &**fun {
+                    Self::Annotated(inner, _) => inner,
+                    fun => fun,
+                };
+
+                // Only evaluate the closure once.
+                let (closure, binding) = match fun {
+                    Self::ConstExpr(ConstExpr::Symbol(_)) => (fun.clone(), None),
+                    _ => (Self::var("__closure__"), Some(fun.clone())),
+                };
+
+                let mut new_args =
+                    vec![closure.clone().field(ConstExpr::Symbol("env".to_string()))];
+                new_args.extend(args.iter().cloned());
+                let result = closure
+                    .field(ConstExpr::Symbol("fn".to_string()))
+                    .app(new_args);
+
+                match binding {
+                    Some(fun) => result.with(vec![(
+                        "__closure__".to_string(),
+                        Mutability::Immutable,
+                        fun,
+                    )]),
+                    None => result,
+                }
+            }
+
+            _ => self.clone(),
+        }
+    }
+
     pub fn get_method_call_mutability(&self, env: &Env) -> Result<Option<Mutability>, Error> {
         match self {
             Self::Annotated(inner, annotation) => inner
@@ -516,6 +590,23 @@ impl Expr {
         self.binop(BitwiseXor, other)
     }
 
+    /// Shift the bits of this expression left by another.
+    #[allow(clippy::should_implement_trait)]
+    pub fn shl(self, other: impl Into<Self>) -> Self {
+        self.binop(Shift::Left, other)
+    }
+
+    /// Shift the bits of this expression right by another.
+    #[allow(clippy::should_implement_trait)]
+    pub fn shr(self, other: impl Into<Self>) -> Self {
+        self.binop(Shift::Right, other)
+    }
+
+    /// Shift the bits of this expression right by another, filling with zeroes.
+    pub fn logical_shr(self, other: impl Into<Self>) -> Self {
+        self.binop(Shift::LogicalRight, other)
+    }
+
     /// BitwiseOr this expression with another.
     pub fn bitor(self, other: impl Into<Self>) -> Self {
         self.binop(BitwiseOr, other)
@@ -855,6 +946,7 @@ impl fmt::Display for Expr {
 
             Self::Member(val, field) => write!(f, "({val}).{field}"),
             Self::Index(val, idx) => write!(f, "{val}[{idx}]"),
+            Self::Closure(closure) => write!(f, "{closure}"),
 
             Self::Return(val) => write!(f, "return {val}"),
             Self::Refer(mutability, val) => {
@@ -970,6 +1062,9 @@ impl PartialEq for Expr {
 
             // Index an array or pointer with an expression that evaluates to an `Int` at runtime.
             (Index(val1, idx1), Index(val2, idx2)) => val1 == val2 && idx1 == idx2,
+
+            // A procedure which captures variables from its scope.
+            (Closure(closure1), Closure(closure2)) => closure1 == closure2,
             _ => false,
         }
     }
@@ -1132,6 +1227,11 @@ impl Hash for Expr {
                 decl.hash(state);
                 expr.hash(state);
             }
+
+            Closure(closure) => {
+                state.write_u8(23);
+                closure.hash(state);
+            }
         }
     }
 }
@@ -12,6 +12,10 @@ impl Add {
     fn return_type_from_types(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<Type, Error> {
         match (lhs.clone(), rhs.clone()) {
             (Type::Int, Type::Int) => Ok(Type::Int),
+            (Type::FixedInt(a), Type::FixedInt(b)) if a == b => Ok(Type::FixedInt(a)),
+            (Type::FixedInt(ty), Type::Int) | (Type::Int, Type::FixedInt(ty)) => {
+                Ok(Type::FixedInt(ty))
+            }
 
             (Type::Int | Type::Float | Type::Cell, Type::Cell)
             | (Type::Cell, Type::Int | Type::Float) => Ok(Type::Cell),
@@ -101,6 +105,14 @@ impl BinaryOp for Add {
         })
     }
 
+    fn fold(&self, lhs: &ConstExpr, rhs: &ConstExpr) -> Option<ConstExpr> {
+        match (lhs, rhs) {
+            (ConstExpr::Int(a), ConstExpr::Int(b)) => a.checked_add(*b).map(ConstExpr::Int),
+            (ConstExpr::Float(a), ConstExpr::Float(b)) => Some(ConstExpr::Float(a + b)),
+            _ => None,
+        }
+    }
+
     // fn compile_types(&self, ty: &Type, env: &mut Env) -> Result<AssemblyProgram, Error> {}
     fn compile_types(
         &self,
@@ -126,6 +138,10 @@ impl BinaryOp for Add {
                 });
                 output.op(CoreOp::Pop(None, 1))
             }
+            // Fixed-width integers are added like any other arithmetic.
+            (a @ Type::FixedInt(_), b) | (a, b @ Type::FixedInt(_)) => {
+                Arithmetic::Add.compile_types(&a, &b, env, output)?;
+            }
             (Type::Float, Type::Float) | (Type::Float, Type::Cell) | (Type::Cell, Type::Float) => {
                 output.std_op(StandardOp::Add {
                     src: SP.deref(),
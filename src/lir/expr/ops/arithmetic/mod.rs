@@ -36,6 +36,9 @@ impl BinaryOp for Arithmetic {
     fn can_apply(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<bool, Error> {
         match (lhs, rhs) {
             (Type::Int, Type::Int) => Ok(true),
+            // Integers are converted to the fixed-width integer they're used with.
+            (Type::FixedInt(a), Type::FixedInt(b)) => Ok(a == b),
+            (Type::FixedInt(_), Type::Int) | (Type::Int, Type::FixedInt(_)) => Ok(true),
             (Type::Int, Type::Float) | (Type::Float, Type::Int) | (Type::Float, Type::Float) => {
                 Ok(true)
             }
@@ -78,6 +81,8 @@ impl BinaryOp for Arithmetic {
 
         Ok(match (lhs.get_type(env)?, rhs.get_type(env)?) {
             (Type::Int, Type::Int) => Type::Int,
+            (Type::FixedInt(a), Type::FixedInt(b)) if a == b => Type::FixedInt(a),
+            (Type::FixedInt(ty), Type::Int) | (Type::Int, Type::FixedInt(ty)) => Type::FixedInt(ty),
             (Type::Int, Type::Float) | (Type::Float, Type::Int) | (Type::Float, Type::Float) => {
                 Type::Float
             }
@@ -249,6 +254,35 @@ impl BinaryOp for Arithmetic {
         }
     }
 
+    /// Fold integer arithmetic which doesn't overflow or divide by zero,
+    /// and floating point arithmetic.
+    fn fold(&self, lhs: &ConstExpr, rhs: &ConstExpr) -> Option<ConstExpr> {
+        match (lhs, self, rhs) {
+            (ConstExpr::Int(a), Self::Add, ConstExpr::Int(b)) => {
+                a.checked_add(*b).map(ConstExpr::Int)
+            }
+            (ConstExpr::Int(a), Self::Subtract, ConstExpr::Int(b)) => {
+                a.checked_sub(*b).map(ConstExpr::Int)
+            }
+            (ConstExpr::Int(a), Self::Multiply, ConstExpr::Int(b)) => {
+                a.checked_mul(*b).map(ConstExpr::Int)
+            }
+            (ConstExpr::Int(a), Self::Divide, ConstExpr::Int(b)) => {
+                a.checked_div(*b).map(ConstExpr::Int)
+            }
+            (ConstExpr::Int(a), Self::Remainder, ConstExpr::Int(b)) => {
+                a.checked_rem(*b).map(ConstExpr::Int)
+            }
+            (ConstExpr::Int(a), Self::Power, ConstExpr::Int(b)) => {
+                a.checked_pow(u32::try_from(*b).ok()?).map(ConstExpr::Int)
+            }
+            (ConstExpr::Float(_), _, ConstExpr::Float(_)) => {
+                self.eval(lhs, rhs, &mut Env::default()).ok()
+            }
+            _ => None,
+        }
+    }
+
     /// Compile the binary operation.
     fn compile_types(
         &self,
@@ -347,6 +381,32 @@ impl BinaryOp for Arithmetic {
                 output.op(core_op);
             }
 
+            // Fixed-width integers are wrapped back into range after the operation.
+            (Type::FixedInt(ty), Type::FixedInt(_) | Type::Int)
+            | (Type::Int, Type::FixedInt(ty)) => {
+                let src = SP.deref();
+                let dst = SP.deref().offset(-1);
+                let tmp = SP.deref().offset(1);
+                // Convert an integer operand to the fixed-width type first.
+                if let Type::Int = lhs {
+                    output.op(ty.wrap_op(dst.clone(), tmp.clone()));
+                }
+                if let Type::Int = rhs {
+                    output.op(ty.wrap_op(src.clone(), tmp.clone()));
+                }
+                match (self, ty) {
+                    // The VM's division is signed, so unsigned 64-bit division is emulated.
+                    (Self::Divide | Self::Remainder, IntType::U64) => {
+                        let remainder = matches!(self, Self::Remainder);
+                        output.op(IntType::unsigned_div_op(dst, src, tmp, remainder));
+                    }
+                    _ => {
+                        output.op(core_op);
+                        output.op(ty.wrap_op(dst, tmp));
+                    }
+                }
+            }
+
             (Type::Unit(_name1, a_type), Type::Unit(_name2, b_type)) => {
                 return self.compile_types(a_type, b_type, env, output);
             }
@@ -9,13 +9,16 @@ pub struct Negate;
 
 impl UnaryOp for Negate {
     fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
-        Ok(ty.can_decay_to(&Type::Int, env).unwrap_or(false)
+        Ok(matches!(ty, Type::FixedInt(_))
+            || ty.can_decay_to(&Type::Int, env).unwrap_or(false)
             || ty.can_decay_to(&Type::Float, env).unwrap_or(false))
     }
 
     fn return_type(&self, x: &Expr, env: &Env) -> Result<Type, Error> {
         let ty = x.get_type(env)?;
-        if ty.can_decay_to(&Type::Int, env).unwrap_or(false) {
+        if let Type::FixedInt(_) = ty {
+            Ok(ty)
+        } else if ty.can_decay_to(&Type::Int, env).unwrap_or(false) {
             Ok(Type::Int)
         } else if ty.can_decay_to(&Type::Float, env).unwrap_or(false) {
             Ok(Type::Float)
@@ -44,6 +47,14 @@ impl UnaryOp for Negate {
         })
     }
 
+    fn fold(&self, x: &ConstExpr) -> Option<ConstExpr> {
+        match x {
+            ConstExpr::Int(i) => i.checked_neg().map(ConstExpr::Int),
+            ConstExpr::Float(f) => Some(ConstExpr::Float(-f)),
+            _ => None,
+        }
+    }
+
     // fn compile_types(&self, ty: &Type, env: &mut Env) -> Result<AssemblyProgram, Error> {}
     fn compile_types(
         &self,
@@ -51,7 +62,11 @@ impl UnaryOp for Negate {
         env: &mut Env,
         output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
-        if ty.can_decay_to(&Type::Int, env).unwrap_or(false) {
+        if let Type::FixedInt(ty) = ty {
+            // Negating a fixed-width integer wraps around, like in two's complement.
+            output.op(CoreOp::Neg(SP.deref()));
+            output.op(ty.wrap_op(SP.deref(), A));
+        } else if ty.can_decay_to(&Type::Int, env).unwrap_or(false) {
             output.op(CoreOp::Set(A, 0));
             output.op(CoreOp::Sub {
                 src: SP.deref(),
@@ -6,11 +6,13 @@
 //! - `And`
 //! - `Nand`
 //! - `Xor`
+//! - `Shift`
 mod and;
 mod nand;
 mod nor;
 mod not;
 mod or;
+mod shift;
 mod xor;
 
 pub use and::*;
@@ -18,4 +20,44 @@ pub use nand::*;
 pub use nor::*;
 pub use not::*;
 pub use or::*;
+pub use shift::*;
 pub use xor::*;
+
+use crate::{
+    asm::{AssemblyProgram, CoreOp, SP},
+    lir::*,
+};
+
+/// Get the fixed-width integer type a bitwise operation on two types works on, if any.
+/// An integer used with a fixed-width integer is converted to its type.
+fn fixed_int_operands(lhs: &Type, rhs: &Type) -> Option<IntType> {
+    match (lhs, rhs) {
+        (Type::FixedInt(a), Type::FixedInt(b)) if a == b => Some(*a),
+        (Type::FixedInt(ty), Type::Int) | (Type::Int, Type::FixedInt(ty)) => Some(*ty),
+        _ => None,
+    }
+}
+
+/// Compile a bitwise operation on the two fixed-width integers on top of the stack.
+/// Operations like `~&` set the bits above the width of the type, so the result
+/// is wrapped back into range.
+fn compile_fixed_int(
+    op: CoreOp,
+    ty: IntType,
+    lhs: &Type,
+    rhs: &Type,
+    output: &mut dyn AssemblyProgram,
+) {
+    let src = SP.deref();
+    let dst = SP.deref().offset(-1);
+    let tmp = SP.deref().offset(1);
+    if let Type::Int = lhs {
+        output.op(ty.wrap_op(dst.clone(), tmp.clone()));
+    }
+    if let Type::Int = rhs {
+        output.op(ty.wrap_op(src, tmp.clone()));
+    }
+    output.op(op);
+    output.op(ty.wrap_op(dst, tmp));
+    output.op(CoreOp::Pop(None, 1));
+}
@@ -10,12 +10,17 @@ pub struct BitwiseNot;
 
 impl UnaryOp for BitwiseNot {
     fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
+        if let Type::FixedInt(_) = ty {
+            return Ok(true);
+        }
         ty.equals(&Type::Int, env).or(ty.equals(&Type::Cell, env))
     }
 
     fn return_type(&self, x: &Expr, env: &Env) -> Result<Type, Error> {
         let ty = x.get_type(env)?;
-        if ty.equals(&Type::Int, env).unwrap_or(false) {
+        if let Type::FixedInt(_) = ty {
+            Ok(ty)
+        } else if ty.equals(&Type::Int, env).unwrap_or(false) {
             Ok(Type::Int)
         } else if ty.equals(&Type::Cell, env).unwrap_or(false) {
             Ok(Type::Cell)
@@ -44,6 +49,10 @@ impl UnaryOp for BitwiseNot {
         })
     }
 
+    fn fold(&self, x: &ConstExpr) -> Option<ConstExpr> {
+        self.eval(x, &mut Env::default()).ok()
+    }
+
     // fn compile_types(&self, ty: &Type, env: &mut Env) -> Result<AssemblyProgram, Error> {}
     fn compile_types(
         &self,
@@ -51,7 +60,11 @@ impl UnaryOp for BitwiseNot {
         env: &mut Env,
         output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
-        if ty.equals(&Type::Int, env)? || ty.equals(&Type::Cell, env)? {
+        if let Type::FixedInt(ty) = ty {
+            // Clear the bits that were set above the width of an unsigned type.
+            output.op(CoreOp::BitwiseNot(SP.deref()));
+            output.op(ty.wrap_op(SP.deref(), SP.deref().offset(1)));
+        } else if ty.equals(&Type::Int, env)? || ty.equals(&Type::Cell, env)? {
             output.op(CoreOp::BitwiseNot(SP.deref()));
         } else {
             return Err(Error::InvalidUnaryOpTypes(self.clone_box(), ty.clone()));
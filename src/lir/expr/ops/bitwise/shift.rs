@@ -0,0 +1,148 @@
+//! # Shift Operations
+use crate::{
+    asm::{AssemblyProgram, CoreOp, SP},
+    lir::*,
+};
+use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+/// A bit shift operation between two values.
+///
+/// Like the VM's shift instructions, the number of bits to shift by
+/// is taken modulo 64.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum Shift {
+    /// Shift the bits to the left, filling with zeroes.
+    Left,
+    /// Shift the bits to the right. This is an arithmetic shift for
+    /// signed integers, and a logical shift for unsigned integers.
+    Right,
+    /// Shift the bits to the right, filling with zeroes.
+    LogicalRight,
+}
+
+impl Shift {
+    /// Shift a constant integer.
+    fn shift(&self, a: i64, b: i64) -> i64 {
+        match self {
+            Self::Left => a.wrapping_shl(b as u32),
+            Self::Right => a.wrapping_shr(b as u32),
+            Self::LogicalRight => (a as u64).wrapping_shr(b as u32) as i64,
+        }
+    }
+}
+
+impl BinaryOp for Shift {
+    /// Can this binary operation be applied to the given types?
+    fn can_apply(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<bool, Error> {
+        let is_int = |ty: &Type| -> Result<bool, Error> {
+            Ok(matches!(ty, Type::FixedInt(_))
+                || ty.equals(&Type::Cell, env)?
+                || ty.equals(&Type::Int, env)?)
+        };
+        Ok(is_int(lhs)? && is_int(rhs)?)
+    }
+
+    /// Get the type of the result of applying this binary operation to the given types.
+    /// The result has the type of the value being shifted.
+    fn return_type(&self, lhs: &Expr, _rhs: &Expr, env: &Env) -> Result<Type, Error> {
+        let lhs = lhs.get_type(env)?;
+        if let Type::FixedInt(_) = lhs {
+            Ok(lhs)
+        } else if lhs.equals(&Type::Cell, env)? {
+            Ok(Type::Cell)
+        } else {
+            Ok(Type::Int)
+        }
+    }
+
+    /// Evaluate this binary operation on the given constant values.
+    fn eval(&self, lhs: &ConstExpr, rhs: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error> {
+        match (lhs.clone().eval(env)?, rhs.clone().eval(env)?) {
+            (ConstExpr::Int(a), ConstExpr::Int(b) | ConstExpr::Cell(b)) => {
+                Ok(ConstExpr::Int(self.shift(a, b)))
+            }
+            (ConstExpr::Cell(a), ConstExpr::Int(b) | ConstExpr::Cell(b)) => {
+                Ok(ConstExpr::Cell(self.shift(a, b)))
+            }
+            _ => Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                Expr::ConstExpr(lhs.clone()),
+                Expr::ConstExpr(rhs.clone()),
+            )),
+        }
+    }
+
+    fn fold(&self, lhs: &ConstExpr, rhs: &ConstExpr) -> Option<ConstExpr> {
+        self.eval(lhs, rhs, &mut Env::default()).ok()
+    }
+
+    /// Compile the binary operation.
+    fn compile_types(
+        &self,
+        lhs: &Type,
+        _rhs: &Type,
+        _env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let src = SP.deref();
+        let dst = SP.deref().offset(-1);
+        let tmp = SP.deref().offset(1);
+        match (self, lhs) {
+            (Self::Left, Type::FixedInt(ty)) => {
+                output.op(CoreOp::LeftShift {
+                    src,
+                    dst: dst.clone(),
+                });
+                output.op(ty.wrap_op(dst, tmp));
+            }
+            // A signed integer is shifted logically as the unsigned integer
+            // with the same bits, and then converted back.
+            (Self::LogicalRight, Type::FixedInt(ty)) if ty.is_signed() => {
+                output.op(ty.to_unsigned().wrap_op(dst.clone(), tmp.clone()));
+                output.op(CoreOp::LogicalRightShift {
+                    src,
+                    dst: dst.clone(),
+                });
+                output.op(ty.wrap_op(dst, tmp));
+            }
+            // The other fixed-width integers are already extended to fill the cell,
+            // so shifting them right keeps them in range.
+            (Self::Right, Type::FixedInt(ty)) if ty.is_signed() => {
+                output.op(CoreOp::ArithmeticRightShift { src, dst });
+            }
+            (Self::Right | Self::LogicalRight, Type::FixedInt(_)) => {
+                output.op(CoreOp::LogicalRightShift { src, dst });
+            }
+            (Self::Left, _) => output.op(CoreOp::LeftShift { src, dst }),
+            (Self::Right, _) => output.op(CoreOp::ArithmeticRightShift { src, dst }),
+            (Self::LogicalRight, _) => output.op(CoreOp::LogicalRightShift { src, dst }),
+        }
+        output.op(CoreOp::Pop(None, 1));
+        Ok(())
+    }
+
+    /// Clone this binary operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+}
+
+impl Debug for Shift {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Shift::Left => write!(f, "<<"),
+            Shift::Right => write!(f, ">>"),
+            Shift::LogicalRight => write!(f, ">>>"),
+        }
+    }
+}
+
+impl Display for Shift {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Shift::Left => write!(f, "<<"),
+            Shift::Right => write!(f, ">>"),
+            Shift::LogicalRight => write!(f, ">>>"),
+        }
+    }
+}
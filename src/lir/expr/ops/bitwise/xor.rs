@@ -1,4 +1,5 @@
 //! # Bitwise Operations
+use super::{compile_fixed_int, fixed_int_operands};
 use crate::{
     asm::{AssemblyProgram, CoreOp, SP},
     lir::*,
@@ -12,6 +13,9 @@ pub struct BitwiseXor;
 impl BinaryOp for BitwiseXor {
     /// Can this binary operation be applied to the given types?
     fn can_apply(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<bool, Error> {
+        if fixed_int_operands(lhs, rhs).is_some() {
+            return Ok(true);
+        }
         Ok(
             (lhs.equals(&Type::Cell, env)? || lhs.equals(&Type::Int, env)?)
                 && (rhs.equals(&Type::Cell, env)? || rhs.equals(&Type::Int, env)?),
@@ -20,7 +24,9 @@ impl BinaryOp for BitwiseXor {
 
     /// Get the type of the result of applying this binary operation to the given types.
     fn return_type(&self, lhs: &Expr, rhs: &Expr, env: &Env) -> Result<Type, Error> {
-        if lhs.get_type(env)?.equals(&Type::Cell, env)?
+        if let Some(ty) = fixed_int_operands(&lhs.get_type(env)?, &rhs.get_type(env)?) {
+            Ok(Type::FixedInt(ty))
+        } else if lhs.get_type(env)?.equals(&Type::Cell, env)?
             || rhs.get_type(env)?.equals(&Type::Cell, env)?
         {
             Ok(Type::Cell)
@@ -44,19 +50,28 @@ impl BinaryOp for BitwiseXor {
         }
     }
 
+    fn fold(&self, lhs: &ConstExpr, rhs: &ConstExpr) -> Option<ConstExpr> {
+        self.eval(lhs, rhs, &mut Env::default()).ok()
+    }
+
     /// Compile the binary operation.
     fn compile_types(
         &self,
-        _lhs: &Type,
-        _rhs: &Type,
+        lhs: &Type,
+        rhs: &Type,
         _env: &mut Env,
         output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
-        output.op(CoreOp::BitwiseXor {
+        let op = CoreOp::BitwiseXor {
             src: SP.deref(),
             dst: SP.deref().offset(-1),
-        });
-        output.op(CoreOp::Pop(None, 1));
+        };
+        if let Some(ty) = fixed_int_operands(lhs, rhs) {
+            compile_fixed_int(op, ty, lhs, rhs, output);
+        } else {
+            output.op(op);
+            output.op(CoreOp::Pop(None, 1));
+        }
         Ok(())
     }
 
@@ -23,6 +23,10 @@ impl BinaryOp for Comparison {
     /// Can this binary operation be applied to the given types?
     fn can_apply(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<bool, Error> {
         match (lhs, self, rhs) {
+            // Integers are converted to the fixed-width integer they're compared with.
+            (Type::FixedInt(a), _, Type::FixedInt(b)) => Ok(a == b),
+            (Type::FixedInt(_), _, Type::Int) | (Type::Int, _, Type::FixedInt(_)) => Ok(true),
+
             (Type::Int, Self::LessThan, Type::Int)
             | (Type::Int, Self::LessThanOrEqual, Type::Int)
             | (Type::Int, Self::GreaterThan, Type::Int)
@@ -105,6 +109,19 @@ impl BinaryOp for Comparison {
         }
     }
 
+    /// Fold comparisons of integers, booleans, and characters. Comparisons of
+    /// floats are compiled differently than they're evaluated, so they aren't folded.
+    fn fold(&self, lhs: &ConstExpr, rhs: &ConstExpr) -> Option<ConstExpr> {
+        match (lhs, rhs) {
+            (ConstExpr::Int(_), ConstExpr::Int(_))
+            | (ConstExpr::Bool(_), ConstExpr::Bool(_))
+            | (ConstExpr::Char(_), ConstExpr::Char(_)) => {
+                self.eval(lhs, rhs, &mut Env::default()).ok()
+            }
+            _ => None,
+        }
+    }
+
     /// Compile the binary operation.
     fn compile_types(
         &self,
@@ -211,6 +228,46 @@ impl BinaryOp for Comparison {
                 output.std_op(std_op)?;
             }
 
+            // Fixed-width integers are compared as integers once they're in the same range.
+            (Type::FixedInt(ty), _, Type::FixedInt(_) | Type::Int)
+            | (Type::Int, _, Type::FixedInt(ty)) => {
+                let src = SP.deref();
+                // Convert an integer operand to the fixed-width type first.
+                if let Type::Int = lhs {
+                    output.op(ty.wrap_op(dst.clone(), tmp.clone()));
+                }
+                if let Type::Int = rhs {
+                    output.op(ty.wrap_op(src.clone(), tmp.clone()));
+                }
+                // Unsigned 64-bit integers are ordered by flipping their sign bits.
+                if *ty == IntType::U64 {
+                    output.op(IntType::unsigned_order_op(dst.clone(), tmp.clone()));
+                    output.op(IntType::unsigned_order_op(src.clone(), tmp.clone()));
+                }
+                output.op(CoreOp::Move {
+                    src: dst.clone(),
+                    dst: tmp.clone(),
+                });
+                let (lhs, rhs) = (tmp.clone(), src);
+                let scratch = tmp.offset(1);
+                output.op(match self {
+                    // The difference of two 64-bit integers can overflow,
+                    // so they're ordered without the VM's comparisons.
+                    Self::Equal | Self::NotEqual => core_op,
+                    _ if ty.bits() < 64 => core_op,
+                    Self::LessThan => IntType::less_than_op(lhs, rhs, dst, scratch),
+                    Self::GreaterThan => IntType::less_than_op(rhs, lhs, dst, scratch),
+                    Self::LessThanOrEqual => CoreOp::Many(vec![
+                        IntType::less_than_op(rhs, lhs, dst.clone(), scratch),
+                        CoreOp::Not(dst),
+                    ]),
+                    Self::GreaterThanOrEqual => CoreOp::Many(vec![
+                        IntType::less_than_op(lhs, rhs, dst.clone(), scratch),
+                        CoreOp::Not(dst),
+                    ]),
+                });
+            }
+
             // If cells and/or ints are used, we just use them as integers.
             (Type::Int, _, Type::Int)
             | (Type::Cell, _, Type::Cell)
@@ -162,7 +162,50 @@ impl Put {
                     output.op(CoreOp::Put(A, Output::stdout_char()));
                 }
             }
-            Type::Int => {
+            Type::FixedInt(IntType::U64) => {
+                // A `U64` too large for a signed integer is printed without its last digit,
+                // which is then printed on its own.
+                let value = SP.deref().offset(1);
+                let quotient = SP.deref().offset(2);
+                let ten = SP.deref().offset(3);
+                use CoreOp::*;
+                output.op(Many(vec![
+                    Move {
+                        src: addr,
+                        dst: value.clone(),
+                    },
+                    Set(A, 0),
+                    IsLess {
+                        a: value.clone(),
+                        b: A,
+                        dst: A,
+                    },
+                    If(A),
+                    Move {
+                        src: value.clone(),
+                        dst: quotient.clone(),
+                    },
+                    Set(ten.clone(), 10),
+                    IntType::unsigned_div_op(
+                        quotient.clone(),
+                        ten.clone(),
+                        SP.deref().offset(4),
+                        false,
+                    ),
+                    Put(quotient.clone(), Output::stdout_int()),
+                    Mul {
+                        src: ten,
+                        dst: quotient.clone(),
+                    },
+                    Sub {
+                        src: quotient,
+                        dst: value.clone(),
+                    },
+                    End,
+                    Put(value, Output::stdout_int()),
+                ]));
+            }
+            Type::Int | Type::FixedInt(_) => {
                 output.op(CoreOp::Put(addr, Output::stdout_int()));
             }
             Type::Float => {
@@ -175,6 +218,13 @@ impl Put {
                 output.op(CoreOp::Set(A, b'\'' as i64));
                 output.op(CoreOp::Put(A, Output::stdout_char()));
             }
+            Type::Str => {
+                output.op(CoreOp::Set(A, b'"' as i64));
+                output.op(CoreOp::Put(A, Output::stdout_char()));
+                Self::display(addr, t, env, output)?;
+                output.op(CoreOp::Set(A, b'"' as i64));
+                output.op(CoreOp::Put(A, Output::stdout_char()));
+            }
             Type::Never => {
                 for c in "Never".to_string().chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
@@ -438,6 +488,25 @@ impl Put {
             Type::Char => {
                 output.op(CoreOp::Put(addr, Output::stdout_char()));
             }
+            // Print the characters of a string slice.
+            Type::Str => {
+                use CoreOp::*;
+                output.op(Many(vec![
+                    Move {
+                        src: addr.clone(),
+                        dst: A,
+                    },
+                    Move {
+                        src: addr.offset(1),
+                        dst: B,
+                    },
+                    While(B),
+                    Put(A.deref(), Output::stdout_char()),
+                    Next(A, None),
+                    Dec(B),
+                    End,
+                ]));
+            }
             // Char pointer is a string
             Type::Pointer(_, inner) => {
                 if inner.equals(&Type::Char, env)? {
@@ -36,6 +36,10 @@ impl BinaryOp for And {
         }
     }
 
+    fn fold(&self, lhs: &ConstExpr, rhs: &ConstExpr) -> Option<ConstExpr> {
+        self.eval(lhs, rhs, &mut Env::default()).ok()
+    }
+
     /// Compile the binary operation.
     fn compile_types(
         &self,
@@ -85,6 +89,10 @@ impl BinaryOp for Or {
         }
     }
 
+    fn fold(&self, lhs: &ConstExpr, rhs: &ConstExpr) -> Option<ConstExpr> {
+        self.eval(lhs, rhs, &mut Env::default()).ok()
+    }
+
     /// Compile the binary operation.
     fn compile_types(
         &self,
@@ -134,6 +142,10 @@ impl UnaryOp for Not {
         }
     }
 
+    fn fold(&self, expr: &ConstExpr) -> Option<ConstExpr> {
+        self.eval(expr, &mut Env::default()).ok()
+    }
+
     /// Compile the unary operation.
     fn compile_types(
         &self,
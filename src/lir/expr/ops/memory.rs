@@ -75,7 +75,9 @@ pub struct Delete;
 impl UnaryOp for Delete {
     /// Can this unary operation be applied to the given type?
     fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
-        ty.equals(&Type::Pointer(Mutability::Any, Box::new(Type::Any)), env)
+        // Deleting a closure frees its environment.
+        let is_closure = matches!(ty.simplify_until_concrete(env), Ok(Type::Closure(_, _)));
+        Ok(is_closure || ty.equals(&Type::Pointer(Mutability::Any, Box::new(Type::Any)), env)?)
     }
 
     /// Get the type of the result of applying this unary operation to the given type.
@@ -91,17 +93,24 @@ impl UnaryOp for Delete {
     /// Compile the unary operation.
     fn compile_types(
         &self,
-        _ty: &Type,
-        _env: &mut Env,
+        ty: &Type,
+        env: &mut Env,
         output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
-        output.std_op(StandardOp::Free(SP.deref())).map_err(|_| {
-            Error::UnsupportedOperation(Expr::UnaryOp(
-                self.clone_box(),
-                Box::new(Expr::ConstExpr(ConstExpr::None)),
-            ))
-        })?;
-        output.op(CoreOp::Pop(None, 1));
+        // A closure object is its environment pointer followed by its procedure.
+        let size = match ty.simplify_until_concrete(env) {
+            Ok(Type::Closure(_, _)) => 2,
+            _ => 1,
+        };
+        output
+            .std_op(StandardOp::Free(SP.deref().offset(1 - size as isize)))
+            .map_err(|_| {
+                Error::UnsupportedOperation(Expr::UnaryOp(
+                    self.clone_box(),
+                    Box::new(Expr::ConstExpr(ConstExpr::None)),
+                ))
+            })?;
+        output.op(CoreOp::Pop(None, size));
         Ok(())
     }
 
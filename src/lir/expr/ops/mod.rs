@@ -176,6 +176,12 @@ pub trait UnaryOp: std::fmt::Debug + std::fmt::Display + Send + Sync {
     }
     /// Evaluates the operation on the given constant expression.
     fn eval(&self, expr: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error>;
+    /// Evaluates the operation on a literal ahead of time, for the optimizer.
+    /// This only succeeds if the result is exactly what the compiled operation
+    /// computes at runtime, so operations don't fold by default.
+    fn fold(&self, _expr: &ConstExpr) -> Option<ConstExpr> {
+        None
+    }
     /// Compiles the operation on the given expression.
     fn compile(
         &self,
@@ -273,6 +279,12 @@ pub trait BinaryOp: std::fmt::Debug + std::fmt::Display + Send + Sync {
     }
     /// Evaluates the operation on the given constant expressions.
     fn eval(&self, lhs: &ConstExpr, rhs: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error>;
+    /// Evaluates the operation on two literals ahead of time, for the optimizer.
+    /// This only succeeds if the result is exactly what the compiled operation
+    /// computes at runtime, so operations don't fold by default.
+    fn fold(&self, _lhs: &ConstExpr, _rhs: &ConstExpr) -> Option<ConstExpr> {
+        None
+    }
     /// Compiles the operation on the given expressions.
     fn compile(
         &self,
@@ -0,0 +1,227 @@
+//! # Closure
+//!
+//! A closure is a procedure which captures variables from the scope it is created in.
+//! Variables can be captured by value, which copies them into the closure's environment,
+//! or by reference, which stores a pointer to them in the environment. A variable can
+//! only be captured by mutable reference if it is mutable where the closure is created.
+//!
+//! A closure is compiled to a procedure which takes a pointer to its environment before
+//! the rest of its arguments. The closure object is a pointer to the environment, which
+//! is allocated on the heap, paired with the procedure. Inside the body of the closure,
+//! variables captured by value are immutable copies, and variables captured by reference
+//! are pointers to the original variables. A closure which captures references must not
+//! be called after the variables it references have gone out of scope.
+//!
+//! The environment is owned by the closure object, and isn't freed automatically.
+//! Deleting a closure with `del` frees its environment (the variables it references
+//! are left alone), after which the closure and any copies of it must not be called.
+//! A closure which is never deleted keeps its environment until the program exits.
+use crate::asm::AssemblyProgram;
+use crate::lir::{
+    Compile, ConstExpr, Env, Error, Expr, GetType, Mutability, New, Procedure, Type, TypeCheck,
+};
+use core::fmt;
+use std::hash::Hash;
+
+/// The name of the parameter which points to a closure's environment.
+const ENV_PARAM: &str = "__closure_env__";
+
+/// How a closure captures a variable from the scope it is created in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capture {
+    /// Copy the value of the variable into the closure's environment.
+    Value,
+    /// Store a pointer to the variable in the closure's environment.
+    Reference(Mutability),
+}
+
+/// A procedure which captures variables from the scope it is created in.
+#[derive(Clone, Debug, PartialEq, Hash)]
+pub struct Closure {
+    /// The variables captured by the closure, and how they are captured.
+    captures: Vec<(String, Capture)>,
+    /// The arguments of the closure, and their types.
+    args: Vec<(String, Mutability, Type)>,
+    /// The return type of the closure.
+    ret: Type,
+    /// The closure's body expression.
+    body: Box<Expr>,
+}
+
+impl Closure {
+    /// Construct a new closure with a list of captured variables, a list of arguments
+    /// and their types, a return type, and the body of the closure.
+    pub fn new(
+        captures: Vec<(String, Capture)>,
+        args: Vec<(String, Mutability, Type)>,
+        ret: Type,
+        body: impl Into<Expr>,
+    ) -> Self {
+        Self {
+            captures,
+            args,
+            ret,
+            body: Box::new(body.into()),
+        }
+    }
+
+    /// Get the variables captured by the closure, and how they are captured.
+    pub fn get_captures(&self) -> &[(String, Capture)] {
+        &self.captures
+    }
+
+    /// Get the arguments of the closure.
+    pub fn get_args(&self) -> &[(String, Mutability, Type)] {
+        &self.args
+    }
+
+    /// Get the body of the closure.
+    pub fn get_body(&self) -> &Expr {
+        &self.body
+    }
+
+    /// Get the type of the closure's environment: a structure with a field
+    /// for each captured variable.
+    fn get_env_type(&self, env: &Env) -> Result<Type, Error> {
+        let mut fields = std::collections::BTreeMap::new();
+        for (name, capture) in &self.captures {
+            let ty = Expr::var(name).get_type(env)?;
+            fields.insert(
+                name.clone(),
+                match capture {
+                    Capture::Value => ty,
+                    Capture::Reference(mutability) => Type::Pointer(*mutability, Box::new(ty)),
+                },
+            );
+        }
+        Ok(Type::Struct(fields))
+    }
+
+    /// Get the procedure which implements the closure. This takes a pointer to the
+    /// environment before the rest of the closure's arguments, and binds each captured
+    /// variable from the environment before evaluating the body.
+    fn get_proc(&self, env: &Env) -> Result<Procedure, Error> {
+        let env_ty = self.get_env_type(env)?;
+        let mut args = vec![(
+            ENV_PARAM.to_string(),
+            Mutability::Immutable,
+            Type::Pointer(Mutability::Immutable, Box::new(env_ty)),
+        )];
+        args.extend(self.args.iter().cloned());
+
+        let mut body = *self.body.clone();
+        if !self.captures.is_empty() {
+            body = body.with(
+                self.captures
+                    .iter()
+                    .map(|(name, _)| {
+                        (
+                            name.clone(),
+                            Mutability::Immutable,
+                            Expr::var(ENV_PARAM).field(ConstExpr::Symbol(name.clone())),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+        Ok(Procedure::new(None, args, self.ret.clone(), body))
+    }
+
+    /// Get the expression which creates the closure object. The captured variables
+    /// are copied (or referenced) into an environment on the heap, which is paired
+    /// with the procedure implementing the closure. The environment is freed by `del`.
+    pub fn to_object(&self, env: &Env) -> Result<Expr, Error> {
+        let env_ptr = if self.captures.is_empty() {
+            // A closure which captures nothing doesn't need an environment.
+            Expr::ConstExpr(ConstExpr::Null)
+        } else {
+            Expr::Struct(
+                self.captures
+                    .iter()
+                    .map(|(name, capture)| {
+                        let var = Expr::var(name);
+                        (
+                            name.clone(),
+                            match capture {
+                                Capture::Value => var,
+                                Capture::Reference(mutability) => var.refer(*mutability),
+                            },
+                        )
+                    })
+                    .collect(),
+            )
+            .unop(New)
+        };
+
+        Ok(Expr::Struct(
+            [
+                ("env".to_string(), env_ptr),
+                (
+                    "fn".to_string(),
+                    Expr::ConstExpr(ConstExpr::Proc(self.get_proc(env)?)),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .as_type(self.get_type(env)?))
+    }
+}
+
+impl GetType for Closure {
+    fn get_type_checked(&self, _env: &Env, _i: usize) -> Result<Type, Error> {
+        Ok(Type::Closure(
+            self.args.iter().map(|(_, _, t)| t.clone()).collect(),
+            Box::new(self.ret.clone()),
+        ))
+    }
+
+    fn substitute(&mut self, name: &str, ty: &Type) {
+        for (_, _, t) in &mut self.args {
+            *t = t.substitute(name, ty);
+        }
+        self.ret = self.ret.substitute(name, ty);
+
+        self.body.substitute(name, ty);
+    }
+}
+
+impl TypeCheck for Closure {
+    fn type_check(&self, env: &Env) -> Result<(), Error> {
+        // Check that the captured variables can be captured, and that
+        // the procedure implementing the closure is sound.
+        self.to_object(env)?.type_check(env)
+    }
+}
+
+impl Compile for Closure {
+    fn compile_expr(self, env: &mut Env, output: &mut dyn AssemblyProgram) -> Result<(), Error> {
+        self.to_object(env)?.compile_expr(env, output)
+    }
+}
+
+impl fmt::Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "closure[")?;
+        for (i, (name, capture)) in self.captures.iter().enumerate() {
+            match capture {
+                Capture::Value => write!(f, "{name}")?,
+                Capture::Reference(mutability) => write!(f, "&{mutability}{name}")?,
+            }
+            if i < self.captures.len() - 1 {
+                write!(f, ", ")?
+            }
+        }
+        write!(f, "](")?;
+        for (i, (name, mutability, ty)) in self.args.iter().enumerate() {
+            if mutability.is_mutable() {
+                write!(f, "mut ")?;
+            }
+            write!(f, "{name}: {ty}")?;
+            if i < self.args.len() - 1 {
+                write!(f, ", ")?
+            }
+        }
+        write!(f, ") -> {} = {}", self.ret, self.body)
+    }
+}
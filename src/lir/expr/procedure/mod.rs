@@ -15,12 +15,19 @@
 //!
 //! Polymorphic procedures take a list of type arguments, and produce a monomorphized
 //! version of the procedure. This can then be compiled directly to assembly.
+//!
+//! ## Closure
+//!
+//! Closures are procedures which capture variables from the scope they are created in.
+//! They are compiled to a procedure which takes a pointer to the captured environment.
 mod builtin;
+mod closure;
 mod ffi;
 mod mono;
 mod poly;
 
 pub use builtin::*;
+pub use closure::*;
 pub use ffi::*;
 pub use mono::*;
 pub use poly::*;
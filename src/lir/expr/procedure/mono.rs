@@ -10,7 +10,7 @@
 //! Procedures are created by the `proc` keyword.
 use crate::asm::{AssemblyProgram, CoreOp, A, FP, SP};
 use crate::lir::{
-    Compile, ConstExpr, Env, Error, Expr, GetSize, GetType, Mutability, Type, TypeCheck,
+    Annotation, Compile, ConstExpr, Env, Error, Expr, GetSize, GetType, Mutability, Type, TypeCheck,
 };
 use core::fmt;
 use std::hash::Hash;
@@ -89,6 +89,12 @@ impl Procedure {
         &self.body
     }
 
+    /// Annotate the body of the procedure.
+    pub fn annotate_body(mut self, annotation: impl Into<Annotation>) -> Self {
+        self.body = Box::new(self.body.annotate(annotation));
+        self
+    }
+
     /// Get the mangled name of the procedure.
     /// The procedure's mangled name is used to store the procedure in the environment.
     pub fn get_mangled_name(&self) -> &str {
@@ -141,7 +147,11 @@ impl TypeCheck for Procedure {
         new_env.set_expected_return_type(self.ret.clone());
 
         // Get the type of the procedure's body, and confirm that it matches the return type.
-        let body_type = self.body.get_type(&new_env)?;
+        let body_type = match self.body.get_type(&new_env) {
+            Ok(body_type) => body_type,
+            // Check the body to find all of its errors, instead of only the first one.
+            Err(err) => return self.body.type_check(&new_env).and(Err(err)),
+        };
         if !body_type.can_decay_to(&self.ret, env)? {
             Err(Error::MismatchedTypes {
                 expected: self.ret.clone(),
@@ -177,6 +187,7 @@ impl Compile for Procedure {
     fn compile_expr(self, env: &mut Env, output: &mut dyn AssemblyProgram) -> Result<(), Error> {
         // Compile the contents of the procedure under a new environment
         let mut new_env = env.new_scope();
+        new_env.set_procedure_name(self.common_name.clone());
 
         // Declare the arguments and get their size
         let args_size = new_env.define_args(self.args)?;
@@ -195,6 +206,7 @@ impl Compile for Procedure {
 
         // Declare the function body
         output.op(CoreOp::Fn(self.mangled_name.clone()));
+        env.record_procedure(&self.mangled_name, self.common_name.as_deref());
         if let Some(common_name) = &self.common_name {
             output.comment(format!("{}({})", common_name, args_size));
         }
@@ -3,11 +3,11 @@
 //! A polymorphic procedure of LIR code which can be applied to a list of arguments with type arguments.
 //! This is mono-morphed into a `Procedure` when it is called with a list of type arguments.
 //! A procedure is compiled down to a label in the assembly code.
-use crate::lir::{ConstExpr, Env, Error, Expr, GetType, Mutability, Type, TypeCheck};
+use crate::lir::{Annotation, ConstExpr, Env, Error, Expr, GetType, Mutability, Type, TypeCheck};
 use core::fmt;
 use log::{debug, error, trace};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     sync::{Arc, RwLock},
 };
 use std::{hash::Hash, hash::Hasher};
@@ -23,6 +23,8 @@ pub struct PolyProcedure {
     name: String,
     /// The type parameters of the procedure.
     ty_params: Vec<String>,
+    /// The traits which each type parameter must implement.
+    bounds: BTreeMap<String, Vec<String>>,
     /// The arguments of the procedure.
     args: Vec<(String, Mutability, Type)>,
     /// The return type of the procedure.
@@ -38,6 +40,7 @@ impl PartialEq for PolyProcedure {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
             && self.ty_params == other.ty_params
+            && self.bounds == other.bounds
             && self.args == other.args
             && self.ret == other.ret
             && self.body == other.body
@@ -57,6 +60,7 @@ impl PolyProcedure {
         Self {
             name,
             ty_params,
+            bounds: BTreeMap::new(),
             args,
             ret,
             body: Box::new(body.into()),
@@ -75,6 +79,7 @@ impl PolyProcedure {
         Self {
             name,
             ty_params,
+            bounds: BTreeMap::new(),
             args: mono.get_args().to_vec(),
             ret: mono.get_ret().clone(),
             body: mono.get_body().clone().into(),
@@ -83,6 +88,31 @@ impl PolyProcedure {
         }
     }
 
+    /// Require the type parameters of this procedure to implement some traits.
+    pub fn with_bounds(mut self, bounds: BTreeMap<String, Vec<String>>) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    /// Confirm that the type arguments supplied to this procedure implement
+    /// the traits which bound their type parameters.
+    pub fn check_bounds(&self, ty_args: &[Type], env: &Env) -> Result<(), Error> {
+        for (ty_param, ty_arg) in self.ty_params.iter().zip(ty_args) {
+            for trait_name in self.bounds.get(ty_param).into_iter().flatten() {
+                if env.get_trait(trait_name).is_none() {
+                    return Err(Error::TraitNotDefined(trait_name.clone()));
+                }
+                if !env.implements(ty_arg, trait_name)? {
+                    return Err(Error::UnsatisfiedTraitBound(
+                        ty_arg.clone(),
+                        trait_name.clone(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Get the name of this polymorphic procedure.
     /// This is not the mangled name, but the name known to the LIR front-end.
     /// The mangled name is unique for each monomorph of the procedure.
@@ -90,11 +120,33 @@ impl PolyProcedure {
         &self.name
     }
 
+    /// Get the arguments of the procedure.
+    pub fn get_args(&self) -> &[(String, Mutability, Type)] {
+        &self.args
+    }
+
+    /// Get the return type of the procedure.
+    pub fn get_ret(&self) -> &Type {
+        &self.ret
+    }
+
+    /// Get the body of the procedure.
+    pub fn get_body(&self) -> &Expr {
+        &self.body
+    }
+
+    /// Annotate the body of the procedure.
+    pub fn annotate_body(mut self, annotation: impl Into<Annotation>) -> Self {
+        self.body = Box::new(self.body.annotate(annotation));
+        self
+    }
+
     /// Take some type arguments and produce a monomorphized version of the procedure.
     /// This monomorphized version can then be compiled directly. Additionally, the
     /// mono version of the procedure is memoized, so that it is only compiled once.
     pub fn monomorphize(&self, ty_args: Vec<Type>, env: &Env) -> Result<Procedure, Error> {
         debug!(target: "mono", "Monomorphizing {} with {:?}", self, ty_args);
+        self.check_bounds(&ty_args, env)?;
 
         // This is a helper function to distribute the defined type
         // arguments over the body and arguments of the function.
@@ -222,6 +274,20 @@ impl TypeCheck for PolyProcedure {
                 .map(|ty_param| (ty_param.clone(), Type::Unit(ty_param, Box::new(Type::None))))
                 .collect(),
         );
+        // The methods of the bounding traits can be called on values of the type parameters.
+        for (ty_param, trait_names) in &self.bounds {
+            let ty = Type::Symbol(ty_param.clone());
+            for trait_name in trait_names {
+                let trait_ = env
+                    .get_trait(trait_name)
+                    .ok_or_else(|| Error::TraitNotDefined(trait_name.clone()))?
+                    .clone();
+                new_env.add_trait_impl(trait_name, ty.clone());
+                for (name, method) in trait_.get_bound_methods(&ty) {
+                    new_env.add_associated_const(ty.clone(), name, method)?;
+                }
+            }
+        }
         // Define the arguments of the procedure.
         new_env.define_args(self.args.clone())?;
         new_env.set_expected_return_type(self.ret.clone());
@@ -233,7 +299,11 @@ impl TypeCheck for PolyProcedure {
         self.ret.type_check(&new_env)?;
 
         // Get the type of the procedure's body, and confirm that it matches the return type.
-        let body_type = self.body.get_type(&new_env)?;
+        let body_type = match self.body.get_type(&new_env) {
+            Ok(body_type) => body_type,
+            // Check the body to find all of its errors, instead of only the first one.
+            Err(err) => return self.body.type_check(&new_env).and(Err(err)),
+        };
 
         if !body_type.can_decay_to(&self.ret, &new_env)? {
             error!(
@@ -258,6 +328,9 @@ impl fmt::Display for PolyProcedure {
         write!(f, "proc[")?;
         for (i, ty_param) in self.ty_params.iter().enumerate() {
             write!(f, "{}", ty_param)?;
+            if let Some(trait_names) = self.bounds.get(ty_param) {
+                write!(f, ": {}", trait_names.join(" + "))?;
+            }
             if i < self.ty_params.len() - 1 {
                 write!(f, ", ")?;
             }
@@ -282,6 +355,7 @@ impl Hash for PolyProcedure {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.name.hash(state);
         self.ty_params.hash(state);
+        self.bounds.hash(state);
         self.args.hash(state);
         self.ret.hash(state);
         self.body.hash(state);
@@ -67,17 +67,23 @@
 
 mod annotate;
 mod compile;
+mod debug_info;
 mod env;
 mod error;
 mod expr;
+mod optimize;
 mod types;
+mod warning;
 
 pub use annotate::*;
 pub use compile::*;
+pub use debug_info::*;
 pub use env::*;
 pub use error::*;
 pub use expr::*;
+pub use optimize::*;
 pub use types::*;
+pub use warning::*;
 
 /// Simplify an expression while maintaining structural equality.
 pub trait Simplify: Sized {
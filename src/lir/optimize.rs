@@ -0,0 +1,836 @@
+//! # Optimizer
+//!
+//! Optimization passes over LIR expressions, which run before they're compiled.
+//!
+//! ## Passes
+//!
+//! - Constant folding: unary and binary operations on literals are evaluated
+//!   ahead of time, when the result is exactly what the compiled operation computes.
+//! - Inlining: calls to small procedures which only compute a value from their
+//!   arguments are replaced with the procedures' bodies, when the arguments are
+//!   literals or variables.
+//! - Dead branch elimination: `if`, `when`, and `match` expressions with constant
+//!   conditions are replaced with the branch which is taken, loops which never run
+//!   are removed, and expressions annotated as dead code are removed from blocks.
+//! - Unused variable elimination: variables which are initialized with a constant
+//!   expression and never used are removed.
+//!
+//! The passes run together in a single bottom-up traversal, so the result of one
+//! pass is optimized by the others: an inlined call with literal arguments is folded,
+//! and a folded condition selects its branch.
+use super::*;
+use log::info;
+use std::{collections::HashMap, rc::Rc};
+
+/// The largest procedure body (counted in expressions) which is inlined.
+const MAX_INLINE_SIZE: usize = 16;
+
+/// The optimization passes to run over an LIR expression.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Optimizations {
+    /// Evaluate operations on literals ahead of time.
+    pub fold_constants: bool,
+    /// Replace calls to small procedures with their bodies.
+    pub inline_procedures: bool,
+    /// Remove branches which are never taken.
+    pub remove_dead_branches: bool,
+    /// Remove variables which are never used.
+    pub remove_unused_variables: bool,
+}
+
+impl Optimizations {
+    /// Don't optimize anything.
+    pub const NONE: Self = Self {
+        fold_constants: false,
+        inline_procedures: false,
+        remove_dead_branches: false,
+        remove_unused_variables: false,
+    };
+
+    /// Run every optimization pass.
+    pub const ALL: Self = Self {
+        fold_constants: true,
+        inline_procedures: true,
+        remove_dead_branches: true,
+        remove_unused_variables: true,
+    };
+}
+
+impl Expr {
+    /// Run the given optimization passes over the expression.
+    ///
+    /// The passes can remove code, so the expression is type checked first:
+    /// errors in code which is optimized away are still reported.
+    pub fn optimize(self, optimizations: Optimizations) -> Result<Self, Error> {
+        if optimizations == Optimizations::NONE {
+            return Ok(self);
+        }
+
+        info!("Type checking before optimizing...");
+        self.type_check(&root_env(false, None))?;
+        info!("Optimizing...");
+        let mut optimizer = Optimizer {
+            optimizations,
+            env: Env::default(),
+        };
+        Ok(optimizer.expr(self, &Scope::new()))
+    }
+}
+
+/// A procedure which can be inlined.
+struct Inlinable {
+    /// The parameters of the procedure.
+    params: Vec<(String, Mutability, Type)>,
+    /// The body of the procedure, without the `return` in tail position.
+    body: Expr,
+}
+
+/// The procedures in scope which can be inlined, by name.
+type Scope = HashMap<String, Rc<Inlinable>>;
+
+struct Optimizer {
+    optimizations: Optimizations,
+    /// An empty environment, used to get the types of literals.
+    env: Env,
+}
+
+impl Optimizer {
+    /// Optimize an expression, with the given procedures in scope.
+    fn expr(&mut self, expr: Expr, scope: &Scope) -> Expr {
+        let remove_dead_branches = self.optimizations.remove_dead_branches;
+        match expr {
+            Expr::Annotated(expr, annotation) => {
+                Expr::Annotated(Box::new(self.expr(*expr, scope)), annotation)
+            }
+            Expr::ConstExpr(_) | Expr::Closure(_) => expr,
+            Expr::Many(exprs) => {
+                let len = exprs.len();
+                Expr::Many(
+                    exprs
+                        .into_iter()
+                        .enumerate()
+                        // The last expression is the value of the block, so it's always kept.
+                        .filter(|(i, expr)| {
+                            !remove_dead_branches || i + 1 == len || !is_dead_code(expr)
+                        })
+                        .map(|(_, expr)| self.expr(expr, scope))
+                        .collect(),
+                )
+            }
+            Expr::Declare(decl, body) => self.declare(*decl, *body, scope),
+
+            Expr::While(cond, body) => {
+                let cond = self.expr(*cond, scope);
+                if remove_dead_branches && bool_literal(&cond) == Some(false) {
+                    return Expr::NONE;
+                }
+                Expr::While(Box::new(cond), Box::new(self.expr(*body, scope)))
+            }
+            Expr::If(cond, then, otherwise) => {
+                let cond = self.expr(*cond, scope);
+                let then = self.expr(*then, scope);
+                let otherwise = self.expr(*otherwise, scope);
+                // A branch which returns has the `Never` type, so it can't replace the `if`.
+                match bool_literal(&cond) {
+                    Some(true) if remove_dead_branches && !returns(&then) => then,
+                    Some(false) if remove_dead_branches && !returns(&otherwise) => otherwise,
+                    _ => Expr::If(Box::new(cond), Box::new(then), Box::new(otherwise)),
+                }
+            }
+            Expr::When(cond, then, otherwise) => match const_literal(&cond) {
+                Some(ConstExpr::Bool(true)) if remove_dead_branches => self.expr(*then, scope),
+                Some(ConstExpr::Bool(false)) if remove_dead_branches => {
+                    self.expr(*otherwise, scope)
+                }
+                _ => Expr::When(
+                    cond,
+                    Box::new(self.expr(*then, scope)),
+                    Box::new(self.expr(*otherwise, scope)),
+                ),
+            },
+            Expr::Match(expr, branches) => {
+                let expr = self.expr(*expr, scope);
+                let mut branches = branches
+                    .into_iter()
+                    .map(|(pattern, branch)| {
+                        let mut names = vec![];
+                        pattern_bindings(&pattern, &mut names);
+                        let branch = self.expr(branch, &without(scope, &names));
+                        (pattern, branch)
+                    })
+                    .collect::<Vec<_>>();
+                match matching_branch(&expr, &branches) {
+                    Some(i) if remove_dead_branches && !returns(&branches[i].1) => {
+                        match branches.swap_remove(i) {
+                            (Pattern::Symbol(mutability, name), branch) => Expr::Declare(
+                                Box::new(Declaration::Var(name, mutability, None, expr)),
+                                Box::new(branch),
+                            ),
+                            (_, branch) => branch,
+                        }
+                    }
+                    _ => Expr::Match(Box::new(expr), branches),
+                }
+            }
+            Expr::IfLet(pattern, expr, then, otherwise) => {
+                let mut names = vec![];
+                pattern_bindings(&pattern, &mut names);
+                let then = self.expr(*then, &without(scope, &names));
+                Expr::IfLet(
+                    pattern,
+                    Box::new(self.expr(*expr, scope)),
+                    Box::new(then),
+                    Box::new(self.expr(*otherwise, scope)),
+                )
+            }
+
+            Expr::UnaryOp(op, expr) => {
+                let expr = self.expr(*expr, scope);
+                self.fold(Expr::UnaryOp(op, Box::new(expr)))
+            }
+            Expr::BinaryOp(op, lhs, rhs) => {
+                let lhs = self.expr(*lhs, scope);
+                let rhs = self.expr(*rhs, scope);
+                self.fold(Expr::BinaryOp(op, Box::new(lhs), Box::new(rhs)))
+            }
+            Expr::TernaryOp(op, a, b, c) => Expr::TernaryOp(
+                op,
+                Box::new(self.expr(*a, scope)),
+                Box::new(self.expr(*b, scope)),
+                Box::new(self.expr(*c, scope)),
+            ),
+            Expr::AssignOp(op, dst, src) => Expr::AssignOp(
+                op,
+                Box::new(self.expr(*dst, scope)),
+                Box::new(self.expr(*src, scope)),
+            ),
+
+            Expr::Refer(mutability, expr) => {
+                Expr::Refer(mutability, Box::new(self.expr(*expr, scope)))
+            }
+            Expr::Deref(expr) => Expr::Deref(Box::new(self.expr(*expr, scope))),
+            Expr::DerefMut(ptr, val) => Expr::DerefMut(
+                Box::new(self.expr(*ptr, scope)),
+                Box::new(self.expr(*val, scope)),
+            ),
+
+            Expr::Apply(fun, args) => {
+                let fun = self.expr(*fun, scope);
+                let args = self.exprs(args, scope);
+                let inlined = match symbol(&fun).and_then(|name| scope.get(name)).cloned() {
+                    Some(proc) if proc.params.len() == args.len() => {
+                        self.inline(&proc, &args, scope)
+                    }
+                    _ => None,
+                };
+                inlined.unwrap_or_else(|| Expr::Apply(Box::new(fun), args))
+            }
+            Expr::Return(expr) => Expr::Return(Box::new(self.expr(*expr, scope))),
+
+            Expr::Array(exprs) => Expr::Array(self.exprs(exprs, scope)),
+            Expr::Tuple(exprs) => Expr::Tuple(self.exprs(exprs, scope)),
+            Expr::Union(ty, variant, expr) => {
+                Expr::Union(ty, variant, Box::new(self.expr(*expr, scope)))
+            }
+            Expr::EnumUnion(ty, variant, expr) => {
+                Expr::EnumUnion(ty, variant, Box::new(self.expr(*expr, scope)))
+            }
+            Expr::Struct(fields) => Expr::Struct(
+                fields
+                    .into_iter()
+                    .map(|(name, expr)| (name, self.expr(expr, scope)))
+                    .collect(),
+            ),
+            Expr::As(expr, ty) => Expr::As(Box::new(self.expr(*expr, scope)), ty),
+            Expr::Member(expr, member) => Expr::Member(Box::new(self.expr(*expr, scope)), member),
+            Expr::Index(expr, index) => Expr::Index(
+                Box::new(self.expr(*expr, scope)),
+                Box::new(self.expr(*index, scope)),
+            ),
+        }
+    }
+
+    /// Optimize a list of expressions.
+    fn exprs(&mut self, exprs: Vec<Expr>, scope: &Scope) -> Vec<Expr> {
+        exprs
+            .into_iter()
+            .map(|expr| self.expr(expr, scope))
+            .collect()
+    }
+
+    /// Optimize a declaration, and the expression in its scope.
+    fn declare(&mut self, decl: Declaration, body: Expr, scope: &Scope) -> Expr {
+        let mut bindings = vec![];
+        declaration_bindings(&decl, &mut bindings);
+        let names = bindings.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        let mut scope = without(scope, &names);
+        if self.optimizations.inline_procedures {
+            for (name, proc) in &bindings {
+                // Names bound more than once in the same declaration are ambiguous.
+                let unique = names.iter().filter(|other| *other == name).count() == 1;
+                if let (Some(inlinable), true) = (proc.and_then(inlinable), unique) {
+                    scope.insert(name.to_string(), Rc::new(inlinable));
+                }
+            }
+        }
+
+        let decl = self.declaration(decl, &scope);
+        let body = self.expr(body, &scope);
+        if self.optimizations.remove_unused_variables {
+            remove_unused_variables(decl, body)
+        } else {
+            Expr::Declare(Box::new(decl), Box::new(body))
+        }
+    }
+
+    /// Optimize the expressions in a declaration.
+    fn declaration(&mut self, decl: Declaration, scope: &Scope) -> Declaration {
+        match decl {
+            Declaration::Var(name, mutability, ty, expr) => {
+                Declaration::Var(name, mutability, ty, self.expr(expr, scope))
+            }
+            Declaration::VarPat(pattern, expr) => {
+                Declaration::VarPat(pattern, self.expr(expr, scope))
+            }
+            Declaration::Proc(name, proc) => {
+                let params = proc.get_args().iter().map(|(name, ..)| name.as_str());
+                let body = self.expr(
+                    proc.get_body().clone(),
+                    &without(scope, &params.collect::<Vec<_>>()),
+                );
+                Declaration::Proc(
+                    name,
+                    Procedure::new(
+                        proc.get_common_name().map(str::to_owned),
+                        proc.get_args().to_vec(),
+                        proc.get_ret().clone(),
+                        body,
+                    ),
+                )
+            }
+            Declaration::Many(decls) => Declaration::Many(
+                decls
+                    .into_iter()
+                    .map(|decl| self.declaration(decl, scope))
+                    .collect(),
+            ),
+            decl => decl,
+        }
+    }
+
+    /// Replace a unary or binary operation on literals with its result.
+    fn fold(&self, expr: Expr) -> Expr {
+        if !self.optimizations.fold_constants {
+            return expr;
+        }
+
+        let folded = match &expr {
+            Expr::UnaryOp(op, x) => literal(x).and_then(|x| op.fold(x)),
+            Expr::BinaryOp(op, lhs, rhs) => literal(lhs)
+                .zip(literal(rhs))
+                .and_then(|(lhs, rhs)| op.fold(lhs, rhs)),
+            _ => None,
+        };
+        // The result must have the same type as the operation, so the program still type checks.
+        match (folded, expr.get_type(&self.env)) {
+            (Some(result), Ok(ty)) if self.has_type(&result, &ty) => Expr::ConstExpr(result),
+            _ => expr,
+        }
+    }
+
+    /// Does the literal have the given type?
+    fn has_type(&self, literal: &ConstExpr, ty: &Type) -> bool {
+        literal
+            .get_type(&self.env)
+            .and_then(|found| found.equals(ty, &self.env))
+            .unwrap_or(false)
+    }
+
+    /// Replace a call to a procedure with the procedure's body, with the arguments
+    /// substituted for the parameters. This only succeeds if every argument is a
+    /// literal or a variable, because the compiler doesn't support declaring variables
+    /// for the other arguments in the middle of an expression. The body can't assign
+    /// to variables, so variables can be substituted without changing their values.
+    fn inline(&mut self, proc: &Inlinable, args: &[Expr], scope: &Scope) -> Option<Expr> {
+        let mut substitutions = HashMap::new();
+        for ((name, _, ty), arg) in proc.params.iter().zip(args) {
+            let value = match (literal(arg), symbol(arg)) {
+                (Some(value), _) if self.has_type(value, ty) => Expr::ConstExpr(value.clone()),
+                // The cast keeps the type of the parameter, in case the variable's type only decays to it.
+                (None, Some(var)) => {
+                    Expr::ConstExpr(ConstExpr::Symbol(var.to_string())).as_type(ty.clone())
+                }
+                _ => return None,
+            };
+            substitutions.insert(name.as_str(), value);
+        }
+        Some(self.expr(substitute(proc.body.clone(), &substitutions), scope))
+    }
+}
+
+/// Remove the variables in a declaration which are initialized with a constant
+/// expression, and are never used.
+fn remove_unused_variables(decl: Declaration, body: Expr) -> Expr {
+    let (decls, many) = match decl {
+        Declaration::Var(..) => (vec![decl], false),
+        Declaration::Many(decls) => (decls, true),
+        decl => return Expr::Declare(Box::new(decl), Box::new(body)),
+    };
+
+    // Go backwards, so variables which are only used by removed variables are removed too.
+    let mut kept: Vec<Declaration> = vec![];
+    for decl in decls.into_iter().rev() {
+        let unused = match &decl {
+            Declaration::Var(name, _, _, expr) => {
+                is_constant(expr)
+                    && !mentions(&body, name)
+                    && !kept.iter().any(|decl| mentions_decl(decl, name))
+            }
+            _ => false,
+        };
+        if !unused {
+            kept.push(decl);
+        }
+    }
+    kept.reverse();
+
+    match (kept.len(), many) {
+        (0, _) => body,
+        (_, true) => Expr::Declare(Box::new(Declaration::Many(kept)), Box::new(body)),
+        (_, false) => Expr::Declare(Box::new(kept.remove(0)), Box::new(body)),
+    }
+}
+
+/// Get the procedure which can be inlined, if this one can.
+///
+/// A procedure can be inlined if it only computes a value from its arguments:
+/// its body only uses literals, its parameters, operations, and conditionals.
+/// Those bodies can't call other procedures, so inlined procedures are never recursive.
+/// The parameters and return value must be primitive types, because names of types
+/// could refer to other types where the procedure is called.
+fn inlinable(proc: &Procedure) -> Option<Inlinable> {
+    let params = proc.get_args();
+    let is_primitive = |ty: &Type| {
+        matches!(
+            ty,
+            Type::Int | Type::FixedInt(_) | Type::Float | Type::Bool | Type::Char
+        )
+    };
+    let mut size = MAX_INLINE_SIZE;
+    let inlinable = (is_primitive(proc.get_ret()) || *proc.get_ret() == Type::None)
+        && params.iter().all(|(_, _, ty)| is_primitive(ty))
+        && is_simple(proc.get_body(), params, &mut size, true);
+
+    inlinable.then(|| Inlinable {
+        params: params.to_vec(),
+        body: without_tail_return(proc.get_body().clone()),
+    })
+}
+
+/// Is the expression simple enough to inline, and no bigger than the given size?
+/// Only expressions in tail position can `return`.
+fn is_simple(
+    expr: &Expr,
+    params: &[(String, Mutability, Type)],
+    size: &mut usize,
+    tail: bool,
+) -> bool {
+    if *size == 0 {
+        return false;
+    }
+    *size -= 1;
+
+    match expr {
+        Expr::Annotated(expr, _) => is_simple(expr, params, size, tail),
+        Expr::ConstExpr(c) => {
+            const_literal(c).is_some()
+                || matches!(const_symbol(c), Some(name) if params.iter().any(|(param, ..)| param == name))
+        }
+        Expr::Return(expr) => tail && is_simple(expr, params, size, false),
+        Expr::UnaryOp(_, expr) => is_simple(expr, params, size, false),
+        Expr::BinaryOp(_, lhs, rhs) => {
+            is_simple(lhs, params, size, false) && is_simple(rhs, params, size, false)
+        }
+        Expr::If(cond, then, otherwise) => {
+            is_simple(cond, params, size, false)
+                && is_simple(then, params, size, tail)
+                && is_simple(otherwise, params, size, tail)
+        }
+        Expr::Many(exprs) => exprs
+            .iter()
+            .enumerate()
+            .all(|(i, expr)| is_simple(expr, params, size, tail && i + 1 == exprs.len())),
+        _ => false,
+    }
+}
+
+/// Remove the `return`s in tail position of a procedure's body.
+fn without_tail_return(expr: Expr) -> Expr {
+    match expr {
+        Expr::Annotated(expr, annotation) => {
+            Expr::Annotated(Box::new(without_tail_return(*expr)), annotation)
+        }
+        Expr::Return(expr) => *expr,
+        Expr::If(cond, then, otherwise) => Expr::If(
+            cond,
+            Box::new(without_tail_return(*then)),
+            Box::new(without_tail_return(*otherwise)),
+        ),
+        Expr::Many(mut exprs) => {
+            if let Some(last) = exprs.pop() {
+                exprs.push(without_tail_return(last));
+            }
+            Expr::Many(exprs)
+        }
+        expr => expr,
+    }
+}
+
+/// Substitute the symbols in a simple expression with the given values.
+fn substitute(expr: Expr, substitutions: &HashMap<&str, Expr>) -> Expr {
+    let substitute = |expr: Box<Expr>| Box::new(substitute(*expr, substitutions));
+    match expr {
+        Expr::Annotated(expr, annotation) => Expr::Annotated(substitute(expr), annotation),
+        Expr::ConstExpr(c) => match const_symbol(&c).and_then(|name| substitutions.get(name)) {
+            Some(value) => value.clone(),
+            None => Expr::ConstExpr(c),
+        },
+        Expr::UnaryOp(op, expr) => Expr::UnaryOp(op, substitute(expr)),
+        Expr::BinaryOp(op, lhs, rhs) => Expr::BinaryOp(op, substitute(lhs), substitute(rhs)),
+        Expr::If(cond, then, otherwise) => {
+            Expr::If(substitute(cond), substitute(then), substitute(otherwise))
+        }
+        Expr::Many(exprs) => Expr::Many(
+            exprs
+                .into_iter()
+                .map(|expr| self::substitute(expr, substitutions))
+                .collect(),
+        ),
+        expr => expr,
+    }
+}
+
+/// Get the branch of a `match` expression which is taken, if the matched
+/// expression is a literal and the branch can be determined.
+fn matching_branch(expr: &Expr, branches: &[(Pattern, Expr)]) -> Option<usize> {
+    let value = literal(expr).filter(|value| {
+        matches!(
+            value,
+            ConstExpr::Int(_) | ConstExpr::Bool(_) | ConstExpr::Char(_)
+        )
+    })?;
+    for (i, (pattern, _)) in branches.iter().enumerate() {
+        match pattern {
+            Pattern::Wildcard | Pattern::Symbol(..) => return Some(i),
+            Pattern::ConstExpr(c) => match const_literal(c) {
+                Some(c) if c == value => return Some(i),
+                Some(_) => continue,
+                None => return None,
+            },
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Get the variables and constants bound by a declaration,
+/// along with the procedures bound to them.
+fn declaration_bindings<'a>(
+    decl: &'a Declaration,
+    bindings: &mut Vec<(&'a str, Option<&'a Procedure>)>,
+) {
+    match decl {
+        Declaration::StaticVar(name, ..)
+        | Declaration::Var(name, ..)
+        | Declaration::PolyProc(name, _)
+        | Declaration::Const(name, _)
+        | Declaration::ExternProc(name, _) => bindings.push((name, None)),
+        Declaration::Proc(name, proc) => bindings.push((name, Some(proc))),
+        Declaration::VarPat(pattern, _) => {
+            let mut names = vec![];
+            pattern_bindings(pattern, &mut names);
+            bindings.extend(names.into_iter().map(|name| (name, None)));
+        }
+        Declaration::Many(decls) => {
+            for decl in decls {
+                declaration_bindings(decl, bindings);
+            }
+        }
+        Declaration::Type(..)
+        | Declaration::Impl(..)
+        | Declaration::Trait(..)
+        | Declaration::ImplTrait(..) => {}
+    }
+}
+
+/// Get the variables bound by a pattern.
+pub(super) fn pattern_bindings<'a>(pattern: &'a Pattern, names: &mut Vec<&'a str>) {
+    match pattern {
+        Pattern::Symbol(_, name) => names.push(name),
+        Pattern::Tuple(patterns) | Pattern::Alt(patterns) => {
+            for pattern in patterns {
+                pattern_bindings(pattern, names);
+            }
+        }
+        Pattern::Struct(fields) => {
+            for pattern in fields.values() {
+                pattern_bindings(pattern, names);
+            }
+        }
+        Pattern::Variant(_, Some(pattern)) | Pattern::Pointer(pattern) => {
+            pattern_bindings(pattern, names)
+        }
+        Pattern::Variant(_, None) | Pattern::ConstExpr(_) | Pattern::Wildcard => {}
+    }
+}
+
+/// Get a scope without the procedures shadowed by the given names.
+fn without(scope: &Scope, names: &[&str]) -> Scope {
+    let mut scope = scope.clone();
+    for name in names {
+        scope.remove(*name);
+    }
+    scope
+}
+
+/// Get the literal value of an expression, if it's a literal.
+fn literal(expr: &Expr) -> Option<&ConstExpr> {
+    match expr {
+        Expr::Annotated(expr, _) => literal(expr),
+        Expr::ConstExpr(c) => const_literal(c),
+        _ => None,
+    }
+}
+
+/// Get the literal value of a constant expression, if it's a literal.
+fn const_literal(c: &ConstExpr) -> Option<&ConstExpr> {
+    match c {
+        ConstExpr::Annotated(c, _) => const_literal(c),
+        ConstExpr::Int(_)
+        | ConstExpr::Float(_)
+        | ConstExpr::Bool(_)
+        | ConstExpr::Char(_)
+        | ConstExpr::Cell(_) => Some(c),
+        _ => None,
+    }
+}
+
+/// Get the value of a boolean literal.
+fn bool_literal(expr: &Expr) -> Option<bool> {
+    match literal(expr) {
+        Some(ConstExpr::Bool(b)) => Some(*b),
+        _ => None,
+    }
+}
+
+/// Get the name of a symbol expression.
+pub(super) fn symbol(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Annotated(expr, _) => symbol(expr),
+        Expr::ConstExpr(c) => const_symbol(c),
+        _ => None,
+    }
+}
+
+/// Get the name of a symbol constant expression.
+fn const_symbol(c: &ConstExpr) -> Option<&str> {
+    match c {
+        ConstExpr::Annotated(c, _) => const_symbol(c),
+        ConstExpr::Symbol(name) => Some(name),
+        _ => None,
+    }
+}
+
+/// Is this a constant expression, which has no effects when it's evaluated?
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Annotated(expr, _) => is_constant(expr),
+        Expr::ConstExpr(_) => true,
+        _ => false,
+    }
+}
+
+/// Is this expression annotated as dead code?
+fn is_dead_code(expr: &Expr) -> bool {
+    matches!(expr, Expr::Annotated(_, annotation) if annotation.is_dead_code())
+}
+
+/// Get the subexpressions of an expression, including the values of the variables it declares.
+pub(super) fn subexpressions(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Annotated(expr, _)
+        | Expr::UnaryOp(_, expr)
+        | Expr::Refer(_, expr)
+        | Expr::Deref(expr)
+        | Expr::Return(expr)
+        | Expr::Union(_, _, expr)
+        | Expr::EnumUnion(_, _, expr)
+        | Expr::As(expr, _)
+        | Expr::Member(expr, _) => vec![expr],
+        Expr::ConstExpr(_) | Expr::Closure(_) => vec![],
+        Expr::Many(exprs) | Expr::Array(exprs) | Expr::Tuple(exprs) => exprs.iter().collect(),
+        Expr::Declare(decl, body) => {
+            let mut exprs = vec![];
+            declared_values(decl, &mut exprs);
+            exprs.push(body);
+            exprs
+        }
+        Expr::While(a, b)
+        | Expr::BinaryOp(_, a, b)
+        | Expr::AssignOp(_, a, b)
+        | Expr::DerefMut(a, b)
+        | Expr::Index(a, b)
+        | Expr::When(_, a, b) => vec![a, b],
+        Expr::If(a, b, c) | Expr::TernaryOp(_, a, b, c) | Expr::IfLet(_, a, b, c) => {
+            vec![a, b, c]
+        }
+        Expr::Match(expr, branches) => std::iter::once(&**expr)
+            .chain(branches.iter().map(|(_, branch)| branch))
+            .collect(),
+        Expr::Apply(fun, args) => std::iter::once(&**fun).chain(args).collect(),
+        Expr::Struct(fields) => fields.values().collect(),
+    }
+}
+
+/// Get the values of the variables declared by a declaration.
+fn declared_values<'a>(decl: &'a Declaration, exprs: &mut Vec<&'a Expr>) {
+    match decl {
+        Declaration::Var(_, _, _, expr) | Declaration::VarPat(_, expr) => exprs.push(expr),
+        Declaration::Many(decls) => {
+            for decl in decls {
+                declared_values(decl, exprs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Could this expression return from the procedure it's in?
+fn returns(expr: &Expr) -> bool {
+    matches!(expr, Expr::Return(_)) || subexpressions(expr).into_iter().any(returns)
+}
+
+/// Could this expression refer to the given name? Shadowing is ignored,
+/// so this errs on the side of finding uses.
+pub(super) fn mentions(expr: &Expr, name: &str) -> bool {
+    let found = match expr {
+        // The values of the variables are checked with the declaration.
+        Expr::Declare(decl, body) => return mentions_decl(decl, name) || mentions(body, name),
+        Expr::ConstExpr(c) | Expr::When(c, ..) | Expr::Member(_, c) => mentions_const(c, name),
+        Expr::Match(_, branches) => branches
+            .iter()
+            .any(|(pattern, _)| mentions_pattern(pattern, name)),
+        Expr::IfLet(pattern, ..) => mentions_pattern(pattern, name),
+        Expr::Union(ty, ..) | Expr::EnumUnion(ty, ..) | Expr::As(_, ty) => mentions_type(ty, name),
+        Expr::Closure(closure) => closure
+            .get_captures()
+            .iter()
+            .any(|(captured, _)| captured == name),
+        _ => false,
+    };
+    found
+        || subexpressions(expr)
+            .into_iter()
+            .any(|expr| mentions(expr, name))
+}
+
+/// Could this declaration refer to the given name?
+pub(super) fn mentions_decl(decl: &Declaration, name: &str) -> bool {
+    match decl {
+        Declaration::StaticVar(_, _, ty, c) => mentions_type(ty, name) || mentions_const(c, name),
+        Declaration::Var(_, _, ty, expr) => {
+            ty.as_ref().is_some_and(|ty| mentions_type(ty, name)) || mentions(expr, name)
+        }
+        Declaration::Type(_, ty) => mentions_type(ty, name),
+        Declaration::Const(_, c) => mentions_const(c, name),
+        Declaration::VarPat(pattern, expr) => {
+            mentions_pattern(pattern, name) || mentions(expr, name)
+        }
+        Declaration::Impl(ty, items) | Declaration::ImplTrait(_, ty, items) => {
+            mentions_type(ty, name) || items.iter().any(|(_, c)| mentions_const(c, name))
+        }
+        Declaration::Many(decls) => decls.iter().any(|decl| mentions_decl(decl, name)),
+        // Procedures can't refer to the variables of the scope they're declared in.
+        Declaration::Proc(..)
+        | Declaration::PolyProc(..)
+        | Declaration::ExternProc(..)
+        | Declaration::Trait(..) => false,
+    }
+}
+
+/// Could this constant expression refer to the given name?
+fn mentions_const(c: &ConstExpr, name: &str) -> bool {
+    match c {
+        ConstExpr::Symbol(symbol) => symbol == name,
+        ConstExpr::Annotated(c, _) | ConstExpr::Template(_, c) => mentions_const(c, name),
+        ConstExpr::Declare(decl, c) => mentions_decl(decl, name) || mentions_const(c, name),
+        ConstExpr::TypeOf(expr) | ConstExpr::SizeOfExpr(expr) => mentions(expr, name),
+        ConstExpr::Of(ty, _) | ConstExpr::SizeOfType(ty) | ConstExpr::Type(ty) => {
+            mentions_type(ty, name)
+        }
+        ConstExpr::Tuple(items) | ConstExpr::Array(items) => {
+            items.iter().any(|c| mentions_const(c, name))
+        }
+        ConstExpr::Struct(fields) => fields.values().any(|c| mentions_const(c, name)),
+        ConstExpr::Union(ty, _, c) | ConstExpr::EnumUnion(ty, _, c) | ConstExpr::As(c, ty) => {
+            mentions_type(ty, name) || mentions_const(c, name)
+        }
+        ConstExpr::Monomorphize(c, ty_args) => {
+            mentions_const(c, name) || ty_args.iter().any(|ty| mentions_type(ty, name))
+        }
+        ConstExpr::Member(c, member) => mentions_const(c, name) || mentions_const(member, name),
+        // Procedures can't refer to the variables of the scope they're declared in.
+        ConstExpr::Proc(_)
+        | ConstExpr::PolyProc(_)
+        | ConstExpr::FFIProcedure(_)
+        | ConstExpr::CoreBuiltin(_)
+        | ConstExpr::StandardBuiltin(_) => false,
+        ConstExpr::None
+        | ConstExpr::Null
+        | ConstExpr::Int(_)
+        | ConstExpr::Cell(_)
+        | ConstExpr::Float(_)
+        | ConstExpr::Char(_)
+        | ConstExpr::Bool(_) => false,
+    }
+}
+
+/// Could this type refer to the given name? Types refer to variables
+/// through the constant expressions for the sizes of arrays.
+fn mentions_type(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Array(elem, size) => mentions_type(elem, name) || mentions_const(size, name),
+        Type::Let(_, a, b) => mentions_type(a, name) || mentions_type(b, name),
+        Type::Unit(_, ty) | Type::Pointer(_, ty) | Type::Type(ty) | Type::Poly(_, ty) => {
+            mentions_type(ty, name)
+        }
+        Type::Tuple(items) => items.iter().any(|ty| mentions_type(ty, name)),
+        Type::Struct(fields) | Type::Union(fields) | Type::EnumUnion(fields) => {
+            fields.values().any(|ty| mentions_type(ty, name))
+        }
+        Type::Proc(args, ret) | Type::Closure(args, ret) => {
+            args.iter().any(|ty| mentions_type(ty, name)) || mentions_type(ret, name)
+        }
+        Type::Apply(ty, args) => {
+            mentions_type(ty, name) || args.iter().any(|ty| mentions_type(ty, name))
+        }
+        _ => false,
+    }
+}
+
+/// Could this pattern refer to the given name?
+fn mentions_pattern(pattern: &Pattern, name: &str) -> bool {
+    match pattern {
+        Pattern::ConstExpr(c) => mentions_const(c, name),
+        Pattern::Tuple(patterns) | Pattern::Alt(patterns) => patterns
+            .iter()
+            .any(|pattern| mentions_pattern(pattern, name)),
+        Pattern::Struct(fields) => fields
+            .values()
+            .any(|pattern| mentions_pattern(pattern, name)),
+        Pattern::Variant(_, Some(pattern)) | Pattern::Pointer(pattern) => {
+            mentions_pattern(pattern, name)
+        }
+        Pattern::Variant(_, None) | Pattern::Symbol(..) | Pattern::Wildcard => false,
+    }
+}
@@ -35,6 +35,8 @@ impl TypeCheck for Type {
             | Self::None
             | Self::Cell
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Bool
             | Self::Char
@@ -107,7 +109,7 @@ impl TypeCheck for Type {
                 Ok(())
             }
 
-            Self::Proc(args, ret) => {
+            Self::Proc(args, ret) | Self::Closure(args, ret) => {
                 // Check each argument type.
                 /*
                 for t in args {
@@ -191,6 +193,13 @@ impl TypeCheck for Type {
 
             // Pointers are sound if their inner type is sound.
             Self::Pointer(_, t) => t.type_check(env),
+
+            // Trait objects are sound if their trait is defined and can be used as an object.
+            Self::TraitObject(name) => match env.get_trait(name) {
+                Some(t) if t.is_object_safe() => Ok(()),
+                Some(_) => Err(Error::TraitNotObjectSafe(name.clone())),
+                None => Err(Error::TraitNotDefined(name.clone())),
+            },
         }
     }
 }
@@ -199,9 +208,28 @@ impl TypeCheck for Type {
 impl TypeCheck for Expr {
     fn type_check(&self, env: &Env) -> Result<(), Error> {
         trace!("Type checking expression: {self}");
-        let ty = self.get_type(env)?;
+        let ty = match self.get_type(env) {
+            Ok(ty) => ty,
+            // The parts of a block, declaration or match are checked on their own,
+            // so that all of their errors are found instead of only the first one.
+            Err(err)
+                if matches!(
+                    self,
+                    Self::Annotated(..) | Self::Declare(..) | Self::Many(_) | Self::Match(..)
+                ) =>
+            {
+                return self.type_check_parts(env).and(Err(err));
+            }
+            Err(err) => return Err(err),
+        };
         ty.type_check(env)?;
+        self.type_check_parts(env)
+    }
+}
 
+impl Expr {
+    /// Type check the subexpressions and declarations of an expression.
+    fn type_check_parts(&self, env: &Env) -> Result<(), Error> {
         match self {
             Self::Annotated(expr, metadata) => {
                 // Check the inner expression.
@@ -213,11 +241,14 @@ impl TypeCheck for Expr {
                 // Create a new environment with the declarations defined.
                 let mut new_env = env.clone();
                 // Check the declaration.
-                declaration.type_check(&new_env)?;
+                let checked = declaration.type_check(&new_env);
                 // Add the declarations to the environment.
-                new_env.add_declaration(declaration)?;
-                // Check the body with the declarations defined.
-                body.type_check(&new_env)
+                if let Err(err) = new_env.add_declaration(declaration) {
+                    return checked.and(Err(err));
+                }
+                // Check the body with the declarations defined,
+                // even if the declarations themselves have errors.
+                Error::all([checked, body.type_check(&new_env)])
             }
 
             Self::UnaryOp(unop, expr) => {
@@ -292,38 +323,43 @@ impl TypeCheck for Expr {
 
                 let mut result_ty: Option<Type> = None;
 
-                // Check each branch.
+                // Check each branch on its own, so that the errors in every branch are found.
+                let mut checked = vec![];
                 for (pat, branch) in branches {
-                    // Create a new environment with the bindings defined.
-                    let mut new_env = env.clone();
-                    // Get the bindings from the pattern.
-                    let bindings = pat.get_bindings(expr, &ty, env)?;
-                    // Define the bindings in the environment.
-                    for (name, (mutability, ty)) in bindings {
-                        new_env.define_var(name, mutability, ty)?;
-                    }
-                    // Check the branch under the new environment.
-                    pat.type_check(expr, branch, env)?;
-
-                    // Check that the branch has the same type as the others.
-                    // Get the type of the branch.
-                    let branch_ty = branch.get_type(&new_env)?;
-                    // If we haven't found a type yet, set it.
-                    if let Some(result_ty) = &mut result_ty {
-                        // Check that the branch type matches the result type.
-                        if !branch_ty.can_decay_to(result_ty, &new_env)? {
-                            // If it doesn't, return an error.
-                            return Err(Error::MismatchedTypes {
-                                found: branch_ty,
-                                expected: result_ty.clone(),
-                                expr: branch.clone(),
-                            });
+                    checked.push((|| {
+                        // Create a new environment with the bindings defined.
+                        let mut new_env = env.clone();
+                        // Get the bindings from the pattern.
+                        let bindings = pat.get_bindings(expr, &ty, env)?;
+                        // Define the bindings in the environment.
+                        for (name, (mutability, ty)) in bindings {
+                            new_env.define_var(name, mutability, ty)?;
                         }
-                    } else {
-                        // Set the result type.
-                        result_ty = Some(branch_ty);
-                    }
+                        // Check the branch under the new environment.
+                        pat.type_check(expr, branch, env)?;
+
+                        // Check that the branch has the same type as the others.
+                        // Get the type of the branch.
+                        let branch_ty = branch.get_type(&new_env)?;
+                        // If we haven't found a type yet, set it.
+                        if let Some(result_ty) = &mut result_ty {
+                            // Check that the branch type matches the result type.
+                            if !branch_ty.can_decay_to(result_ty, &new_env)? {
+                                // If it doesn't, return an error.
+                                return Err(Error::MismatchedTypes {
+                                    found: branch_ty,
+                                    expected: result_ty.clone(),
+                                    expr: branch.clone(),
+                                });
+                            }
+                        } else {
+                            // Set the result type.
+                            result_ty = Some(branch_ty);
+                        }
+                        Ok(())
+                    })());
                 }
+                Error::all(checked)?;
 
                 // Now collect patterns into a list and check if they're exhaustive with Pattern::are_patterns_exhaustive.
                 let patterns = branches
@@ -387,10 +423,11 @@ impl TypeCheck for Expr {
                 }
                 */
 
+                // Every expression is checked, so that all of their errors are found.
                 let count = exprs.len();
-                exprs.into_par_iter()
+                let checked = exprs.into_par_iter()
                     .enumerate()
-                    .try_for_each(|(i, expr)| {
+                    .map(|(i, expr)| {
                         expr.type_check(env)?;
                         if i < count - 1 {
                             // If it's not the last expression, confirm that it's of type `None`.
@@ -403,10 +440,11 @@ impl TypeCheck for Expr {
                             }
                         }
                         Ok(())
-                    })?;
+                    })
+                    .collect::<Vec<_>>();
 
                 // Return success if all the expressions are sound.
-                Ok(())
+                Error::all(checked)
             }
 
             Self::While(cond, body) => {
@@ -595,6 +633,11 @@ impl TypeCheck for Expr {
 
             // Typecheck a function application.
             Self::Apply(f, args) => {
+                if self.is_closure_call(env)? {
+                    // Typecheck the application of the closure's procedure.
+                    return self.transform_closure_call().type_check(env);
+                }
+
                 if self.is_method_call(env)? {
                     // Get the type of the object we're calling the method on.
                     let method_call = self.transform_method_call(env)?;
@@ -828,8 +871,37 @@ impl TypeCheck for Expr {
                 // Get the actual type of the expression.
                 let found_ty = e.get_type(env)?;
 
+                // Casting a pointer to a trait object creates the object with its vtable.
+                if let Type::TraitObject(name) = desired_ty {
+                    if let Type::Pointer(_, _) = found_ty.simplify_until_concrete(env)? {
+                        let trait_ = env
+                            .get_trait(name)
+                            .ok_or_else(|| Error::TraitNotDefined(name.clone()))?;
+                        return trait_.to_object(e, env)?.type_check(env);
+                    }
+                }
+
                 // Check that the cast is valid.
                 if found_ty.can_cast_to(desired_ty, env)? {
+                    // Constant integers must be in the range of a fixed-width type.
+                    // A negative literal is parsed as a negation, so fold it first.
+                    let unannotated = |mut value: &Expr| {
+                        while let Expr::Annotated(inner, _) = value {
+                            value = inner;
+                        }
+                        value.clone()
+                    };
+                    let constant = match unannotated(e) {
+                        Expr::ConstExpr(n) => Some(n),
+                        Expr::UnaryOp(op, x) => match unannotated(&x) {
+                            Expr::ConstExpr(n) => op.fold(&n),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let (Some(n), Type::FixedInt(_)) = (constant, desired_ty) {
+                        ConstExpr::As(Box::new(n), desired_ty.clone()).eval(env)?;
+                    }
                     // If it is, return success.
                     Ok(())
                 } else {
@@ -880,7 +952,7 @@ impl TypeCheck for Expr {
                 }
 
                 // Confirm that the index is an integer.
-                if let Type::Int = idx_type {
+                if let Type::Int | Type::FixedInt(_) = idx_type {
                     // If it is, return success.
                     Ok(())
                 } else {
@@ -888,6 +960,9 @@ impl TypeCheck for Expr {
                     Err(Error::InvalidIndex(self.clone()))
                 }
             }
+
+            // Typecheck a closure's captures and body.
+            Self::Closure(closure) => closure.type_check(env),
         }
     }
 }
@@ -1003,6 +1078,8 @@ impl TypeCheck for ConstExpr {
                         //         .zip(ty_args.clone())
                         //         .collect(),
                         // );
+                        // Check the type arguments satisfy the bounds of the procedure.
+                        poly.check_bounds(ty_args, env)?;
                         // Check the template type.
                         poly.type_check(env)
                         // Ok(())
@@ -1010,6 +1087,10 @@ impl TypeCheck for ConstExpr {
                     _ => {
                         self.get_type(env)?.type_check(env)?;
                         expr.type_check(env)?;
+                        // Check the type arguments satisfy the bounds of a polymorphic procedure.
+                        if let Ok(Self::PolyProc(poly)) = expr.clone().eval(env) {
+                            poly.check_bounds(ty_args, env)?;
+                        }
                         // if let Self::PolyProc(poly) = *expr.clone() {
                         //     poly.type_check(env)?
                         // }
@@ -1042,6 +1123,10 @@ impl TypeCheck for ConstExpr {
                         cast_ty.clone(),
                     ));
                 }
+                // Constant integers must be in the range of a fixed-width type.
+                if let Type::FixedInt(_) = cast_ty {
+                    self.clone().eval(env)?;
+                }
                 // If it is, return the result of the inner expression's typechecking result.
                 expr.type_check(env)
             }
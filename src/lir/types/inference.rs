@@ -234,7 +234,7 @@ impl GetType for Expr {
                     .get_type_checked(env, i)?
                     .simplify_until_concrete(env)?;
                 match ty {
-                    Type::Proc(_, ret) => *ret,
+                    Type::Proc(_, ret) | Type::Closure(_, ret) => *ret,
                     _ => return Err(Error::ApplyNonProc(self.clone())),
                 }
             }
@@ -405,6 +405,8 @@ impl GetType for Expr {
                 // we cannot access an index.
                 _ => return Err(Error::InvalidIndex(self.clone())),
             },
+
+            Self::Closure(closure) => closure.get_type_checked(env, i)?,
         })
     }
 
@@ -541,6 +543,8 @@ impl GetType for Expr {
                 expr.substitute(name, ty);
                 cexpr.substitute(name, ty)
             }
+
+            Self::Closure(closure) => closure.substitute(name, ty),
         }
     }
 }
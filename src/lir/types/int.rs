@@ -0,0 +1,281 @@
+//! # Fixed-Width Integers
+//!
+//! This module implements the sized integer types `I8` through `U64`.
+//!
+//! A sized integer still takes up a single cell. Signed values are kept
+//! sign-extended, and unsigned values are kept zero-extended, so the usual
+//! integer operations can compare, divide, and print values of the smaller
+//! types as they are. The results of operations which can leave the range of
+//! the type are wrapped back into it. `U64` is the only type whose values
+//! don't all fit in a signed cell, so it gets its own comparison and division.
+use crate::asm::{CoreOp, Location};
+use core::fmt;
+
+/// A fixed-width integer type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntType {
+    /// The number of bits in a value of this type.
+    pub fn bits(&self) -> u32 {
+        match self {
+            Self::I8 | Self::U8 => 8,
+            Self::I16 | Self::U16 => 16,
+            Self::I32 | Self::U32 => 32,
+            Self::I64 | Self::U64 => 64,
+        }
+    }
+
+    /// Is this a signed integer type?
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64)
+    }
+
+    /// The unsigned type with the same width as this type.
+    pub fn to_unsigned(&self) -> Self {
+        match self {
+            Self::I8 | Self::U8 => Self::U8,
+            Self::I16 | Self::U16 => Self::U16,
+            Self::I32 | Self::U32 => Self::U32,
+            Self::I64 | Self::U64 => Self::U64,
+        }
+    }
+
+    /// The smallest value of this type.
+    pub fn min(&self) -> i128 {
+        if self.is_signed() {
+            -(1 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    /// The largest value of this type.
+    pub fn max(&self) -> i128 {
+        if self.is_signed() {
+            (1 << (self.bits() - 1)) - 1
+        } else {
+            (1 << self.bits()) - 1
+        }
+    }
+
+    /// Is an integer in the range of this type?
+    pub fn contains(&self, n: i64) -> bool {
+        (self.min()..=self.max()).contains(&(n as i128))
+    }
+
+    /// Wrap an integer into the range of this type, as it's represented in a cell.
+    pub fn wrap(&self, n: i64) -> i64 {
+        let shift = 64 - self.bits();
+        if self.is_signed() {
+            n.wrapping_shl(shift).wrapping_shr(shift)
+        } else {
+            ((n as u64).wrapping_shl(shift).wrapping_shr(shift)) as i64
+        }
+    }
+
+    /// Wrap the integer stored at `dst` into the range of this type.
+    /// The `tmp` location is overwritten.
+    pub fn wrap_op(&self, dst: Location, tmp: Location) -> CoreOp {
+        let shift = 64 - self.bits() as i64;
+        if shift == 0 {
+            // A full cell is already wrapped.
+            CoreOp::Many(vec![])
+        } else if self.is_signed() {
+            // Shift the sign bit to the top of the cell, and then shift it back down.
+            CoreOp::Many(vec![
+                CoreOp::Set(tmp.clone(), shift),
+                CoreOp::LeftShift {
+                    src: tmp.clone(),
+                    dst: dst.clone(),
+                },
+                CoreOp::ArithmeticRightShift { src: tmp, dst },
+            ])
+        } else {
+            // Mask off the bits above the width of the type.
+            CoreOp::Many(vec![
+                CoreOp::Set(tmp.clone(), self.max() as i64),
+                CoreOp::BitwiseAnd { src: tmp, dst },
+            ])
+        }
+    }
+
+    /// Flip the sign bit of the `U64` stored at `dst`, so that signed comparisons
+    /// order it as an unsigned integer. The `tmp` location is overwritten.
+    pub fn unsigned_order_op(dst: Location, tmp: Location) -> CoreOp {
+        CoreOp::Many(vec![
+            CoreOp::Set(tmp.clone(), i64::MIN),
+            CoreOp::BitwiseXor { src: tmp, dst },
+        ])
+    }
+
+    /// Store whether `a < b` in `dst`, comparing them as 64-bit signed integers.
+    /// The `dst` location must be different from `a` and `b`, and
+    /// the two cells starting at `tmp` are overwritten.
+    ///
+    /// The VM compares integers by subtracting them, which overflows for integers
+    /// far enough apart, so this corrects the sign of the difference for overflow.
+    pub fn less_than_op(a: Location, b: Location, dst: Location, tmp: Location) -> CoreOp {
+        let (diff, mask) = (dst, tmp.clone());
+        CoreOp::Many(vec![
+            // diff = a - b
+            CoreOp::Move {
+                src: a.clone(),
+                dst: diff.clone(),
+            },
+            CoreOp::Sub {
+                src: b.clone(),
+                dst: diff.clone(),
+            },
+            // mask = (a ^ b) & (diff ^ a)
+            CoreOp::Move {
+                src: a.clone(),
+                dst: mask.clone(),
+            },
+            CoreOp::BitwiseXor {
+                src: b,
+                dst: mask.clone(),
+            },
+            CoreOp::Move {
+                src: diff.clone(),
+                dst: tmp.offset(1),
+            },
+            CoreOp::BitwiseXor {
+                src: a,
+                dst: tmp.offset(1),
+            },
+            CoreOp::BitwiseAnd {
+                src: tmp.offset(1),
+                dst: mask.clone(),
+            },
+            // dst = (diff ^ mask) >>> 63
+            CoreOp::BitwiseXor {
+                src: mask.clone(),
+                dst: diff.clone(),
+            },
+            CoreOp::Set(mask.clone(), 63),
+            CoreOp::LogicalRightShift {
+                src: mask,
+                dst: diff,
+            },
+        ])
+    }
+
+    /// Store whether `a >= b` in `dst`, comparing them as `U64`s.
+    /// The `a` and `b` locations are left as they are, and
+    /// the four cells starting at `tmp` are overwritten.
+    fn unsigned_greater_equal_op(a: Location, b: Location, dst: Location, tmp: Location) -> CoreOp {
+        let (x, y) = (tmp.clone(), tmp.offset(1));
+        CoreOp::Many(vec![
+            CoreOp::Move {
+                src: a,
+                dst: x.clone(),
+            },
+            CoreOp::Move {
+                src: b,
+                dst: y.clone(),
+            },
+            Self::unsigned_order_op(x.clone(), dst.clone()),
+            Self::unsigned_order_op(y.clone(), dst.clone()),
+            Self::less_than_op(x, y, dst.clone(), tmp.offset(2)),
+            CoreOp::Not(dst),
+        ])
+    }
+
+    /// Divide the `U64` at `dst` by the `U64` at `src`, storing the quotient (or the
+    /// remainder) in `dst`. The eight cells starting at `tmp` are overwritten.
+    ///
+    /// The VM only divides signed integers, so this halves the dividend to make it
+    /// non-negative, divides it, and then corrects the doubled quotient by at most one.
+    pub fn unsigned_div_op(dst: Location, src: Location, tmp: Location, remainder: bool) -> CoreOp {
+        let is_large = tmp.clone();
+        let quotient = tmp.offset(1);
+        let product = tmp.offset(2);
+        let rest = tmp.offset(3);
+        let mut ops = vec![
+            CoreOp::Set(is_large.clone(), 0),
+            CoreOp::IsLess {
+                a: src.clone(),
+                b: is_large.clone(),
+                dst: is_large.clone(),
+            },
+            CoreOp::If(is_large.clone()),
+            // A divisor of at least 2^63 goes into the dividend at most once.
+            Self::unsigned_greater_equal_op(
+                dst.clone(),
+                src.clone(),
+                quotient.clone(),
+                product.clone(),
+            ),
+            CoreOp::Else,
+            // quotient = ((dst >>> 1) / src) << 1
+            CoreOp::Move {
+                src: dst.clone(),
+                dst: quotient.clone(),
+            },
+            CoreOp::Set(product.clone(), 1),
+            CoreOp::LogicalRightShift {
+                src: product.clone(),
+                dst: quotient.clone(),
+            },
+            CoreOp::Div {
+                src: src.clone(),
+                dst: quotient.clone(),
+            },
+            CoreOp::LeftShift {
+                src: product.clone(),
+                dst: quotient.clone(),
+            },
+            // rest = dst - quotient * src
+            CoreOp::Move {
+                src: quotient.clone(),
+                dst: product.clone(),
+            },
+            CoreOp::Mul {
+                src: src.clone(),
+                dst: product.clone(),
+            },
+            CoreOp::Move {
+                src: dst.clone(),
+                dst: rest.clone(),
+            },
+            CoreOp::Sub {
+                src: product.clone(),
+                dst: rest.clone(),
+            },
+            // quotient += rest >= src
+            Self::unsigned_greater_equal_op(rest, src.clone(), product.clone(), tmp.offset(4)),
+            CoreOp::Add {
+                src: product,
+                dst: quotient.clone(),
+            },
+            CoreOp::End,
+        ];
+        if remainder {
+            // dst -= quotient * src
+            ops.push(CoreOp::Mul {
+                src,
+                dst: quotient.clone(),
+            });
+            ops.push(CoreOp::Sub { src: quotient, dst });
+        } else {
+            ops.push(CoreOp::Move { src: quotient, dst });
+        }
+        CoreOp::Many(ops)
+    }
+}
+
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
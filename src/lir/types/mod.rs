@@ -11,10 +11,14 @@ use std::sync::Mutex;
 
 mod check;
 mod inference;
+mod int;
 mod size;
+mod traits;
 pub use check::*;
 pub use inference::*;
+pub use int::*;
 pub use size::*;
+pub use traits::*;
 
 use log::*;
 
@@ -136,6 +140,10 @@ pub enum Type {
     None,
     /// The integer type.
     Int,
+    /// A fixed-width integer type, which wraps around when it overflows.
+    FixedInt(IntType),
+    /// A string slice: a pointer to some characters, and the number of characters.
+    Str,
     /// The floating-point number type.
     Float,
     /// The type of the most basic unit of memory.
@@ -160,9 +168,10 @@ pub enum Type {
     /// The tag is stored at the very beginning of the value.
     EnumUnion(BTreeMap<String, Self>),
 
-    /// A trait object. This is internally represented as an `EnumUnion` over the possible
-    /// types that can be stored in the trait object.
-    // TraitObject(TraitObject),
+    /// A trait object of the named trait. This is internally represented as a pointer to
+    /// a value of any type implementing the trait, and a pointer to a vtable of the value's
+    /// implementations of the trait's methods.
+    TraitObject(String),
 
     /// A type. This converts a type into a value.
     Type(Box<Type>),
@@ -176,6 +185,10 @@ pub enum Type {
     Union(BTreeMap<String, Self>),
     /// A procedure with a list of parameters and a return type.
     Proc(Vec<Type>, Box<Type>),
+    /// A closure with a list of parameters and a return type. This is internally
+    /// represented as a pointer to the closure's captured environment, and a procedure
+    /// which takes the environment pointer before the rest of its arguments.
+    Closure(Vec<Type>, Box<Type>),
     /// A pointer to another type.
     Pointer(Mutability, Box<Self>),
     /// A type reserved by the compiler.
@@ -270,7 +283,7 @@ impl Type {
                 t.is_recursive_helper(symbols, env)
             }
 
-            Self::Proc(args, ret) => {
+            Self::Proc(args, ret) | Self::Closure(args, ret) => {
                 for arg in args {
                     if arg.is_recursive_helper(symbols, env)? {
                         return Ok(true);
@@ -316,8 +329,12 @@ impl Type {
                 Ok(false)
             }
             Self::Enum(_) => Ok(false),
+            // Trait objects only point to their values.
+            Self::TraitObject(_) => Ok(false),
             Self::None
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Cell
             | Self::Char
@@ -429,7 +446,8 @@ impl Type {
                 inner1.get_monomorph_template_args(inner2, matched_symbols, param_symbols, env)?;
             }
 
-            (Self::Proc(args1, ret1), Self::Proc(args2, ret2)) => {
+            (Self::Proc(args1, ret1), Self::Proc(args2, ret2))
+            | (Self::Closure(args1, ret1), Self::Closure(args2, ret2)) => {
                 for (arg1, arg2) in args1.iter().zip(args2.iter()) {
                     arg1.get_monomorph_template_args(arg2, matched_symbols, param_symbols, env)?;
                 }
@@ -576,7 +594,7 @@ impl Type {
                 inner.add_monomorphized_associated_consts(env)?;
             }
 
-            Self::Proc(args, ret) => {
+            Self::Proc(args, ret) | Self::Closure(args, ret) => {
                 for arg in args {
                     arg.add_monomorphized_associated_consts(env)?;
                 }
@@ -597,11 +615,14 @@ impl Type {
             | Self::None
             | Self::Never
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Cell
             | Self::Char
             | Self::Bool
-            | Self::Enum(_) => {}
+            | Self::Enum(_)
+            | Self::TraitObject(_) => {}
         }
         Ok(())
     }
@@ -657,6 +678,8 @@ impl Type {
         match self {
             Self::None
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Cell
             | Self::Char
@@ -668,12 +691,14 @@ impl Type {
             Self::Unit(_, t) => t.is_simple(),
             Self::Tuple(inner) => inner.iter().all(|t| t.is_simple()),
             Self::Array(inner, expr) => inner.is_simple() && matches!(**expr, ConstExpr::Int(_)),
-            Self::Proc(args, ret) => args.iter().all(|t| t.is_simple()) && ret.is_simple(),
+            Self::Proc(args, ret) | Self::Closure(args, ret) => {
+                args.iter().all(|t| t.is_simple()) && ret.is_simple()
+            }
             Self::Pointer(_, inner) => inner.is_simple(),
             Self::Struct(inner) | Self::Union(inner) | Self::EnumUnion(inner) => {
                 inner.iter().all(|(_, t)| t.is_simple())
             }
-            Self::Symbol(_) => false,
+            Self::Symbol(_) | Self::TraitObject(_) => false,
             Self::Poly(_params, _ret) => true,
 
             Self::Apply(template, args) => {
@@ -701,9 +726,15 @@ impl Type {
 
     pub fn is_concrete(&self) -> bool {
         match self {
-            Self::Poly(_, _) | Self::Symbol(_) | Self::Apply(_, _) | Self::Let(_, _, _) => false,
+            Self::Poly(_, _)
+            | Self::Symbol(_)
+            | Self::Apply(_, _)
+            | Self::Let(_, _, _)
+            | Self::TraitObject(_) => false,
             Self::None
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Cell
             | Self::Char
@@ -715,6 +746,7 @@ impl Type {
             | Self::Struct(_)
             | Self::Union(_)
             | Self::Proc(_, _)
+            | Self::Closure(_, _)
             | Self::Tuple(_)
             | Self::Unit(_, _)
             | Self::Type(_)
@@ -728,6 +760,8 @@ impl Type {
         match self {
             Self::None
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Cell
             | Self::Char
@@ -739,7 +773,9 @@ impl Type {
             Self::Unit(_, t) => t.is_atomic(),
             Self::Tuple(inner) => inner.iter().all(|t| t.is_atomic()),
             Self::Array(inner, expr) => inner.is_atomic() && matches!(**expr, ConstExpr::Int(_)),
-            Self::Proc(args, ret) => args.iter().all(|t| t.is_atomic()) && ret.is_atomic(),
+            Self::Proc(args, ret) | Self::Closure(args, ret) => {
+                args.iter().all(|t| t.is_atomic()) && ret.is_atomic()
+            }
             Self::Pointer(_, inner) => inner.is_atomic(),
             Self::Struct(inner) => inner.iter().all(|(_, t)| t.is_atomic()),
             Self::EnumUnion(inner) => inner.iter().all(|(_, t)| t.is_atomic()),
@@ -749,6 +785,39 @@ impl Type {
         }
     }
 
+    /// Get the type which represents a string slice.
+    /// This is a pointer to the characters, and the number of characters.
+    pub fn str_object_type() -> Type {
+        Type::Struct(
+            [
+                (
+                    "data".to_string(),
+                    Type::Pointer(Mutability::Immutable, Box::new(Type::Char)),
+                ),
+                ("length".to_string(), Type::Int),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+
+    /// Get the type which represents a closure with the given parameters and return type.
+    /// This is a pointer to the captured environment, and a procedure which takes the
+    /// environment pointer as its first argument.
+    pub fn closure_object_type(args: &[Type], ret: &Type) -> Type {
+        let env_ptr = Type::Pointer(Mutability::Any, Box::new(Type::Any));
+        let mut fn_args = vec![env_ptr.clone()];
+        fn_args.extend(args.iter().cloned());
+        Type::Struct(
+            [
+                ("env".to_string(), env_ptr),
+                ("fn".to_string(), Type::Proc(fn_args, Box::new(ret.clone()))),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+
     /// Is first argument of function a reference?
     pub fn is_self_param_reference(&self, env: &Env) -> Result<bool, Error> {
         Ok(match self.simplify_until_concrete(env)? {
@@ -796,7 +865,12 @@ impl Type {
     fn possibly_has_members(&self) -> bool {
         if matches!(
             self,
-            Self::Tuple(_) | Self::Struct(_) | Self::Union(_) | Self::Pointer(_, _) | Self::Type(_)
+            Self::Tuple(_)
+                | Self::Struct(_)
+                | Self::Union(_)
+                | Self::Str
+                | Self::Pointer(_, _)
+                | Self::Type(_)
         ) {
             return true;
         }
@@ -994,11 +1068,14 @@ impl Type {
             | Self::Never
             | Self::Any
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Cell
             | Self::Char
             | Self::Bool
-            | Self::Enum(_) => false,
+            | Self::Enum(_)
+            | Self::TraitObject(_) => false,
 
             Self::Tuple(items) => items.iter().any(|t| t.contains_symbol(name)),
             Self::Array(t, _) => t.contains_symbol(name),
@@ -1006,7 +1083,7 @@ impl Type {
             Self::Union(fields) => fields.values().any(|t| t.contains_symbol(name)),
             Self::EnumUnion(fields) => fields.values().any(|t| t.contains_symbol(name)),
 
-            Self::Proc(params, ret) => {
+            Self::Proc(params, ret) | Self::Closure(params, ret) => {
                 params.iter().any(|t| t.contains_symbol(name)) || ret.contains_symbol(name)
             }
             Self::Pointer(_, t) => t.contains_symbol(name),
@@ -1068,11 +1145,14 @@ impl Type {
             | Self::Never
             | Self::Any
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Cell
             | Self::Char
             | Self::Bool
-            | Self::Enum(_) => self.clone(),
+            | Self::Enum(_)
+            | Self::TraitObject(_) => self.clone(),
             Self::Tuple(items) => Self::Tuple(
                 items
                     .iter()
@@ -1113,6 +1193,12 @@ impl Type {
                     .collect(),
                 Box::new(ret.substitute(name, substitution)),
             ),
+            Self::Closure(args, ret) => Self::Closure(
+                args.iter()
+                    .map(|arg| arg.substitute(name, substitution))
+                    .collect(),
+                Box::new(ret.substitute(name, substitution)),
+            ),
             Self::Pointer(mutability, ptr) => {
                 Self::Pointer(*mutability, Box::new(ptr.substitute(name, substitution)))
             }
@@ -1327,6 +1413,15 @@ impl Type {
                 }
             }
 
+            (Self::TraitObject(a), Self::TraitObject(b)) => Ok(a == b),
+            // Casting to or from a trait object casts its representation.
+            (Self::TraitObject(name), other) | (other, Self::TraitObject(name)) => {
+                match env.get_trait(name) {
+                    Some(t) => t.get_object_type().can_cast_to_checked(other, env, i),
+                    None => Err(Error::TraitNotDefined(name.clone())),
+                }
+            }
+
             // Two Units can only be cast between one another if they have the same name, and the types inside them can be cast.
             (Self::Unit(unit_name1, t1), Self::Unit(unit_name2, t2))
                 if unit_name1 == unit_name2 =>
@@ -1343,6 +1438,32 @@ impl Type {
             (Self::Int, Self::Bool) | (Self::Bool, Self::Int) => Ok(true),
             (Self::Int, Self::Enum(_)) | (Self::Enum(_), Self::Int) => Ok(true),
 
+            // Fixed-width integers are wrapped into range when they're cast to.
+            (Self::FixedInt(_), Self::FixedInt(_)) => Ok(true),
+            (
+                Self::FixedInt(_),
+                Self::Int | Self::Cell | Self::Char | Self::Bool | Self::Enum(_),
+            )
+            | (
+                Self::Int | Self::Cell | Self::Char | Self::Bool | Self::Enum(_),
+                Self::FixedInt(_),
+            ) => Ok(true),
+            // Floats are converted through signed integers, which can't hold every `U64`.
+            (Self::FixedInt(ty), Self::Float) | (Self::Float, Self::FixedInt(ty)) => {
+                Ok(*ty != IntType::U64)
+            }
+
+            // A pointer to characters, or to an array of characters, can be sliced into a string.
+            (Self::Pointer(_, elem), Self::Str) => match elem.simplify_until_concrete(env)? {
+                Self::Char => Ok(true),
+                Self::Array(ty, _) => ty.equals(&Self::Char, env),
+                _ => Ok(false),
+            },
+            // Otherwise, a string slice is cast like the struct which represents it.
+            (Self::Str, other) | (other, Self::Str) => {
+                Self::str_object_type().can_cast_to_checked(other, env, i)
+            }
+
             (Self::Cell, Self::Int) | (Self::Int, Self::Cell) => Ok(true),
             (Self::Cell, Self::Float) | (Self::Float, Self::Cell) => Ok(true),
             (Self::Cell, Self::Char) | (Self::Char, Self::Cell) => Ok(true),
@@ -1399,7 +1520,14 @@ impl Type {
                 Ok(true)
             }
 
-            (Self::Proc(args1, ret1), Self::Proc(args2, ret2)) => {
+            // Casting to or from a closure casts its representation.
+            (Self::Closure(args, ret), other) | (other, Self::Closure(args, ret))
+                if !matches!(other, Self::Closure(_, _)) =>
+            {
+                Self::closure_object_type(args, ret).can_cast_to_checked(other, env, i)
+            }
+            (Self::Proc(args1, ret1), Self::Proc(args2, ret2))
+            | (Self::Closure(args1, ret1), Self::Closure(args2, ret2)) => {
                 if args1.len() != args2.len() {
                     return Ok(false);
                 }
@@ -1484,6 +1612,12 @@ impl Type {
                     .collect::<Result<Vec<_>, _>>()?,
                 Box::new(ret.perform_template_applications(env, previous_applications)?),
             ),
+            Self::Closure(args, ret) => Self::Closure(
+                args.into_iter()
+                    .map(|t| t.perform_template_applications(env, previous_applications))
+                    .collect::<Result<Vec<_>, _>>()?,
+                Box::new(ret.perform_template_applications(env, previous_applications)?),
+            ),
             Self::Struct(fields) if !self.is_recursive(env)? => Self::Struct(
                 fields
                     .into_iter()
@@ -1570,6 +1704,8 @@ impl Type {
             | (Self::Int, Self::Int)
             | (Self::Float, Self::Float)
             | (Self::Cell, Self::Cell) => true,
+            (Self::FixedInt(a), Self::FixedInt(b)) => a == b,
+            (Self::Str, Self::Str) => true,
 
             (Self::Symbol(a), Self::Symbol(b)) => {
                 if a == b {
@@ -1658,6 +1794,18 @@ impl Type {
                 }
             }
 
+            // Trait objects are equal if they are objects of the same trait.
+            (Self::TraitObject(a), Self::TraitObject(b)) => a == b,
+            // Otherwise, compare the representation of the trait object.
+            (Self::TraitObject(name), x) | (x, Self::TraitObject(name)) => {
+                match env.get_trait(name) {
+                    Some(t) => t
+                        .get_object_type()
+                        .equals_checked(x, compared_symbols, env, i)?,
+                    None => false,
+                }
+            }
+
             // If we're comparing two units, then we can just compare their names and confirm
             // their structures are equal.
             (Self::Unit(unit_name1, t1), Self::Unit(unit_name2, t2)) => {
@@ -1723,7 +1871,8 @@ impl Type {
                 true
             }
 
-            (Self::Proc(args1, ret1), Self::Proc(args2, ret2)) => {
+            (Self::Proc(args1, ret1), Self::Proc(args2, ret2))
+            | (Self::Closure(args1, ret1), Self::Closure(args2, ret2)) => {
                 if args1.len() != args2.len() {
                     return Ok(false);
                 }
@@ -1886,10 +2035,14 @@ impl Type {
 
             Type::Unit(_unit_name, t) => t.get_member_offset(member, expr, env),
 
-            Type::Apply(_, _) | Type::Poly(_, _) => {
+            Type::Apply(_, _) | Type::Poly(_, _) | Type::TraitObject(_) => {
                 let t = self.simplify_until_concrete(env)?;
                 t.get_member_offset(member, expr, env)
             }
+            Type::Closure(args, ret) => {
+                Self::closure_object_type(args, ret).get_member_offset(member, expr, env)
+            }
+            Type::Str => Self::str_object_type().get_member_offset(member, expr, env),
 
             Type::Symbol(name) => {
                 if let Some(t) = env.get_type(name) {
@@ -1992,11 +2145,15 @@ impl Type {
                 }
             }
 
-            Type::Apply(_, _) | Type::Poly(_, _) => {
+            Type::Apply(_, _) | Type::Poly(_, _) | Type::TraitObject(_) => {
                 let t = self.simplify_until_concrete(env)?;
                 trace!("Simplified {self} to {t}");
                 t.type_check_member(member, expr, env)
             }
+            Type::Closure(args, ret) => {
+                Self::closure_object_type(args, ret).type_check_member(member, expr, env)
+            }
+            Type::Str => Self::str_object_type().type_check_member(member, expr, env),
 
             Type::Any => {
                 // Any type can have any member
@@ -2039,6 +2196,8 @@ impl Simplify for Type {
             | Self::Never
             | Self::Any
             | Self::Int
+            | Self::FixedInt(_)
+            | Self::Str
             | Self::Float
             | Self::Char
             | Self::Bool
@@ -2087,12 +2246,23 @@ impl Simplify for Type {
                 }
             }
 
+            Self::TraitObject(ref name) => env
+                .get_trait(name)
+                .ok_or_else(|| Error::TraitNotDefined(name.clone()))?
+                .get_object_type(),
+
             Self::Proc(args, ret) => Self::Proc(
                 args.into_iter()
                     .flat_map(|t| t.simplify_checked(env, i))
                     .collect(),
                 Box::new(ret.simplify_checked(env, i)?),
             ),
+            Self::Closure(args, ret) => Self::Closure(
+                args.into_iter()
+                    .flat_map(|t| t.simplify_checked(env, i))
+                    .collect(),
+                Box::new(ret.simplify_checked(env, i)?),
+            ),
 
             Self::Tuple(items) => Self::Tuple(
                 items
@@ -2161,8 +2331,11 @@ impl fmt::Display for Type {
             }
             Self::Bool => write!(f, "Bool"),
             Self::Char => write!(f, "Char"),
+            Self::TraitObject(name) => write!(f, "dyn {name}"),
             Self::Cell => write!(f, "Cell"),
             Self::Int => write!(f, "Int"),
+            Self::FixedInt(ty) => write!(f, "{ty}"),
+            Self::Str => write!(f, "Str"),
             Self::Float => write!(f, "Float"),
             Self::None => write!(f, "None"),
             Self::Array(ty, len) => write!(f, "[{ty} * {len}]"),
@@ -2254,6 +2427,16 @@ impl fmt::Display for Type {
                 }
                 write!(f, ") -> {ret}")
             }
+            Self::Closure(args, ret) => {
+                write!(f, "dyn (")?;
+                for (i, ty) in args.iter().enumerate() {
+                    write!(f, "{ty}")?;
+                    if i < args.len() - 1 {
+                        write!(f, ", ")?
+                    }
+                }
+                write!(f, ") -> {ret}")
+            }
 
             Self::Symbol(name) => write!(f, "{name}"),
             Self::Unit(unit_name, _ty) => write!(f, "unit {unit_name}"),
@@ -2292,6 +2475,13 @@ impl std::hash::Hash for Type {
             Self::Float => {
                 state.write_u8(7);
             }
+            Self::FixedInt(ty) => {
+                state.write_u8(24);
+                ty.hash(state);
+            }
+            Self::Str => {
+                state.write_u8(25);
+            }
             Self::None => {
                 state.write_u8(8);
             }
@@ -2335,6 +2525,11 @@ impl std::hash::Hash for Type {
                 args.hash(state);
                 ret.hash(state);
             }
+            Self::Closure(args, ret) => {
+                state.write_u8(23);
+                args.hash(state);
+                ret.hash(state);
+            }
             Self::Symbol(name) => {
                 state.write_u8(18);
                 name.hash(state);
@@ -2354,6 +2549,10 @@ impl std::hash::Hash for Type {
                 state.write_u8(21);
                 t.hash(state);
             }
+            Self::TraitObject(name) => {
+                state.write_u8(22);
+                name.hash(state);
+            }
         }
     }
 }
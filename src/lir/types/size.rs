@@ -97,6 +97,7 @@ impl GetSize for Type {
 
             // These types are all one cell.
             Self::Int
+            | Self::FixedInt(_)
             | Self::Float
             | Self::Char
             | Self::Bool
@@ -105,6 +106,13 @@ impl GetSize for Type {
             | Self::Pointer(_, _)
             | Self::Proc(_, _) => 1,
 
+            // Trait objects are a pointer to the value and a pointer to the vtable.
+            Self::TraitObject(_) => 2,
+            // Closures are a pointer to the environment and a procedure.
+            Self::Closure(_, _) => 2,
+            // String slices are a pointer to the characters and the number of characters.
+            Self::Str => 2,
+
             // Tuple types are the sum of the sizes of their elements.
             Self::Tuple(items) => items
                 .par_iter()
@@ -0,0 +1,317 @@
+//! # Traits
+//!
+//! A trait is a named set of method signatures. A type implements a trait with an
+//! `impl Trait for Type` declaration, which must define every method of the trait with
+//! the trait's signature (where the `Self` type stands for the implementing type).
+//!
+//! Traits are used in two ways:
+//! - As bounds on the type parameters of polymorphic procedures. The methods of the
+//!   trait can be called on values of the bounded type, and every monomorph must be
+//!   supplied a type argument which implements the trait. This is static dispatch.
+//! - As trait objects. A `dyn Trait` value is a pointer to a value of any type which
+//!   implements the trait, paired with a pointer to a vtable of that type's methods.
+//!   This is dynamic dispatch.
+//!
+//! A trait object is created by casting a pointer to the object type, like `&x as dyn Trait`.
+//! Only traits whose methods all take `self` by reference (and use `Self` nowhere else)
+//! can be used as trait objects.
+use super::{Mutability, Type};
+use crate::lir::{ConstExpr, Env, Error, Expr, FFIProcedure, GetType, Procedure};
+use core::fmt;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+/// The name of the type which stands for the implementing type in a trait's method signatures.
+pub const SELF_TYPE: &str = "Self";
+
+/// A trait: a named set of method signatures which types can implement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trait {
+    /// The name of the trait.
+    name: String,
+    /// The types of the trait's methods, which use the `Self` type for the implementing type.
+    methods: BTreeMap<String, Type>,
+    /// The methods of the trait object type, which call the methods in the object's vtable.
+    /// This is empty if the trait cannot be used as a trait object.
+    object_methods: BTreeMap<String, ConstExpr>,
+}
+
+impl Trait {
+    /// Create a trait with a name and the types of its methods.
+    pub fn new(name: impl ToString, methods: BTreeMap<String, Type>) -> Self {
+        let mut result = Self {
+            name: name.to_string(),
+            methods,
+            object_methods: BTreeMap::new(),
+        };
+        // The methods of the trait object are only created once, so that they are
+        // only compiled once.
+        if result.is_object_safe() {
+            result.object_methods = result
+                .methods
+                .iter()
+                .map(|(name, ty)| (name.clone(), result.object_method(name, ty)))
+                .collect();
+        }
+        result
+    }
+
+    /// Get the name of the trait.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the types of the trait's methods, which use the `Self` type for the implementing type.
+    pub fn get_methods(&self) -> &BTreeMap<String, Type> {
+        &self.methods
+    }
+
+    /// Get the methods of the trait object type.
+    pub fn get_object_methods(&self) -> &BTreeMap<String, ConstExpr> {
+        &self.object_methods
+    }
+
+    /// Get the type of a method of the trait, as implemented by a given type.
+    pub fn get_method_type(&self, method: &str, ty: &Type) -> Option<Type> {
+        self.methods
+            .get(method)
+            .map(|method_ty| method_ty.substitute(SELF_TYPE, ty))
+    }
+
+    /// Get the mutability of the `self` pointer taken by a method of the trait.
+    /// This is `None` if the method can't be called through a trait object: if it
+    /// doesn't take `self` by reference, or if it uses `Self` anywhere else.
+    fn receiver_mutability(method_ty: &Type) -> Option<Mutability> {
+        match method_ty {
+            Type::Proc(args, ret) => match args.first() {
+                Some(Type::Pointer(mutability, receiver))
+                    if **receiver == Type::Symbol(SELF_TYPE.to_string())
+                        && !args[1..].iter().any(|arg| arg.contains_symbol(SELF_TYPE))
+                        && !ret.contains_symbol(SELF_TYPE) =>
+                {
+                    Some(*mutability)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Can this trait be used as a trait object?
+    pub fn is_object_safe(&self) -> bool {
+        self.methods
+            .values()
+            .all(|method_ty| Self::receiver_mutability(method_ty).is_some())
+    }
+
+    /// Does a trait object of this trait need a mutable pointer to its value?
+    fn needs_mutable_object(&self) -> bool {
+        self.methods.values().any(|method_ty| {
+            matches!(
+                Self::receiver_mutability(method_ty),
+                Some(Mutability::Mutable)
+            )
+        })
+    }
+
+    /// Get the type of the vtable of a trait object: a structure of the methods
+    /// of the implementing type.
+    fn vtable_type(&self) -> Type {
+        Type::Struct(
+            self.methods
+                .iter()
+                .map(|(name, ty)| (name.clone(), ty.substitute(SELF_TYPE, &Type::Any)))
+                .collect(),
+        )
+    }
+
+    /// Get the type which represents a trait object of this trait.
+    /// This is a pointer to the value, and a pointer to the vtable of the value's type.
+    pub fn get_object_type(&self) -> Type {
+        Type::Struct(
+            [
+                (
+                    "data".to_string(),
+                    Type::Pointer(Mutability::Any, Box::new(Type::Any)),
+                ),
+                (
+                    "vtable".to_string(),
+                    Type::Pointer(Mutability::Immutable, Box::new(self.vtable_type())),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+
+    /// Create the method of the trait object which calls a method through the vtable.
+    fn object_method(&self, name: &str, method_ty: &Type) -> ConstExpr {
+        let (args, ret) = match method_ty {
+            Type::Proc(args, ret) => (args.clone(), *ret.clone()),
+            _ => unreachable!("trait methods are procedures"),
+        };
+
+        let object = Expr::var("self");
+        let mut params = vec![(
+            "self".to_string(),
+            Mutability::Immutable,
+            Type::TraitObject(self.name.clone()),
+        )];
+        let mut call_args = vec![object.clone().field(ConstExpr::Symbol("data".to_string()))];
+        for (i, arg_ty) in args.into_iter().enumerate().skip(1) {
+            let arg_name = format!("arg{i}");
+            call_args.push(Expr::var(&arg_name));
+            params.push((arg_name, Mutability::Immutable, arg_ty));
+        }
+
+        let body = object
+            .field(ConstExpr::Symbol("vtable".to_string()))
+            .field(ConstExpr::Symbol(name.to_string()))
+            .app(call_args);
+        ConstExpr::Proc(Procedure::new(
+            Some(format!("dyn {}.{name}", self.name)),
+            params,
+            ret,
+            body,
+        ))
+    }
+
+    /// Get placeholders for the methods of a type parameter bounded by this trait.
+    /// These have the types of the trait's methods, so that the body of a polymorphic
+    /// procedure can be type checked before it is monomorphized.
+    pub(crate) fn get_bound_methods(&self, ty: &Type) -> Vec<(String, ConstExpr)> {
+        self.methods
+            .iter()
+            .map(|(name, method_ty)| {
+                let (args, ret) = match method_ty.substitute(SELF_TYPE, ty) {
+                    Type::Proc(args, ret) => (args, *ret),
+                    _ => unreachable!("trait methods are procedures"),
+                };
+                (
+                    name.clone(),
+                    ConstExpr::FFIProcedure(FFIProcedure::new(name.clone(), args, ret)),
+                )
+            })
+            .collect()
+    }
+
+    /// Confirm that the associated constants of an `impl` declaration implement this trait
+    /// for a type. Every method of the trait must be defined with the trait's signature,
+    /// and nothing else may be defined.
+    pub fn check_impl(
+        &self,
+        ty: &Type,
+        impls: &[(String, ConstExpr)],
+        env: &Env,
+    ) -> Result<(), Error> {
+        for (name, _) in impls {
+            if !self.methods.contains_key(name) {
+                return Err(Error::UnknownTraitMethod(
+                    self.name.clone(),
+                    ty.clone(),
+                    name.clone(),
+                ));
+            }
+        }
+
+        for name in self.methods.keys() {
+            let method = match impls.iter().find(|(impl_name, _)| impl_name == name) {
+                Some((_, method)) => method,
+                None => {
+                    return Err(Error::MissingTraitMethod(
+                        self.name.clone(),
+                        ty.clone(),
+                        name.clone(),
+                    ))
+                }
+            };
+
+            let expected = self.get_method_type(name, ty).unwrap();
+            let found = method.get_type(env)?;
+            if !found.equals(&expected, env)? {
+                return Err(Error::MismatchedTypes {
+                    expected,
+                    found,
+                    expr: Expr::ConstExpr(method.clone()),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a trait object from a pointer to a value which implements this trait.
+    /// The vtable of the object is stored in a static variable.
+    pub fn to_object(&self, ptr: &Expr, env: &Env) -> Result<Expr, Error> {
+        if !self.is_object_safe() {
+            return Err(Error::TraitNotObjectSafe(self.name.clone()));
+        }
+
+        let (mutability, ty) = match ptr.get_type(env)? {
+            Type::Pointer(mutability, ty) => (mutability, *ty),
+            other => match other.simplify_until_concrete(env)? {
+                Type::Pointer(mutability, ty) => (mutability, *ty),
+                found => {
+                    return Err(Error::InvalidAs(
+                        ptr.clone(),
+                        found,
+                        Type::TraitObject(self.name.clone()),
+                    ))
+                }
+            },
+        };
+
+        if !env.implements(&ty, &self.name)? {
+            return Err(Error::UnsatisfiedTraitBound(ty, self.name.clone()));
+        }
+        if self.needs_mutable_object() && !mutability.can_decay_to(&Mutability::Mutable) {
+            return Err(Error::MismatchedMutability {
+                expected: Mutability::Mutable,
+                found: mutability,
+                expr: ptr.clone(),
+            });
+        }
+
+        let mut vtable = BTreeMap::new();
+        for name in self.methods.keys() {
+            let (method, _) = env.get_associated_const(&ty, name).ok_or_else(|| {
+                Error::MissingTraitMethod(self.name.clone(), ty.clone(), name.clone())
+            })?;
+            vtable.insert(name.clone(), method);
+        }
+
+        Ok(Expr::Struct(
+            [
+                ("data".to_string(), ptr.clone()),
+                (
+                    "vtable".to_string(),
+                    Expr::ConstExpr(ConstExpr::Struct(vtable)).refer(Mutability::Immutable),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .as_type(Type::TraitObject(self.name.clone())))
+    }
+}
+
+impl fmt::Display for Trait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "trait {} {{", self.name)?;
+        for (i, (name, ty)) in self.methods.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, " {name}: {ty}")?;
+        }
+        write!(f, " }}")
+    }
+}
+
+impl Eq for Trait {}
+
+impl Hash for Trait {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.methods.hash(state);
+    }
+}
@@ -0,0 +1,557 @@
+//! # Warnings
+//!
+//! Lints over LIR expressions, which find code that compiles but is probably a mistake.
+//! Each lint has a stable name, which is used to allow it in the source code or to
+//! enable it from the command line.
+//!
+//! ## Lints
+//!
+//! - `unused_variables`: a variable which is never used. Variables whose names start
+//!   with an underscore are exempt.
+//! - `unused_mut`: a mutable variable which is never assigned to or borrowed mutably.
+//! - `shadowing`: a variable with the same name as another variable in scope. This is
+//!   allowed by default, because shadowing is common in Sage code.
+//! - `unreachable_code`: code after a `return` which can never run.
+//! - `dead_when_branches`: a branch of a `when` expression which is never taken,
+//!   whichever variant of the virtual machine the program is compiled to.
+//!
+//! The name `warnings` refers to every lint at once.
+//!
+//! ## Allowing Lints
+//!
+//! Expressions annotated with `Annotation::Allow` don't produce warnings for the lint
+//! anywhere inside of them. The declarations of a variable are merged with the
+//! expressions after it, so a variable's location and its allowed lints are
+//! carried by the annotations of its initial value.
+use super::{
+    optimize::{mentions, mentions_decl, pattern_bindings, subexpressions, symbol},
+    *,
+};
+use crate::parse::SourceCodeLocation;
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::SimpleFiles,
+    term::{emit, termcolor::WriteColor, Config},
+};
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Error as IoError, Result as IoResult},
+};
+
+/// The name which refers to every lint.
+pub const ALL_LINTS: &str = "warnings";
+
+/// A kind of warning, which can be enabled or allowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lint {
+    /// A variable which is never used.
+    UnusedVariables,
+    /// A mutable variable which is never mutated.
+    UnusedMut,
+    /// A variable which shadows another variable in scope.
+    Shadowing,
+    /// Code which can never run, because it comes after a `return`.
+    UnreachableCode,
+    /// A branch of a `when` expression which is never taken.
+    DeadWhenBranches,
+}
+
+impl Lint {
+    /// Every lint.
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariables,
+        Lint::UnusedMut,
+        Lint::Shadowing,
+        Lint::UnreachableCode,
+        Lint::DeadWhenBranches,
+    ];
+
+    /// Get the stable name of the lint.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedMut => "unused_mut",
+            Lint::Shadowing => "shadowing",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::DeadWhenBranches => "dead_when_branches",
+        }
+    }
+
+    /// Get the lints referred to by a name: either a single lint, or every lint for `warnings`.
+    pub fn named(name: &str) -> Option<Vec<Lint>> {
+        if name == ALL_LINTS {
+            return Some(Self::ALL.to_vec());
+        }
+        Self::ALL
+            .into_iter()
+            .find(|lint| lint.name() == name)
+            .map(|lint| vec![lint])
+    }
+
+    /// Is this lint checked unless it's explicitly enabled?
+    pub fn is_enabled_by_default(&self) -> bool {
+        *self != Lint::Shadowing
+    }
+
+    /// The lints which are checked by default.
+    pub fn defaults() -> BTreeSet<Lint> {
+        Self::ALL
+            .into_iter()
+            .filter(Lint::is_enabled_by_default)
+            .collect()
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A warning about some code which compiles, but is probably a mistake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// The lint which found the code.
+    pub lint: Lint,
+    /// What's wrong with the code.
+    pub message: String,
+    /// The location of the code, if it's known.
+    pub location: Option<SourceCodeLocation>,
+}
+
+impl Warning {
+    /// Emit the warning to a terminal, pointing at the code it's about.
+    ///
+    /// `source_of` is asked for the file the warning's location is in. Warnings
+    /// without a location, or in files it can't find, are emitted without a snippet.
+    pub fn emit(
+        &self,
+        writer: &mut dyn WriteColor,
+        source_of: impl Fn(&SourceCodeLocation) -> Option<String>,
+    ) -> IoResult<()> {
+        let mut files = SimpleFiles::new();
+        let mut diagnostic = Diagnostic::warning()
+            .with_message(&self.message)
+            .with_code(self.lint.name());
+        if let Some(location) = &self.location {
+            if let Some(source) = source_of(location) {
+                let range = location.span_in(&source);
+                let file_id = files.add(
+                    location.filename.clone().unwrap_or("<unknown>".to_string()),
+                    source,
+                );
+                diagnostic = diagnostic.with_labels(vec![Label::primary(file_id, range)]);
+            }
+        }
+
+        emit(writer, &Config::default(), &files, &diagnostic)
+            .map_err(|e| IoError::other(e.to_string()))
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        if let Some(location) = &self.location {
+            if let Some(filename) = &location.filename {
+                write!(f, "{filename}:")?;
+            }
+            write!(f, "{}:{}: ", location.line, location.column)?;
+        }
+        write!(f, "warning[{}]: {}", self.lint, self.message)
+    }
+}
+
+impl Expr {
+    /// Find the warnings for the given lints in the expression.
+    ///
+    /// The expression should type check: the lints assume the variables it uses are defined.
+    pub fn lint(&self, enabled: &BTreeSet<Lint>) -> Vec<Warning> {
+        let mut linter = Linter {
+            enabled,
+            warnings: vec![],
+            vars: vec![],
+            envs: [root_env(true, None), root_env(false, None)],
+        };
+        linter.expr(self, &Context::default());
+        linter.warnings
+    }
+
+    /// Type check the expression, and find the warnings for the given lints in it.
+    pub fn type_check_with_warnings(
+        &self,
+        env: &Env,
+        enabled: &BTreeSet<Lint>,
+    ) -> Result<Vec<Warning>, Error> {
+        self.type_check(env)?;
+        Ok(self.lint(enabled))
+    }
+}
+
+/// What the annotations around an expression say about it.
+#[derive(Clone, Default)]
+struct Context {
+    /// The lints allowed for the expression.
+    allowed: BTreeSet<Lint>,
+    /// The location of the innermost annotated expression around it.
+    location: Option<SourceCodeLocation>,
+}
+
+impl Context {
+    /// Get the context of an expression inside an annotated expression.
+    fn annotated(&self, annotation: &Annotation) -> Self {
+        let mut result = self.clone();
+        result.allowed.extend(
+            Lint::ALL
+                .into_iter()
+                .filter(|lint| annotation.allows(*lint)),
+        );
+        if let Some(location) = annotation.location() {
+            result.location = Some(location.clone());
+        }
+        result
+    }
+
+    /// Get the context of an expression, including its own annotations.
+    fn of(&self, mut expr: &Expr) -> Self {
+        let mut result = self.clone();
+        while let Expr::Annotated(inner, annotation) = expr {
+            result = result.annotated(annotation);
+            expr = inner;
+        }
+        result
+    }
+}
+
+struct Linter<'a> {
+    /// The lints to check.
+    enabled: &'a BTreeSet<Lint>,
+    /// The warnings found so far.
+    warnings: Vec<Warning>,
+    /// The variables in scope in the current procedure, innermost last.
+    vars: Vec<String>,
+    /// The environments to evaluate `when` conditions in, with the constants
+    /// in scope, for the core and standard variants.
+    envs: [Env; 2],
+}
+
+impl Linter<'_> {
+    /// Is the lint enabled, and not allowed in the context?
+    fn checks(&self, lint: Lint, cx: &Context) -> bool {
+        self.enabled.contains(&lint) && !cx.allowed.contains(&lint)
+    }
+
+    /// Add a warning, unless the lint is disabled or allowed in the context.
+    fn warn(&mut self, lint: Lint, message: String, cx: &Context) {
+        if self.checks(lint, cx) {
+            self.warnings.push(Warning {
+                lint,
+                message,
+                location: cx.location.clone(),
+            });
+        }
+    }
+
+    /// Check an expression.
+    fn expr(&mut self, expr: &Expr, cx: &Context) {
+        match expr {
+            Expr::Annotated(expr, annotation) => self.expr(expr, &cx.annotated(annotation)),
+            Expr::ConstExpr(c) => self.const_expr(c, cx),
+            Expr::Many(exprs) => {
+                // Only warn about the first unreachable expression in a block.
+                let unreachable = exprs
+                    .iter()
+                    .position(diverges)
+                    .and_then(|i| exprs[i + 1..].iter().find(|expr| !is_trivial(expr)));
+                if let Some(unreachable) = unreachable {
+                    self.warn(
+                        Lint::UnreachableCode,
+                        "unreachable code after a return".to_string(),
+                        &cx.of(unreachable),
+                    );
+                }
+                for expr in exprs {
+                    self.expr(expr, cx);
+                }
+            }
+            Expr::Declare(decl, body) => self.declare(decl, body, cx),
+            Expr::When(cond, then, otherwise) => {
+                if self.checks(Lint::DeadWhenBranches, cx) {
+                    self.dead_branches(cond, then, otherwise, cx);
+                }
+                self.expr(then, cx);
+                self.expr(otherwise, cx);
+            }
+            Expr::Match(expr, branches) => {
+                self.expr(expr, cx);
+                for (pattern, branch) in branches {
+                    self.pattern_scope(pattern, branch, cx);
+                }
+            }
+            Expr::IfLet(pattern, expr, then, otherwise) => {
+                self.expr(expr, cx);
+                self.pattern_scope(pattern, then, cx);
+                self.expr(otherwise, cx);
+            }
+            Expr::Closure(closure) => {
+                let params = closure
+                    .get_captures()
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .chain(closure.get_args().iter().map(|(name, ..)| name.clone()))
+                    .collect();
+                self.procedure(params, closure.get_body(), cx);
+            }
+            expr => {
+                for expr in subexpressions(expr) {
+                    self.expr(expr, cx);
+                }
+            }
+        }
+    }
+
+    /// Check for a branch of a `when` expression which is never taken.
+    /// A branch is only dead if its condition is the same in both variants.
+    fn dead_branches(&mut self, cond: &ConstExpr, then: &Expr, otherwise: &Expr, cx: &Context) {
+        let taken = self
+            .envs
+            .iter()
+            .map(|env| match cond.clone().eval(env) {
+                Ok(ConstExpr::Bool(b)) => Some(b),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if let [Some(a), Some(b)] = taken[..] {
+            let dead = if a { otherwise } else { then };
+            if a == b && !is_trivial(dead) {
+                self.warn(
+                    Lint::DeadWhenBranches,
+                    format!(
+                        "this `when` branch is never taken, because the condition is always {a}"
+                    ),
+                    &cx.of(dead),
+                );
+            }
+        }
+    }
+
+    /// Check the procedures in a constant expression.
+    fn const_expr(&mut self, c: &ConstExpr, cx: &Context) {
+        match c {
+            ConstExpr::Annotated(c, annotation) => self.const_expr(c, &cx.annotated(annotation)),
+            ConstExpr::Proc(proc) => {
+                let params = proc.get_args().iter().map(|(name, ..)| name.clone());
+                self.procedure(params.collect(), proc.get_body(), cx);
+            }
+            ConstExpr::PolyProc(proc) => {
+                let params = proc.get_args().iter().map(|(name, ..)| name.clone());
+                self.procedure(params.collect(), proc.get_body(), cx);
+            }
+            _ => {}
+        }
+    }
+
+    /// Check the body of a procedure, with its parameters in scope.
+    /// Procedures can't refer to the variables of the scope they're declared in.
+    fn procedure(&mut self, params: Vec<String>, body: &Expr, cx: &Context) {
+        let outer = std::mem::replace(&mut self.vars, params);
+        self.expr(body, cx);
+        self.vars = outer;
+    }
+
+    /// Check an expression in the scope of the variables bound by a pattern.
+    fn pattern_scope(&mut self, pattern: &Pattern, expr: &Expr, cx: &Context) {
+        let mut names = vec![];
+        pattern_bindings(pattern, &mut names);
+        let len = self.vars.len();
+        for name in names {
+            self.bind(name, cx);
+        }
+        self.expr(expr, cx);
+        self.vars.truncate(len);
+    }
+
+    /// Bring a variable into scope, and check whether it shadows another one.
+    fn bind(&mut self, name: &str, cx: &Context) {
+        if self.checks(Lint::Shadowing, cx)
+            && !name.starts_with('_')
+            && self.vars.iter().any(|var| var == name)
+        {
+            self.warn(
+                Lint::Shadowing,
+                format!("variable `{name}` shadows another variable in scope"),
+                cx,
+            );
+        }
+        self.vars.push(name.to_string());
+    }
+
+    /// Check a declaration, and the expression in its scope.
+    fn declare(&mut self, decl: &Declaration, body: &Expr, cx: &Context) {
+        let decls = match decl {
+            Declaration::Many(decls) => decls.iter().collect(),
+            decl => vec![decl],
+        };
+        let (len, envs) = (self.vars.len(), self.envs.clone());
+        // Procedures can use the constants declared after them.
+        for decl in &decls {
+            if let Declaration::Const(name, c) = decl {
+                for env in &mut self.envs {
+                    env.define_const(name, c.clone());
+                }
+            }
+        }
+
+        for (i, decl) in decls.iter().enumerate() {
+            self.declaration(decl, cx);
+            // The variables declared later in the same declaration can use this one.
+            let later = &decls[i + 1..];
+            let is_unused = |name: &str| {
+                !name.starts_with('_')
+                    && !mentions(body, name)
+                    && !later.iter().any(|decl| mentions_decl(decl, name))
+            };
+            match decl {
+                Declaration::Var(name, mutability, _, value) => {
+                    let cx = cx.of(value);
+                    if self.checks(Lint::UnusedVariables, &cx) && is_unused(name) {
+                        self.warn(
+                            Lint::UnusedVariables,
+                            format!("unused variable `{name}`"),
+                            &cx,
+                        );
+                    }
+                    if mutability.is_mutable()
+                        && self.checks(Lint::UnusedMut, &cx)
+                        && !mutates(body, name)
+                        && !later.iter().any(|decl| declared_values_mutate(decl, name))
+                    {
+                        self.warn(
+                            Lint::UnusedMut,
+                            format!("variable `{name}` is declared mutable, but never mutated"),
+                            &cx,
+                        );
+                    }
+                    self.bind(name, &cx);
+                }
+                Declaration::VarPat(pattern, value) => {
+                    let cx = cx.of(value);
+                    let mut names = vec![];
+                    pattern_bindings(pattern, &mut names);
+                    for name in names {
+                        if self.checks(Lint::UnusedVariables, &cx) && is_unused(name) {
+                            self.warn(
+                                Lint::UnusedVariables,
+                                format!("unused variable `{name}`"),
+                                &cx,
+                            );
+                        }
+                        self.bind(name, &cx);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.expr(body, cx);
+        self.vars.truncate(len);
+        self.envs = envs;
+    }
+
+    /// Check the expressions in a single declaration.
+    fn declaration(&mut self, decl: &Declaration, cx: &Context) {
+        match decl {
+            Declaration::Var(_, _, _, expr) | Declaration::VarPat(_, expr) => self.expr(expr, cx),
+            Declaration::Proc(_, proc) => self.const_expr(&ConstExpr::Proc(proc.clone()), cx),
+            Declaration::PolyProc(_, proc) => {
+                self.const_expr(&ConstExpr::PolyProc(proc.clone()), cx)
+            }
+            Declaration::Const(_, c) => self.const_expr(c, cx),
+            Declaration::Impl(_, items) | Declaration::ImplTrait(_, _, items) => {
+                for (_, c) in items {
+                    self.const_expr(c, cx);
+                }
+            }
+            Declaration::Many(decls) => {
+                for decl in decls {
+                    self.declaration(decl, cx);
+                }
+            }
+            Declaration::StaticVar(..)
+            | Declaration::Type(..)
+            | Declaration::ExternProc(..)
+            | Declaration::Trait(..) => {}
+        }
+    }
+}
+
+/// Could the values of the variables in a declaration mutate the variable with the given name?
+fn declared_values_mutate(decl: &Declaration, name: &str) -> bool {
+    match decl {
+        Declaration::Var(_, _, _, expr) | Declaration::VarPat(_, expr) => mutates(expr, name),
+        Declaration::Many(decls) => decls.iter().any(|decl| declared_values_mutate(decl, name)),
+        _ => false,
+    }
+}
+
+/// Could this expression mutate the variable with the given name? Method calls
+/// could borrow the variable mutably, so they count as mutations. Shadowing is
+/// ignored, so this errs on the side of finding mutations.
+fn mutates(expr: &Expr, name: &str) -> bool {
+    let is_var = |expr: &Expr| root_symbol(expr) == Some(name);
+    let found = match expr {
+        Expr::Refer(Mutability::Mutable, expr) => is_var(expr),
+        Expr::AssignOp(_, dst, _) => is_var(dst),
+        Expr::Apply(fun, _) => matches!(unannotated(fun), Expr::Member(expr, _) if is_var(expr)),
+        Expr::Closure(closure) => closure.get_captures().iter().any(|(captured, capture)| {
+            captured == name && *capture == Capture::Reference(Mutability::Mutable)
+        }),
+        _ => false,
+    };
+    found
+        || subexpressions(expr)
+            .into_iter()
+            .any(|expr| mutates(expr, name))
+}
+
+/// Get the variable an expression refers to part of, like `x` in `x.a[1]`.
+fn root_symbol(expr: &Expr) -> Option<&str> {
+    match unannotated(expr) {
+        Expr::Member(expr, _) | Expr::Index(expr, _) | Expr::As(expr, _) => root_symbol(expr),
+        expr => symbol(expr),
+    }
+}
+
+/// Get an expression without its annotations.
+fn unannotated(mut expr: &Expr) -> &Expr {
+    while let Expr::Annotated(inner, _) = expr {
+        expr = inner;
+    }
+    expr
+}
+
+/// Does this expression always return from the procedure it's in?
+fn diverges(expr: &Expr) -> bool {
+    match unannotated(expr) {
+        Expr::Return(_) => true,
+        Expr::Many(exprs) => exprs.iter().any(diverges),
+        Expr::Declare(_, body) => diverges(body),
+        Expr::If(cond, then, otherwise) => {
+            diverges(cond) || (diverges(then) && diverges(otherwise))
+        }
+        Expr::When(_, then, otherwise) => diverges(then) && diverges(otherwise),
+        Expr::Match(expr, branches) => {
+            diverges(expr)
+                || (!branches.is_empty() && branches.iter().all(|(_, branch)| diverges(branch)))
+        }
+        _ => false,
+    }
+}
+
+/// Is this expression just `None`, which the frontend uses for empty code?
+fn is_trivial(expr: &Expr) -> bool {
+    match unannotated(expr) {
+        Expr::ConstExpr(ConstExpr::None) => true,
+        Expr::Many(exprs) => exprs.iter().all(is_trivial),
+        _ => false,
+    }
+}
@@ -31,6 +31,7 @@ use super::lir::Expr;
 use super::vm;
 
 use log::trace;
+use std::ops::Range;
 
 use lalrpop_util::lalrpop_mod;
 use no_comment::{languages, IntoWithoutComments};
@@ -71,6 +72,31 @@ impl SourceCodeLocation {
 
         code
     }
+
+    /// Get the byte range of the first line of this location's code in the source.
+    /// The offset of the location may refer to the code without its comments,
+    /// so the code is found by its line and column.
+    pub fn span_in(&self, source: &str) -> Range<usize> {
+        let line_start = source
+            .split_inclusive('\n')
+            .take(self.line.saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>()
+            .min(source.len());
+        let line = source[line_start..].lines().next().unwrap_or("");
+        let char_offset = |n: usize| {
+            line.char_indices()
+                .nth(n)
+                .map(|(i, _)| i)
+                .unwrap_or(line.len())
+        };
+        let start = char_offset(self.column.saturating_sub(1));
+        let end = match self.length {
+            Some(length) => char_offset(self.column.saturating_sub(1) + length),
+            None => line.len(),
+        };
+        line_start + start..line_start + end.max(start)
+    }
 }
 
 lalrpop_mod!(
@@ -147,7 +173,10 @@ pub fn parse_lir(input: impl ToString) -> Result<Expr, String> {
 }
 
 /// Parse frontend sage code into an LIR expression.
-pub fn parse_frontend(input: impl ToString, filename: Option<&str>) -> Result<Expr, String> {
+pub fn parse_frontend(
+    input: impl ToString,
+    filename: Option<&str>,
+) -> Result<Expr, frontend::Error> {
     // let start = std::time::Instant::now();
     let result = frontend::parse(input, filename)?;
     trace!(target: "parse", "Parsed frontend code: {result}");
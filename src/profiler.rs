@@ -0,0 +1,193 @@
+//! # Profiler
+//!
+//! This module implements a profiler for the virtual machine interpreters.
+//! The profiler steps through a program with any interpreter implementing
+//! the `Debuggable` trait, and counts how many times each instruction is
+//! executed under each stack of function calls.
+//!
+//! Functions are identified by their virtual machine function index (the
+//! value in the register when they are called). The debug information of a
+//! program compiled with `Compile::compile_with_debug_info` records the
+//! procedure each function was compiled from, so the profile can name them.
+//!
+//! The profiler produces two reports:
+//!
+//! 1. A flat report of the number of instructions executed in each function
+//!    (not counting the functions it calls), and of each kind of instruction.
+//! 2. The collapsed call stacks, with one line for each stack of calls and the
+//!    number of instructions executed in it. This is the input format of the
+//!    flamegraph tools, like `flamegraph.pl` and `inferno-flamegraph`.
+//!
+//! The fast interpreter executes some instructions together, so they only
+//! count as one instruction in its profile.
+use crate::{
+    lir::DebugInfo,
+    vm::{Debuggable, RuntimeError},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+/// The name of the code outside of any function.
+const MAIN: &str = "<main>";
+
+/// Counts the instructions executed by a program, by function and by kind of instruction.
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    /// The names of the functions, indexed by their virtual machine function indices.
+    names: Vec<String>,
+    /// The number of times each instruction in the program was executed.
+    executed: Vec<u64>,
+    /// The number of instructions executed under each stack of function calls.
+    stacks: HashMap<Vec<usize>, u64>,
+    /// The function indices of the calls the program is currently in, outermost first.
+    frames: Vec<usize>,
+}
+
+impl Profiler {
+    /// Create a profiler which names functions with the procedures in the debug information.
+    pub fn new(debug_info: &DebugInfo) -> Self {
+        Self {
+            names: debug_info
+                .procedures()
+                .iter()
+                .map(|procedure| procedure.name().to_string())
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Execute the current instruction of the program, and count it.
+    pub fn step<I: Debuggable>(
+        &mut self,
+        interpreter: &mut I,
+        code: &I::Program,
+    ) -> Result<(), RuntimeError> {
+        let ip = interpreter.instruction_pointer();
+        let len = I::program_len(code);
+        if ip >= len {
+            // Stepping past the end of the program only stops the interpreter.
+            return interpreter.step(code);
+        }
+        if self.executed.len() < len {
+            self.executed.resize(len, 0);
+        }
+        self.executed[ip] += 1;
+        match self.stacks.get_mut(self.frames.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.frames.clone(), 1);
+            }
+        }
+
+        // The register holds the index of the function, if this is a call.
+        let function = interpreter.register().first().copied().unwrap_or_default();
+        let depth = interpreter.call_stack().len();
+        interpreter.step(code)?;
+        let new_depth = interpreter.call_stack().len();
+        if new_depth > depth {
+            self.frames.push(function as usize);
+        } else {
+            self.frames.truncate(new_depth);
+        }
+        Ok(())
+    }
+
+    /// Run the program to the end, counting its instructions.
+    pub fn run<I: Debuggable>(
+        &mut self,
+        interpreter: &mut I,
+        code: &I::Program,
+    ) -> Result<(), RuntimeError> {
+        while !interpreter.is_done() {
+            self.step(interpreter, code)?;
+        }
+        Ok(())
+    }
+
+    /// The total number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.executed.iter().sum()
+    }
+
+    /// The name of the function with the given index.
+    pub fn function_name(&self, index: usize) -> String {
+        match self.names.get(index) {
+            Some(name) => name.clone(),
+            None => format!("function #{index}"),
+        }
+    }
+
+    /// The number of instructions executed in each function, not counting the functions
+    /// it calls, from the most to the fewest. Code outside of any function is `None`.
+    pub fn functions(&self) -> Vec<(Option<usize>, u64)> {
+        let mut functions = BTreeMap::new();
+        for (stack, count) in &self.stacks {
+            *functions.entry(stack.last().copied()).or_default() += count;
+        }
+        sorted(functions)
+    }
+
+    /// The number of instructions executed of each kind, from the most to the fewest.
+    pub fn instructions<I: Debuggable>(&self, code: &I::Program) -> Vec<(String, u64)> {
+        let mut kinds = BTreeMap::new();
+        for (ip, count) in self.executed.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            let instruction = I::instruction(code, ip).unwrap_or_default();
+            let kind = instruction.split_whitespace().next().unwrap_or("?");
+            *kinds.entry(kind.to_string()).or_default() += count;
+        }
+        sorted(kinds)
+    }
+
+    /// Format the flat report of the functions and kinds of instructions executed.
+    pub fn report<I: Debuggable>(&self, code: &I::Program) -> String {
+        let total = self.total();
+        let percent = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+
+        let mut result = format!("{total} instructions executed\n\n");
+        writeln!(result, "{:>12} {:>8}  function", "count", "percent").unwrap();
+        for (function, count) in self.functions() {
+            let name = match function {
+                Some(index) => format!("{} (#{index})", self.function_name(index)),
+                None => MAIN.to_string(),
+            };
+            writeln!(result, "{count:>12} {:>7.2}%  {name}", percent(count)).unwrap();
+        }
+
+        writeln!(result, "\n{:>12} {:>8}  instruction", "count", "percent").unwrap();
+        for (kind, count) in self.instructions::<I>(code) {
+            writeln!(result, "{count:>12} {:>7.2}%  {kind}", percent(count)).unwrap();
+        }
+        result
+    }
+
+    /// Format the collapsed call stacks, one stack per line, for the flamegraph tools.
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let mut line = MAIN.to_string();
+                for index in stack {
+                    // Semicolons separate the frames of a stack.
+                    line += ";";
+                    line += &self.function_name(*index).replace(';', ":");
+                }
+                format!("{line} {count}\n")
+            })
+            .collect::<Vec<_>>();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+/// Sort counts from the most to the fewest.
+fn sorted<T: Ord>(counts: BTreeMap<T, u64>) -> Vec<(T, u64)> {
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    counts
+}
@@ -3,17 +3,194 @@
 //! An implementation of the virtual machine for the C language.
 //!
 //! This allows the virtual machine to target C programs.
+//!
+//! ## Portability
+//!
+//! The generated code is standard C99, so it builds with GCC, Clang, and
+//! other conforming compilers with `-std=c99 -pedantic`. Every virtual machine
+//! function is compiled to a top-level static function, and `call` looks them
+//! up in a constant table of function pointers.
 use super::{Architecture, CompiledTarget};
 use crate::{
+    lir::DebugInfo,
+    parse::SourceCodeLocation,
     side_effects::{Input, InputMode, Output, OutputMode},
-    vm::{CoreOp, StandardOp},
+    vm::{CoreOp, SourceMap, StandardOp},
 };
+use codespan_reporting::term::termcolor::NoColor;
 use log::warn;
 
+/// The placeholder for the error message in the rendered diagnostics.
+const MESSAGE_PLACEHOLDER: &str = "\0";
+
 /// The type for the C target which implements the `Target` trait.
 /// This allows the compiler to target the C language.
 #[derive(Default)]
-pub struct C;
+pub struct C {
+    /// For each span in the program's source map, the diagnostic for a runtime
+    /// error in that span (as a format string for the message), and the span's
+    /// frame in a backtrace.
+    locations: Vec<(String, String)>,
+}
+
+impl C {
+    /// Report runtime errors with diagnostics pointing at the source code, using
+    /// the source map of the program. The program must be built with its debug
+    /// markers, which the compiled code uses to keep track of its location.
+    ///
+    /// The snippet for every span is rendered now, when the program is compiled, so
+    /// `source_of` is only called here. Spans in files it returns `None` for are
+    /// reported with just the message and the backtrace.
+    pub fn with_source_map(
+        mut self,
+        source_map: &SourceMap,
+        source_of: impl Fn(&SourceCodeLocation) -> Option<String>,
+    ) -> Self {
+        self.locations = source_map
+            .spans()
+            .iter()
+            .map(|span| {
+                let mut snippet = NoColor::new(vec![]);
+                SourceMap::emit_snippet(&mut snippet, MESSAGE_PLACEHOLDER, Some(span), &source_of)
+                    .expect("Could not render diagnostic");
+                let snippet = String::from_utf8_lossy(&snippet.into_inner())
+                    .split(MESSAGE_PLACEHOLDER)
+                    .map(|part| c_string(&part.replace('%', "%%")))
+                    .collect::<Vec<_>>()
+                    .join("%s");
+                (snippet, c_string(&span.to_string()))
+            })
+            .collect();
+        self
+    }
+
+    /// The code which reports runtime errors.
+    fn runtime(&self) -> String {
+        if self.locations.is_empty() {
+            return r#"
+void runtime_error(const char *format, int64_t value) {
+    fflush(stdout);
+    fprintf(stderr, format, (long long)value);
+    fprintf(stderr, "\n");
+    exit(1);
+}
+"#
+            .to_string();
+        }
+
+        let (snippets, frames): (Vec<_>, Vec<_>) = self
+            .locations
+            .iter()
+            .map(|(snippet, frame)| (format!("\t\"{snippet}\""), format!("\t\"{frame}\"")))
+            .unzip();
+        format!(
+            r#"
+/* The source code location of the running code, and of each call on the call stack. */
+int64_t loc = -1, loc_stack[65536];
+size_t loc_depth = 0;
+const char *loc_snippets[] = {{
+{}
+}};
+const char *loc_frames[] = {{
+{}
+}};
+
+void runtime_error(const char *format, int64_t value) {{
+    char message[256];
+    int64_t frames[{max}];
+    size_t i, count = 0;
+    fflush(stdout);
+    snprintf(message, sizeof message, format, (long long)value);
+
+    /* Find the locations of the frames we know about, innermost first. */
+    if (loc >= 0) frames[count++] = loc;
+    for (i = loc_depth < 65536? loc_depth : 65536; i > 0; i--) {{
+        if (loc_stack[i - 1] < 0) continue;
+        if (count < {max}) frames[count] = loc_stack[i - 1];
+        count++;
+    }}
+
+    if (count == 0) {{
+        fprintf(stderr, "error: %s\n", message);
+        exit(1);
+    }}
+    fprintf(stderr, loc_snippets[frames[0]], message);
+    fprintf(stderr, "backtrace:\n");
+    for (i = 0; i < count && i < {max}; i++) fprintf(stderr, "  #%d %s\n", (int)i, loc_frames[frames[i]]);
+    if (count > {max}) fprintf(stderr, "  ... %d more frames\n", (int)(count - {max}));
+    exit(1);
+}}
+"#,
+            snippets.join(",\n"),
+            frames.join(",\n"),
+            max = SourceMap::MAX_BACKTRACE,
+        )
+    }
+
+    /// The code which calls a function from the function table by its index.
+    fn call(&self) -> &'static str {
+        if self.locations.is_empty() {
+            return r#"
+void call(int64_t f) {
+    if (f < 0 || f >= funs_len) runtime_error("function %lld not defined", f);
+    funs[f]();
+}
+"#;
+        }
+
+        r#"
+void call(int64_t f) {
+    if (f < 0 || f >= funs_len) runtime_error("function %lld not defined", f);
+    if (loc_depth < 65536) loc_stack[loc_depth] = loc;
+    loc_depth++;
+    /* The function has no location until it reaches its first marker. */
+    loc = -1;
+    funs[f]();
+    loc_depth--;
+    if (loc_depth < 65536) loc = loc_stack[loc_depth];
+}
+"#
+    }
+}
+
+/// Write a float as a C99 constant expression.
+fn c_float(n: f64) -> String {
+    if n.is_nan() {
+        "NAN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "INFINITY" } else { "-INFINITY" }.to_string()
+    } else {
+        // The debug format always has a decimal point or an exponent, unlike the display format,
+        // which writes large floats out as integer constants that are too large for C.
+        format!("{n:?}")
+    }
+}
+
+/// Write an integer as a C99 constant expression.
+fn c_int(n: i64) -> String {
+    if n == i64::MIN {
+        // The negation of `9223372036854775808`, which is too large for a signed constant.
+        "INT64_MIN".to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+/// Quote a string as the contents of a C string literal.
+fn c_string(text: &str) -> String {
+    let mut result = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'\\' => result += "\\\\",
+            b'"' => result += "\\\"",
+            b'\n' => result += "\\n",
+            b' '..=b'~' => result.push(byte as char),
+            // Octal escapes always have three digits, so they can't run into the next character.
+            _ => result += &format!("\\{byte:03o}"),
+        }
+    }
+    result
+}
 
 impl Architecture for C {
     fn supports_input(&self, i: &Input) -> bool {
@@ -33,6 +210,12 @@ impl Architecture for C {
     fn op(&mut self, op: &CoreOp) -> String {
         match op {
             CoreOp::Comment(text) => {
+                // Keep track of the location of the running code for reporting runtime errors.
+                if let Some(id) = DebugInfo::parse_marker(text) {
+                    if id < self.locations.len() {
+                        return format!("loc = {id}; // {text}");
+                    }
+                }
                 format!("// {}", text.replace('\n', "\n// ").replace('\r', ""))
                 // let mut comment = String::new();
                 // for line in n.split('\n') {
@@ -44,19 +227,19 @@ impl Architecture for C {
             CoreOp::If => "if (scalar_reg.i) {".to_string(),
             CoreOp::Else => "} else {".to_string(),
             CoreOp::Set(n) => {
-                let mut tmp = format!("scalar_reg.i = {};", n[0]);
+                let mut tmp = format!("scalar_reg.i = {};", c_int(n[0]));
                 // // If all the values in the vector are the same, use `memset`
                 // if n.iter().all(|&x| x == n[0]) {
                 //     // tmp += &format!("memset(vector_reg, {}, {} * sizeof(cell));", n[0], n.len());
                 // } else {
                 // }
                 for (i, val) in n.iter().enumerate() {
-                    tmp += &format!("vector_reg[{}].i = {};", i, val);
+                    tmp += &format!("vector_reg[{}].i = {};", i, c_int(*val));
                 }
                 tmp
                 // format!("scalar_reg.i = {};", n)
             }
-            CoreOp::Call => "funs[scalar_reg.i]();".to_string(),
+            CoreOp::Call => "call(scalar_reg.i);".to_string(),
             CoreOp::Return => "return;".to_string(),
             CoreOp::Store(1) => "*ptr = scalar_reg;".to_string(),
             CoreOp::Load(1) => "scalar_reg = *ptr; vector_reg[0] = scalar_reg;".to_string(),
@@ -83,23 +266,23 @@ impl Architecture for C {
             CoreOp::Index(1) => "scalar_reg.p += ptr->i;".to_string(),
             CoreOp::Index(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].p += ptr[i].i;"),
 
-            CoreOp::Add(1) => "scalar_reg.i += ptr->i;".to_string(),
-            CoreOp::Add(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i += ptr[i].i;"),
+            CoreOp::Add(1) => "scalar_reg.i = (unsigned long long)scalar_reg.i + (unsigned long long)ptr->i;".to_string(),
+            CoreOp::Add(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (unsigned long long)vector_reg[i].i + (unsigned long long)ptr[i].i;"),
 
-            CoreOp::Sub(1) => "scalar_reg.i -= ptr->i;".to_string(),
-            CoreOp::Sub(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i -= ptr[i].i;"),
+            CoreOp::Sub(1) => "scalar_reg.i = (unsigned long long)scalar_reg.i - (unsigned long long)ptr->i;".to_string(),
+            CoreOp::Sub(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (unsigned long long)vector_reg[i].i - (unsigned long long)ptr[i].i;"),
 
-            CoreOp::Mul(1) => "scalar_reg.i *= ptr->i;".to_string(),
-            CoreOp::Mul(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i *= ptr[i].i;"),
+            CoreOp::Mul(1) => "scalar_reg.i = (unsigned long long)scalar_reg.i * (unsigned long long)ptr->i;".to_string(),
+            CoreOp::Mul(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (unsigned long long)vector_reg[i].i * (unsigned long long)ptr[i].i;"),
 
-            CoreOp::Div(1) => "scalar_reg.i /= ptr->i;".to_string(),
-            CoreOp::Div(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i /= ptr[i].i;"),
+            CoreOp::Div(1) => "if (ptr->i == 0) runtime_error(\"division by zero\", 0); scalar_reg.i /= ptr->i;".to_string(),
+            CoreOp::Div(n) => format!("for (int i = 0; i < {n}; i++) {{ if (ptr[i].i == 0) runtime_error(\"division by zero\", 0); vector_reg[i].i /= ptr[i].i; }}"),
 
-            CoreOp::Rem(1) => "scalar_reg.i %= ptr->i;".to_string(),
-            CoreOp::Rem(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i %= ptr[i].i;"),
+            CoreOp::Rem(1) => "if (ptr->i == 0) runtime_error(\"division by zero\", 0); scalar_reg.i %= ptr->i;".to_string(),
+            CoreOp::Rem(n) => format!("for (int i = 0; i < {n}; i++) {{ if (ptr[i].i == 0) runtime_error(\"division by zero\", 0); vector_reg[i].i %= ptr[i].i; }}"),
 
-            CoreOp::Neg(1) => "scalar_reg.i = -scalar_reg.i;".to_string(),
-            CoreOp::Neg(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = -vector_reg[i].i;"),
+            CoreOp::Neg(1) => "scalar_reg.i = -(unsigned long long)scalar_reg.i;".to_string(),
+            CoreOp::Neg(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = -(unsigned long long)vector_reg[i].i;"),
 
             CoreOp::Inc(1) => "scalar_reg.i++;".to_string(),
             CoreOp::Inc(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i++;"),
@@ -138,14 +321,15 @@ impl Architecture for C {
             CoreOp::BitwiseNot(1) => "scalar_reg.i = ~scalar_reg.i;".to_string(),
             CoreOp::BitwiseNot(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = ~vector_reg[i].i;",),
 
-            CoreOp::LeftShift(1) => "scalar_reg.i <<= ptr->i;".to_string(),
-            CoreOp::LeftShift(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i <<= ptr[i].i;",),
+            // Shifting by 64 bits or more is undefined in C, so the shift is masked like on x86.
+            CoreOp::LeftShift(1) => "scalar_reg.i = (unsigned long long)scalar_reg.i << (ptr->i & 63);".to_string(),
+            CoreOp::LeftShift(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (unsigned long long)vector_reg[i].i << (ptr[i].i & 63);",),
 
-            CoreOp::LogicalRightShift(1) => "scalar_reg.i = (unsigned long long)scalar_reg.i >> ptr->i;".to_string(),
-            CoreOp::LogicalRightShift(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (unsigned long long)vector_reg[i].i >> ptr[i].i;",),
+            CoreOp::LogicalRightShift(1) => "scalar_reg.i = (unsigned long long)scalar_reg.i >> (ptr->i & 63);".to_string(),
+            CoreOp::LogicalRightShift(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (unsigned long long)vector_reg[i].i >> (ptr[i].i & 63);",),
 
-            CoreOp::ArithmeticRightShift(1) => "scalar_reg.i >>= ptr->i;".to_string(),
-            CoreOp::ArithmeticRightShift(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i >>= ptr[i].i;",),
+            CoreOp::ArithmeticRightShift(1) => "scalar_reg.i >>= ptr->i & 63;".to_string(),
+            CoreOp::ArithmeticRightShift(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i >>= ptr[i].i & 63;",),
 
             CoreOp::IsNonNegative(1) => "scalar_reg.i = scalar_reg.i >= 0;".to_string(),
             CoreOp::IsNonNegative(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = vector_reg[i].i >= 0;",),
@@ -162,9 +346,9 @@ impl Architecture for C {
             StandardOp::Peek => self.peek()?,
             StandardOp::Poke => self.poke()?,
             StandardOp::Set(n) => {
-                let mut tmp = format!("scalar_reg.f = {};", n[0]);
+                let mut tmp = format!("scalar_reg.f = {};", c_float(n[0]));
                 for (i, val) in n.iter().enumerate() {
-                    tmp += &format!("vector_reg[{}].f = {};", i, val);
+                    tmp += &format!("vector_reg[{}].f = {};", i, c_float(*val));
                 }
                 tmp
             }
@@ -244,10 +428,8 @@ impl Architecture for C {
                 format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = vector_reg[i].f >= 0;")
             }
 
-            StandardOp::Alloc => {
-                "scalar_reg.p = (cell*)malloc(scalar_reg.i * sizeof(cell));".to_string()
-            }
-            StandardOp::Free => "free(scalar_reg.p);".to_string(),
+            StandardOp::Alloc => "scalar_reg.p = allocate(scalar_reg.i);".to_string(),
+            StandardOp::Free => "deallocate(scalar_reg);".to_string(),
             _ => return Err(format!("Invalid standard op for C target {op:?}")),
         })
     }
@@ -260,7 +442,7 @@ impl Architecture for C {
     }
 
     fn declare_proc(&mut self, label_id: usize) -> String {
-        format!("void f{label_id}() {{")
+        format!("static void f{label_id}(void) {{")
     }
 
     fn name(&self) -> &str {
@@ -278,8 +460,8 @@ impl Architecture for C {
         let ch = src.channel.0;
         match src.mode {
             InputMode::StdinChar => Ok("tmp = getchar(); scalar_reg.i = tmp == EOF? 0 : tmp;".to_string()),
-            InputMode::StdinInt => Ok("scanf(\"%ld\", &tmp_scalar_reg.i); reg = tmp_reg;".to_string()),
-            InputMode::StdinFloat => Ok("scanf(\"%lf\", &tmp_scalar_reg.f); reg = tmp_reg;".to_string()),
+            InputMode::StdinInt => Ok("input = 0; scanf(\"%lld\", &input); scalar_reg.i = input;".to_string()),
+            InputMode::StdinFloat => Ok("tmp_reg.f = 0; scanf(\"%lf\", &tmp_reg.f); scalar_reg = tmp_reg;".to_string()),
             InputMode::Thermometer => Ok("scalar_reg.f = 293.15;".to_string()),
             InputMode::Clock => Ok("scalar_reg.i = time(NULL);".to_string()),
             InputMode::Random => Ok("scalar_reg.i = rand();".to_string()),
@@ -292,25 +474,28 @@ impl Architecture for C {
 
     fn put(&mut self, dst: &Output) -> Result<String, String> {
         match dst.mode {
-            OutputMode::StdoutChar => Ok("putchar(scalar_reg.i);".to_string()),
-            OutputMode::StdoutInt => Ok("printf(\"%lld\", scalar_reg.i);".to_string()),
-            OutputMode::StdoutFloat => Ok("printf(\"%.1lf\", scalar_reg.f);".to_string()),
-            OutputMode::StderrChar => Ok("fprintf(stderr, \"%c\", scalar_reg.i);".to_string()),
-            OutputMode::StderrInt => Ok("fprintf(stderr, \"%lld\", scalar_reg.i);".to_string()),
-            OutputMode::StderrFloat => Ok("fprintf(stderr, \"%.1lf\", scalar_reg.f);".to_string()),
+            OutputMode::StdoutChar => Ok("putchar((int)scalar_reg.i);".to_string()),
+            OutputMode::StdoutInt => Ok("printf(\"%lld\", (long long)scalar_reg.i);".to_string()),
+            OutputMode::StdoutFloat => Ok("printf(\"%.1f\", scalar_reg.f);".to_string()),
+            OutputMode::StderrChar => Ok("fputc((int)scalar_reg.i, stderr);".to_string()),
+            OutputMode::StderrInt => {
+                Ok("fprintf(stderr, \"%lld\", (long long)scalar_reg.i);".to_string())
+            }
+            OutputMode::StderrFloat => Ok("fprintf(stderr, \"%.1f\", scalar_reg.f);".to_string()),
             OutputMode::Heater => Ok("printf(\"Heating...\");".to_string()),
             OutputMode::Cooler => Ok("printf(\"Cooling...\");".to_string()),
             _ => Err("Output not supported by this target".to_string()),
         }
     }
     fn peek(&mut self) -> Result<String, String> {
-        Ok("reg = *(ffi_ptr--);".to_string())
+        Ok("scalar_reg = *(ffi_ptr--);".to_string())
     }
     fn poke(&mut self) -> Result<String, String> {
-        Ok("*(++ffi_ptr) = reg;".to_string())
+        Ok("*(++ffi_ptr) = scalar_reg;".to_string())
     }
     fn prelude(&self, is_core: bool) -> Option<String> {
         let mut result = r#"#include <stdint.h>
+#include <stdlib.h>
 #include <stdio.h>
 #include <math.h>
 #include <string.h>
@@ -324,34 +509,109 @@ typedef union cell {
 cell tape[200000], *refs[1024], *ptr = tape, **ref = refs, scalar_reg, vector_reg[1024], tmp_reg, ffi_channel[256], *ffi_ptr = ffi_channel;
 
 unsigned int ref_ptr = 0;
-void (*funs[10000])(void);
 
 int tmp;
+long long input;
 "#
         .to_string();
+        result += &self.runtime();
 
         if !is_core {
-            result = "#include <stdlib.h>\n".to_string() + &result;
+            result += ALLOCATOR;
         }
 
         Some(result)
     }
 
-    fn post_funs(&self, funs: Vec<i32>) -> Option<String> {
-        let mut result = String::from("int main () {\n");
-        for fun in funs {
-            result += &format!("\tfuns[{fun}] = f{fun};\n", fun = fun)
+    fn pre_funs(&self, mut funs: Vec<i32>) -> Option<String> {
+        funs.sort();
+        // Declare the functions before the table, so that they can call each other in any order.
+        let mut result = String::new();
+        for fun in &funs {
+            result += &format!("static void f{fun}(void);\n");
         }
+        let table = if funs.is_empty() {
+            // C doesn't allow empty initializers, so the empty table holds a null pointer.
+            "NULL".to_string()
+        } else {
+            funs.iter()
+                .map(|fun| format!("f{fun}"))
+                .collect::<Vec<_>>()
+                .join(",\n\t")
+        };
+        result += &format!(
+            "static void (*const funs[])(void) = {{\n\t{table}\n}};\nstatic const int64_t funs_len = {};\n",
+            funs.len()
+        );
+        result += self.call();
         Some(result)
     }
 
+    fn post_funs(&self, _funs: Vec<i32>) -> Option<String> {
+        // The main code can return early like any other function, so it gets its own function.
+        Some("static void sage_main(void) {\n".to_string())
+    }
+
     fn postop(&self) -> Option<String> {
         Some("\n".to_string())
     }
 
     fn postlude(&self, _is_core: bool) -> Option<String> {
-        Some("return 0;\n}".to_string())
+        Some("return;\n}\n\nint main(void) {\n\tsage_main();\n\treturn 0;\n}\n".to_string())
     }
 }
 
 impl CompiledTarget for C {}
+
+/// The heap allocator used by the standard variant. Each block is `malloc`ed
+/// with a header, and every block ever allocated stays in a list along with
+/// whether it's freed. Allocating zeroes and reuses the first freed block which
+/// is large enough (whole, without splitting it), or else `malloc`s a new one.
+/// Freed blocks are never merged or returned to the C runtime.
+///
+/// Unlike the interpreter's allocator, blocks don't live on the tape, so their
+/// addresses differ. Like it, freeing the null pointer does nothing, and double
+/// frees or frees of pointers which were never allocated are runtime errors,
+/// which the list lets us detect without reading memory in front of a pointer.
+const ALLOCATOR: &str = r#"
+typedef struct block {
+    struct block *next;
+    int64_t size;
+    int freed;
+    cell data[];
+} block;
+
+block *blocks = NULL;
+
+cell *allocate(int64_t size) {
+    block *b;
+    if (size < 0) runtime_error("cannot allocate a negative number of cells: %lld", size);
+    if (size < 1) size = 1;
+    for (b = blocks; b != NULL; b = b->next) {
+        if (b->freed && b->size >= size) break;
+    }
+    if (b == NULL) {
+        b = (block*)malloc(sizeof(block) + size * sizeof(cell));
+        if (b == NULL) runtime_error("out of memory while allocating cells: %lld", size);
+        b->size = size;
+        b->next = blocks;
+        blocks = b;
+    }
+    b->freed = 0;
+    memset(b->data, 0, b->size * sizeof(cell));
+    return b->data;
+}
+
+void deallocate(cell c) {
+    block *b;
+    if (c.i == -128) return; /* The null pointer. */
+    for (b = blocks; b != NULL; b = b->next) {
+        if (b->data == c.p) {
+            if (b->freed) runtime_error("double free of address %lld", c.i);
+            b->freed = 1;
+            return;
+        }
+    }
+    runtime_error("invalid free of address %lld", c.i);
+}
+"#;
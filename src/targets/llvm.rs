@@ -0,0 +1,1055 @@
+//! # LLVM Target
+//!
+//! An implementation of the virtual machine for LLVM, as textual LLVM IR.
+//!
+//! This allows Sage programs to be optimized by LLVM and compiled for any host
+//! it supports. The IR uses opaque pointers, so it needs LLVM 15 or later
+//! (or LLVM 14 with `-opaque-pointers`), and it links against the C standard
+//! library and the math library:
+//!
+//! ```bash
+//! $ opt -O2 out.ll -S -o out.ll
+//! $ llc -relocation-model=pic out.ll -o out.s
+//! $ cc out.s -o out -lm
+//! ```
+//!
+//! ## Machine State
+//!
+//! The tape is a global array, and the tape pointer is a global pointer into it.
+//! Within a basic block, the cells of the register and the tape pointer are kept
+//! in SSA registers, and they are only written back to their globals at the end
+//! of the block or before calling a function. Like the C target, pointers are
+//! addresses in memory rather than indices on the tape, and the standard variant's
+//! heap is allocated with `malloc`.
+//!
+//! ## Foreign Functions
+//!
+//! A foreign function call to `name` calls the external function `__name`, which
+//! takes no arguments. Like the C target, foreign functions pop their arguments
+//! from, and push their results onto, the foreign function channel through the
+//! external global `ffi_ptr`, which points to the top of the channel.
+use super::{Architecture, CompiledTarget};
+use crate::{
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{CoreOp, StandardOp},
+    NULL,
+};
+use std::collections::BTreeSet;
+
+/// The number of cells on the tape.
+const TAPE_SIZE: usize = 200000;
+/// The number of cells in the register.
+const REGISTER_SIZE: usize = 1024;
+/// The maximum depth of the deref stack.
+const DEREF_STACK_SIZE: usize = 1024;
+/// The number of cells in the foreign function channel.
+const FFI_CHANNEL_SIZE: usize = 256;
+/// The largest number of cells copied between the register and the tape
+/// with individual instructions, rather than with `memcpy`.
+const MAX_UNROLLED_COPY: usize = 8;
+
+/// A value in the cache of the machine state, and whether it still
+/// needs to be written back to memory.
+#[derive(Clone, Debug)]
+struct Cached {
+    value: String,
+    dirty: bool,
+}
+
+/// The type for the LLVM target which implements the `Target` trait.
+/// This allows the compiler to target any machine LLVM supports.
+#[derive(Default)]
+pub struct Llvm {
+    /// The number of SSA registers used so far.
+    values: usize,
+    /// The number of labels used for the conditionals and loops so far.
+    labels: usize,
+    /// The labels of the conditionals and loops we're currently in.
+    blocks: Vec<usize>,
+    /// The values of the register's cells in the current basic block.
+    reg: Vec<Option<Cached>>,
+    /// The value of the tape pointer in the current basic block.
+    ptr: Option<Cached>,
+    /// The names of the foreign functions called by the program.
+    ffi: BTreeSet<String>,
+}
+
+impl Llvm {
+    /// Create a new SSA register.
+    fn fresh(&mut self) -> String {
+        self.values += 1;
+        format!("%v{}", self.values)
+    }
+
+    /// Create a new label for a conditional or a loop, and enter its block.
+    fn enter_block(&mut self) -> usize {
+        let label = self.labels;
+        self.labels += 1;
+        self.blocks.push(label);
+        label
+    }
+
+    /// Start a new basic block. Nothing is known about the machine state in it.
+    fn label(&mut self, name: &str) -> String {
+        self.reg.clear();
+        self.ptr = None;
+        format!("{name}:")
+    }
+
+    /// The code to write the changed machine state back to memory.
+    fn flush_code(&self) -> Vec<String> {
+        let mut code = vec![];
+        for (i, cell) in self.reg.iter().enumerate() {
+            if let Some(Cached { value, dirty: true }) = cell {
+                code.push(format!("store i64 {value}, ptr {}", reg_addr(i)));
+            }
+        }
+        if let Some(Cached { value, dirty: true }) = &self.ptr {
+            code.push(format!("store ptr {value}, ptr @sage_ptr"));
+        }
+        code
+    }
+
+    /// Write the changed machine state back to memory, and forget it,
+    /// before leaving the basic block or calling a function.
+    fn flush(&mut self) -> Vec<String> {
+        let code = self.flush_code();
+        self.reg.clear();
+        self.ptr = None;
+        code
+    }
+
+    /// Get the value of a cell of the register.
+    fn get_reg(&mut self, i: usize, code: &mut Vec<String>) -> String {
+        if let Some(Some(cached)) = self.reg.get(i) {
+            return cached.value.clone();
+        }
+        let value = self.fresh();
+        code.push(format!("{value} = load i64, ptr {}", reg_addr(i)));
+        self.cache_reg(i, value.clone(), false);
+        value
+    }
+
+    /// Set the value of a cell of the register.
+    fn set_reg(&mut self, i: usize, value: String) {
+        self.cache_reg(i, value, true)
+    }
+
+    fn cache_reg(&mut self, i: usize, value: String, dirty: bool) {
+        if self.reg.len() <= i {
+            self.reg.resize(i + 1, None);
+        }
+        self.reg[i] = Some(Cached { value, dirty });
+    }
+
+    /// Get the tape pointer.
+    fn get_ptr(&mut self, code: &mut Vec<String>) -> String {
+        if let Some(cached) = &self.ptr {
+            return cached.value.clone();
+        }
+        let value = self.fresh();
+        code.push(format!("{value} = load ptr, ptr @sage_ptr"));
+        self.ptr = Some(Cached {
+            value: value.clone(),
+            dirty: false,
+        });
+        value
+    }
+
+    /// Set the tape pointer.
+    fn set_ptr(&mut self, value: String) {
+        self.ptr = Some(Cached { value, dirty: true });
+    }
+
+    /// Get the address of a cell on the tape, relative to the tape pointer.
+    fn tape_addr(&mut self, i: usize, code: &mut Vec<String>) -> String {
+        let ptr = self.get_ptr(code);
+        if i == 0 {
+            return ptr;
+        }
+        let addr = self.fresh();
+        code.push(format!("{addr} = getelementptr i64, ptr {ptr}, i64 {i}"));
+        addr
+    }
+
+    /// Get the value of a cell on the tape, relative to the tape pointer.
+    fn get_tape(&mut self, i: usize, code: &mut Vec<String>) -> String {
+        let addr = self.tape_addr(i, code);
+        let value = self.fresh();
+        code.push(format!("{value} = load i64, ptr {addr}"));
+        value
+    }
+
+    /// Apply an operation to the first `n` cells of the register (and the tape, if `tape` is set).
+    ///
+    /// The operation is given the values of the cells in the register and on the tape
+    /// (or an empty string), and it returns its instructions and the new value of the register.
+    fn each(
+        &mut self,
+        n: usize,
+        tape: bool,
+        op: impl Fn(&mut Self, &str, &str) -> (Vec<String>, String),
+    ) -> String {
+        let mut code = vec![];
+        for i in 0..n {
+            let a = self.get_reg(i, &mut code);
+            let b = if tape {
+                self.get_tape(i, &mut code)
+            } else {
+                String::new()
+            };
+            let (instructions, result) = op(self, &a, &b);
+            code.extend(instructions);
+            self.set_reg(i, result);
+        }
+        lines(&code)
+    }
+
+    /// Apply a binary integer instruction to the register and the tape.
+    fn int_op(&mut self, n: usize, instruction: &str) -> String {
+        self.each(n, true, |this, a, b| {
+            let result = this.fresh();
+            (
+                vec![format!("{result} = {instruction} i64 {a}, {b}")],
+                result,
+            )
+        })
+    }
+
+    /// Apply a unary integer operation to the register, given as an instruction with
+    /// the register's cell as its last operand (like `sub i64 0`).
+    fn int_unary(&mut self, n: usize, instruction: &str) -> String {
+        self.each(n, false, |this, a, _| {
+            let result = this.fresh();
+            (vec![format!("{result} = {instruction}, {a}")], result)
+        })
+    }
+
+    /// Apply a boolean comparison to the register (and the tape), storing 1 or 0 in the register.
+    fn compare(&mut self, n: usize, tape: bool, compare: impl Fn(&str, &str) -> String) -> String {
+        self.each(n, tape, |this, a, b| {
+            let (cond, result) = (this.fresh(), this.fresh());
+            (
+                vec![
+                    format!("{cond} = {}", compare(a, b)),
+                    format!("{result} = zext i1 {cond} to i64"),
+                ],
+                result,
+            )
+        })
+    }
+
+    /// Apply a float operation to the register (and the tape). The operation is
+    /// given the cells as doubles, and returns the expression for the result.
+    fn float_op(&mut self, n: usize, tape: bool, op: impl Fn(&str, &str) -> String) -> String {
+        self.each(n, tape, |this, a, b| {
+            let (x, y, z, result) = (this.fresh(), this.fresh(), this.fresh(), this.fresh());
+            let mut code = vec![format!("{x} = bitcast i64 {a} to double")];
+            if tape {
+                code.push(format!("{y} = bitcast i64 {b} to double"));
+            }
+            code.push(format!("{z} = {}", op(&x, &y)));
+            code.push(format!("{result} = bitcast double {z} to i64"));
+            (code, result)
+        })
+    }
+
+    /// Copy `n` cells from `src` to `dst`. Copying into the register
+    /// must forget the cells of the register first.
+    fn copy(&mut self, n: usize, to_tape: bool) -> String {
+        let mut code = vec![];
+        if n <= MAX_UNROLLED_COPY {
+            for i in 0..n {
+                if to_tape {
+                    let value = self.get_reg(i, &mut code);
+                    let addr = self.tape_addr(i, &mut code);
+                    code.push(format!("store i64 {value}, ptr {addr}"));
+                } else {
+                    let value = self.get_tape(i, &mut code);
+                    self.set_reg(i, value);
+                }
+            }
+        } else {
+            let ptr = self.get_ptr(&mut code);
+            // Write the register to memory, and keep the tape pointer.
+            code.extend(self.flush_code());
+            self.reg.clear();
+            if let Some(cached) = &mut self.ptr {
+                cached.dirty = false;
+            }
+            let (dst, src) = if to_tape {
+                (ptr, "@sage_reg".to_string())
+            } else {
+                ("@sage_reg".to_string(), ptr)
+            };
+            code.push(format!(
+                "call ptr @memcpy(ptr {dst}, ptr {src}, i64 {})",
+                n * 8
+            ));
+        }
+        lines(&code)
+    }
+}
+
+/// The address of a cell of the register, as a constant.
+fn reg_addr(i: usize) -> String {
+    if i == 0 {
+        "@sage_reg".to_string()
+    } else {
+        format!("getelementptr inbounds ([{REGISTER_SIZE} x i64], ptr @sage_reg, i64 0, i64 {i})")
+    }
+}
+
+/// Declare a constant C string.
+fn string_constant(name: &str, text: &str) -> String {
+    format!(
+        "@.str.{name} = private unnamed_addr constant [{} x i8] c\"{}\\00\"\n",
+        text.len() + 1,
+        text.replace('\\', "\\5C").replace('"', "\\22")
+    )
+}
+
+/// Join instructions into the code for a single virtual machine instruction.
+fn lines(instructions: &[String]) -> String {
+    instructions.join("\n\t")
+}
+
+impl Architecture for Llvm {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(
+            i.mode,
+            InputMode::StdinChar | InputMode::StdinFloat | InputMode::StdinInt
+        )
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutFloat
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrFloat
+                | OutputMode::StderrInt
+        )
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => {
+                format!("; {}", text.replace('\n', "\n\t; ").replace('\r', ""))
+            }
+            CoreOp::While => {
+                let label = self.enter_block();
+                let mut code = self.flush();
+                code.push(format!("br label %while{label}"));
+                code.push(self.label(&format!("while{label}")));
+                let value = self.get_reg(0, &mut code);
+                let cond = self.fresh();
+                code.push(format!("{cond} = icmp ne i64 {value}, 0"));
+                code.push(format!(
+                    "br i1 {cond}, label %body{label}, label %end{label}"
+                ));
+                code.push(self.label(&format!("body{label}")));
+                lines(&code)
+            }
+            CoreOp::If => {
+                let label = self.enter_block();
+                let mut code = vec![];
+                let value = self.get_reg(0, &mut code);
+                code.extend(self.flush());
+                let cond = self.fresh();
+                code.push(format!("{cond} = icmp ne i64 {value}, 0"));
+                code.push(format!(
+                    "br i1 {cond}, label %then{label}, label %else{label}"
+                ));
+                code.push(self.label(&format!("then{label}")));
+                lines(&code)
+            }
+            CoreOp::Else => {
+                let label = *self.blocks.last().expect("Unexpected else");
+                let mut code = self.flush();
+                code.push(format!("br label %end{label}"));
+                code.push(self.label(&format!("else{label}")));
+                lines(&code)
+            }
+            CoreOp::Set(n) => {
+                for (i, value) in n.iter().enumerate() {
+                    self.set_reg(i, value.to_string());
+                }
+                String::new()
+            }
+            CoreOp::Call => {
+                let mut code = vec![];
+                let value = self.get_reg(0, &mut code);
+                code.extend(self.flush());
+                code.push(format!("call void @sage_call(i64 {value})"));
+                lines(&code)
+            }
+            CoreOp::Return => {
+                // The code after the return is unreachable, but it still needs a block.
+                let mut code = self.flush();
+                code.push("ret void".to_string());
+                self.labels += 1;
+                code.push(self.label(&format!("next{}", self.labels)));
+                lines(&code)
+            }
+
+            CoreOp::Store(n) => self.copy(*n, true),
+            CoreOp::Load(n) => self.copy(*n, false),
+
+            CoreOp::Move(n) => {
+                let mut code = vec![];
+                let ptr = self.get_ptr(&mut code);
+                let moved = self.fresh();
+                code.push(format!("{moved} = getelementptr i64, ptr {ptr}, i64 {n}"));
+                self.set_ptr(moved);
+                lines(&code)
+            }
+            CoreOp::Where => {
+                let mut code = vec![];
+                let ptr = self.get_ptr(&mut code);
+                let value = self.fresh();
+                code.push(format!("{value} = ptrtoint ptr {ptr} to i64"));
+                self.set_reg(0, value);
+                lines(&code)
+            }
+            CoreOp::Deref => {
+                let mut code = vec![];
+                let ptr = self.get_ptr(&mut code);
+                let (top, next, cell, deref) =
+                    (self.fresh(), self.fresh(), self.fresh(), self.fresh());
+                code.push(format!("{top} = load ptr, ptr @sage_ref"));
+                code.push(format!("store ptr {ptr}, ptr {top}"));
+                code.push(format!("{next} = getelementptr ptr, ptr {top}, i64 1"));
+                code.push(format!("store ptr {next}, ptr @sage_ref"));
+                code.push(format!("{cell} = load i64, ptr {ptr}"));
+                code.push(format!("{deref} = inttoptr i64 {cell} to ptr"));
+                self.set_ptr(deref);
+                lines(&code)
+            }
+            CoreOp::Refer => {
+                let (top, prev, ptr) = (self.fresh(), self.fresh(), self.fresh());
+                self.set_ptr(ptr.clone());
+                lines(&[
+                    format!("{top} = load ptr, ptr @sage_ref"),
+                    format!("{prev} = getelementptr ptr, ptr {top}, i64 -1"),
+                    format!("store ptr {prev}, ptr @sage_ref"),
+                    format!("{ptr} = load ptr, ptr {prev}"),
+                ])
+            }
+            CoreOp::Offset(offset, n) => {
+                let offset = offset.wrapping_mul(8);
+                self.int_unary(*n, &format!("add i64 {offset}"))
+            }
+            CoreOp::Index(n) => self.each(*n, true, |this, a, b| {
+                let (bytes, result) = (this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("{bytes} = shl i64 {b}, 3"),
+                        format!("{result} = add i64 {a}, {bytes}"),
+                    ],
+                    result,
+                )
+            }),
+
+            CoreOp::Add(n) => self.int_op(*n, "add"),
+            CoreOp::Sub(n) => self.int_op(*n, "sub"),
+            CoreOp::Mul(n) => self.int_op(*n, "mul"),
+            // Like the interpreter, dividing by zero is a runtime error.
+            // The divisor is replaced when dividing by -1, since the overflow
+            // is undefined behavior in LLVM.
+            CoreOp::Div(n) => self.each(*n, true, |this, a, b| {
+                let (minus_one, divisor, quotient, negated, result) = (
+                    this.fresh(),
+                    this.fresh(),
+                    this.fresh(),
+                    this.fresh(),
+                    this.fresh(),
+                );
+                (
+                    vec![
+                        format!("call void @sage_check_divisor(i64 {b})"),
+                        format!("{minus_one} = icmp eq i64 {b}, -1"),
+                        format!("{divisor} = select i1 {minus_one}, i64 1, i64 {b}"),
+                        format!("{quotient} = sdiv i64 {a}, {divisor}"),
+                        format!("{negated} = sub i64 0, {a}"),
+                        format!("{result} = select i1 {minus_one}, i64 {negated}, i64 {quotient}"),
+                    ],
+                    result,
+                )
+            }),
+            CoreOp::Rem(n) => self.each(*n, true, |this, a, b| {
+                let (minus_one, divisor, remainder, result) =
+                    (this.fresh(), this.fresh(), this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("call void @sage_check_divisor(i64 {b})"),
+                        format!("{minus_one} = icmp eq i64 {b}, -1"),
+                        format!("{divisor} = select i1 {minus_one}, i64 1, i64 {b}"),
+                        format!("{remainder} = srem i64 {a}, {divisor}"),
+                        format!("{result} = select i1 {minus_one}, i64 0, i64 {remainder}"),
+                    ],
+                    result,
+                )
+            }),
+            CoreOp::Neg(n) => self.int_unary(*n, "sub i64 0"),
+            CoreOp::Inc(n) => self.int_unary(*n, "add i64 1"),
+            CoreOp::Dec(n) => self.int_unary(*n, "add i64 -1"),
+            CoreOp::Swap(n) => {
+                let mut code = vec![];
+                for i in 0..*n {
+                    let value = self.get_reg(i, &mut code);
+                    let addr = self.tape_addr(i, &mut code);
+                    let cell = self.fresh();
+                    code.push(format!("{cell} = load i64, ptr {addr}"));
+                    code.push(format!("store i64 {value}, ptr {addr}"));
+                    self.set_reg(i, cell);
+                }
+                lines(&code)
+            }
+
+            CoreOp::And(n) => self.each(*n, true, |this, a, b| {
+                let (x, y, both, result) = (this.fresh(), this.fresh(), this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("{x} = icmp ne i64 {a}, 0"),
+                        format!("{y} = icmp ne i64 {b}, 0"),
+                        format!("{both} = and i1 {x}, {y}"),
+                        format!("{result} = zext i1 {both} to i64"),
+                    ],
+                    result,
+                )
+            }),
+            CoreOp::Or(n) => self.each(*n, true, |this, a, b| {
+                let (either, cond, result) = (this.fresh(), this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("{either} = or i64 {a}, {b}"),
+                        format!("{cond} = icmp ne i64 {either}, 0"),
+                        format!("{result} = zext i1 {cond} to i64"),
+                    ],
+                    result,
+                )
+            }),
+            CoreOp::Not(n) => self.compare(*n, false, |a, _| format!("icmp eq i64 {a}, 0")),
+
+            CoreOp::BitwiseNand(n) => self.each(*n, true, |this, a, b| {
+                let (both, result) = (this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("{both} = and i64 {a}, {b}"),
+                        format!("{result} = xor i64 {both}, -1"),
+                    ],
+                    result,
+                )
+            }),
+            CoreOp::BitwiseAnd(n) => self.int_op(*n, "and"),
+            CoreOp::BitwiseOr(n) => self.int_op(*n, "or"),
+            CoreOp::BitwiseXor(n) => self.int_op(*n, "xor"),
+            CoreOp::BitwiseNot(n) => self.int_unary(*n, "xor i64 -1"),
+
+            // Shifting by 64 bits or more is undefined in LLVM, so the shift is masked like on x86.
+            CoreOp::LeftShift(n)
+            | CoreOp::LogicalRightShift(n)
+            | CoreOp::ArithmeticRightShift(n) => {
+                let instruction = match op {
+                    CoreOp::LeftShift(_) => "shl",
+                    CoreOp::LogicalRightShift(_) => "lshr",
+                    _ => "ashr",
+                };
+                self.each(*n, true, |this, a, b| {
+                    let (amount, result) = (this.fresh(), this.fresh());
+                    (
+                        vec![
+                            format!("{amount} = and i64 {b}, 63"),
+                            format!("{result} = {instruction} i64 {a}, {amount}"),
+                        ],
+                        result,
+                    )
+                })
+            }
+
+            CoreOp::IsNonNegative(n) => {
+                self.compare(*n, false, |a, _| format!("icmp sge i64 {a}, 0"))
+            }
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for LLVM target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => {
+                self.ffi.insert(ffi.name.clone());
+                // The foreign function can't see the register, but it might use the tape.
+                let mut code = self.flush();
+                code.push(format!("call void @__{}()", ffi.name));
+                lines(&code)
+            }
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+            StandardOp::Set(n) => {
+                for (i, value) in n.iter().enumerate() {
+                    self.set_reg(i, (value.to_bits() as i64).to_string());
+                }
+                String::new()
+            }
+
+            StandardOp::ToInt(n) => self.each(*n, false, |this, a, _| {
+                let (x, result) = (this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("{x} = bitcast i64 {a} to double"),
+                        format!("{result} = call i64 @llvm.fptosi.sat.i64.f64(double {x})"),
+                    ],
+                    result,
+                )
+            }),
+            StandardOp::ToFloat(n) => self.each(*n, false, |this, a, _| {
+                let (x, result) = (this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("{x} = sitofp i64 {a} to double"),
+                        format!("{result} = bitcast double {x} to i64"),
+                    ],
+                    result,
+                )
+            }),
+
+            StandardOp::Add(n) => self.float_op(*n, true, |x, y| format!("fadd double {x}, {y}")),
+            StandardOp::Sub(n) => self.float_op(*n, true, |x, y| format!("fsub double {x}, {y}")),
+            StandardOp::Mul(n) => self.float_op(*n, true, |x, y| format!("fmul double {x}, {y}")),
+            StandardOp::Div(n) => self.float_op(*n, true, |x, y| format!("fdiv double {x}, {y}")),
+            StandardOp::Rem(n) => self.float_op(*n, true, |x, y| format!("frem double {x}, {y}")),
+            StandardOp::Pow(n) => self.float_op(*n, true, |x, y| {
+                format!("call double @llvm.pow.f64(double {x}, double {y})")
+            }),
+            StandardOp::Neg(n) => self.float_op(*n, false, |x, _| format!("fneg double {x}")),
+            StandardOp::IsNonNegative(n) => self.each(*n, false, |this, a, _| {
+                let (x, cond, result) = (this.fresh(), this.fresh(), this.fresh());
+                (
+                    vec![
+                        format!("{x} = bitcast i64 {a} to double"),
+                        format!("{cond} = fcmp oge double {x}, 0.0"),
+                        format!("{result} = zext i1 {cond} to i64"),
+                    ],
+                    result,
+                )
+            }),
+
+            StandardOp::Sin(n) => self.float_op(*n, false, |x, _| {
+                format!("call double @llvm.sin.f64(double {x})")
+            }),
+            StandardOp::Cos(n) => self.float_op(*n, false, |x, _| {
+                format!("call double @llvm.cos.f64(double {x})")
+            }),
+            StandardOp::Tan(n) => {
+                self.float_op(*n, false, |x, _| format!("call double @tan(double {x})"))
+            }
+            StandardOp::ASin(n) => {
+                self.float_op(*n, false, |x, _| format!("call double @asin(double {x})"))
+            }
+            StandardOp::ACos(n) => {
+                self.float_op(*n, false, |x, _| format!("call double @acos(double {x})"))
+            }
+            StandardOp::ATan(n) => {
+                self.float_op(*n, false, |x, _| format!("call double @atan(double {x})"))
+            }
+
+            StandardOp::Alloc => {
+                let mut code = vec![];
+                let size = self.get_reg(0, &mut code);
+                let addr = self.fresh();
+                code.push(format!("{addr} = call i64 @sage_alloc(i64 {size})"));
+                self.set_reg(0, addr);
+                lines(&code)
+            }
+            StandardOp::Free => {
+                let mut code = vec![];
+                let addr = self.get_reg(0, &mut code);
+                code.push(format!("call void @sage_free(i64 {addr})"));
+                lines(&code)
+            }
+            _ => return Err(format!("Invalid standard op for LLVM target {op:?}")),
+        })
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        let mut code = self.flush();
+        match (matching, fun) {
+            (CoreOp::Function, _) => {
+                code.push("ret void".to_string());
+                return format!("{}\n}}\n", lines(&code));
+            }
+            (CoreOp::While, _) => {
+                let label = self.blocks.pop().expect("Unexpected end");
+                code.push(format!("br label %while{label}"));
+                code.push(self.label(&format!("end{label}")));
+            }
+            (CoreOp::If, _) => {
+                let label = self.blocks.pop().expect("Unexpected end");
+                code.push(format!("br label %else{label}"));
+                code.push(self.label(&format!("else{label}")));
+            }
+            (CoreOp::Else, _) => {
+                let label = self.blocks.pop().expect("Unexpected end");
+                code.push(format!("br label %end{label}"));
+                code.push(self.label(&format!("end{label}")));
+            }
+            _ => unreachable!("Invalid matching op for end"),
+        }
+        lines(&code)
+    }
+
+    fn declare_proc(&mut self, label_id: usize) -> String {
+        format!(
+            "define internal void @f{label_id}() {{\n{}",
+            self.label("entry")
+        )
+    }
+
+    fn name(&self) -> &str {
+        "LLVM"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        let mut code = vec![];
+        match src.mode {
+            InputMode::StdinChar => {
+                let (c, eof, value, result) =
+                    (self.fresh(), self.fresh(), self.fresh(), self.fresh());
+                code.push(format!("{c} = call i32 @getchar()"));
+                // The end of the input reads as zero.
+                code.push(format!("{eof} = icmp slt i32 {c}, 0"));
+                code.push(format!("{value} = sext i32 {c} to i64"));
+                code.push(format!("{result} = select i1 {eof}, i64 0, i64 {value}"));
+                self.set_reg(0, result);
+            }
+            InputMode::StdinInt | InputMode::StdinFloat => {
+                let format = if let InputMode::StdinInt = src.mode {
+                    "@.str.int"
+                } else {
+                    "@.str.float"
+                };
+                let result = self.fresh();
+                code.push("store i64 0, ptr @sage_input".to_string());
+                code.push(format!(
+                    "call i32 (ptr, ...) @scanf(ptr {format}, ptr @sage_input)"
+                ));
+                code.push(format!("{result} = load i64, ptr @sage_input"));
+                self.set_reg(0, result);
+            }
+            _ => return Err("Input not supported by this target".to_string()),
+        }
+        Ok(lines(&code))
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let mut code = vec![];
+        let value = self.get_reg(0, &mut code);
+        let stream = match dst.mode {
+            OutputMode::StdoutChar | OutputMode::StdoutInt | OutputMode::StdoutFloat => "stdout",
+            OutputMode::StderrChar | OutputMode::StderrInt | OutputMode::StderrFloat => "stderr",
+            _ => return Err("Output not supported by this target".to_string()),
+        };
+        let file = self.fresh();
+        code.push(format!("{file} = load ptr, ptr @{stream}"));
+        match dst.mode {
+            OutputMode::StdoutChar | OutputMode::StderrChar => {
+                let c = self.fresh();
+                code.push(format!("{c} = trunc i64 {value} to i32"));
+                code.push(format!("call i32 @fputc(i32 {c}, ptr {file})"));
+            }
+            OutputMode::StdoutInt | OutputMode::StderrInt => code.push(format!(
+                "call i32 (ptr, ptr, ...) @fprintf(ptr {file}, ptr @.str.int, i64 {value})"
+            )),
+            _ => {
+                let x = self.fresh();
+                code.push(format!("{x} = bitcast i64 {value} to double"));
+                code.push(format!(
+                    "call i32 (ptr, ptr, ...) @fprintf(ptr {file}, ptr @.str.float.out, double {x})"
+                ));
+            }
+        }
+        Ok(lines(&code))
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        let (top, prev, value) = (self.fresh(), self.fresh(), self.fresh());
+        self.set_reg(0, value.clone());
+        Ok(lines(&[
+            format!("{top} = load ptr, ptr @ffi_ptr"),
+            format!("{value} = load i64, ptr {top}"),
+            format!("{prev} = getelementptr i64, ptr {top}, i64 -1"),
+            format!("store ptr {prev}, ptr @ffi_ptr"),
+        ]))
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        let mut code = vec![];
+        let value = self.get_reg(0, &mut code);
+        let (top, next) = (self.fresh(), self.fresh());
+        code.extend([
+            format!("{top} = load ptr, ptr @ffi_ptr"),
+            format!("{next} = getelementptr i64, ptr {top}, i64 1"),
+            format!("store i64 {value}, ptr {next}"),
+            format!("store ptr {next}, ptr @ffi_ptr"),
+        ]);
+        Ok(lines(&code))
+    }
+
+    fn prelude(&self, is_core: bool) -> Option<String> {
+        let mut result = format!(
+            r#"; Compile with `llc -relocation-model=pic out.ll -o out.s && cc out.s -o out -lm`.
+@sage_tape = internal global [{TAPE_SIZE} x i64] zeroinitializer
+@sage_ptr = internal global ptr @sage_tape
+@sage_reg = internal global [{REGISTER_SIZE} x i64] zeroinitializer
+@sage_refs = internal global [{DEREF_STACK_SIZE} x ptr] zeroinitializer
+@sage_ref = internal global ptr @sage_refs
+@sage_input = internal global i64 0
+@ffi_channel = global [{FFI_CHANNEL_SIZE} x i64] zeroinitializer
+@ffi_ptr = global ptr @ffi_channel
+"#
+        );
+        for (name, text) in [
+            ("int", "%lld"),
+            ("float", "%lf"),
+            ("float.out", "%.1f"),
+            ("not.defined", "function %lld not defined"),
+            ("divide.by.zero", "division by zero"),
+        ] {
+            result += &string_constant(name, text);
+        }
+        result += RUNTIME;
+        if !is_core {
+            for (name, text) in [
+                (
+                    "negative",
+                    "cannot allocate a negative number of cells: %lld",
+                ),
+                (
+                    "out.of.memory",
+                    "out of memory while allocating cells: %lld",
+                ),
+                ("double.free", "double free of address %lld"),
+                ("invalid.free", "invalid free of address %lld"),
+            ] {
+                result += &string_constant(name, text);
+            }
+            result += &ALLOCATOR.replace("{NULL}", &NULL.to_string());
+        }
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<i32>) -> Option<String> {
+        let mut funs = funs;
+        funs.sort();
+        let entries = funs
+            .iter()
+            .map(|fun| format!("ptr @f{fun}"))
+            .collect::<Vec<_>>();
+        Some(format!(
+            "@sage_funs = internal constant [{len} x ptr] [{}]\n@sage_funs_len = internal constant i64 {len}\n\ndefine i32 @main() {{\nentry:\n",
+            entries.join(", "),
+            len = funs.len(),
+        ))
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        let mut result = String::new();
+        for line in self.flush_code() {
+            result += &line;
+            result += "\n\t";
+        }
+        result += "ret i32 0\n}\n";
+        for name in &self.ffi {
+            result += &format!("\ndeclare void @__{name}()");
+        }
+        Some(result + "\n")
+    }
+}
+
+impl CompiledTarget for Llvm {}
+
+/// The declarations of the C library functions and LLVM intrinsics used by
+/// programs, and the routines for calling functions and reporting runtime errors.
+const RUNTIME: &str = r#"
+@stdout = external global ptr
+@stderr = external global ptr
+
+declare i32 @getchar()
+declare i32 @scanf(ptr, ...)
+declare i32 @fprintf(ptr, ptr, ...)
+declare i32 @fputc(i32, ptr)
+declare i32 @fflush(ptr)
+declare void @exit(i32) noreturn
+declare ptr @malloc(i64)
+declare ptr @memset(ptr, i32, i64)
+declare ptr @memcpy(ptr, ptr, i64)
+declare double @tan(double)
+declare double @asin(double)
+declare double @acos(double)
+declare double @atan(double)
+declare double @llvm.sin.f64(double)
+declare double @llvm.cos.f64(double)
+declare double @llvm.pow.f64(double, double)
+declare i64 @llvm.fptosi.sat.i64.f64(double)
+
+; Report a runtime error, whose message is formatted with the value, and exit.
+define internal void @sage_runtime_error(ptr %format, i64 %value) noreturn {
+entry:
+	call i32 @fflush(ptr null)
+	%err = load ptr, ptr @stderr
+	call i32 (ptr, ptr, ...) @fprintf(ptr %err, ptr %format, i64 %value)
+	call i32 @fputc(i32 10, ptr %err)
+	call void @exit(i32 1)
+	unreachable
+}
+
+; Report a runtime error if a divisor is zero.
+define internal void @sage_check_divisor(i64 %divisor) {
+entry:
+	%zero = icmp eq i64 %divisor, 0
+	br i1 %zero, label %fail, label %ok
+fail:
+	call void @sage_runtime_error(ptr @.str.divide.by.zero, i64 0)
+	unreachable
+ok:
+	ret void
+}
+
+; Call the function with the given index.
+define internal void @sage_call(i64 %f) {
+entry:
+	%len = load i64, ptr @sage_funs_len
+	%defined = icmp ult i64 %f, %len
+	br i1 %defined, label %call, label %undefined
+call:
+	%slot = getelementptr ptr, ptr @sage_funs, i64 %f
+	%fun = load ptr, ptr %slot
+	call void %fun()
+	ret void
+undefined:
+	call void @sage_runtime_error(ptr @.str.not.defined, i64 %f)
+	unreachable
+}
+"#;
+
+/// The heap allocator used by the standard variant, written directly in IR.
+/// Blocks come from `malloc` and are linked into `@sage_blocks` for the life of
+/// the program; `@sage_alloc` hands out the first freed `%block` that fits (it
+/// never splits or coalesces them) before asking `malloc` for another, and
+/// `@sage_free` searches the list so it can report invalid and double frees.
+const ALLOCATOR: &str = r#"
+; A block of memory: the next block, its size in cells, whether it's freed, and then its cells.
+%block = type { ptr, i64, i64 }
+@sage_blocks = internal global ptr null
+
+define internal i64 @sage_alloc(i64 %size) {
+entry:
+	%negative = icmp slt i64 %size, 0
+	br i1 %negative, label %fail.negative, label %start
+fail.negative:
+	call void @sage_runtime_error(ptr @.str.negative, i64 %size)
+	unreachable
+start:
+	%empty = icmp slt i64 %size, 1
+	%n = select i1 %empty, i64 1, i64 %size
+	%first = load ptr, ptr @sage_blocks
+	br label %search
+search:
+	%b = phi ptr [ %first, %start ], [ %next, %continue ]
+	%last = icmp eq ptr %b, null
+	br i1 %last, label %new, label %check
+check:
+	%freed.ptr = getelementptr %block, ptr %b, i32 0, i32 2
+	%freed = load i64, ptr %freed.ptr
+	%size.ptr = getelementptr %block, ptr %b, i32 0, i32 1
+	%b.size = load i64, ptr %size.ptr
+	%is.freed = icmp ne i64 %freed, 0
+	%fits = icmp sge i64 %b.size, %n
+	%reuse = and i1 %is.freed, %fits
+	br i1 %reuse, label %found, label %continue
+continue:
+	%next.ptr = getelementptr %block, ptr %b, i32 0, i32 0
+	%next = load ptr, ptr %next.ptr
+	br label %search
+new:
+	%bytes = mul i64 %n, 8
+	%total = add i64 %bytes, 24
+	%new.b = call ptr @malloc(i64 %total)
+	%failed = icmp eq ptr %new.b, null
+	br i1 %failed, label %fail.memory, label %init
+fail.memory:
+	call void @sage_runtime_error(ptr @.str.out.of.memory, i64 %size)
+	unreachable
+init:
+	%new.next = getelementptr %block, ptr %new.b, i32 0, i32 0
+	store ptr %first, ptr %new.next
+	%new.size = getelementptr %block, ptr %new.b, i32 0, i32 1
+	store i64 %n, ptr %new.size
+	store ptr %new.b, ptr @sage_blocks
+	br label %found
+found:
+	%found.b = phi ptr [ %b, %check ], [ %new.b, %init ]
+	%found.freed = getelementptr %block, ptr %found.b, i32 0, i32 2
+	store i64 0, ptr %found.freed
+	%found.size = getelementptr %block, ptr %found.b, i32 0, i32 1
+	%cells = load i64, ptr %found.size
+	%cells.bytes = mul i64 %cells, 8
+	%data = getelementptr %block, ptr %found.b, i64 1
+	call ptr @memset(ptr %data, i32 0, i64 %cells.bytes)
+	%addr = ptrtoint ptr %data to i64
+	ret i64 %addr
+}
+
+define internal void @sage_free(i64 %addr) {
+entry:
+	%null = icmp eq i64 %addr, {NULL}
+	br i1 %null, label %done, label %start
+start:
+	%p = inttoptr i64 %addr to ptr
+	%first = load ptr, ptr @sage_blocks
+	br label %search
+search:
+	%b = phi ptr [ %first, %start ], [ %next, %continue ]
+	%last = icmp eq ptr %b, null
+	br i1 %last, label %fail.invalid, label %check
+check:
+	%data = getelementptr %block, ptr %b, i64 1
+	%match = icmp eq ptr %data, %p
+	br i1 %match, label %found, label %continue
+continue:
+	%next.ptr = getelementptr %block, ptr %b, i32 0, i32 0
+	%next = load ptr, ptr %next.ptr
+	br label %search
+found:
+	%freed.ptr = getelementptr %block, ptr %b, i32 0, i32 2
+	%freed = load i64, ptr %freed.ptr
+	%twice = icmp ne i64 %freed, 0
+	br i1 %twice, label %fail.double, label %release
+release:
+	store i64 1, ptr %freed.ptr
+	br label %done
+fail.double:
+	call void @sage_runtime_error(ptr @.str.double.free, i64 %addr)
+	unreachable
+fail.invalid:
+	call void @sage_runtime_error(ptr @.str.invalid.free, i64 %addr)
+	unreachable
+done:
+	ret void
+}
+"#;
@@ -5,10 +5,11 @@
 //!
 //! ## Current Structure
 //!
-//! Right now, this module is a bit empty, only implementing C (GCC only)
-//! as a compiler target. This is due to the fact that it has been much
-//! simpler to build the language on top of the virtual machine when there
-//! are fewer implementations to change.
+//! Right now, this module is a bit empty, only implementing C (C99),
+//! x86-64 Linux assembly (GNU assembler), WebAssembly (text format), and
+//! LLVM IR (text format) as compiler targets. This is
+//! due to the fact that it has been much simpler to build the language on
+//! top of the virtual machine when there are fewer implementations to change.
 //!
 //! ## Future Structure
 //!
@@ -42,8 +43,14 @@ pub use c::*;
 // pub mod sage_os;
 // pub use sage_os::*;
 
-// pub mod x86;
-// pub use x86::*;
+pub mod x86;
+pub use x86::*;
+
+pub mod wasm;
+pub use wasm::*;
+
+pub mod llvm;
+pub use llvm::*;
 
 use log::info;
 
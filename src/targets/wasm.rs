@@ -0,0 +1,716 @@
+//! # WebAssembly Target
+//!
+//! An implementation of the virtual machine for WebAssembly, as a module in the
+//! WebAssembly text format. Convert it to a binary module with a tool like
+//! `wat2wasm`, and run it in any WebAssembly runtime which provides its imports.
+//!
+//! ## Machine State
+//!
+//! The tape, the register, the deref stack, and the foreign function channel
+//! are kept in the module's linear memory, which is exported as `memory`.
+//! Like the interpreter, pointers are indices of cells on the tape rather
+//! than addresses in memory, so a program's pointers have the same values
+//! as they do in the interpreter. The standard variant's heap works like
+//! the interpreter's allocator too, starting at the same address.
+//!
+//! Functions are put in a table, so `Call` is an indirect call through it.
+//! The program is run by calling the exported `main` function.
+//!
+//! ## Imports
+//!
+//! The module imports its input, output, and error reporting from the host.
+//! Every function is imported from the `sage` module:
+//!
+//! | Import                          | Type                    | Description |
+//! |---------------------------------|-------------------------|-------------|
+//! | `error`                         | `(i32, i32) -> ()`      | Report the runtime error message at the address with the length, and stop. |
+//! | `stdin.char`, `stdin.int`       | `() -> i64`             | Read a value from the input. |
+//! | `stdin.float`                   | `() -> f64`             | Read a float from the input. |
+//! | `stdout.char`, `stdout.int`     | `(i64) -> ()`           | Write a value to the output (likewise for `stderr`). |
+//! | `stdout.float`                  | `(f64) -> ()`           | Write a float to the output (likewise for `stderr`). |
+//! | `ffi` (standard only)           | `(i32, i32, i32) -> i32`| Call the foreign function named by the string at the address with the length. |
+//!
+//! A foreign function is given the address of the top of the foreign function channel,
+//! pops its arguments from the channel (the top is the last argument), pushes its
+//! results, and returns the new top of the channel. The channel grows upwards in
+//! eight byte cells.
+//!
+//! The standard variant also imports the floating point functions WebAssembly doesn't
+//! have from the `math` module: `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `pow`,
+//! and `fmod`. In JavaScript, these are the `Math` functions, and `(x, y) => x % y`.
+use super::{Architecture, CompiledTarget};
+use crate::{
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{CoreOp, StandardOp, HEAP_START},
+    NULL,
+};
+
+/// The number of cells on the tape.
+const TAPE_SIZE: usize = 1 << 21;
+/// The number of cells in the register.
+const REGISTER_SIZE: usize = 1024;
+/// The maximum depth of the deref stack.
+const DEREF_STACK_SIZE: usize = 1024;
+/// The number of cells in the foreign function channel.
+const FFI_CHANNEL_SIZE: usize = 256;
+/// The number of bytes in the buffer for error messages.
+const ERROR_BUFFER_SIZE: usize = 256;
+
+/// The address of the heap's bookkeeping in memory. For every cell on the tape,
+/// it holds the size of the block allocated there if the cell is the start of a
+/// block, -1 if the cell is inside of a block, and 0 if the cell is free.
+const HEAP_INFO: usize = TAPE_SIZE * 8;
+/// The address of the register in memory.
+const REGISTER: usize = HEAP_INFO + TAPE_SIZE * 4;
+/// The address of the deref stack in memory.
+const DEREF_STACK: usize = REGISTER + REGISTER_SIZE * 8;
+/// The address of the foreign function channel in memory.
+/// The first cell is never used, the top starts there when the channel is empty.
+const FFI_CHANNEL: usize = DEREF_STACK + DEREF_STACK_SIZE * 4;
+/// The address of the buffer used to build error messages.
+const ERROR_BUFFER: usize = FFI_CHANNEL + (FFI_CHANNEL_SIZE + 1) * 8;
+/// The address of the constant strings in memory.
+const STRINGS: usize = ERROR_BUFFER + ERROR_BUFFER_SIZE;
+/// The messages used by the runtime, stored at the start of the constant strings.
+const MESSAGES: &[&str] = &[
+    "function ",
+    " not defined",
+    "double free of address ",
+    "invalid free of address ",
+    "cannot allocate a negative number of cells: ",
+    "out of memory while allocating cells: ",
+    "division by zero",
+];
+/// The number of bytes in a page of WebAssembly memory.
+const PAGE_SIZE: usize = 65536;
+
+/// The type for the WebAssembly target which implements the `Target` trait.
+/// This allows the compiler to target WebAssembly runtimes, like web browsers.
+#[derive(Default)]
+pub struct Wasm {
+    /// The number of labels used for the loops so far.
+    labels: usize,
+    /// The labels of the loops we're currently in.
+    blocks: Vec<usize>,
+    /// The names of the foreign functions called so far, which are stored after the messages.
+    ffi_names: Vec<String>,
+}
+
+impl Wasm {
+    /// Create a new label for a loop, and enter its block.
+    fn enter_block(&mut self) -> usize {
+        let label = self.labels;
+        self.labels += 1;
+        self.blocks.push(label);
+        label
+    }
+
+    /// Get the address and length of the name of a foreign function in memory.
+    fn ffi_name(&mut self, name: &str) -> (usize, usize) {
+        let mut addr = STRINGS + MESSAGES.concat().len();
+        for other in &self.ffi_names {
+            if other == name {
+                return (addr, name.len());
+            }
+            addr += other.len();
+        }
+        self.ffi_names.push(name.to_string());
+        (addr, name.len())
+    }
+}
+
+/// Get the address and length of a runtime message, as two constant operands.
+fn message(text: &str) -> String {
+    let index = MESSAGES
+        .iter()
+        .position(|message| *message == text)
+        .expect("Undefined runtime message");
+    let addr = STRINGS + MESSAGES[..index].concat().len();
+    format!("(i32.const {addr}) (i32.const {})", text.len())
+}
+
+/// Stop with a runtime error if the divisor in `$t` is zero.
+fn divide_by_zero_check() -> String {
+    format!(
+        "(if (i64.eqz (local.get $t)) (then (call $error {}) (unreachable)))",
+        message("division by zero")
+    )
+}
+
+/// Quote bytes as a string in the WebAssembly text format.
+fn string(bytes: &[u8]) -> String {
+    let mut result = String::from("\"");
+    for byte in bytes {
+        match byte {
+            b' '..=b'~' if *byte != b'"' && *byte != b'\\' => result.push(*byte as char),
+            _ => result += &format!("\\{byte:02x}"),
+        }
+    }
+    result + "\""
+}
+
+/// Join instructions into the code for a single virtual machine instruction.
+fn lines(instructions: &[&str]) -> String {
+    instructions.join("\n\t")
+}
+
+/// Apply an operation to the first `n` cells of the register and the tape.
+///
+/// In the body, `{reg}` and `{tape}` stand for the addresses of the operands
+/// in the register and on the tape. When operating on more than one cell, the
+/// body is run in a loop counting with the local `$i`, so it must not use `$i`.
+fn each(n: usize, body: &[&str]) -> String {
+    let body = lines(body);
+    match n {
+        0 => String::new(),
+        1 => body
+            .replace("{reg}", &format!("(i32.const {REGISTER})"))
+            .replace("{tape}", "(i32.shl (global.get $ptr) (i32.const 3))"),
+        n => format!(
+            "(local.set $i (i32.const 0))\n\t(loop $each\n\t{}\n\t(local.set $i (i32.add (local.get $i) (i32.const 1)))\n\t(br_if $each (i32.lt_u (local.get $i) (i32.const {n}))))",
+            body.replace(
+                "{reg}",
+                &format!("(i32.add (i32.const {REGISTER}) (i32.shl (local.get $i) (i32.const 3)))")
+            )
+            .replace(
+                "{tape}",
+                "(i32.shl (i32.add (global.get $ptr) (local.get $i)) (i32.const 3))"
+            )
+        ),
+    }
+}
+
+/// Apply a binary integer instruction to the register and the tape, storing the result in the register.
+fn int_op(n: usize, instruction: &str) -> String {
+    each(
+        n,
+        &[&format!(
+            "(i64.store {{reg}} ({instruction} (i64.load {{reg}}) (i64.load {{tape}})))"
+        )],
+    )
+}
+
+/// Apply a binary float instruction to the register and the tape, storing the result in the register.
+fn float_op(n: usize, instruction: &str) -> String {
+    each(
+        n,
+        &[&format!(
+            "(f64.store {{reg}} ({instruction} (f64.load {{reg}}) (f64.load {{tape}})))"
+        )],
+    )
+}
+
+/// Call an imported float function with the register, storing the result in the register.
+fn float_call(n: usize, function: &str) -> String {
+    each(
+        n,
+        &[&format!(
+            "(f64.store {{reg}} (call ${function} (f64.load {{reg}})))"
+        )],
+    )
+}
+
+/// Set the cells of the register to the given bits.
+fn set(values: impl IntoIterator<Item = i64>) -> String {
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            format!(
+                "(i64.store (i32.const {}) (i64.const {value}))",
+                REGISTER + i * 8
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\t")
+}
+
+impl Architecture for Wasm {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(
+            i.mode,
+            InputMode::StdinChar | InputMode::StdinFloat | InputMode::StdinInt
+        )
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutFloat
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrFloat
+                | OutputMode::StderrInt
+        )
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => {
+                format!(";; {}", text.replace('\n', "\n\t;; ").replace('\r', ""))
+            }
+            CoreOp::While => {
+                let label = self.enter_block();
+                format!("(block $end{label}\n\t(loop $while{label}\n\t(br_if $end{label} (i64.eqz (i64.load (i32.const {REGISTER}))))")
+            }
+            CoreOp::If => format!("(if (i64.ne (i64.load (i32.const {REGISTER})) (i64.const 0)) (then"),
+            CoreOp::Else => ") (else".to_string(),
+            CoreOp::Set(n) => set(n.iter().copied()),
+            CoreOp::Call => "(call $call)".to_string(),
+            CoreOp::Return => "(return)".to_string(),
+
+            CoreOp::Store(1) => each(1, &["(i64.store {tape} (i64.load {reg}))"]),
+            CoreOp::Store(n) => format!(
+                "(memory.copy (i32.shl (global.get $ptr) (i32.const 3)) (i32.const {REGISTER}) (i32.const {}))",
+                n * 8
+            ),
+            CoreOp::Load(1) => each(1, &["(i64.store {reg} (i64.load {tape}))"]),
+            CoreOp::Load(n) => format!(
+                "(memory.copy (i32.const {REGISTER}) (i32.shl (global.get $ptr) (i32.const 3)) (i32.const {}))",
+                n * 8
+            ),
+
+            CoreOp::Move(n) => {
+                format!("(global.set $ptr (i32.add (global.get $ptr) (i32.const {n})))")
+            }
+            CoreOp::Where => format!(
+                "(i64.store (i32.const {REGISTER}) (i64.extend_i32_u (global.get $ptr)))"
+            ),
+            CoreOp::Deref => lines(&[
+                "(i32.store (global.get $refs) (global.get $ptr))",
+                "(global.set $refs (i32.add (global.get $refs) (i32.const 4)))",
+                "(global.set $ptr (i32.wrap_i64 (i64.load (i32.shl (global.get $ptr) (i32.const 3)))))",
+            ]),
+            CoreOp::Refer => lines(&[
+                "(global.set $refs (i32.sub (global.get $refs) (i32.const 4)))",
+                "(global.set $ptr (i32.load (global.get $refs)))",
+            ]),
+            CoreOp::Offset(offset, n) => each(
+                *n,
+                &[&format!(
+                    "(i64.store {{reg}} (i64.add (i64.load {{reg}}) (i64.const {offset})))"
+                )],
+            ),
+            CoreOp::Index(n) => int_op(*n, "i64.add"),
+
+            CoreOp::Add(n) => int_op(*n, "i64.add"),
+            CoreOp::Sub(n) => int_op(*n, "i64.sub"),
+            CoreOp::Mul(n) => int_op(*n, "i64.mul"),
+            // Like the interpreter, dividing by zero is a runtime error.
+            CoreOp::Div(n) => each(
+                *n,
+                &[
+                    "(local.set $t (i64.load {tape}))",
+                    &divide_by_zero_check(),
+                    "(if (i64.eq (local.get $t) (i64.const -1))",
+                    "\t(then (i64.store {reg} (i64.sub (i64.const 0) (i64.load {reg}))))",
+                    "\t(else (i64.store {reg} (i64.div_s (i64.load {reg}) (local.get $t)))))",
+                ],
+            ),
+            CoreOp::Rem(n) => each(
+                *n,
+                &[
+                    "(local.set $t (i64.load {tape}))",
+                    &divide_by_zero_check(),
+                    "(i64.store {reg} (i64.rem_s (i64.load {reg}) (local.get $t)))",
+                ],
+            ),
+            CoreOp::Neg(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.sub (i64.const 0) (i64.load {reg})))"],
+            ),
+            CoreOp::Inc(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.add (i64.load {reg}) (i64.const 1)))"],
+            ),
+            CoreOp::Dec(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.sub (i64.load {reg}) (i64.const 1)))"],
+            ),
+            CoreOp::Swap(n) => each(
+                *n,
+                &[
+                    "(local.set $t (i64.load {tape}))",
+                    "(i64.store {tape} (i64.load {reg}))",
+                    "(i64.store {reg} (local.get $t))",
+                ],
+            ),
+
+            CoreOp::And(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.extend_i32_u (i32.and (i64.ne (i64.load {reg}) (i64.const 0)) (i64.ne (i64.load {tape}) (i64.const 0)))))"],
+            ),
+            CoreOp::Or(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.extend_i32_u (i64.ne (i64.or (i64.load {reg}) (i64.load {tape})) (i64.const 0))))"],
+            ),
+            CoreOp::Not(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.extend_i32_u (i64.eqz (i64.load {reg}))))"],
+            ),
+
+            CoreOp::BitwiseNand(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.xor (i64.and (i64.load {reg}) (i64.load {tape})) (i64.const -1)))"],
+            ),
+            CoreOp::BitwiseAnd(n) => int_op(*n, "i64.and"),
+            CoreOp::BitwiseOr(n) => int_op(*n, "i64.or"),
+            CoreOp::BitwiseXor(n) => int_op(*n, "i64.xor"),
+            CoreOp::BitwiseNot(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.xor (i64.load {reg}) (i64.const -1)))"],
+            ),
+
+            CoreOp::LeftShift(n) => int_op(*n, "i64.shl"),
+            CoreOp::LogicalRightShift(n) => int_op(*n, "i64.shr_u"),
+            CoreOp::ArithmeticRightShift(n) => int_op(*n, "i64.shr_s"),
+
+            CoreOp::IsNonNegative(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.extend_i32_u (i64.ge_s (i64.load {reg}) (i64.const 0))))"],
+            ),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for wasm target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => {
+                let (addr, len) = self.ffi_name(&ffi.name);
+                format!("(global.set $ffi_ptr (call $ffi (i32.const {addr}) (i32.const {len}) (global.get $ffi_ptr)))")
+            }
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+            StandardOp::Set(n) => set(n.iter().map(|val| val.to_bits() as i64)),
+
+            StandardOp::ToInt(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.trunc_sat_f64_s (f64.load {reg})))"],
+            ),
+            StandardOp::ToFloat(n) => each(
+                *n,
+                &["(f64.store {reg} (f64.convert_i64_s (i64.load {reg})))"],
+            ),
+
+            StandardOp::Add(n) => float_op(*n, "f64.add"),
+            StandardOp::Sub(n) => float_op(*n, "f64.sub"),
+            StandardOp::Mul(n) => float_op(*n, "f64.mul"),
+            StandardOp::Div(n) => float_op(*n, "f64.div"),
+            StandardOp::Rem(n) => float_op(*n, "call $fmod"),
+            StandardOp::Pow(n) => float_op(*n, "call $pow"),
+            StandardOp::Neg(n) => each(*n, &["(f64.store {reg} (f64.neg (f64.load {reg})))"]),
+            StandardOp::IsNonNegative(n) => each(
+                *n,
+                &["(i64.store {reg} (i64.extend_i32_u (f64.ge (f64.load {reg}) (f64.const 0))))"],
+            ),
+
+            StandardOp::Sin(n) => float_call(*n, "sin"),
+            StandardOp::Cos(n) => float_call(*n, "cos"),
+            StandardOp::Tan(n) => float_call(*n, "tan"),
+            StandardOp::ASin(n) => float_call(*n, "asin"),
+            StandardOp::ACos(n) => float_call(*n, "acos"),
+            StandardOp::ATan(n) => float_call(*n, "atan"),
+
+            StandardOp::Alloc => format!(
+                "(i64.store (i32.const {REGISTER}) (call $alloc (i64.load (i32.const {REGISTER}))))"
+            ),
+            StandardOp::Free => format!("(call $free (i64.load (i32.const {REGISTER})))"),
+            _ => return Err(format!("Invalid standard op for wasm target {op:?}")),
+        })
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function, _) => ")".to_string(),
+            (CoreOp::While, _) => {
+                let label = self.blocks.pop().expect("Unexpected end");
+                format!("(br $while{label})))")
+            }
+            (CoreOp::If | CoreOp::Else, _) => "))".to_string(),
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize) -> String {
+        format!("(func $f{label_id} (type $fun) (local $i i32) (local $t i64)")
+    }
+
+    fn name(&self) -> &str {
+        "wasm"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        match src.mode {
+            InputMode::StdinChar => Ok(format!(
+                "(i64.store (i32.const {REGISTER}) (call $stdin_char))"
+            )),
+            InputMode::StdinInt => Ok(format!(
+                "(i64.store (i32.const {REGISTER}) (call $stdin_int))"
+            )),
+            InputMode::StdinFloat => Ok(format!(
+                "(f64.store (i32.const {REGISTER}) (call $stdin_float))"
+            )),
+            _ => Err("Input not supported by this target".to_string()),
+        }
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let (function, load) = match dst.mode {
+            OutputMode::StdoutChar => ("stdout_char", "i64.load"),
+            OutputMode::StdoutInt => ("stdout_int", "i64.load"),
+            OutputMode::StdoutFloat => ("stdout_float", "f64.load"),
+            OutputMode::StderrChar => ("stderr_char", "i64.load"),
+            OutputMode::StderrInt => ("stderr_int", "i64.load"),
+            OutputMode::StderrFloat => ("stderr_float", "f64.load"),
+            _ => return Err("Output not supported by this target".to_string()),
+        };
+        Ok(format!(
+            "(call ${function} ({load} (i32.const {REGISTER})))"
+        ))
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Ok(format!(
+            "(i64.store (i32.const {REGISTER}) (i64.load (global.get $ffi_ptr)))\n\t(global.set $ffi_ptr (i32.sub (global.get $ffi_ptr) (i32.const 8)))"
+        ))
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Ok(format!(
+            "(global.set $ffi_ptr (i32.add (global.get $ffi_ptr) (i32.const 8)))\n\t(i64.store (global.get $ffi_ptr) (i64.load (i32.const {REGISTER})))"
+        ))
+    }
+
+    fn prelude(&self, is_core: bool) -> Option<String> {
+        let mut result = String::from(
+            r#";; Convert to a binary module with `wat2wasm out.wat`, and call the exported `main`.
+(module
+	(type $fun (func))
+	(import "sage" "error" (func $error (param i32 i32)))
+	(import "sage" "stdin.char" (func $stdin_char (result i64)))
+	(import "sage" "stdin.int" (func $stdin_int (result i64)))
+	(import "sage" "stdin.float" (func $stdin_float (result f64)))
+	(import "sage" "stdout.char" (func $stdout_char (param i64)))
+	(import "sage" "stdout.int" (func $stdout_int (param i64)))
+	(import "sage" "stdout.float" (func $stdout_float (param f64)))
+	(import "sage" "stderr.char" (func $stderr_char (param i64)))
+	(import "sage" "stderr.int" (func $stderr_int (param i64)))
+	(import "sage" "stderr.float" (func $stderr_float (param f64)))
+"#,
+        );
+        if !is_core {
+            result += r#"	(import "sage" "ffi" (func $ffi (param i32 i32 i32) (result i32)))
+"#;
+            for function in ["sin", "cos", "tan", "asin", "acos", "atan"] {
+                result += &format!(
+                    "\t(import \"math\" \"{function}\" (func ${function} (param f64) (result f64)))\n"
+                );
+            }
+            for function in ["pow", "fmod"] {
+                result += &format!(
+                    "\t(import \"math\" \"{function}\" (func ${function} (param f64 f64) (result f64)))\n"
+                );
+            }
+        }
+
+        result += &format!(
+            r#"
+	(global $ptr (mut i32) (i32.const 0))
+	(global $refs (mut i32) (i32.const {DEREF_STACK}))
+	(global $ffi_ptr (mut i32) (i32.const {FFI_CHANNEL}))
+	(data (i32.const {STRINGS}) {messages})
+"#,
+            messages = string(MESSAGES.concat().as_bytes()),
+        );
+        result += &RUNTIME
+            .replace("{FUNCTION}", &message("function "))
+            .replace("{NOT_DEFINED}", &message(" not defined"))
+            .replace("{REGISTER}", &REGISTER.to_string())
+            .replace("{ERROR_BUFFER}", &ERROR_BUFFER.to_string());
+        if !is_core {
+            result += &ALLOCATOR
+                .replace("{NULL}", &NULL.to_string())
+                .replace("{HEAP_START}", &HEAP_START.to_string())
+                .replace("{HEAP_INFO}", &HEAP_INFO.to_string())
+                .replace("{TAPE_SIZE}", &TAPE_SIZE.to_string())
+                .replace(
+                    "{NEGATIVE}",
+                    &message("cannot allocate a negative number of cells: "),
+                )
+                .replace(
+                    "{OUT_OF_MEMORY}",
+                    &message("out of memory while allocating cells: "),
+                )
+                .replace("{DOUBLE_FREE}", &message("double free of address "))
+                .replace("{INVALID_FREE}", &message("invalid free of address "));
+        }
+        result += "\n";
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<i32>) -> Option<String> {
+        let mut funs = funs;
+        funs.sort();
+        let mut result = format!(
+            "\n\t(table $funs {} funcref)\n\t(global $funs_len i64 (i64.const {}))\n",
+            funs.len(),
+            funs.len()
+        );
+        if !funs.is_empty() {
+            result += "\t(elem (table $funs) (i32.const 0) func";
+            for fun in &funs {
+                result += &format!(" $f{fun}");
+            }
+            result += ")\n";
+        }
+        result += "\n\t(func $main (export \"main\") (type $fun) (local $i i32) (local $t i64)\n";
+        Some(result)
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        let names = self.ffi_names.concat();
+        let end = STRINGS + MESSAGES.concat().len() + names.len();
+        let mut result = format!(
+            ")\n\n\t(memory (export \"memory\") {})\n",
+            end.div_ceil(PAGE_SIZE)
+        );
+        if !names.is_empty() {
+            result += &format!(
+                "\t(data (i32.const {}) {})\n",
+                STRINGS + MESSAGES.concat().len(),
+                string(names.as_bytes())
+            );
+        }
+        result += ")\n";
+        Some(result)
+    }
+}
+
+impl CompiledTarget for Wasm {}
+
+/// The functions used by every program for calling functions and reporting runtime errors.
+const RUNTIME: &str = r#"
+	;; Call the function whose index is in the register.
+	(func $call
+		(local $f i64)
+		(local.set $f (i64.load (i32.const {REGISTER})))
+		(if (i64.ge_u (local.get $f) (global.get $funs_len))
+			(then (call $fail {FUNCTION} (local.get $f) {NOT_DEFINED})))
+		(call_indirect $funs (type $fun) (i32.wrap_i64 (local.get $f))))
+
+	;; Report a runtime error and stop. The message is the prefix string,
+	;; followed by the integer value, followed by the suffix string.
+	(func $fail (param $prefix i32) (param $prefix_len i32) (param $value i64) (param $suffix i32) (param $suffix_len i32)
+		(local $end i32)
+		(memory.copy (i32.const {ERROR_BUFFER}) (local.get $prefix) (local.get $prefix_len))
+		(local.set $end (call $fmt_int (local.get $value) (i32.add (i32.const {ERROR_BUFFER}) (local.get $prefix_len))))
+		(memory.copy (local.get $end) (local.get $suffix) (local.get $suffix_len))
+		(call $error (i32.const {ERROR_BUFFER}) (i32.sub (i32.add (local.get $end) (local.get $suffix_len)) (i32.const {ERROR_BUFFER})))
+		(unreachable))
+
+	;; Write an integer in decimal at the address, and return the address after it.
+	(func $fmt_int (param $value i64) (param $at i32) (result i32)
+		(local $digits i64)
+		(local $end i32)
+		(if (i64.lt_s (local.get $value) (i64.const 0))
+			(then
+				(i32.store8 (local.get $at) (i32.const 45))
+				(local.set $at (i32.add (local.get $at) (i32.const 1)))
+				(local.set $value (i64.sub (i64.const 0) (local.get $value)))))
+		;; Find the end of the number, and write its digits backwards from there.
+		(local.set $digits (local.get $value))
+		(local.set $end (local.get $at))
+		(loop $count
+			(local.set $end (i32.add (local.get $end) (i32.const 1)))
+			(local.set $digits (i64.div_u (local.get $digits) (i64.const 10)))
+			(br_if $count (i64.ne (local.get $digits) (i64.const 0))))
+		(local.set $at (local.get $end))
+		(loop $write
+			(local.set $at (i32.sub (local.get $at) (i32.const 1)))
+			(i32.store8 (local.get $at) (i32.add (i32.const 48) (i32.wrap_i64 (i64.rem_u (local.get $value) (i64.const 10)))))
+			(local.set $value (i64.div_u (local.get $value) (i64.const 10)))
+			(br_if $write (i64.ne (local.get $value) (i64.const 0))))
+		(local.get $end))
+"#;
+
+/// The heap allocator for the standard variant, which allocates blocks
+/// at the same addresses as the interpreter's allocator. The heap always
+/// starts at `HEAP_START`, which is where the interpreter's starts too unless
+/// the stack is within `STACK_GAP` cells of it at the first allocation.
+const ALLOCATOR: &str = r#"
+	(global $heap_end (mut i32) (i32.const {HEAP_START}))
+
+	;; Allocate a block of cells, and return its address.
+	;; The block is put in the first free range large enough to hold it.
+	(func $alloc (param $size i64) (result i64)
+		(local $n i32)
+		(local $cell i32)
+		(local $start i32)
+		(if (i64.lt_s (local.get $size) (i64.const 0))
+			(then (call $fail {NEGATIVE} (local.get $size) (i32.const 0) (i32.const 0))))
+		(if (i64.gt_s (local.get $size) (i64.const {TAPE_SIZE}))
+			(then (call $fail {OUT_OF_MEMORY} (local.get $size) (i32.const 0) (i32.const 0))))
+		;; Every block gets a unique address, even if it is empty.
+		(local.set $n (i32.wrap_i64 (local.get $size)))
+		(if (i32.eqz (local.get $n)) (then (local.set $n (i32.const 1))))
+
+		;; Search the free ranges between the blocks. If none are large enough,
+		;; the block starts at the free range at the end of the heap (or the end).
+		(local.set $cell (i32.const {HEAP_START}))
+		(local.set $start (i32.const {HEAP_START}))
+		(block $found
+			(loop $search
+				(br_if $found (i32.ge_u (local.get $cell) (global.get $heap_end)))
+				(if (i32.gt_s (i32.load offset={HEAP_INFO} (i32.shl (local.get $cell) (i32.const 2))) (i32.const 0))
+					(then
+						;; Skip over the allocated block.
+						(local.set $cell (i32.add (local.get $cell) (i32.load offset={HEAP_INFO} (i32.shl (local.get $cell) (i32.const 2)))))
+						(local.set $start (local.get $cell))
+						(br $search)))
+				(local.set $cell (i32.add (local.get $cell) (i32.const 1)))
+				(br_if $search (i32.lt_u (i32.sub (local.get $cell) (local.get $start)) (local.get $n)))))
+
+		(if (i32.gt_u (i32.add (local.get $start) (local.get $n)) (i32.const {TAPE_SIZE}))
+			(then (call $fail {OUT_OF_MEMORY} (local.get $size) (i32.const 0) (i32.const 0))))
+		(if (i32.gt_u (i32.add (local.get $start) (local.get $n)) (global.get $heap_end))
+			(then (global.set $heap_end (i32.add (local.get $start) (local.get $n)))))
+		;; Record the block, and zero its cells.
+		(i32.store offset={HEAP_INFO} (i32.shl (local.get $start) (i32.const 2)) (local.get $n))
+		(memory.fill
+			(i32.add (i32.const {HEAP_INFO}) (i32.shl (i32.add (local.get $start) (i32.const 1)) (i32.const 2)))
+			(i32.const 255)
+			(i32.shl (i32.sub (local.get $n) (i32.const 1)) (i32.const 2)))
+		(memory.fill (i32.shl (local.get $start) (i32.const 3)) (i32.const 0) (i32.shl (local.get $n) (i32.const 3)))
+		(i64.extend_i32_u (local.get $start)))
+
+	;; Free the block at the address. Freeing the null pointer does nothing.
+	(func $free (param $addr i64)
+		(local $size i32)
+		(if (i64.eq (local.get $addr) (i64.const {NULL})) (then (return)))
+		(if (i32.or
+				(i64.lt_s (local.get $addr) (i64.const {HEAP_START}))
+				(i64.ge_s (local.get $addr) (i64.extend_i32_u (global.get $heap_end))))
+			(then (call $fail {INVALID_FREE} (local.get $addr) (i32.const 0) (i32.const 0))))
+		(local.set $size (i32.load offset={HEAP_INFO} (i32.shl (i32.wrap_i64 (local.get $addr)) (i32.const 2))))
+		(if (i32.eqz (local.get $size))
+			(then (call $fail {DOUBLE_FREE} (local.get $addr) (i32.const 0) (i32.const 0))))
+		(if (i32.lt_s (local.get $size) (i32.const 0))
+			(then (call $fail {INVALID_FREE} (local.get $addr) (i32.const 0) (i32.const 0))))
+		(memory.fill
+			(i32.add (i32.const {HEAP_INFO}) (i32.shl (i32.wrap_i64 (local.get $addr)) (i32.const 2)))
+			(i32.const 0)
+			(i32.shl (local.get $size) (i32.const 2))))
+"#;
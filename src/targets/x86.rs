@@ -0,0 +1,1152 @@
+//! # x86-64 Target
+//!
+//! An implementation of the virtual machine for x86-64 Linux, as GNU assembler code.
+//!
+//! This allows the virtual machine to target native programs without a C compiler.
+//! The output only depends on the Linux system call interface, not the C standard
+//! library, so it can be assembled and linked with just binutils:
+//!
+//! ```bash
+//! $ as out.s -o out.o
+//! $ ld out.o -o out
+//! ```
+//!
+//! ## Machine State
+//!
+//! The tape, the register, the deref stack, and the foreign function channel
+//! are kept in memory. The virtual machine's call stack is the native stack, so
+//! calling a function is a native `call`, and returning is a native `ret`.
+//! A few machine registers hold the pointers into the machine state:
+//!
+//! | Register | Contents                                                        |
+//! |----------|-----------------------------------------------------------------|
+//! | `%r12`   | The tape pointer.                                               |
+//! | `%r13`   | The address of the register (the scalar register is its first cell). |
+//! | `%r14`   | The top of the deref stack.                                     |
+//! | `%r15`   | The top of the foreign function channel.                        |
+//!
+//! Like the C target, pointers are addresses in memory rather than indices on the tape.
+//! The standard variant's heap is grown with the `brk` system call.
+use super::{Architecture, CompiledTarget};
+use crate::{
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{CoreOp, StandardOp},
+    NULL,
+};
+
+/// The number of cells on the tape.
+const TAPE_SIZE: usize = 200000;
+/// The number of cells in the register.
+const REGISTER_SIZE: usize = 1024;
+/// The maximum depth of the deref stack.
+const DEREF_STACK_SIZE: usize = 1024;
+/// The number of cells in the foreign function channel.
+const FFI_CHANNEL_SIZE: usize = 256;
+
+/// The type for the x86-64 target which implements the `Target` trait.
+/// This allows the compiler to target x86-64 Linux machines.
+#[derive(Default)]
+pub struct X86 {
+    /// The number of labels used for the conditionals and loops so far.
+    labels: usize,
+    /// The labels of the conditionals and loops we're currently in.
+    blocks: Vec<usize>,
+}
+
+impl X86 {
+    /// Create a new label for a conditional or a loop, and enter its block.
+    fn enter_block(&mut self) -> usize {
+        let label = self.labels;
+        self.labels += 1;
+        self.blocks.push(label);
+        label
+    }
+}
+
+/// Join instructions into the code for a single virtual machine instruction.
+fn lines(instructions: &[&str]) -> String {
+    instructions.join("\n\t")
+}
+
+/// Apply an operation to the first `n` cells of the register and the tape.
+///
+/// In the body, `{reg}` and `{tape}` stand for the operands in the register
+/// and on the tape. When operating on more than one cell, the body is run
+/// in a loop counting with `%rsi`, so it must not use `%rsi`, or the local
+/// label `9`. The body may use `%rax`, `%rcx`, `%rdx`, and the floating point
+/// registers.
+fn each(n: usize, body: &[&str]) -> String {
+    let body = lines(body);
+    match n {
+        0 => String::new(),
+        1 => body.replace("{reg}", "(%r13)").replace("{tape}", "(%r12)"),
+        n => format!(
+            "xorl %esi, %esi\n9:\t{}\n\tincq %rsi\n\tcmpq ${n}, %rsi\n\tjb 9b",
+            body.replace("{reg}", "(%r13,%rsi,8)")
+                .replace("{tape}", "(%r12,%rsi,8)")
+        ),
+    }
+}
+
+/// Set the cells of the register to the given bits.
+fn set(values: impl IntoIterator<Item = i64>) -> String {
+    let mut result = vec![];
+    for (i, value) in values.into_iter().enumerate() {
+        let offset = i * 8;
+        if i32::try_from(value).is_ok() {
+            result.push(format!("movq ${value}, {offset}(%r13)"));
+        } else {
+            result.push(format!("movabsq ${value}, %rax"));
+            result.push(format!("movq %rax, {offset}(%r13)"));
+        }
+    }
+    result.join("\n\t")
+}
+
+impl Architecture for X86 {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(
+            i.mode,
+            InputMode::StdinChar | InputMode::StdinFloat | InputMode::StdinInt
+        )
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutFloat
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrFloat
+                | OutputMode::StderrInt
+        )
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => {
+                format!("# {}", text.replace('\n', "\n\t# ").replace('\r', ""))
+            }
+            CoreOp::While => {
+                let label = self.enter_block();
+                format!(".Lwhile{label}:\n\tcmpq $0, (%r13)\n\tje .Lend{label}")
+            }
+            CoreOp::If => {
+                let label = self.enter_block();
+                format!("cmpq $0, (%r13)\n\tje .Lelse{label}")
+            }
+            CoreOp::Else => {
+                let label = *self.blocks.last().expect("Unexpected else");
+                format!("jmp .Lend{label}\n.Lelse{label}:")
+            }
+            CoreOp::Set(n) => set(n.iter().copied()),
+            CoreOp::Call => "call sage_call".to_string(),
+            CoreOp::Return => "ret".to_string(),
+
+            CoreOp::Store(1) => lines(&["movq (%r13), %rax", "movq %rax, (%r12)"]),
+            CoreOp::Store(n) => {
+                format!("movq %r13, %rsi\n\tmovq %r12, %rdi\n\tmovl ${n}, %ecx\n\trep movsq")
+            }
+            CoreOp::Load(1) => lines(&["movq (%r12), %rax", "movq %rax, (%r13)"]),
+            CoreOp::Load(n) => {
+                format!("movq %r12, %rsi\n\tmovq %r13, %rdi\n\tmovl ${n}, %ecx\n\trep movsq")
+            }
+
+            CoreOp::Move(n) => format!("leaq {}(%r12), %r12", n * 8),
+            CoreOp::Where => "movq %r12, (%r13)".to_string(),
+            CoreOp::Deref => lines(&["movq %r12, (%r14)", "addq $8, %r14", "movq (%r12), %r12"]),
+            CoreOp::Refer => lines(&["subq $8, %r14", "movq (%r14), %r12"]),
+            CoreOp::Offset(offset, n) => {
+                let offset = offset * 8;
+                if i32::try_from(offset).is_ok() {
+                    each(*n, &[&format!("addq ${offset}, {{reg}}")])
+                } else {
+                    each(
+                        *n,
+                        &[&format!("movabsq ${offset}, %rax"), "addq %rax, {reg}"],
+                    )
+                }
+            }
+            CoreOp::Index(n) => each(
+                *n,
+                &["movq {tape}, %rax", "shlq $3, %rax", "addq %rax, {reg}"],
+            ),
+
+            CoreOp::Add(n) => each(*n, &["movq {tape}, %rax", "addq %rax, {reg}"]),
+            CoreOp::Sub(n) => each(*n, &["movq {tape}, %rax", "subq %rax, {reg}"]),
+            CoreOp::Mul(n) => each(
+                *n,
+                &["movq {reg}, %rax", "imulq {tape}, %rax", "movq %rax, {reg}"],
+            ),
+            // Like the interpreter, dividing by zero is a runtime error.
+            CoreOp::Div(n) => each(
+                *n,
+                &[
+                    "movq {tape}, %rcx",
+                    "testq %rcx, %rcx",
+                    "jz sage_divide_by_zero",
+                    "cmpq $-1, %rcx",
+                    "jne 1f",
+                    "negq {reg}",
+                    "jmp 2f",
+                    "1: movq {reg}, %rax",
+                    "cqto",
+                    "idivq %rcx",
+                    "movq %rax, {reg}",
+                    "2:",
+                ],
+            ),
+            CoreOp::Rem(n) => each(
+                *n,
+                &[
+                    "movq {tape}, %rcx",
+                    "testq %rcx, %rcx",
+                    "jz sage_divide_by_zero",
+                    "xorl %edx, %edx",
+                    "cmpq $-1, %rcx",
+                    "je 1f",
+                    "movq {reg}, %rax",
+                    "cqto",
+                    "idivq %rcx",
+                    "1: movq %rdx, {reg}",
+                ],
+            ),
+            CoreOp::Neg(n) => each(*n, &["negq {reg}"]),
+            CoreOp::Inc(n) => each(*n, &["incq {reg}"]),
+            CoreOp::Dec(n) => each(*n, &["decq {reg}"]),
+            CoreOp::Swap(n) => each(
+                *n,
+                &[
+                    "movq {tape}, %rax",
+                    "movq {reg}, %rdx",
+                    "movq %rdx, {tape}",
+                    "movq %rax, {reg}",
+                ],
+            ),
+
+            CoreOp::And(n) => each(
+                *n,
+                &[
+                    "xorl %eax, %eax",
+                    "cmpq $0, {reg}",
+                    "je 1f",
+                    "cmpq $0, {tape}",
+                    "setne %al",
+                    "1: movq %rax, {reg}",
+                ],
+            ),
+            CoreOp::Or(n) => each(
+                *n,
+                &[
+                    "xorl %eax, %eax",
+                    "movq {reg}, %rdx",
+                    "orq {tape}, %rdx",
+                    "setne %al",
+                    "movq %rax, {reg}",
+                ],
+            ),
+            CoreOp::Not(n) => each(
+                *n,
+                &[
+                    "xorl %eax, %eax",
+                    "cmpq $0, {reg}",
+                    "sete %al",
+                    "movq %rax, {reg}",
+                ],
+            ),
+
+            CoreOp::BitwiseNand(n) => each(
+                *n,
+                &[
+                    "movq {tape}, %rax",
+                    "andq {reg}, %rax",
+                    "notq %rax",
+                    "movq %rax, {reg}",
+                ],
+            ),
+            CoreOp::BitwiseAnd(n) => each(*n, &["movq {tape}, %rax", "andq %rax, {reg}"]),
+            CoreOp::BitwiseOr(n) => each(*n, &["movq {tape}, %rax", "orq %rax, {reg}"]),
+            CoreOp::BitwiseXor(n) => each(*n, &["movq {tape}, %rax", "xorq %rax, {reg}"]),
+            CoreOp::BitwiseNot(n) => each(*n, &["notq {reg}"]),
+
+            CoreOp::LeftShift(n) => each(*n, &["movq {tape}, %rcx", "shlq %cl, {reg}"]),
+            CoreOp::LogicalRightShift(n) => each(*n, &["movq {tape}, %rcx", "shrq %cl, {reg}"]),
+            CoreOp::ArithmeticRightShift(n) => each(*n, &["movq {tape}, %rcx", "sarq %cl, {reg}"]),
+
+            CoreOp::IsNonNegative(n) => each(
+                *n,
+                &[
+                    "xorl %eax, %eax",
+                    "cmpq $0, {reg}",
+                    "setge %al",
+                    "movq %rax, {reg}",
+                ],
+            ),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for x86 target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => {
+                return Err(format!(
+                    "Foreign function `{}` is not supported by the x86 target",
+                    ffi.name
+                ))
+            }
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+            StandardOp::Set(n) => set(n.iter().map(|val| val.to_bits() as i64)),
+
+            StandardOp::ToInt(n) => each(*n, &["cvttsd2si {reg}, %rax", "movq %rax, {reg}"]),
+            StandardOp::ToFloat(n) => each(*n, &["cvtsi2sdq {reg}, %xmm0", "movsd %xmm0, {reg}"]),
+
+            StandardOp::Add(n) => each(
+                *n,
+                &[
+                    "movsd {reg}, %xmm0",
+                    "addsd {tape}, %xmm0",
+                    "movsd %xmm0, {reg}",
+                ],
+            ),
+            StandardOp::Sub(n) => each(
+                *n,
+                &[
+                    "movsd {reg}, %xmm0",
+                    "subsd {tape}, %xmm0",
+                    "movsd %xmm0, {reg}",
+                ],
+            ),
+            StandardOp::Mul(n) => each(
+                *n,
+                &[
+                    "movsd {reg}, %xmm0",
+                    "mulsd {tape}, %xmm0",
+                    "movsd %xmm0, {reg}",
+                ],
+            ),
+            StandardOp::Div(n) => each(
+                *n,
+                &[
+                    "movsd {reg}, %xmm0",
+                    "divsd {tape}, %xmm0",
+                    "movsd %xmm0, {reg}",
+                ],
+            ),
+            // The remainder has the sign of the dividend, like C's `fmod`.
+            StandardOp::Rem(n) => each(
+                *n,
+                &[
+                    "fldl {tape}",
+                    "fldl {reg}",
+                    "1: fprem",
+                    "fnstsw %ax",
+                    "testw $0x400, %ax",
+                    "jnz 1b",
+                    "fstp %st(1)",
+                    "fstpl {reg}",
+                ],
+            ),
+            StandardOp::Pow(n) => each(
+                *n,
+                &[
+                    "movsd {reg}, %xmm0",
+                    "movsd {tape}, %xmm1",
+                    "call sage_pow",
+                    "movsd %xmm0, {reg}",
+                ],
+            ),
+            StandardOp::Neg(n) => each(*n, &["btcq $63, {reg}"]),
+            StandardOp::IsNonNegative(n) => each(
+                *n,
+                &[
+                    "xorl %eax, %eax",
+                    "xorpd %xmm1, %xmm1",
+                    "movsd {reg}, %xmm0",
+                    "ucomisd %xmm1, %xmm0",
+                    "setae %al",
+                    "movq %rax, {reg}",
+                ],
+            ),
+
+            StandardOp::Sin(n) => each(*n, &["fldl {reg}", "fsin", "fstpl {reg}"]),
+            StandardOp::Cos(n) => each(*n, &["fldl {reg}", "fcos", "fstpl {reg}"]),
+            StandardOp::Tan(n) => each(*n, &["fldl {reg}", "fptan", "fstp %st(0)", "fstpl {reg}"]),
+            // asin(x) = atan2(x, sqrt(1 - x^2))
+            StandardOp::ASin(n) => each(
+                *n,
+                &[
+                    "fldl {reg}",
+                    "fld %st(0)",
+                    "fmul %st(0), %st",
+                    "fld1",
+                    "fsub %st(1), %st",
+                    "fstp %st(1)",
+                    "fsqrt",
+                    "fpatan",
+                    "fstpl {reg}",
+                ],
+            ),
+            // acos(x) = atan2(sqrt(1 - x^2), x)
+            StandardOp::ACos(n) => each(
+                *n,
+                &[
+                    "fldl {reg}",
+                    "fld %st(0)",
+                    "fmul %st(0), %st",
+                    "fld1",
+                    "fsub %st(1), %st",
+                    "fstp %st(1)",
+                    "fsqrt",
+                    "fxch %st(1)",
+                    "fpatan",
+                    "fstpl {reg}",
+                ],
+            ),
+            StandardOp::ATan(n) => each(*n, &["fldl {reg}", "fld1", "fpatan", "fstpl {reg}"]),
+
+            StandardOp::Alloc => {
+                lines(&["movq (%r13), %rax", "call sage_alloc", "movq %rax, (%r13)"])
+            }
+            StandardOp::Free => lines(&["movq (%r13), %rax", "call sage_free"]),
+            _ => return Err(format!("Invalid standard op for x86 target {op:?}")),
+        })
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function, _) => "ret".to_string(),
+            (CoreOp::While, _) => {
+                let label = self.blocks.pop().expect("Unexpected end");
+                format!("jmp .Lwhile{label}\n.Lend{label}:")
+            }
+            (CoreOp::If, _) => {
+                let label = self.blocks.pop().expect("Unexpected end");
+                format!(".Lelse{label}:")
+            }
+            (CoreOp::Else, _) => {
+                let label = self.blocks.pop().expect("Unexpected end");
+                format!(".Lend{label}:")
+            }
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize) -> String {
+        format!("f{label_id}:")
+    }
+
+    fn name(&self) -> &str {
+        "x86-64"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        match src.mode {
+            InputMode::StdinChar => Ok(lines(&[
+                "call sage_getc",
+                // The end of the input reads as zero.
+                "xorl %edx, %edx",
+                "testq %rax, %rax",
+                "cmovsq %rdx, %rax",
+                "movq %rax, (%r13)",
+            ])),
+            InputMode::StdinInt => Ok(lines(&["call sage_read_int", "movq %rax, (%r13)"])),
+            InputMode::StdinFloat => Ok(lines(&["call sage_read_float", "movsd %xmm0, (%r13)"])),
+            _ => Err("Input not supported by this target".to_string()),
+        }
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        match dst.mode {
+            OutputMode::StdoutChar => Ok(lines(&["movq (%r13), %rax", "call sage_putc"])),
+            OutputMode::StdoutInt => Ok(lines(&[
+                "movq (%r13), %rax",
+                "call sage_fmt_int",
+                "call sage_write_out",
+            ])),
+            OutputMode::StdoutFloat => Ok(lines(&[
+                "movsd (%r13), %xmm0",
+                "call sage_fmt_float",
+                "call sage_write_out",
+            ])),
+            OutputMode::StderrChar => Ok(lines(&[
+                "movq (%r13), %rax",
+                "leaq sage_num_buf(%rip), %rsi",
+                "movb %al, (%rsi)",
+                "movl $1, %edx",
+                "call sage_write_err",
+            ])),
+            OutputMode::StderrInt => Ok(lines(&[
+                "movq (%r13), %rax",
+                "call sage_fmt_int",
+                "call sage_write_err",
+            ])),
+            OutputMode::StderrFloat => Ok(lines(&[
+                "movsd (%r13), %xmm0",
+                "call sage_fmt_float",
+                "call sage_write_err",
+            ])),
+            _ => Err("Output not supported by this target".to_string()),
+        }
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Ok(lines(&[
+            "movq (%r15), %rax",
+            "movq %rax, (%r13)",
+            "subq $8, %r15",
+        ]))
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Ok(lines(&[
+            "addq $8, %r15",
+            "movq (%r13), %rax",
+            "movq %rax, (%r15)",
+        ]))
+    }
+
+    fn prelude(&self, is_core: bool) -> Option<String> {
+        let mut result = format!(
+            r#"# Assemble and link with `as out.s -o out.o && ld out.o -o out`.
+	.bss
+	.align 16
+sage_tape:	.zero {tape}
+sage_reg:	.zero {reg}
+sage_refs:	.zero {refs}
+sage_ffi_channel:	.zero {ffi}
+sage_out_buf:	.zero 4096
+sage_in_buf:	.zero 4096
+sage_num_buf:	.zero 64
+sage_out_len:	.zero 8
+sage_in_pos:	.zero 8
+sage_in_len:	.zero 8
+"#,
+            tape = TAPE_SIZE * 8,
+            reg = REGISTER_SIZE * 8,
+            refs = DEREF_STACK_SIZE * 8,
+            ffi = FFI_CHANNEL_SIZE * 8,
+        );
+        // Core programs can still read and write floats.
+        result += RUNTIME;
+        result += FLOAT_RUNTIME;
+        if !is_core {
+            result += &ALLOCATOR.replace("{NULL}", &NULL.to_string());
+        }
+        result += "\n\t.text\n";
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<i32>) -> Option<String> {
+        let mut funs = funs;
+        funs.sort();
+        let mut result = String::from("\n\t.data\n\t.align 8\nsage_funs:\n");
+        for fun in &funs {
+            result += &format!("\t.quad f{fun}\n");
+        }
+        result += &format!("sage_funs_len:\n\t.quad {}\n", funs.len());
+        result += r#"
+	.text
+	.globl _start
+_start:
+	leaq sage_tape(%rip), %r12
+	leaq sage_reg(%rip), %r13
+	leaq sage_refs(%rip), %r14
+	leaq sage_ffi_channel(%rip), %r15
+	call sage_main
+	call sage_flush
+	movl $231, %eax
+	xorl %edi, %edi
+	syscall
+
+sage_main:
+"#;
+        Some(result)
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some("ret\n".to_string())
+    }
+}
+
+impl CompiledTarget for X86 {}
+
+/// The routines used by every program for calling functions, input and output,
+/// and reporting runtime errors. Output to stdout is buffered, and flushed before
+/// reading input, writing to stderr, and exiting.
+const RUNTIME: &str = r#"
+	.section .rodata
+sage_msg_empty:	.asciz ""
+sage_msg_newline:	.asciz "\n"
+sage_msg_function:	.asciz "function "
+sage_msg_not_defined:	.asciz " not defined\n"
+sage_msg_divide_by_zero:	.asciz "division by zero\n"
+
+	.text
+# Call the function whose index is in the register.
+sage_call:
+	movq (%r13), %rax
+	cmpq sage_funs_len(%rip), %rax
+	jae 1f
+	leaq sage_funs(%rip), %rdx
+	jmpq *(%rdx,%rax,8)
+1:	movq %rax, %rsi
+	leaq sage_msg_function(%rip), %rdi
+	leaq sage_msg_not_defined(%rip), %rdx
+	jmp sage_runtime_error
+
+# Report a runtime error and exit. The message is the string at %rdi,
+# followed by the integer in %rsi, followed by the string at %rdx.
+sage_runtime_error:
+	pushq %rdx
+	pushq %rsi
+	pushq %rdi
+	call sage_flush
+	popq %rsi
+	call sage_write_str_err
+	popq %rax
+	call sage_fmt_int
+	movl $2, %edi
+	call sage_write
+	popq %rsi
+	call sage_write_str_err
+	movl $231, %eax
+	movl $1, %edi
+	syscall
+
+# Report a division by zero and exit.
+sage_divide_by_zero:
+	call sage_flush
+	leaq sage_msg_divide_by_zero(%rip), %rsi
+	call sage_write_str_err
+	movl $231, %eax
+	movl $1, %edi
+	syscall
+
+# Write the %rdx bytes at %rsi to the file descriptor %edi.
+sage_write:
+	testq %rdx, %rdx
+	jle 2f
+1:	movl $1, %eax
+	syscall
+	testq %rax, %rax
+	jle 2f
+	addq %rax, %rsi
+	subq %rax, %rdx
+	jg 1b
+2:	ret
+
+# Write the null terminated string at %rsi to stderr.
+sage_write_str_err:
+	movq %rsi, %rdx
+1:	cmpb $0, (%rdx)
+	je 2f
+	incq %rdx
+	jmp 1b
+2:	subq %rsi, %rdx
+	movl $2, %edi
+	jmp sage_write
+
+# Write the buffered output to stdout.
+sage_flush:
+	movl $1, %edi
+	leaq sage_out_buf(%rip), %rsi
+	movq sage_out_len(%rip), %rdx
+	call sage_write
+	movq $0, sage_out_len(%rip)
+	ret
+
+# Write the byte in %al to stdout.
+sage_putc:
+	movq sage_out_len(%rip), %rdx
+	cmpq $4096, %rdx
+	jb 1f
+	pushq %rax
+	call sage_flush
+	popq %rax
+	xorl %edx, %edx
+1:	leaq sage_out_buf(%rip), %rcx
+	movb %al, (%rcx,%rdx)
+	incq %rdx
+	movq %rdx, sage_out_len(%rip)
+	ret
+
+# Write the %rdx bytes at %rsi to stdout.
+sage_write_out:
+	movq %rsi, %r8
+	movq %rdx, %r9
+	testq %r9, %r9
+	jle 2f
+1:	movzbl (%r8), %eax
+	call sage_putc
+	incq %r8
+	decq %r9
+	jnz 1b
+2:	ret
+
+# Write the %rdx bytes at %rsi to stderr, after everything written to stdout.
+sage_write_err:
+	pushq %rsi
+	pushq %rdx
+	call sage_flush
+	popq %rdx
+	popq %rsi
+	movl $2, %edi
+	jmp sage_write
+
+# Format the integer in %rax, returning the text in %rsi with its length in %rdx.
+sage_fmt_int:
+	leaq sage_num_buf+64(%rip), %rsi
+	movq %rax, %r8
+	testq %rax, %rax
+	jns 1f
+	negq %rax
+1:	movl $10, %ecx
+2:	xorl %edx, %edx
+	divq %rcx
+	addb $48, %dl
+	decq %rsi
+	movb %dl, (%rsi)
+	testq %rax, %rax
+	jnz 2b
+	testq %r8, %r8
+	jns 3f
+	decq %rsi
+	movb $45, (%rsi)
+3:	leaq sage_num_buf+64(%rip), %rdx
+	subq %rsi, %rdx
+	ret
+
+# Get the next byte of input in %rax without consuming it, or -1 at the end of the input.
+sage_peekc:
+	movq sage_in_pos(%rip), %rax
+	cmpq sage_in_len(%rip), %rax
+	jb 2f
+	call sage_flush
+	xorl %edi, %edi
+	leaq sage_in_buf(%rip), %rsi
+	movl $4096, %edx
+	xorl %eax, %eax
+	syscall
+	movq $0, sage_in_pos(%rip)
+	testq %rax, %rax
+	jg 1f
+	movq $0, sage_in_len(%rip)
+	movq $-1, %rax
+	ret
+1:	movq %rax, sage_in_len(%rip)
+	xorl %eax, %eax
+2:	leaq sage_in_buf(%rip), %rcx
+	movzbl (%rcx,%rax), %eax
+	ret
+
+# Read the next byte of input into %rax, or -1 at the end of the input.
+sage_getc:
+	call sage_peekc
+	testq %rax, %rax
+	js 1f
+	incq sage_in_pos(%rip)
+1:	ret
+
+# Skip the whitespace at the start of the input, and read the sign of a number.
+# Returns 1 in %rbp if the number is negative, otherwise 0.
+sage_read_sign:
+1:	call sage_peekc
+	cmpq $32, %rax
+	je 2f
+	leaq -9(%rax), %rdx
+	cmpq $4, %rdx
+	ja 3f
+2:	call sage_getc
+	jmp 1b
+3:	xorl %ebp, %ebp
+	cmpq $45, %rax
+	jne 4f
+	movl $1, %ebp
+	call sage_getc
+4:	ret
+
+# Read an integer from the input into %rax.
+sage_read_int:
+	pushq %rbx
+	pushq %rbp
+	call sage_read_sign
+	xorl %ebx, %ebx
+1:	call sage_peekc
+	subq $48, %rax
+	cmpq $9, %rax
+	ja 2f
+	imulq $10, %rbx
+	addq %rax, %rbx
+	call sage_getc
+	jmp 1b
+2:	movq %rbx, %rax
+	testq %rbp, %rbp
+	jz 3f
+	negq %rax
+3:	popq %rbp
+	popq %rbx
+	ret
+"#;
+
+/// The routines for reading, formatting, and raising floats.
+const FLOAT_RUNTIME: &str = r#"
+	.section .rodata
+	.align 8
+sage_ten:	.double 10.0
+sage_one:	.double 1.0
+sage_max_fixed:	.double 1e16
+sage_min_fixed:	.double 1e-4
+sage_powers_of_ten:
+	.double 1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7
+	.double 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15
+
+	.text
+# Read a float from the input into %xmm0.
+sage_read_float:
+	pushq %rbx
+	pushq %rbp
+	call sage_read_sign
+	xorpd %xmm0, %xmm0
+1:	call sage_peekc
+	subq $48, %rax
+	cmpq $9, %rax
+	ja 2f
+	mulsd sage_ten(%rip), %xmm0
+	cvtsi2sdq %rax, %xmm1
+	addsd %xmm1, %xmm0
+	call sage_getc
+	jmp 1b
+2:	cmpq $-2, %rax
+	jne 4f
+	call sage_getc
+	movsd sage_one(%rip), %xmm2
+3:	call sage_peekc
+	subq $48, %rax
+	cmpq $9, %rax
+	ja 4f
+	divsd sage_ten(%rip), %xmm2
+	cvtsi2sdq %rax, %xmm1
+	mulsd %xmm2, %xmm1
+	addsd %xmm1, %xmm0
+	call sage_getc
+	jmp 3b
+4:	testq %rbp, %rbp
+	jz 5f
+	movq %xmm0, %rax
+	btcq $63, %rax
+	movq %rax, %xmm0
+5:	popq %rbp
+	popq %rbx
+	ret
+
+# Write the unsigned integer in %rax at %rdi, with at least %rcx digits, and advance %rdi.
+sage_fmt_digits:
+	movq %rcx, %r9
+	xorl %r10d, %r10d
+	movl $10, %r11d
+1:	xorl %edx, %edx
+	divq %r11
+	pushq %rdx
+	incq %r10
+	testq %rax, %rax
+	jnz 1b
+	cmpq %r9, %r10
+	jb 1b
+2:	popq %rdx
+	addb $48, %dl
+	movb %dl, (%rdi)
+	incq %rdi
+	decq %r10
+	jnz 2b
+	ret
+
+# Format the float in %xmm0 with about 15 significant digits, returning the text
+# in %rsi with its length in %rdx. Numbers at least 1e16 or less than 1e-4 are written
+# in scientific notation.
+sage_fmt_float:
+	pushq %rbx
+	pushq %rbp
+	leaq sage_num_buf(%rip), %rdi
+	ucomisd %xmm0, %xmm0
+	jp .Lfmt_nan
+	movq %xmm0, %rax
+	btrq $63, %rax
+	jnc 1f
+	movb $45, (%rdi)
+	incq %rdi
+1:	movq %rax, %xmm0
+	movabsq $0x7ff0000000000000, %rdx
+	cmpq %rdx, %rax
+	je .Lfmt_inf
+	# Scale large and small numbers into scientific notation, keeping track of the exponent.
+	xorl %r8d, %r8d
+	ucomisd sage_max_fixed(%rip), %xmm0
+	jb 12f
+2:	divsd sage_ten(%rip), %xmm0
+	incq %r8
+	ucomisd sage_ten(%rip), %xmm0
+	jae 2b
+	jmp 3f
+12:	xorpd %xmm1, %xmm1
+	ucomisd %xmm1, %xmm0
+	je 3f
+	ucomisd sage_min_fixed(%rip), %xmm0
+	jae 3f
+13:	mulsd sage_ten(%rip), %xmm0
+	decq %r8
+	ucomisd sage_one(%rip), %xmm0
+	jb 13b
+3:	cvttsd2si %xmm0, %rbx
+	cvtsi2sdq %rbx, %xmm1
+	subsd %xmm1, %xmm0
+	# The digits of the integer part count towards the significant digits.
+	movl $15, %ebp
+	movq %rbx, %rax
+	movl $10, %ecx
+4:	testq %rax, %rax
+	jz 5f
+	xorl %edx, %edx
+	divq %rcx
+	decq %rbp
+	jmp 4b
+5:	testq %rbp, %rbp
+	jns 6f
+	xorl %ebp, %ebp
+	# Round the fractional part to the remaining digits, carrying into the integer part.
+6:	leaq sage_powers_of_ten(%rip), %rcx
+	mulsd (%rcx,%rbp,8), %xmm0
+	cvtsd2si %xmm0, %rax
+	cvttsd2si (%rcx,%rbp,8), %rdx
+	cmpq %rdx, %rax
+	jb 7f
+	subq %rdx, %rax
+	incq %rbx
+7:	pushq %r8
+	pushq %rax
+	movq %rbx, %rax
+	movl $1, %ecx
+	call sage_fmt_digits
+	movb $46, (%rdi)
+	incq %rdi
+	popq %rax
+	# Drop the trailing zeros of the fractional part, keeping at least one digit.
+	testq %rbp, %rbp
+	jnz 8f
+	incq %rbp
+8:	movl $10, %ecx
+9:	cmpq $1, %rbp
+	jbe 11f
+	movq %rax, %rbx
+	xorl %edx, %edx
+	divq %rcx
+	testq %rdx, %rdx
+	jnz 10f
+	decq %rbp
+	jmp 9b
+10:	movq %rbx, %rax
+11:	movq %rbp, %rcx
+	call sage_fmt_digits
+	popq %r8
+	testq %r8, %r8
+	jz .Lfmt_done
+	# In scientific notation, whole numbers are written without their fractional part.
+	cmpw $0x302e, -2(%rdi)
+	jne 14f
+	subq $2, %rdi
+14:	movb $101, (%rdi)
+	incq %rdi
+	movq %r8, %rax
+	testq %rax, %rax
+	jns 15f
+	movb $45, (%rdi)
+	incq %rdi
+	negq %rax
+15:	movl $1, %ecx
+	call sage_fmt_digits
+	jmp .Lfmt_done
+.Lfmt_nan:
+	movl $0x4e614e, (%rdi)
+	addq $3, %rdi
+	jmp .Lfmt_done
+.Lfmt_inf:
+	movl $0x666e69, (%rdi)
+	addq $3, %rdi
+.Lfmt_done:
+	leaq sage_num_buf(%rip), %rsi
+	movq %rdi, %rdx
+	subq %rsi, %rdx
+	popq %rbp
+	popq %rbx
+	ret
+
+# Raise the float in %xmm0 to the power of the float in %xmm1, returning the result in %xmm0.
+sage_pow:
+	subq $16, %rsp
+	xorpd %xmm2, %xmm2
+	ucomisd %xmm2, %xmm1
+	jne 1f
+	jp 1f
+	# Anything to the power of zero is one.
+	movsd sage_one(%rip), %xmm0
+	jmp 5f
+1:	ucomisd %xmm2, %xmm0
+	jne 2f
+	jp 2f
+	# Zero to a positive power is zero, and to a negative power is infinity.
+	ucomisd %xmm2, %xmm1
+	jae 5f
+	movsd sage_one(%rip), %xmm1
+	divsd %xmm0, %xmm1
+	movapd %xmm1, %xmm0
+	jmp 5f
+2:	# Negative numbers can only be raised to integer powers.
+	xorl %eax, %eax
+	ucomisd %xmm2, %xmm0
+	jae 3f
+	cvttsd2si %xmm1, %rax
+	cvtsi2sdq %rax, %xmm2
+	ucomisd %xmm1, %xmm2
+	jne 6f
+	movq %xmm0, %rdx
+	btrq $63, %rdx
+	movq %rdx, %xmm0
+	andl $1, %eax
+	# Compute 2^(y * log2(x)), splitting the exponent into its integer and fractional parts.
+3:	movsd %xmm0, (%rsp)
+	movsd %xmm1, 8(%rsp)
+	fldl 8(%rsp)
+	fldl (%rsp)
+	fyl2x
+	fld %st(0)
+	frndint
+	fxch %st(1)
+	fsub %st(1), %st
+	f2xm1
+	fld1
+	faddp
+	fscale
+	fstp %st(1)
+	fstpl (%rsp)
+	movsd (%rsp), %xmm0
+	testl %eax, %eax
+	jz 5f
+	movq %xmm0, %rdx
+	btcq $63, %rdx
+	movq %rdx, %xmm0
+5:	addq $16, %rsp
+	ret
+6:	xorpd %xmm0, %xmm0
+	divsd %xmm0, %xmm0
+	jmp 5b
+"#;
+
+/// The heap allocator used by the standard variant. There is no C runtime to
+/// lean on, so new blocks are carved from the end of the program break with the
+/// `brk` system call, and the break only ever grows.
+///
+/// Each block starts with a header of three cells: the next block in the list
+/// of all blocks, the size of the block, and whether the block is freed.
+/// `sage_alloc` zeroes and reuses the first freed block in the list which is
+/// large enough, whole; blocks are never split or merged. `sage_free` walks the
+/// list to tell invalid frees and double frees apart from valid ones.
+const ALLOCATOR: &str = r#"
+	.section .rodata
+sage_msg_negative_alloc:	.asciz "cannot allocate a negative number of cells: "
+sage_msg_out_of_memory:	.asciz "out of memory while allocating cells: "
+sage_msg_double_free:	.asciz "double free of address "
+sage_msg_invalid_free:	.asciz "invalid free of address "
+
+	.bss
+	.align 8
+sage_blocks:	.zero 8
+sage_heap_end:	.zero 8
+
+	.text
+# Allocate a block of %rax cells, returning its address in %rax.
+sage_alloc:
+	testq %rax, %rax
+	jns 1f
+	leaq sage_msg_negative_alloc(%rip), %rdi
+	jmp 8f
+1:	jnz 2f
+	movl $1, %eax
+2:	movq %rax, %r8
+	# Find the first freed block which is large enough.
+	movq sage_blocks(%rip), %r9
+3:	testq %r9, %r9
+	jz 4f
+	cmpq $0, 16(%r9)
+	je 9f
+	cmpq %r8, 8(%r9)
+	jae 6f
+9:	movq (%r9), %r9
+	jmp 3b
+	# Otherwise, grow the heap to make room for a new block.
+4:	movq sage_heap_end(%rip), %r9
+	testq %r9, %r9
+	jnz 5f
+	movl $12, %eax
+	xorl %edi, %edi
+	syscall
+	movq %rax, %r9
+5:	leaq 24(%r9,%r8,8), %rdi
+	movq %rdi, %r10
+	movl $12, %eax
+	syscall
+	cmpq %r10, %rax
+	jb 7f
+	movq %r10, sage_heap_end(%rip)
+	movq %r8, 8(%r9)
+	movq sage_blocks(%rip), %rax
+	movq %rax, (%r9)
+	movq %r9, sage_blocks(%rip)
+6:	movq $0, 16(%r9)
+	leaq 24(%r9), %rdi
+	movq 8(%r9), %rcx
+	xorl %eax, %eax
+	rep stosq
+	leaq 24(%r9), %rax
+	ret
+7:	movq %r8, %rax
+	leaq sage_msg_out_of_memory(%rip), %rdi
+8:	movq %rax, %rsi
+	leaq sage_msg_newline(%rip), %rdx
+	jmp sage_runtime_error
+
+# Free the block at the address in %rax.
+sage_free:
+	cmpq ${NULL}, %rax
+	je 3f
+	movq sage_blocks(%rip), %r9
+1:	testq %r9, %r9
+	jz 4f
+	leaq 24(%r9), %rdx
+	cmpq %rax, %rdx
+	je 2f
+	movq (%r9), %r9
+	jmp 1b
+2:	cmpq $0, 16(%r9)
+	jne 5f
+	movq $1, 16(%r9)
+3:	ret
+4:	leaq sage_msg_invalid_free(%rip), %rdi
+	jmp 6f
+5:	leaq sage_msg_double_free(%rip), %rdi
+6:	movq %rax, %rsi
+	leaq sage_msg_newline(%rip), %rdx
+	jmp sage_runtime_error
+"#;
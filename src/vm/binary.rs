@@ -0,0 +1,717 @@
+//! # Binary Programs
+//!
+//! This module implements a binary format for virtual machine programs of
+//! both variants. Binary programs are much smaller and faster to load than
+//! the textual virtual machine code, so they're used to ship precompiled
+//! programs (with the `.sgb` extension).
+//!
+//! Like the textual code, binary programs leave out comments, so the
+//! source map of a program can be used with its binary program too.
+//!
+//! ## Format
+//!
+//! ```text
+//! "SGB" <version>
+//! <variant: 0 for core, 1 for standard>
+//! <number of FFI bindings> (<name> <input cells> <output cells>)...
+//! <number of instructions> (<tag> <operands>...)...
+//! ```
+//!
+//! The version and the variant are single bytes. Unsigned numbers are
+//! LEB128 encoded, and signed numbers are zigzag encoded before that.
+//! Floats are their 8 bytes in little endian order, and strings are their
+//! length followed by their UTF-8 bytes.
+//!
+//! Each instruction starts with a byte tag for its kind. Core instructions
+//! have tags below 64, and standard instructions have tags from 64. Foreign
+//! function calls refer to their binding by its index in the table of FFI
+//! bindings. Input and output modes are tagged the same way: modes without
+//! parameters are tagged by their index in a table, and the others have
+//! tags from 128.
+//!
+//! New instructions and modes must get new tags, so that older binary
+//! programs can still be loaded. Changing the meaning of an existing tag
+//! needs a new version.
+use super::{CoreOp, CoreProgram, StandardOp, StandardProgram};
+use crate::side_effects::{
+    Axis, Channel, Color, Direction, FFIBinding, Input, InputMode, Output, OutputMode,
+};
+
+/// The bytes every binary program starts with.
+const MAGIC: &[u8] = b"SGB";
+/// The version of the binary format.
+const VERSION: u8 = 1;
+
+const CORE_VARIANT: u8 = 0;
+const STANDARD_VARIANT: u8 = 1;
+
+/// The input modes without parameters, indexed by their tags.
+const INPUT_MODES: &[InputMode] = &[
+    InputMode::StdinChar,
+    InputMode::StdinInt,
+    InputMode::StdinFloat,
+    InputMode::Random,
+    InputMode::Button,
+    InputMode::Keyboard,
+    InputMode::Clock,
+    InputMode::Microphone,
+    InputMode::RedLight,
+    InputMode::GreenLight,
+    InputMode::BlueLight,
+    InputMode::Brightness,
+    InputMode::Humidity,
+    InputMode::Barometer,
+    InputMode::Thermometer,
+    InputMode::RainGauge,
+    InputMode::UVSensor,
+    InputMode::WindSpeed,
+    InputMode::WindDirection,
+    InputMode::PressureGauge,
+    InputMode::FlowSensor,
+    InputMode::VolumeSensor,
+    InputMode::WeightSensor,
+    InputMode::PHSensor,
+    InputMode::ConductivitySensor,
+    InputMode::Odometer,
+    InputMode::Compass,
+    InputMode::Proximity,
+    InputMode::Altimeter,
+    InputMode::DepthSensor,
+    InputMode::AnalogPin,
+    InputMode::DigitalPin,
+];
+
+/// The output modes without parameters, indexed by their tags.
+const OUTPUT_MODES: &[OutputMode] = &[
+    OutputMode::StdoutChar,
+    OutputMode::StdoutInt,
+    OutputMode::StdoutFloat,
+    OutputMode::StderrChar,
+    OutputMode::StderrInt,
+    OutputMode::StderrFloat,
+    OutputMode::PrinterChar,
+    OutputMode::PrinterInt,
+    OutputMode::PrinterFloat,
+    OutputMode::Brightness,
+    OutputMode::AnalogPin,
+    OutputMode::DigitalPin,
+    OutputMode::StepperMotor,
+    OutputMode::Solenoid,
+    OutputMode::Valve,
+    OutputMode::MotorSpeed,
+    OutputMode::Servo,
+    OutputMode::Temperature,
+    OutputMode::Pump,
+    OutputMode::Fan,
+    OutputMode::Blower,
+    OutputMode::Heater,
+    OutputMode::Cooler,
+    OutputMode::Pressure,
+    OutputMode::Buzzer,
+    OutputMode::Bell,
+    OutputMode::Note,
+    OutputMode::SpeakerVolume,
+    OutputMode::SpeakerFrequency,
+    OutputMode::UpdateDisplay,
+    OutputMode::ClearDisplay,
+    OutputMode::SetCursorRow,
+    OutputMode::SetCursorColumn,
+    OutputMode::MoveCursorUp,
+    OutputMode::MoveCursorDown,
+    OutputMode::MoveCursorLeft,
+    OutputMode::MoveCursorRight,
+];
+
+const DIRECTIONS: &[Direction] = &[
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+const AXES: &[Axis] = &[Axis::X, Axis::Y, Axis::Z];
+const COLORS: &[Color] = &[
+    Color::Black,
+    Color::White,
+    Color::Red,
+    Color::Green,
+    Color::Blue,
+    Color::Yellow,
+    Color::Cyan,
+    Color::Magenta,
+    Color::Orange,
+];
+
+impl CoreProgram {
+    /// Serialize the program in the binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        let ops = self
+            .0
+            .iter()
+            .filter(|op| !matches!(op, CoreOp::Comment(_)))
+            .collect::<Vec<_>>();
+        encoder.unsigned(ops.len());
+        for op in ops {
+            encoder.core_op(op);
+        }
+        encoder.finish(CORE_VARIANT)
+    }
+}
+
+impl StandardProgram {
+    /// Serialize the program in the binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        let ops = self
+            .0
+            .iter()
+            .filter(|op| !matches!(op, StandardOp::CoreOp(CoreOp::Comment(_))))
+            .collect::<Vec<_>>();
+        encoder.unsigned(ops.len());
+        for op in ops {
+            encoder.std_op(op);
+        }
+        encoder.finish(STANDARD_VARIANT)
+    }
+}
+
+/// Parse a program in the binary format. This returns core
+/// programs as `Ok`, and standard programs as `Err`, like `parse_vm`.
+pub fn from_bytes(bytes: &[u8]) -> Result<Result<CoreProgram, StandardProgram>, String> {
+    let mut decoder = Decoder {
+        bytes,
+        pos: 0,
+        bindings: vec![],
+    };
+    if !bytes.starts_with(MAGIC) {
+        return Err("not a binary sage program".to_string());
+    }
+    decoder.pos = MAGIC.len();
+    match decoder.byte()? {
+        VERSION => {}
+        version => return Err(format!("unsupported binary program version {version}")),
+    }
+    let variant = decoder.byte()?;
+
+    let bindings = decoder.count()?;
+    for _ in 0..bindings {
+        let name = decoder.string()?;
+        let input_cells = decoder.unsigned()?;
+        let output_cells = decoder.unsigned()?;
+        decoder
+            .bindings
+            .push(FFIBinding::new(name, input_cells, output_cells));
+    }
+
+    let len = decoder.count()?;
+    let result = match variant {
+        CORE_VARIANT => {
+            let mut ops = Vec::with_capacity(len);
+            for _ in 0..len {
+                ops.push(decoder.core_op()?);
+            }
+            Ok(CoreProgram(ops))
+        }
+        STANDARD_VARIANT => {
+            let mut ops = Vec::with_capacity(len);
+            for _ in 0..len {
+                ops.push(decoder.std_op()?);
+            }
+            Err(StandardProgram(ops))
+        }
+        variant => return Err(format!("invalid program variant {variant}")),
+    };
+    if decoder.pos < bytes.len() {
+        return Err(format!(
+            "unexpected bytes after the end of the program at byte {}",
+            decoder.pos
+        ));
+    }
+    Ok(result)
+}
+
+/// Writes the instructions of a program, and collects its FFI bindings.
+#[derive(Default)]
+struct Encoder {
+    /// The encoded instructions.
+    bytes: Vec<u8>,
+    /// The FFI bindings called by the program, in the order of their indices.
+    bindings: Vec<FFIBinding>,
+}
+
+impl Encoder {
+    /// Put the header and the FFI bindings before the encoded instructions.
+    fn finish(self, variant: u8) -> Vec<u8> {
+        let mut result = Self::default();
+        result.bytes.extend_from_slice(MAGIC);
+        result.bytes.extend([VERSION, variant]);
+        result.unsigned(self.bindings.len());
+        for binding in &self.bindings {
+            result.string(&binding.name);
+            result.unsigned(binding.input_cells);
+            result.unsigned(binding.output_cells);
+        }
+        result.bytes.extend(self.bytes);
+        result.bytes
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.bytes.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.bytes.push(n as u8);
+    }
+
+    fn unsigned(&mut self, n: usize) {
+        self.varint(n as u64);
+    }
+
+    fn signed(&mut self, n: i64) {
+        self.varint(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn float(&mut self, n: f64) {
+        self.bytes.extend(n.to_bits().to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.unsigned(s.len());
+        self.bytes.extend(s.as_bytes());
+    }
+
+    /// Write an instruction with a tag and a size.
+    fn sized(&mut self, tag: u8, n: usize) {
+        self.byte(tag);
+        self.unsigned(n);
+    }
+
+    fn core_op(&mut self, op: &CoreOp) {
+        match op {
+            CoreOp::Comment(_) => {}
+            CoreOp::Set(values) => {
+                self.byte(0);
+                self.unsigned(values.len());
+                for value in values {
+                    self.signed(*value);
+                }
+            }
+            CoreOp::Function => self.byte(1),
+            CoreOp::Call => self.byte(2),
+            CoreOp::Return => self.byte(3),
+            CoreOp::While => self.byte(4),
+            CoreOp::If => self.byte(5),
+            CoreOp::Else => self.byte(6),
+            CoreOp::End => self.byte(7),
+            CoreOp::Store(n) => self.sized(8, *n),
+            CoreOp::Load(n) => self.sized(9, *n),
+            CoreOp::Move(n) => {
+                self.byte(10);
+                self.signed(*n as i64);
+            }
+            CoreOp::Where => self.byte(11),
+            CoreOp::Deref => self.byte(12),
+            CoreOp::Refer => self.byte(13),
+            CoreOp::Index(n) => self.sized(14, *n),
+            CoreOp::Offset(offset, n) => {
+                self.byte(15);
+                self.signed(*offset as i64);
+                self.unsigned(*n);
+            }
+            CoreOp::BitwiseNand(n) => self.sized(16, *n),
+            CoreOp::BitwiseAnd(n) => self.sized(17, *n),
+            CoreOp::BitwiseOr(n) => self.sized(18, *n),
+            CoreOp::BitwiseXor(n) => self.sized(19, *n),
+            CoreOp::BitwiseNot(n) => self.sized(20, *n),
+            CoreOp::LeftShift(n) => self.sized(21, *n),
+            CoreOp::LogicalRightShift(n) => self.sized(22, *n),
+            CoreOp::ArithmeticRightShift(n) => self.sized(23, *n),
+            CoreOp::And(n) => self.sized(24, *n),
+            CoreOp::Or(n) => self.sized(25, *n),
+            CoreOp::Not(n) => self.sized(26, *n),
+            CoreOp::Add(n) => self.sized(27, *n),
+            CoreOp::Sub(n) => self.sized(28, *n),
+            CoreOp::Mul(n) => self.sized(29, *n),
+            CoreOp::Div(n) => self.sized(30, *n),
+            CoreOp::Rem(n) => self.sized(31, *n),
+            CoreOp::Neg(n) => self.sized(32, *n),
+            CoreOp::Inc(n) => self.sized(33, *n),
+            CoreOp::Dec(n) => self.sized(34, *n),
+            CoreOp::Swap(n) => self.sized(35, *n),
+            CoreOp::IsNonNegative(n) => self.sized(36, *n),
+            CoreOp::Get(input) => {
+                self.byte(37);
+                self.input(input);
+            }
+            CoreOp::Put(output) => {
+                self.byte(38);
+                self.output(output);
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) {
+        match op {
+            StandardOp::CoreOp(op) => self.core_op(op),
+            StandardOp::Set(values) => {
+                self.byte(64);
+                self.unsigned(values.len());
+                for value in values {
+                    self.float(*value);
+                }
+            }
+            StandardOp::Alloc => self.byte(65),
+            StandardOp::Free => self.byte(66),
+            StandardOp::ToInt(n) => self.sized(67, *n),
+            StandardOp::ToFloat(n) => self.sized(68, *n),
+            StandardOp::Add(n) => self.sized(69, *n),
+            StandardOp::Sub(n) => self.sized(70, *n),
+            StandardOp::Mul(n) => self.sized(71, *n),
+            StandardOp::Div(n) => self.sized(72, *n),
+            StandardOp::Rem(n) => self.sized(73, *n),
+            StandardOp::Neg(n) => self.sized(74, *n),
+            StandardOp::IsNonNegative(n) => self.sized(75, *n),
+            StandardOp::Sin(n) => self.sized(76, *n),
+            StandardOp::Cos(n) => self.sized(77, *n),
+            StandardOp::Tan(n) => self.sized(78, *n),
+            StandardOp::ASin(n) => self.sized(79, *n),
+            StandardOp::ACos(n) => self.sized(80, *n),
+            StandardOp::ATan(n) => self.sized(81, *n),
+            StandardOp::Pow(n) => self.sized(82, *n),
+            StandardOp::Peek => self.byte(83),
+            StandardOp::Poke => self.byte(84),
+            StandardOp::Call(binding) => {
+                let index = match self.bindings.iter().position(|b| b == binding) {
+                    Some(index) => index,
+                    None => {
+                        self.bindings.push(binding.clone());
+                        self.bindings.len() - 1
+                    }
+                };
+                self.sized(85, index);
+            }
+        }
+    }
+
+    /// Write a value by its index in a table.
+    fn index<T: PartialEq>(&mut self, table: &[T], value: &T) {
+        let index = table.iter().position(|item| item == value);
+        self.byte(index.expect("value is missing from its table") as u8);
+    }
+
+    fn input(&mut self, input: &Input) {
+        match &input.mode {
+            InputMode::DPad(direction) => {
+                self.byte(128);
+                self.index(DIRECTIONS, direction);
+            }
+            InputMode::JoyStick(direction) => {
+                self.byte(129);
+                self.index(DIRECTIONS, direction);
+            }
+            InputMode::Accelerometer(axis) => {
+                self.byte(130);
+                self.index(AXES, axis);
+            }
+            InputMode::Gyroscope(axis) => {
+                self.byte(131);
+                self.index(AXES, axis);
+            }
+            InputMode::Magnetometer(axis) => {
+                self.byte(132);
+                self.index(AXES, axis);
+            }
+            InputMode::Speedometer(axis) => {
+                self.byte(133);
+                match axis {
+                    Some(axis) => {
+                        self.byte(1);
+                        self.index(AXES, axis);
+                    }
+                    None => self.byte(0),
+                }
+            }
+            InputMode::Position(axis) => {
+                self.byte(134);
+                self.index(AXES, axis);
+            }
+            InputMode::Custom(name) => {
+                self.byte(135);
+                self.string(name);
+            }
+            mode => self.index(INPUT_MODES, mode),
+        }
+        self.unsigned(input.channel.0);
+    }
+
+    fn output(&mut self, output: &Output) {
+        match &output.mode {
+            OutputMode::SetCursorChar(color) => {
+                self.byte(128);
+                self.color(color);
+            }
+            OutputMode::SetCursorPixel(color) => {
+                self.byte(129);
+                self.color(color);
+            }
+            OutputMode::Custom(name) => {
+                self.byte(130);
+                self.string(name);
+            }
+            mode => self.index(OUTPUT_MODES, mode),
+        }
+        self.unsigned(output.channel.0);
+    }
+
+    fn color(&mut self, color: &Color) {
+        match color {
+            Color::RGB(r, g, b) => self.bytes.extend([COLORS.len() as u8, *r, *g, *b]),
+            color => self.index(COLORS, color),
+        }
+    }
+}
+
+/// Reads the instructions of a program.
+struct Decoder<'a> {
+    /// The binary program.
+    bytes: &'a [u8],
+    /// The position of the next byte to read.
+    pos: usize,
+    /// The FFI bindings called by the program, in the order of their indices.
+    bindings: Vec<FFIBinding>,
+}
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        match self.bytes.get(self.pos) {
+            Some(byte) => {
+                self.pos += 1;
+                Ok(*byte)
+            }
+            None => Err("unexpected end of binary program".to_string()),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let start = self.pos;
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 || (shift == 63 && byte & 0x7f > 1) {
+                return Err(format!("number too large at byte {start}"));
+            }
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn unsigned(&mut self) -> Result<usize, String> {
+        let start = self.pos;
+        usize::try_from(self.varint()?).map_err(|_| format!("number too large at byte {start}"))
+    }
+
+    /// Read the number of items in a list. Every item takes at least one byte,
+    /// so there can't be more items than bytes left.
+    fn count(&mut self) -> Result<usize, String> {
+        let start = self.pos;
+        let count = self.unsigned()?;
+        if count > self.bytes.len() - self.pos {
+            return Err(format!("invalid length {count} at byte {start}"));
+        }
+        Ok(count)
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let n = self.varint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn float(&mut self) -> Result<f64, String> {
+        match self.bytes.get(self.pos..self.pos + 8) {
+            Some(bytes) => {
+                self.pos += 8;
+                Ok(f64::from_bits(u64::from_le_bytes(
+                    bytes.try_into().unwrap(),
+                )))
+            }
+            None => Err("unexpected end of binary program".to_string()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        let len = self.count()?;
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        String::from_utf8(bytes.to_vec()).map_err(|_| format!("invalid string at byte {start}"))
+    }
+
+    /// Read a value by its index in a table.
+    fn index<T: Clone>(&mut self, table: &[T]) -> Result<T, String> {
+        let start = self.pos;
+        let index = self.byte()?;
+        table
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| format!("invalid tag {index} at byte {start}"))
+    }
+
+    fn core_op(&mut self) -> Result<CoreOp, String> {
+        let start = self.pos;
+        let tag = self.byte()?;
+        self.core_op_with_tag(start, tag)
+    }
+
+    fn core_op_with_tag(&mut self, start: usize, tag: u8) -> Result<CoreOp, String> {
+        Ok(match tag {
+            0 => {
+                let len = self.count()?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(self.signed()?);
+                }
+                CoreOp::Set(values)
+            }
+            1 => CoreOp::Function,
+            2 => CoreOp::Call,
+            3 => CoreOp::Return,
+            4 => CoreOp::While,
+            5 => CoreOp::If,
+            6 => CoreOp::Else,
+            7 => CoreOp::End,
+            8 => CoreOp::Store(self.unsigned()?),
+            9 => CoreOp::Load(self.unsigned()?),
+            10 => CoreOp::Move(self.signed()? as isize),
+            11 => CoreOp::Where,
+            12 => CoreOp::Deref,
+            13 => CoreOp::Refer,
+            14 => CoreOp::Index(self.unsigned()?),
+            15 => CoreOp::Offset(self.signed()? as isize, self.unsigned()?),
+            16 => CoreOp::BitwiseNand(self.unsigned()?),
+            17 => CoreOp::BitwiseAnd(self.unsigned()?),
+            18 => CoreOp::BitwiseOr(self.unsigned()?),
+            19 => CoreOp::BitwiseXor(self.unsigned()?),
+            20 => CoreOp::BitwiseNot(self.unsigned()?),
+            21 => CoreOp::LeftShift(self.unsigned()?),
+            22 => CoreOp::LogicalRightShift(self.unsigned()?),
+            23 => CoreOp::ArithmeticRightShift(self.unsigned()?),
+            24 => CoreOp::And(self.unsigned()?),
+            25 => CoreOp::Or(self.unsigned()?),
+            26 => CoreOp::Not(self.unsigned()?),
+            27 => CoreOp::Add(self.unsigned()?),
+            28 => CoreOp::Sub(self.unsigned()?),
+            29 => CoreOp::Mul(self.unsigned()?),
+            30 => CoreOp::Div(self.unsigned()?),
+            31 => CoreOp::Rem(self.unsigned()?),
+            32 => CoreOp::Neg(self.unsigned()?),
+            33 => CoreOp::Inc(self.unsigned()?),
+            34 => CoreOp::Dec(self.unsigned()?),
+            35 => CoreOp::Swap(self.unsigned()?),
+            36 => CoreOp::IsNonNegative(self.unsigned()?),
+            37 => CoreOp::Get(self.input()?),
+            38 => CoreOp::Put(self.output()?),
+            tag => return Err(format!("invalid instruction tag {tag} at byte {start}")),
+        })
+    }
+
+    fn std_op(&mut self) -> Result<StandardOp, String> {
+        let start = self.pos;
+        Ok(match self.byte()? {
+            64 => {
+                let len = self.count()?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(self.float()?);
+                }
+                StandardOp::Set(values)
+            }
+            65 => StandardOp::Alloc,
+            66 => StandardOp::Free,
+            67 => StandardOp::ToInt(self.unsigned()?),
+            68 => StandardOp::ToFloat(self.unsigned()?),
+            69 => StandardOp::Add(self.unsigned()?),
+            70 => StandardOp::Sub(self.unsigned()?),
+            71 => StandardOp::Mul(self.unsigned()?),
+            72 => StandardOp::Div(self.unsigned()?),
+            73 => StandardOp::Rem(self.unsigned()?),
+            74 => StandardOp::Neg(self.unsigned()?),
+            75 => StandardOp::IsNonNegative(self.unsigned()?),
+            76 => StandardOp::Sin(self.unsigned()?),
+            77 => StandardOp::Cos(self.unsigned()?),
+            78 => StandardOp::Tan(self.unsigned()?),
+            79 => StandardOp::ASin(self.unsigned()?),
+            80 => StandardOp::ACos(self.unsigned()?),
+            81 => StandardOp::ATan(self.unsigned()?),
+            82 => StandardOp::Pow(self.unsigned()?),
+            83 => StandardOp::Peek,
+            84 => StandardOp::Poke,
+            85 => {
+                let index = self.unsigned()?;
+                match self.bindings.get(index) {
+                    Some(binding) => StandardOp::Call(binding.clone()),
+                    None => return Err(format!("invalid FFI binding {index} at byte {start}")),
+                }
+            }
+            tag => StandardOp::CoreOp(self.core_op_with_tag(start, tag)?),
+        })
+    }
+
+    fn input(&mut self) -> Result<Input, String> {
+        let start = self.pos;
+        let mode = match self.byte()? {
+            128 => InputMode::DPad(self.index(DIRECTIONS)?),
+            129 => InputMode::JoyStick(self.index(DIRECTIONS)?),
+            130 => InputMode::Accelerometer(self.index(AXES)?),
+            131 => InputMode::Gyroscope(self.index(AXES)?),
+            132 => InputMode::Magnetometer(self.index(AXES)?),
+            133 => match self.byte()? {
+                0 => InputMode::Speedometer(None),
+                _ => InputMode::Speedometer(Some(self.index(AXES)?)),
+            },
+            134 => InputMode::Position(self.index(AXES)?),
+            135 => InputMode::Custom(self.string()?),
+            _ => {
+                self.pos = start;
+                self.index(INPUT_MODES)?
+            }
+        };
+        Ok(Input {
+            mode,
+            channel: Channel(self.unsigned()?),
+        })
+    }
+
+    fn output(&mut self) -> Result<Output, String> {
+        let start = self.pos;
+        let mode = match self.byte()? {
+            128 => OutputMode::SetCursorChar(self.color()?),
+            129 => OutputMode::SetCursorPixel(self.color()?),
+            130 => OutputMode::Custom(self.string()?),
+            _ => {
+                self.pos = start;
+                self.index(OUTPUT_MODES)?
+            }
+        };
+        Ok(Output {
+            mode,
+            channel: Channel(self.unsigned()?),
+        })
+    }
+
+    fn color(&mut self) -> Result<Color, String> {
+        if self.bytes.get(self.pos) == Some(&(COLORS.len() as u8)) {
+            self.pos += 1;
+            Ok(Color::RGB(self.byte()?, self.byte()?, self.byte()?))
+        } else {
+            self.index(COLORS)
+        }
+    }
+}
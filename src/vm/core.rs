@@ -5,7 +5,8 @@
 //! for ***every*** target.
 use crate::side_effects::{Input, Output};
 
-use super::{Error, StandardOp, StandardProgram, VirtualMachineProgram};
+use super::{Error, SourceMap, StandardOp, StandardProgram, VirtualMachineProgram};
+use crate::lir::DebugInfo;
 use core::fmt;
 use std::{collections::HashMap, hash::Hash};
 
@@ -34,6 +35,23 @@ impl CoreProgram {
         Self(flatten(self.0).0)
     }
 
+    /// Build the source map of this program from its debug markers. The source
+    /// map refers to the instructions of the program without its comments.
+    pub fn source_map(&self, debug_info: &DebugInfo) -> SourceMap {
+        SourceMap::new(self.0.iter().map(Some), debug_info)
+    }
+
+    /// Remove the comments from this program, so that its instructions are
+    /// indexed the same way as in its source map.
+    pub fn without_comments(self) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .filter(|op| !matches!(op, CoreOp::Comment(_)))
+                .collect(),
+        )
+    }
+
     /// Get the code outside of any functions.
     pub fn get_main(&self) -> Vec<CoreOp> {
         flatten(self.0.clone()).2
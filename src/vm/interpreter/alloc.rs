@@ -0,0 +1,150 @@
+//! # Allocator Module
+//!
+//! This module implements the heap allocator behind the `Alloc` and `Free`
+//! instructions of the standard interpreter.
+//!
+//! The heap lives on the tape, after the cells used by the program's stack.
+//! The stack keeps growing after the heap is placed, so the heap starts at
+//! `HEAP_START`, or at least `STACK_GAP` cells past the end of the tape when
+//! the first block is allocated.
+//! Freed blocks are kept in a list of free ranges (merged with their neighbors),
+//! and new blocks are taken from the first free range large enough to hold them.
+//! The tape only grows when none of the free ranges are large enough.
+//!
+//! The allocator keeps its bookkeeping off the tape, so a program cannot corrupt it.
+//! This lets it detect frees of pointers which were never allocated, and blocks
+//! which are freed twice.
+
+use crate::{vm::RuntimeError, NULL};
+use ::std::collections::BTreeMap;
+
+/// The minimum number of cells on the tape before the heap starts.
+/// The cells before the heap are used for the program's stack.
+pub const HEAP_START: usize = 30000;
+
+/// The number of cells left free for the stack to grow into, between the end
+/// of the tape and the heap, when the first block is allocated.
+pub const STACK_GAP: usize = 10000;
+
+/// The heap allocator for the standard interpreter.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Allocator {
+    /// The blocks currently allocated, mapping their addresses to their sizes.
+    pub(super) allocated: BTreeMap<usize, usize>,
+    /// The free ranges of the heap, mapping their addresses to their sizes.
+    /// No two free ranges are adjacent; they are merged when blocks are freed.
+    pub(super) free: BTreeMap<usize, usize>,
+    /// The address past the end of the heap.
+    pub(super) end: usize,
+}
+
+impl Allocator {
+    /// Allocate a block of `size` cells on the tape, and return its address.
+    /// The cells of the block are zeroed.
+    pub fn alloc(&mut self, size: i64, tape: &mut Vec<i64>) -> Result<usize, RuntimeError> {
+        if size < 0 {
+            return Err(RuntimeError::NegativeAlloc(size));
+        }
+        // Every block gets a unique address, even if it is empty.
+        let size = (size as usize).max(1);
+
+        let (addr, end) = self.place(size, tape.len());
+        // Make sure the tape can grow before the heap is changed.
+        if tape.len() < end {
+            tape.try_reserve(end - tape.len())
+                .map_err(|_| RuntimeError::TapeOverflow(end))?;
+        }
+        // Take the block from the free range it was placed in (if there is one).
+        if let Some(free_size) = self.free.remove(&addr) {
+            if free_size > size {
+                self.free.insert(addr + size, free_size - size);
+            }
+        }
+        self.end = end;
+
+        if tape.len() < self.end {
+            tape.resize(self.end, 0);
+        }
+        tape[addr..addr + size].fill(0);
+        self.allocated.insert(addr, size);
+        Ok(addr)
+    }
+
+    /// The number of cells the tape needs to allocate a block of `size` cells.
+    pub fn required_len(&self, size: i64, tape_len: usize) -> usize {
+        let (_, end) = self.place(size.max(1) as usize, tape_len);
+        end.max(tape_len)
+    }
+
+    /// Find the address for a block of `size` cells, without allocating it.
+    /// Returns the address and the end of the heap after the block is allocated.
+    fn place(&self, size: usize, tape_len: usize) -> (usize, usize) {
+        let end = if self.end == 0 {
+            (tape_len + STACK_GAP).max(HEAP_START)
+        } else {
+            self.end
+        };
+
+        // Find the first free range which is large enough to hold the block.
+        if let Some((&addr, _)) = self.free.iter().find(|(_, &free_size)| free_size >= size) {
+            return (addr, end);
+        }
+        // Otherwise, grow the heap to make room for the block, starting with
+        // the free range at the end of the heap (if there is one).
+        let addr = match self.free.iter().next_back() {
+            Some((&last, &last_size)) if last + last_size == end => last,
+            _ => end,
+        };
+        (addr, addr + size)
+    }
+
+    /// Free the block at the given address. Freeing the null pointer does nothing.
+    pub fn free(&mut self, addr: i64) -> Result<(), RuntimeError> {
+        if addr == NULL {
+            return Ok(());
+        }
+        let Some(size) = usize::try_from(addr)
+            .ok()
+            .and_then(|addr| self.allocated.remove(&addr))
+        else {
+            return Err(if self.is_free(addr) {
+                RuntimeError::DoubleFree(addr)
+            } else {
+                RuntimeError::InvalidFree(addr)
+            });
+        };
+        let mut addr = addr as usize;
+        let mut size = size;
+
+        // Merge the block with the free ranges before and after it.
+        if let Some((&prev, &prev_size)) = self.free.range(..addr).next_back() {
+            if prev + prev_size == addr {
+                self.free.remove(&prev);
+                addr = prev;
+                size += prev_size;
+            }
+        }
+        if let Some(next_size) = self.free.remove(&(addr + size)) {
+            size += next_size;
+        }
+
+        self.free.insert(addr, size);
+        Ok(())
+    }
+
+    /// Is the given address inside of a free range of the heap?
+    fn is_free(&self, addr: i64) -> bool {
+        let Ok(addr) = usize::try_from(addr) else {
+            return false;
+        };
+        self.free
+            .range(..=addr)
+            .next_back()
+            .is_some_and(|(&start, &size)| addr < start + size)
+    }
+
+    /// The number of cells currently allocated.
+    pub fn allocated_cells(&self) -> usize {
+        self.allocated.values().sum()
+    }
+}
@@ -0,0 +1,370 @@
+//! # Checkpoint Module
+//!
+//! This module implements checkpoints of the standard interpreter. A checkpoint
+//! holds everything the interpreter needs to carry on running a program from
+//! where it left off: the tape, the register, the pointer, the heap, the
+//! defined functions, the call and dereference stacks, the instruction pointer,
+//! and the values waiting in the device's FFI channel. The program itself is
+//! not saved, only a hash of it, so a checkpoint can only be restored with the
+//! same program. Restoring a checkpoint checks the hash, and that the saved
+//! state is consistent with the program.
+//!
+//! ## Format
+//!
+//! A serialized checkpoint is a text file starting with a header of the format
+//! version and the hash of the program, followed by one line for each part of
+//! the interpreter's state:
+//!
+//! ```text
+//! sage-checkpoint 2
+//! program <hash of the program>
+//! ip <instruction pointer>
+//! done <0 or 1>
+//! pointer <pointer>
+//! register <cells>...
+//! functions <addresses>...
+//! calls <addresses>...
+//! refs <pointers>...
+//! ffi <values>...
+//! heap <end of the heap>
+//! alloc <address> <size>
+//! free <address> <size>
+//! tape <length>
+//! cells <first cell> <cells>...
+//! ```
+//!
+//! Fields are separated by tabs. Most of the tape is usually zero, so only
+//! the runs of nonzero cells are saved, each on its own `cells` line.
+//!
+//! ## Periodic Checkpoints
+//!
+//! A `Checkpointer` attached to the standard interpreter saves a checkpoint
+//! every so many instructions, or every so many seconds, so a long running program
+//! which is killed can be resumed from its last checkpoint. The output printed
+//! since that checkpoint is printed again when it's resumed.
+use super::limits::TIME_CHECK_INTERVAL;
+use crate::vm::{Allocator, StandardProgram, HEAP_START};
+use ::std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::{rename, File},
+    io::{Result as IoResult, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const HEADER: &str = "sage-checkpoint 2";
+
+/// The state of a standard interpreter, saved so it can be restored later.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Checkpoint {
+    /// The hash of the program the checkpoint was saved from.
+    pub program: u64,
+    /// The index of the next instruction to execute.
+    pub instruction: usize,
+    /// Has the interpreter finished running the program?
+    pub done: bool,
+    /// The pointer on the turing tape.
+    pub pointer: usize,
+    /// The contents of the register.
+    pub register: Vec<i64>,
+    /// The cells of the turing tape.
+    pub tape: Vec<i64>,
+    /// The allocator which manages the heap on the tape.
+    pub heap: Allocator,
+    /// The addresses of the functions defined so far.
+    pub functions: Vec<usize>,
+    /// The stack of instruction pointers to return to from each called function.
+    pub call_stack: Vec<usize>,
+    /// The stack of pointers saved by dereferences.
+    pub deref_stack: Vec<usize>,
+    /// The values waiting in the device's FFI channel.
+    pub ffi_channel: Vec<i64>,
+}
+
+impl Checkpoint {
+    /// Hash a program, to check that a checkpoint is restored with the program it was saved from.
+    /// This is the FNV-1a hash of the program's text, so it's the same on every machine.
+    pub fn hash_program(code: &StandardProgram) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        for op in &code.0 {
+            for byte in op.to_string().bytes().chain([b'\n']) {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    /// Check that the checkpoint was saved from the given program,
+    /// and that its state is consistent with the program.
+    pub fn validate(&self, code: &StandardProgram) -> Result<(), String> {
+        if self.program != Self::hash_program(code) {
+            return Err("checkpoint was saved from a different program".to_string());
+        }
+        if self.register.is_empty() {
+            return Err("empty register in checkpoint".to_string());
+        }
+        let len = code.0.len();
+        if self.instruction > len {
+            return Err(format!(
+                "instruction pointer {} past the end of the program in checkpoint",
+                self.instruction
+            ));
+        }
+        if let Some(addr) = self
+            .call_stack
+            .iter()
+            .chain(&self.functions)
+            .find(|addr| **addr >= len)
+        {
+            return Err(format!(
+                "address {addr} past the end of the program in checkpoint"
+            ));
+        }
+        if let Some(pointer) = [self.pointer]
+            .iter()
+            .chain(&self.deref_stack)
+            .find(|pointer| **pointer >= self.tape.len())
+        {
+            return Err(format!(
+                "pointer {pointer} past the end of the tape in checkpoint"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write the checkpoint to a file. It's written to a temporary file which then
+    /// replaces the file, so the file never holds half of a checkpoint, even if the
+    /// program is killed while it's being saved.
+    pub fn save(&self, path: impl AsRef<Path>) -> IoResult<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(self.to_string().as_bytes())?;
+        file.sync_all()?;
+        rename(&temporary, path)
+    }
+
+    /// Parse a serialized checkpoint.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(format!("expected checkpoint header `{HEADER}`")),
+        }
+
+        let mut result = Self::default();
+        for (i, line) in lines {
+            let error = |message: &str| format!("line {}: {message} in checkpoint", i + 1);
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let number = |field: &str| {
+                field
+                    .parse::<usize>()
+                    .map_err(|_| error(&format!("invalid number `{field}`")))
+            };
+            let numbers = |fields: &[&str]| {
+                fields
+                    .iter()
+                    .map(|field| number(field))
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            let values = |fields: &[&str]| {
+                fields
+                    .iter()
+                    .map(|field| {
+                        field
+                            .parse::<i64>()
+                            .map_err(|_| error(&format!("invalid value `{field}`")))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+
+            match fields.as_slice() {
+                [] | [""] => {}
+                ["program", hash] => {
+                    result.program = u64::from_str_radix(hash, 16)
+                        .map_err(|_| error(&format!("invalid hash `{hash}`")))?
+                }
+                ["ip", ip] => result.instruction = number(ip)?,
+                ["done", done] => result.done = number(done)? != 0,
+                ["pointer", pointer] => result.pointer = number(pointer)?,
+                ["register", cells @ ..] => result.register = values(cells)?,
+                ["functions", addrs @ ..] => result.functions = numbers(addrs)?,
+                ["calls", addrs @ ..] => result.call_stack = numbers(addrs)?,
+                ["refs", pointers @ ..] => result.deref_stack = numbers(pointers)?,
+                ["ffi", channel @ ..] => result.ffi_channel = values(channel)?,
+                ["heap", end] => result.heap.end = number(end)?,
+                ["alloc", addr, size] => {
+                    result.heap.allocated.insert(number(addr)?, number(size)?);
+                }
+                ["free", addr, size] => {
+                    result.heap.free.insert(number(addr)?, number(size)?);
+                }
+                ["tape", len] => result.tape = vec![0; number(len)?],
+                ["cells", start, cells @ ..] => {
+                    let start = number(start)?;
+                    let cells = values(cells)?;
+                    let end = start.saturating_add(cells.len());
+                    match result.tape.get_mut(start..end) {
+                        Some(tape) => tape.copy_from_slice(&cells),
+                        None => return Err(error("cells past the end of the tape")),
+                    }
+                }
+                _ => return Err(error("invalid entry")),
+            }
+        }
+
+        if result.heap.end > result.tape.len() {
+            return Err("heap past the end of the tape in checkpoint".to_string());
+        }
+        // The allocated blocks and free ranges must tile part of the heap
+        // without overlapping, or the allocator would hand out the same cells
+        // twice, or cells past the end of the tape.
+        let mut ranges = result
+            .heap
+            .allocated
+            .iter()
+            .chain(&result.heap.free)
+            .map(|(&addr, &size)| (addr, size))
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+        let mut heap_end = HEAP_START;
+        for (addr, size) in ranges {
+            if size == 0 || addr < HEAP_START {
+                return Err(format!("invalid heap range at {addr} in checkpoint"));
+            }
+            if addr < heap_end {
+                return Err(format!("overlapping heap ranges at {addr} in checkpoint"));
+            }
+            heap_end = addr.saturating_add(size);
+            if heap_end > result.heap.end {
+                return Err(format!(
+                    "heap range at {addr} past the end of the heap in checkpoint"
+                ));
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// How often a checkpointer saves the state of the interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckpointInterval {
+    /// Save a checkpoint after every given number of instructions.
+    Instructions(u64),
+    /// Save a checkpoint when the given time has passed since the last one.
+    Time(Duration),
+}
+
+/// Saves checkpoints of the standard interpreter to a file while it runs a program.
+pub struct Checkpointer {
+    /// The file to save the checkpoints to.
+    path: PathBuf,
+    /// How often to save a checkpoint.
+    interval: CheckpointInterval,
+    /// The number of instructions executed so far.
+    steps: u64,
+    /// The time the last checkpoint was saved, or the checkpointer was created.
+    last: Instant,
+    /// The hash of the program, computed when the first checkpoint is saved.
+    program: Option<u64>,
+    /// The first error saving a checkpoint.
+    error: Option<::std::io::Error>,
+}
+
+impl Checkpointer {
+    /// Create a checkpointer which saves checkpoints to the given file.
+    pub fn new(path: impl Into<PathBuf>, interval: CheckpointInterval) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            steps: 0,
+            last: Instant::now(),
+            program: None,
+            error: None,
+        }
+    }
+
+    /// Report the first error saving a checkpoint, if there was one.
+    pub fn finish(mut self) -> IoResult<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Count an instruction after it's executed, and check if it's time to save a checkpoint.
+    #[inline]
+    pub(super) fn tick(&mut self) -> bool {
+        self.steps += 1;
+        match self.interval {
+            CheckpointInterval::Instructions(n) => self.steps.is_multiple_of(n.max(1)),
+            CheckpointInterval::Time(time) => {
+                self.steps.is_multiple_of(TIME_CHECK_INTERVAL) && self.last.elapsed() >= time
+            }
+        }
+    }
+
+    /// Get the hash of the program, hashing it the first time.
+    pub(super) fn program(&mut self, code: &StandardProgram) -> u64 {
+        *self
+            .program
+            .get_or_insert_with(|| Checkpoint::hash_program(code))
+    }
+
+    /// Save a checkpoint. After an error, no more checkpoints are saved.
+    pub(super) fn save(&mut self, checkpoint: &Checkpoint) {
+        if self.error.is_none() {
+            if let Err(err) = checkpoint.save(&self.path) {
+                self.error = Some(err);
+            }
+        }
+        self.last = Instant::now();
+    }
+}
+
+/// Serialize the checkpoint.
+impl Display for Checkpoint {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        fn list<T: Display>(values: &[T]) -> String {
+            values.iter().map(|value| format!("\t{value}")).collect()
+        }
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "program\t{:016x}", self.program)?;
+        writeln!(f, "ip\t{}", self.instruction)?;
+        writeln!(f, "done\t{}", self.done as u8)?;
+        writeln!(f, "pointer\t{}", self.pointer)?;
+        writeln!(f, "register{}", list(&self.register))?;
+        writeln!(f, "functions{}", list(&self.functions))?;
+        writeln!(f, "calls{}", list(&self.call_stack))?;
+        writeln!(f, "refs{}", list(&self.deref_stack))?;
+        writeln!(f, "ffi{}", list(&self.ffi_channel))?;
+        writeln!(f, "heap\t{}", self.heap.end)?;
+        for (addr, size) in &self.heap.allocated {
+            writeln!(f, "alloc\t{addr}\t{size}")?;
+        }
+        for (addr, size) in &self.heap.free {
+            writeln!(f, "free\t{addr}\t{size}")?;
+        }
+
+        writeln!(f, "tape\t{}", self.tape.len())?;
+        let mut start = 0;
+        while start < self.tape.len() {
+            if self.tape[start] == 0 {
+                start += 1;
+                continue;
+            }
+            let count = self.tape[start..]
+                .iter()
+                .take_while(|cell| **cell != 0)
+                .count();
+            writeln!(
+                f,
+                "cells\t{start}{}",
+                list(&self.tape[start..start + count])
+            )?;
+            start += count;
+        }
+        Ok(())
+    }
+}
@@ -2,7 +2,11 @@
 //!
 //! This module implements an interpreter for the Core virtual machine
 //! variant.
-use crate::vm::{CoreOp, CoreProgram, Device, StandardDevice};
+use crate::vm::{
+    CoreOp, CoreProgram, Debuggable, Device, Limits, RuntimeError, StandardDevice, Tracer,
+};
+
+use super::limits::Meter;
 
 impl Default for CoreInterpreter<StandardDevice> {
     fn default() -> Self {
@@ -37,6 +41,10 @@ where
     i: usize,
     /// Is the interpreter finished interpreting?s
     done: bool,
+    /// The resources used by the program, checked against the interpreter's limits.
+    meter: Meter,
+    /// The tracer recording the instructions executed, if the program is traced.
+    tracer: Option<Tracer>,
 }
 
 impl<T> CoreInterpreter<T>
@@ -54,6 +62,32 @@ where
             refs: vec![],
             i: 0,
             done: false,
+            meter: Meter::default(),
+            tracer: None,
+        }
+    }
+
+    /// Limit the resources the interpreter can use to run a program.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.meter = Meter::new(limits);
+        self
+    }
+
+    /// Record the instructions the interpreter executes with the tracer, if there is one.
+    pub fn with_tracer(mut self, tracer: Option<Tracer>) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    /// Detach the tracer from the interpreter, to finish the trace.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Record the cells written on the tape by the current instruction, if the program is traced.
+    fn trace_write(&mut self, addr: usize, n: usize) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.write(addr, &self.cells[addr..addr + n]);
         }
     }
 
@@ -83,19 +117,21 @@ where
     }
 
     /// Dereference the current pointer on the tape.
-    fn deref(&mut self) {
-        // Add the old pointer to the dereference stack.
-        self.refs.push(self.pointer);
-        let cell = *self.get_cell();
+    fn deref(&mut self) -> Result<(), RuntimeError> {
+        self.meter.check_refs(self.refs.len() + 1)?;
+        let cell = *self.get_cell()?;
         if cell < 0 {
-            panic!("Dereferencing negative cell with value {cell:?}");
+            return Err(RuntimeError::DerefOutOfBounds(cell));
         }
+        // Add the old pointer to the dereference stack.
+        self.refs.push(self.pointer);
         // Set the pointer to the address on the tape.
         self.pointer = cell as usize;
+        Ok(())
     }
 
     /// Undo a dereference.
-    fn refer(&mut self) -> Result<(), String> {
+    fn refer(&mut self) -> Result<(), RuntimeError> {
         // Get the previous value of the pointer before
         // the last dereference instruction.
         if let Some(old) = self.refs.pop() {
@@ -103,12 +139,13 @@ where
             Ok(())
         } else {
             // There was no previous dereference, throw an error
-            Err(String::from("cannot Refer due to empty Deref stack"))
+            Err(RuntimeError::EmptyDerefStack)
         }
     }
 
     /// Call the Nth function defined in the program, where N is the value of the register.
-    fn call(&mut self, code: &CoreProgram) -> Result<(), String> {
+    fn call(&mut self, code: &CoreProgram) -> Result<(), RuntimeError> {
+        self.meter.check_calls(self.calls.len() + 1)?;
         // If the function has been defined
         if self.functions.len() > self.reg_scalar() as usize {
             // Push the current instruction pointer to the call stack
@@ -130,7 +167,11 @@ where
                         count += 1;
                     }
                     Some(_) => {}
-                    None => return Err(format!("function {} not defined", self.reg_scalar())),
+                    None => {
+                        // Report the error at the call instruction.
+                        self.i = self.calls.pop().unwrap_or_default();
+                        return Err(RuntimeError::InvalidCall(self.reg_scalar()));
+                    }
                 }
                 // If `count` hasn't reached the function we want,
                 // keep going.
@@ -233,25 +274,38 @@ where
     }
 
     /// Get the current cell pointed to on the turing tape.
-    fn get_cell(&mut self) -> &mut i64 {
-        while self.pointer >= self.cells.len() {
-            self.cells.extend(vec![0; 1000]);
-        }
-
-        &mut self.cells[self.pointer]
+    fn get_cell(&mut self) -> Result<&mut i64, RuntimeError> {
+        self.meter.reserve(&mut self.cells, self.pointer + 1)?;
+        Ok(&mut self.cells[self.pointer])
     }
 
     /// Run a core program using this interpreter and its device.
-    pub fn run(mut self, code: &CoreProgram) -> Result<T, String> {
+    pub fn run(mut self, code: &CoreProgram) -> Result<T, RuntimeError> {
         while !self.done {
             self.step(code)?
         }
         Ok(self.device)
     }
 
-    /// Run a single step of the interpreter.
-    fn step(&mut self, code: &CoreProgram) -> Result<(), String> {
+    /// Run a single step of the interpreter. Errors are annotated with the
+    /// state of the interpreter when they happened.
+    pub fn step(&mut self, code: &CoreProgram) -> Result<(), RuntimeError> {
+        let (i, depth) = (self.i, self.calls.len());
+        // The register holds the index of the function, if this is a call.
+        let function = self.register.first().copied().unwrap_or_default();
+        self.execute(code).map_err(|err| err.with_state(self))?;
+        if let (Some(tracer), Some(op)) = (&mut self.tracer, code.0.get(i)) {
+            let register = self.register.first().copied().unwrap_or_default();
+            tracer.record(i, op, self.pointer, register);
+            tracer.follow_calls(function, depth, self.calls.len());
+        }
+        Ok(())
+    }
+
+    /// Execute the current instruction of the program.
+    fn execute(&mut self, code: &CoreProgram) -> Result<(), RuntimeError> {
         if let Some(op) = self.fetch(code) {
+            self.meter.tick()?;
             match op {
                 CoreOp::Comment(_) => {}
                 CoreOp::Set(n) => *self.reg_mut_vector() = n.clone(),
@@ -284,9 +338,7 @@ where
                 }
 
                 CoreOp::Load(n) => {
-                    while self.pointer + n >= self.cells.len() {
-                        self.cells.extend(vec![0; 1000]);
-                    }
+                    self.meter.reserve(&mut self.cells, self.pointer + n)?;
 
                     self.reg_mut_vector().clear();
                     // let cells = self.get_cells();
@@ -297,23 +349,19 @@ where
                 }
 
                 CoreOp::Store(n) => {
-                    while self.pointer + n >= self.cells.len() {
-                        self.cells.extend(vec![0; 1000]);
-                    }
+                    self.meter.reserve(&mut self.cells, self.pointer + n)?;
                     for i in 0..*n {
                         let val = self.reg_vector()[i];
                         self.cells[self.pointer + i] = val;
                     }
+                    self.trace_write(self.pointer, *n);
                 }
                 CoreOp::Move(n) => {
                     if *n >= 0 {
                         self.pointer += *n as usize
                     } else {
                         if self.pointer < -*n as usize {
-                            return Err(format!(
-                                "Instruction #{} tried to move the pointer to a negative index.",
-                                self.i
-                            ));
+                            return Err(RuntimeError::NegativePointer(self.i));
                         }
                         self.pointer -= -*n as usize
                     }
@@ -325,7 +373,7 @@ where
                         self.reg_mut_vector()[i] += *offset as i64;
                     }
                 }
-                CoreOp::Deref => self.deref(),
+                CoreOp::Deref => self.deref()?,
                 CoreOp::Refer => self.refer()?,
 
                 CoreOp::Index(n) => {
@@ -361,7 +409,8 @@ where
                 }
                 CoreOp::LeftShift(n) => {
                     for i in 0..*n {
-                        self.reg_mut_vector()[i] <<= self.cells[self.pointer + i];
+                        self.reg_mut_vector()[i] =
+                            self.reg_vector()[i].wrapping_shl(self.cells[self.pointer + i] as u32);
                     }
                 }
 
@@ -375,47 +424,50 @@ where
 
                 CoreOp::ArithmeticRightShift(n) => {
                     for i in 0..*n {
-                        self.reg_mut_vector()[i] >>= self.cells[self.pointer + i];
+                        self.reg_mut_vector()[i] =
+                            self.reg_vector()[i].wrapping_shr(self.cells[self.pointer + i] as u32);
                     }
                 }
 
                 CoreOp::Add(n) => {
                     for i in 0..*n {
                         let val = self.cells[self.pointer + i];
-                        self.reg_mut_vector()[i] += val;
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_add(val);
                     }
                 }
                 CoreOp::Sub(n) => {
                     for i in 0..*n {
                         let val = self.cells[self.pointer + i];
-                        self.reg_mut_vector()[i] -= val;
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_sub(val);
                     }
                 }
                 CoreOp::Mul(n) => {
                     for i in 0..*n {
                         let val = self.cells[self.pointer + i];
-                        self.reg_mut_vector()[i] *= val;
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_mul(val);
                     }
                 }
                 CoreOp::Div(n) => {
                     for i in 0..*n {
                         let val = self.cells[self.pointer + i];
-                        if val != 0 {
-                            self.reg_mut_vector()[i] /= val;
+                        if val == 0 {
+                            return Err(RuntimeError::DivideByZero);
                         }
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_div(val);
                     }
                 }
                 CoreOp::Rem(n) => {
                     for i in 0..*n {
                         let val = self.cells[self.pointer + i];
-                        if val != 0 {
-                            self.reg_mut_vector()[i] %= val;
+                        if val == 0 {
+                            return Err(RuntimeError::DivideByZero);
                         }
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_rem(val);
                     }
                 }
                 CoreOp::Neg(n) => {
                     for i in 0..*n {
-                        self.reg_mut_vector()[i] *= -1;
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_neg();
                     }
                 }
                 CoreOp::And(n) => {
@@ -440,12 +492,12 @@ where
 
                 CoreOp::Inc(n) => {
                     for i in 0..*n {
-                        self.reg_mut_vector()[i] += 1;
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_add(1);
                     }
                 }
                 CoreOp::Dec(n) => {
                     for i in 0..*n {
-                        self.reg_mut_vector()[i] -= 1;
+                        self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_sub(1);
                     }
                 }
 
@@ -455,6 +507,7 @@ where
                         self.reg_mut_vector()[i] = self.cells[self.pointer + i];
                         self.cells[self.pointer + i] = temp;
                     }
+                    self.trace_write(self.pointer, *n);
                 }
 
                 CoreOp::IsNonNegative(n) => {
@@ -473,3 +526,57 @@ where
         Ok(())
     }
 }
+
+impl<T> Debuggable for CoreInterpreter<T>
+where
+    T: Device,
+{
+    type Program = CoreProgram;
+
+    fn step(&mut self, code: &CoreProgram) -> Result<(), RuntimeError> {
+        CoreInterpreter::step(self, code)
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn program_len(code: &CoreProgram) -> usize {
+        code.0.len()
+    }
+
+    fn instruction(code: &CoreProgram, i: usize) -> Option<String> {
+        code.0.get(i).map(|op| op.to_string())
+    }
+
+    fn comment(code: &CoreProgram, i: usize) -> Option<&str> {
+        match code.0.get(i) {
+            Some(CoreOp::Comment(comment)) => Some(comment),
+            _ => None,
+        }
+    }
+
+    fn instruction_pointer(&self) -> usize {
+        self.i
+    }
+
+    fn register(&self) -> &[i64] {
+        &self.register
+    }
+
+    fn tape(&self) -> &[i64] {
+        &self.cells
+    }
+
+    fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    fn deref_stack(&self) -> &[usize] {
+        &self.refs
+    }
+
+    fn call_stack(&self) -> &[usize] {
+        &self.calls
+    }
+}
@@ -0,0 +1,148 @@
+//! # Runtime Error Module
+//!
+//! This module implements the errors which stop a program running in one of
+//! the interpreters. Every error is one of a fixed set of kinds, so the caller
+//! can tell them apart without matching on their messages. Errors returned by
+//! the interpreters are annotated with the state of the interpreter when they
+//! happened, including the index of the instruction that caused them.
+//!
+//! Devices return the same errors for failed input, output, and foreign function calls.
+
+use crate::{
+    side_effects::FFIBinding,
+    vm::{Debuggable, LimitExceeded},
+};
+use ::std::fmt;
+
+/// The state of an interpreter when an error stopped the program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateSnapshot {
+    /// The index of the instruction which caused the error.
+    pub instruction: usize,
+    /// The pointer on the turing tape.
+    pub pointer: usize,
+    /// The contents of the register.
+    pub register: Vec<i64>,
+    /// The cells of the turing tape.
+    pub tape: Vec<i64>,
+    /// The stack of instruction pointers to return to from each called function.
+    pub call_stack: Vec<usize>,
+    /// The stack of pointers saved by dereferences.
+    pub deref_stack: Vec<usize>,
+}
+
+impl StateSnapshot {
+    /// Take a snapshot of the current state of an interpreter.
+    pub fn of(interpreter: &impl Debuggable) -> Self {
+        Self {
+            instruction: interpreter.instruction_pointer(),
+            pointer: interpreter.pointer(),
+            register: interpreter.register().to_vec(),
+            tape: interpreter.tape().to_vec(),
+            call_stack: interpreter.call_stack().to_vec(),
+            deref_stack: interpreter.deref_stack().to_vec(),
+        }
+    }
+}
+
+/// An error which stopped a program running in the virtual machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// An error annotated with the state of the interpreter when it happened.
+    WithState(Box<Self>, Box<StateSnapshot>),
+
+    /// The program exceeded one of the interpreter's limits.
+    LimitExceeded(LimitExceeded),
+    /// An integer was divided by zero (or the remainder of dividing by zero was taken).
+    DivideByZero,
+    /// The program called a function which isn't defined.
+    InvalidCall(i64),
+    /// The instruction at the given index moved the pointer below the start of the tape.
+    NegativePointer(usize),
+    /// The tape couldn't grow to the given number of cells.
+    TapeOverflow(usize),
+    /// The program dereferenced a cell whose value isn't an address on the tape.
+    DerefOutOfBounds(i64),
+    /// The program referred back without a dereference to undo.
+    EmptyDerefStack,
+    /// The program tried to allocate a negative number of cells.
+    NegativeAlloc(i64),
+    /// The program freed the same address twice.
+    DoubleFree(i64),
+    /// The program freed an address which was never allocated.
+    InvalidFree(i64),
+    /// The program called a foreign function which the device doesn't have.
+    FFIUnbound(FFIBinding),
+    /// The program peeked at the FFI channel when it was empty.
+    EmptyFFIChannel,
+    /// The device couldn't get input or put output.
+    DeviceError(String),
+    /// The program couldn't be prepared to run, because its blocks don't match up.
+    InvalidProgram(String),
+}
+
+impl RuntimeError {
+    /// Annotate the error with the state of the interpreter which returned it.
+    /// An error which is already annotated is left as is.
+    pub fn with_state(self, interpreter: &impl Debuggable) -> Self {
+        match self {
+            Self::WithState(..) => self,
+            _ => Self::WithState(Box::new(self), Box::new(StateSnapshot::of(interpreter))),
+        }
+    }
+
+    /// The error without its annotation.
+    pub fn kind(&self) -> &Self {
+        match self {
+            Self::WithState(err, _) => err.kind(),
+            _ => self,
+        }
+    }
+
+    /// The state of the interpreter when the error happened, if it's known.
+    pub fn state(&self) -> Option<&StateSnapshot> {
+        match self {
+            Self::WithState(_, state) => Some(state),
+            _ => None,
+        }
+    }
+
+    /// The index of the instruction which caused the error, if it's known.
+    pub fn instruction(&self) -> Option<usize> {
+        self.state().map(|state| state.instruction)
+    }
+}
+
+impl From<LimitExceeded> for RuntimeError {
+    fn from(limit: LimitExceeded) -> Self {
+        Self::LimitExceeded(limit)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::WithState(err, _) => write!(f, "{err}"),
+            Self::LimitExceeded(limit) => write!(f, "{limit}"),
+            Self::DivideByZero => write!(f, "division by zero"),
+            Self::InvalidCall(n) => write!(f, "function {n} not defined"),
+            Self::NegativePointer(i) => write!(
+                f,
+                "Instruction #{i} tried to move the pointer to a negative index."
+            ),
+            Self::TapeOverflow(n) => write!(f, "cannot grow the tape to {n} cells"),
+            Self::DerefOutOfBounds(cell) => {
+                write!(f, "Dereferencing negative cell with value {cell:?}")
+            }
+            Self::EmptyDerefStack => write!(f, "cannot Refer due to empty Deref stack"),
+            Self::NegativeAlloc(size) => {
+                write!(f, "cannot allocate a negative number of cells: {size}")
+            }
+            Self::DoubleFree(addr) => write!(f, "double free of address {addr}"),
+            Self::InvalidFree(addr) => write!(f, "invalid free of address {addr}"),
+            Self::FFIUnbound(ffi) => write!(f, "ffi call not found: {ffi:?}"),
+            Self::EmptyFFIChannel => write!(f, "ffi channel is empty"),
+            Self::DeviceError(message) | Self::InvalidProgram(message) => write!(f, "{message}"),
+        }
+    }
+}
@@ -0,0 +1,630 @@
+//! # Fast Interpreter Module
+//!
+//! This module implements a faster interpreter for the Core virtual machine
+//! variant.
+//!
+//! The `CoreInterpreter` runs the instructions of a program as they are, so it
+//! finds the matching instruction of every `If`, `Else`, `While`, and `End` by
+//! scanning the program whenever it jumps. This interpreter first compiles the
+//! program into `Bytecode`: a flat list of instructions where every jump is
+//! resolved to the index of the instruction it continues at. Comments and
+//! instructions which only close blocks are removed, and the most common pairs
+//! of instructions generated by the assembler are fused into single instructions,
+//! so fewer instructions are dispatched.
+//!
+//! Both interpreters use the same `Device` interface, and produce the same output.
+use crate::{
+    side_effects::{Input, Output},
+    vm::{CoreOp, CoreProgram, Debuggable, Device, Limits, RuntimeError, StandardDevice},
+};
+
+use super::limits::Meter;
+
+/// A bytecode instruction for the fast interpreter.
+#[derive(Clone, Debug, PartialEq)]
+enum Op {
+    Set(Vec<i64>),
+    Call,
+    Return,
+    /// Continue at the given instruction.
+    Jump(usize),
+    /// Continue at the given instruction if the register is zero.
+    JumpIfZero(usize),
+    /// Continue at the given instruction if the register is not zero.
+    JumpIfNonZero(usize),
+
+    Load(usize),
+    Store(usize),
+    Move(isize),
+    Where,
+    Offset(isize, usize),
+    Deref,
+    Refer,
+
+    Index(usize),
+    BitwiseNand(usize),
+    BitwiseAnd(usize),
+    BitwiseOr(usize),
+    BitwiseXor(usize),
+    BitwiseNot(usize),
+    LeftShift(usize),
+    LogicalRightShift(usize),
+    ArithmeticRightShift(usize),
+    Add(usize),
+    Sub(usize),
+    Mul(usize),
+    Div(usize),
+    Rem(usize),
+    Neg(usize),
+    And(usize),
+    Or(usize),
+    Not(usize),
+    Inc(usize),
+    Dec(usize),
+    Swap(usize),
+    IsNonNegative(usize),
+    Get(Input),
+    Put(Output),
+
+    /// `Move` followed by `Load`.
+    MoveLoad(isize, usize),
+    /// `Move` followed by `Store`.
+    MoveStore(isize, usize),
+    /// `Move` followed by `Deref`.
+    MoveDeref(isize),
+    /// `Deref` followed by `Move`.
+    DerefMove(isize),
+    /// `Refer` followed by `Move`.
+    ReferMove(isize),
+    /// `Load` followed by `Offset`.
+    LoadOffset(usize, isize, usize),
+    /// `Offset` followed by `Store`.
+    OffsetStore(isize, usize, usize),
+    /// `Store` followed by `Refer`.
+    StoreRefer(usize),
+}
+
+impl Op {
+    /// Fuse this instruction with the instruction after it, if they can be
+    /// replaced with a single instruction.
+    fn fuse(&self, next: &Self) -> Option<Self> {
+        Some(match (self, next) {
+            // Moving in the same direction twice can't move the pointer below zero
+            // unless the combined move does too.
+            (Self::Move(a), Self::Move(b)) if a.signum() * b.signum() >= 0 => {
+                Self::Move(a.checked_add(*b)?)
+            }
+            (Self::Move(offset), Self::Load(n)) => Self::MoveLoad(*offset, *n),
+            (Self::Move(offset), Self::Store(n)) => Self::MoveStore(*offset, *n),
+            (Self::Move(offset), Self::Deref) => Self::MoveDeref(*offset),
+            (Self::Deref, Self::Move(offset)) => Self::DerefMove(*offset),
+            (Self::Refer, Self::Move(offset)) => Self::ReferMove(*offset),
+            (Self::Load(n), Self::Offset(offset, m)) => Self::LoadOffset(*n, *offset, *m),
+            (Self::Offset(offset, m), Self::Store(n)) => Self::OffsetStore(*offset, *m, *n),
+            (Self::Store(n), Self::Refer) => Self::StoreRefer(*n),
+            _ => return None,
+        })
+    }
+}
+
+/// A core program compiled into bytecode for the fast interpreter.
+#[derive(Clone)]
+pub struct Bytecode {
+    /// The program the bytecode was compiled from.
+    program: CoreProgram,
+    /// The bytecode instructions.
+    ops: Vec<Op>,
+    /// The index of the first instruction in the program that each
+    /// bytecode instruction was compiled from.
+    origins: Vec<usize>,
+    /// The index of the first bytecode instruction of each function's body.
+    functions: Vec<usize>,
+}
+
+impl Bytecode {
+    /// Compile a core program into bytecode. This fails if the program's
+    /// `If`, `Else`, `While`, `Function`, and `End` instructions don't match up.
+    pub fn new(program: &CoreProgram) -> Result<Self, String> {
+        let code = &program.0;
+        // The decoded instructions, with the jumps pointing to instructions in the program.
+        let mut decoded: Vec<Option<Op>> = Vec::with_capacity(code.len());
+        let mut functions = vec![];
+        // The blocks which haven't been closed yet: the index of the instruction which
+        // opened them, and the index of their `Else` instruction (if they have one).
+        let mut blocks: Vec<(usize, Option<usize>)> = vec![];
+
+        for (i, op) in code.iter().enumerate() {
+            let op = match op {
+                CoreOp::Comment(_) => None,
+                CoreOp::Function => {
+                    functions.push(i + 1);
+                    blocks.push((i, None));
+                    None
+                }
+                CoreOp::If | CoreOp::While => {
+                    blocks.push((i, None));
+                    None
+                }
+                CoreOp::Else => match blocks.last_mut() {
+                    Some((start, else_ @ None)) if code[*start] == CoreOp::If => {
+                        *else_ = Some(i);
+                        None
+                    }
+                    _ => return Err(format!("instruction #{i} is an unmatched else")),
+                },
+                CoreOp::End => {
+                    let Some((start, else_)) = blocks.pop() else {
+                        return Err(format!("instruction #{i} is an unmatched end"));
+                    };
+                    match (&code[start], else_) {
+                        (CoreOp::Function, _) => {
+                            decoded[start] = Some(Op::Jump(i + 1));
+                            None
+                        }
+                        (CoreOp::While, _) => {
+                            decoded[start] = Some(Op::JumpIfZero(i + 1));
+                            Some(Op::JumpIfNonZero(start + 1))
+                        }
+                        (_, Some(else_)) => {
+                            decoded[start] = Some(Op::JumpIfZero(else_ + 1));
+                            decoded[else_] = Some(Op::Jump(i + 1));
+                            None
+                        }
+                        (_, None) => {
+                            decoded[start] = Some(Op::JumpIfZero(i + 1));
+                            None
+                        }
+                    }
+                }
+                CoreOp::Set(values) => Some(Op::Set(values.clone())),
+                CoreOp::Call => Some(Op::Call),
+                CoreOp::Return => Some(Op::Return),
+                CoreOp::Load(n) => Some(Op::Load(*n)),
+                CoreOp::Store(n) => Some(Op::Store(*n)),
+                CoreOp::Move(offset) => Some(Op::Move(*offset)),
+                CoreOp::Where => Some(Op::Where),
+                CoreOp::Offset(offset, n) => Some(Op::Offset(*offset, *n)),
+                CoreOp::Deref => Some(Op::Deref),
+                CoreOp::Refer => Some(Op::Refer),
+                CoreOp::Index(n) => Some(Op::Index(*n)),
+                CoreOp::BitwiseNand(n) => Some(Op::BitwiseNand(*n)),
+                CoreOp::BitwiseAnd(n) => Some(Op::BitwiseAnd(*n)),
+                CoreOp::BitwiseOr(n) => Some(Op::BitwiseOr(*n)),
+                CoreOp::BitwiseXor(n) => Some(Op::BitwiseXor(*n)),
+                CoreOp::BitwiseNot(n) => Some(Op::BitwiseNot(*n)),
+                CoreOp::LeftShift(n) => Some(Op::LeftShift(*n)),
+                CoreOp::LogicalRightShift(n) => Some(Op::LogicalRightShift(*n)),
+                CoreOp::ArithmeticRightShift(n) => Some(Op::ArithmeticRightShift(*n)),
+                CoreOp::Add(n) => Some(Op::Add(*n)),
+                CoreOp::Sub(n) => Some(Op::Sub(*n)),
+                CoreOp::Mul(n) => Some(Op::Mul(*n)),
+                CoreOp::Div(n) => Some(Op::Div(*n)),
+                CoreOp::Rem(n) => Some(Op::Rem(*n)),
+                CoreOp::Neg(n) => Some(Op::Neg(*n)),
+                CoreOp::And(n) => Some(Op::And(*n)),
+                CoreOp::Or(n) => Some(Op::Or(*n)),
+                CoreOp::Not(n) => Some(Op::Not(*n)),
+                CoreOp::Inc(n) => Some(Op::Inc(*n)),
+                CoreOp::Dec(n) => Some(Op::Dec(*n)),
+                CoreOp::Swap(n) => Some(Op::Swap(*n)),
+                CoreOp::IsNonNegative(n) => Some(Op::IsNonNegative(*n)),
+                CoreOp::Get(input) => Some(Op::Get(input.clone())),
+                CoreOp::Put(output) => Some(Op::Put(output.clone())),
+            };
+            decoded.push(op);
+        }
+        if let Some((start, _)) = blocks.pop() {
+            return Err(format!("instruction #{start} is never ended"));
+        }
+
+        // Find the instructions which are jumped to. These can't be fused
+        // with the instructions before them.
+        let mut targets = vec![false; code.len() + 1];
+        for op in decoded.iter().flatten() {
+            if let Op::Jump(target) | Op::JumpIfZero(target) | Op::JumpIfNonZero(target) = op {
+                targets[*target] = true;
+            }
+        }
+        for start in &functions {
+            targets[*start] = true;
+        }
+
+        // Remove the instructions which do nothing, and fuse the others.
+        let mut ops: Vec<Op> = Vec::with_capacity(code.len());
+        let mut origins = Vec::with_capacity(code.len());
+        // The index of the bytecode instruction for each instruction in the program.
+        let mut indices = Vec::with_capacity(code.len() + 1);
+        let mut can_fuse = false;
+        for (i, op) in decoded.into_iter().enumerate() {
+            indices.push(ops.len());
+            can_fuse &= !targets[i];
+            let Some(op) = op else {
+                continue;
+            };
+            match ops
+                .last()
+                .filter(|_| can_fuse)
+                .and_then(|last| last.fuse(&op))
+            {
+                Some(fused) => *ops.last_mut().unwrap() = fused,
+                None => {
+                    ops.push(op);
+                    origins.push(i);
+                }
+            }
+            can_fuse = true;
+        }
+        indices.push(ops.len());
+
+        for op in &mut ops {
+            if let Op::Jump(target) | Op::JumpIfZero(target) | Op::JumpIfNonZero(target) = op {
+                *target = indices[*target];
+            }
+        }
+        Ok(Self {
+            program: program.clone(),
+            ops,
+            origins,
+            functions: functions.into_iter().map(|start| indices[start]).collect(),
+        })
+    }
+
+    /// The number of bytecode instructions.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Is the bytecode empty?
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// The index in the program of the bytecode instruction at the given index.
+    fn origin(&self, i: usize) -> usize {
+        self.origins.get(i).copied().unwrap_or(self.program.0.len())
+    }
+}
+
+impl Default for FastInterpreter<StandardDevice> {
+    fn default() -> Self {
+        Self::new(StandardDevice::default())
+    }
+}
+
+/// The interpreter which runs core virtual machine programs compiled to bytecode.
+pub struct FastInterpreter<T>
+where
+    T: Device,
+{
+    /// The interpreter's I/O device.
+    device: T,
+    /// The current pointer on the turing tape.
+    pointer: usize,
+    /// The register (which contains a single cell of data).
+    register: Vec<i64>,
+    /// The turing tape (composed of integer cells)
+    cells: Vec<i64>,
+    /// The call stack of the program's `Call` instructions which haven't returned yet.
+    calls: Vec<usize>,
+    /// The bytecode instructions to return to from each call on the call stack.
+    returns: Vec<usize>,
+    /// The stack of dereferences made by the program (to be undone
+    /// by a reference instruction).
+    refs: Vec<usize>,
+    /// The index of the next bytecode instruction.
+    i: usize,
+    /// The index in the program of the next instruction.
+    origin: usize,
+    /// Is the interpreter finished interpreting?
+    done: bool,
+    /// The resources used by the program, checked against the interpreter's limits.
+    meter: Meter,
+}
+
+impl<T> FastInterpreter<T>
+where
+    T: Device,
+{
+    pub fn new(device: T) -> Self {
+        Self {
+            device,
+            pointer: 0,
+            register: vec![0; 1024],
+            cells: vec![],
+            calls: vec![],
+            returns: vec![],
+            refs: vec![],
+            i: 0,
+            origin: 0,
+            done: false,
+            meter: Meter::default(),
+        }
+    }
+
+    /// Limit the resources the interpreter can use to run a program.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.meter = Meter::new(limits);
+        self
+    }
+
+    /// Compile a core program to bytecode, and run it using this interpreter and its device.
+    pub fn run(self, code: &CoreProgram) -> Result<T, RuntimeError> {
+        self.run_bytecode(&Bytecode::new(code).map_err(RuntimeError::InvalidProgram)?)
+    }
+
+    /// Run a program compiled to bytecode using this interpreter and its device.
+    pub fn run_bytecode(mut self, code: &Bytecode) -> Result<T, RuntimeError> {
+        while !self.done {
+            Debuggable::step(&mut self, code)?
+        }
+        Ok(self.device)
+    }
+
+    /// Make sure the tape has the `n` cells starting at the pointer.
+    fn reserve(&mut self, n: usize) -> Result<(), RuntimeError> {
+        self.meter.reserve(&mut self.cells, self.pointer + n)
+    }
+
+    /// Move the pointer on the tape.
+    fn move_pointer(&mut self, offset: isize, code: &Bytecode) -> Result<(), RuntimeError> {
+        self.pointer = self
+            .pointer
+            .checked_add_signed(offset)
+            .ok_or_else(|| RuntimeError::NegativePointer(code.origin(self.i)))?;
+        Ok(())
+    }
+
+    /// Dereference the current pointer on the tape.
+    fn deref(&mut self) -> Result<(), RuntimeError> {
+        self.reserve(1)?;
+        let cell = self.cells[self.pointer];
+        if cell < 0 {
+            return Err(RuntimeError::DerefOutOfBounds(cell));
+        }
+        // Add the old pointer to the dereference stack.
+        self.meter.check_refs(self.refs.len() + 1)?;
+        self.refs.push(self.pointer);
+        // Set the pointer to the address on the tape.
+        self.pointer = cell as usize;
+        Ok(())
+    }
+
+    /// Undo a dereference.
+    fn refer(&mut self) -> Result<(), RuntimeError> {
+        // Get the previous value of the pointer before
+        // the last dereference instruction.
+        self.pointer = self.refs.pop().ok_or(RuntimeError::EmptyDerefStack)?;
+        Ok(())
+    }
+
+    fn load(&mut self, n: usize) -> Result<(), RuntimeError> {
+        self.reserve(n)?;
+        self.register.clear();
+        self.register
+            .extend_from_slice(&self.cells[self.pointer..self.pointer + n]);
+        Ok(())
+    }
+
+    fn store(&mut self, n: usize) -> Result<(), RuntimeError> {
+        self.reserve(n)?;
+        self.cells[self.pointer..self.pointer + n].copy_from_slice(&self.register[..n]);
+        Ok(())
+    }
+
+    fn offset(&mut self, offset: isize, n: usize) {
+        for val in &mut self.register[..n] {
+            *val = val.wrapping_add(offset as i64);
+        }
+    }
+
+    /// Combine the first `n` cells of the register with the cells on the tape.
+    fn binary(&mut self, n: usize, f: impl Fn(i64, i64) -> i64) -> Result<(), RuntimeError> {
+        self.reserve(n)?;
+        let cells = &self.cells[self.pointer..self.pointer + n];
+        for (val, cell) in self.register[..n].iter_mut().zip(cells) {
+            *val = f(*val, *cell);
+        }
+        Ok(())
+    }
+
+    /// Check that none of the `n` cells on the tape are zero before dividing by them.
+    fn check_divisors(&mut self, n: usize) -> Result<(), RuntimeError> {
+        self.reserve(n)?;
+        if self.cells[self.pointer..self.pointer + n].contains(&0) {
+            return Err(RuntimeError::DivideByZero);
+        }
+        Ok(())
+    }
+
+    /// Apply a function to the first `n` cells of the register.
+    fn unary(&mut self, n: usize, f: impl Fn(i64) -> i64) {
+        for val in &mut self.register[..n] {
+            *val = f(*val);
+        }
+    }
+
+    /// Run the next bytecode instruction.
+    fn execute(&mut self, code: &Bytecode) -> Result<(), RuntimeError> {
+        let Some(op) = code.ops.get(self.i) else {
+            self.done = true;
+            return Ok(());
+        };
+        self.meter.tick()?;
+        let mut next = self.i + 1;
+        match op {
+            Op::Set(values) => {
+                self.register.clear();
+                self.register.extend_from_slice(values);
+            }
+            Op::Call => {
+                self.meter.check_calls(self.calls.len() + 1)?;
+                let function = self.register[0];
+                let Some(&start) = usize::try_from(function)
+                    .ok()
+                    .and_then(|function| code.functions.get(function))
+                else {
+                    return Err(RuntimeError::InvalidCall(function));
+                };
+                self.calls.push(code.origin(self.i));
+                self.returns.push(next);
+                next = start;
+            }
+            Op::Return => match self.returns.pop() {
+                Some(ret) => {
+                    self.calls.pop();
+                    next = ret;
+                }
+                None => self.done = true,
+            },
+            Op::Jump(target) => next = *target,
+            Op::JumpIfZero(target) => {
+                if self.register[0] == 0 {
+                    next = *target
+                }
+            }
+            Op::JumpIfNonZero(target) => {
+                if self.register[0] != 0 {
+                    next = *target
+                }
+            }
+
+            Op::Load(n) => self.load(*n)?,
+            Op::Store(n) => self.store(*n)?,
+            Op::Move(offset) => self.move_pointer(*offset, code)?,
+            Op::Where => self.register[0] = self.pointer as i64,
+            Op::Offset(offset, n) => self.offset(*offset, *n),
+            Op::Deref => self.deref()?,
+            Op::Refer => self.refer()?,
+
+            Op::Index(n) | Op::Add(n) => self.binary(*n, i64::wrapping_add)?,
+            Op::BitwiseNand(n) => self.binary(*n, |a, b| !(a & b))?,
+            Op::BitwiseAnd(n) => self.binary(*n, |a, b| a & b)?,
+            Op::BitwiseOr(n) => self.binary(*n, |a, b| a | b)?,
+            Op::BitwiseXor(n) => self.binary(*n, |a, b| a ^ b)?,
+            Op::BitwiseNot(n) => self.unary(*n, |a| !a),
+            Op::LeftShift(n) => self.binary(*n, |a, b| a.wrapping_shl(b as u32))?,
+            Op::LogicalRightShift(n) => {
+                self.binary(*n, |a, b| (a as u64).wrapping_shr(b as u32) as i64)?
+            }
+            Op::ArithmeticRightShift(n) => self.binary(*n, |a, b| a.wrapping_shr(b as u32))?,
+            Op::Sub(n) => self.binary(*n, i64::wrapping_sub)?,
+            Op::Mul(n) => self.binary(*n, i64::wrapping_mul)?,
+            Op::Div(n) => {
+                self.check_divisors(*n)?;
+                self.binary(*n, i64::wrapping_div)?
+            }
+            Op::Rem(n) => {
+                self.check_divisors(*n)?;
+                self.binary(*n, i64::wrapping_rem)?
+            }
+            Op::Neg(n) => self.unary(*n, i64::wrapping_neg),
+            Op::And(n) => self.binary(*n, |a, b| i64::from(a != 0 && b != 0))?,
+            Op::Or(n) => self.binary(*n, |a, b| i64::from(a != 0 || b != 0))?,
+            Op::Not(n) => self.unary(*n, |a| i64::from(a == 0)),
+            Op::Inc(n) => self.unary(*n, |a| a.wrapping_add(1)),
+            Op::Dec(n) => self.unary(*n, |a| a.wrapping_sub(1)),
+            Op::Swap(n) => {
+                self.reserve(*n)?;
+                let cells = &mut self.cells[self.pointer..self.pointer + n];
+                self.register[..*n].swap_with_slice(cells);
+            }
+            Op::IsNonNegative(n) => self.unary(*n, |a| i64::from(a >= 0)),
+            Op::Get(input) => self.register[0] = self.device.get(input.clone())?,
+            Op::Put(output) => self.device.put(self.register[0], output.clone())?,
+
+            Op::MoveLoad(offset, n) => {
+                self.move_pointer(*offset, code)?;
+                self.load(*n)?
+            }
+            Op::MoveStore(offset, n) => {
+                self.move_pointer(*offset, code)?;
+                self.store(*n)?
+            }
+            Op::MoveDeref(offset) => {
+                self.move_pointer(*offset, code)?;
+                self.deref()?
+            }
+            Op::DerefMove(offset) => {
+                self.deref()?;
+                self.move_pointer(*offset, code)?
+            }
+            Op::ReferMove(offset) => {
+                self.refer()?;
+                self.move_pointer(*offset, code)?
+            }
+            Op::LoadOffset(n, offset, m) => {
+                self.load(*n)?;
+                self.offset(*offset, *m)
+            }
+            Op::OffsetStore(offset, m, n) => {
+                self.offset(*offset, *m);
+                self.store(*n)?
+            }
+            Op::StoreRefer(n) => {
+                self.store(*n)?;
+                self.refer()?
+            }
+        }
+        self.i = next;
+        Ok(())
+    }
+}
+
+/// The fast interpreter can be stepped through one bytecode instruction at a time.
+/// Its instruction pointer and call stack refer to the instructions of the program
+/// the bytecode was compiled from, so they can be used with the program's source map.
+impl<T> Debuggable for FastInterpreter<T>
+where
+    T: Device,
+{
+    type Program = Bytecode;
+
+    fn step(&mut self, code: &Bytecode) -> Result<(), RuntimeError> {
+        let result = self.execute(code);
+        self.origin = code.origin(self.i);
+        result.map_err(|err| err.with_state(self))
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn program_len(code: &Bytecode) -> usize {
+        code.program.0.len()
+    }
+
+    fn instruction(code: &Bytecode, i: usize) -> Option<String> {
+        code.program.0.get(i).map(|op| op.to_string())
+    }
+
+    fn comment(code: &Bytecode, i: usize) -> Option<&str> {
+        match code.program.0.get(i) {
+            Some(CoreOp::Comment(comment)) => Some(comment),
+            _ => None,
+        }
+    }
+
+    fn instruction_pointer(&self) -> usize {
+        self.origin
+    }
+
+    fn register(&self) -> &[i64] {
+        &self.register
+    }
+
+    fn tape(&self) -> &[i64] {
+        &self.cells
+    }
+
+    fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    fn deref_stack(&self) -> &[usize] {
+        &self.refs
+    }
+
+    fn call_stack(&self) -> &[usize] {
+        &self.calls
+    }
+}
@@ -0,0 +1,154 @@
+//! # Limits Module
+//!
+//! This module implements the resource limits of the interpreters, so that
+//! untrusted programs can be run safely. An interpreter with limits stops the
+//! program with an error when it executes too many instructions, uses too many
+//! cells on the tape, nests too many calls or dereferences, or runs for too long.
+//!
+//! No limits are set by default.
+
+use crate::vm::RuntimeError;
+use ::std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The number of instructions between checks of the time limit.
+/// Reading the clock after every instruction would slow down the interpreters.
+pub(super) const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// The tape grows in chunks of this many cells.
+const TAPE_CHUNK_SIZE: usize = 1000;
+
+/// The limits on the resources an interpreter can use to run a program.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of instructions to execute.
+    pub instructions: Option<u64>,
+    /// The maximum number of cells on the tape.
+    pub tape_size: Option<usize>,
+    /// The maximum number of functions which can be called without returning.
+    pub call_depth: Option<usize>,
+    /// The maximum number of dereferences which can be made without referring back.
+    pub deref_depth: Option<usize>,
+    /// The maximum time to run the program for. The time is only checked
+    /// between instructions, so a program waiting for input isn't stopped.
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    /// No limits at all.
+    pub const NONE: Self = Self {
+        instructions: None,
+        tape_size: None,
+        call_depth: None,
+        deref_depth: None,
+        time: None,
+    };
+}
+
+/// The error for a program which exceeded one of the interpreter's limits.
+/// Each variant holds the limit which was exceeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The program executed too many instructions.
+    Instructions(u64),
+    /// The program used too many cells on the tape.
+    TapeSize(usize),
+    /// The program called too many functions without returning.
+    CallDepth(usize),
+    /// The program made too many dereferences without referring back.
+    DerefDepth(usize),
+    /// The program ran for too long.
+    Time(Duration),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Instructions(n) => write!(f, "exceeded the limit of {n} instructions"),
+            Self::TapeSize(n) => write!(f, "exceeded the limit of {n} cells on the tape"),
+            Self::CallDepth(n) => write!(f, "exceeded the limit of {n} nested calls"),
+            Self::DerefDepth(n) => write!(f, "exceeded the limit of {n} nested dereferences"),
+            Self::Time(time) => write!(f, "exceeded the time limit of {time:?}"),
+        }
+    }
+}
+
+/// Keeps track of the resources used by an interpreter, and checks them against its limits.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Meter {
+    /// The limits of the interpreter.
+    limits: Limits,
+    /// The number of instructions executed so far.
+    instructions: u64,
+    /// The time the first instruction was executed.
+    started: Option<Instant>,
+}
+
+impl Meter {
+    pub(super) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Count an instruction before it's executed, and check the instruction and time limits.
+    #[inline]
+    pub(super) fn tick(&mut self) -> Result<(), RuntimeError> {
+        self.instructions += 1;
+        if let Some(max) = self.limits.instructions {
+            if self.instructions > max {
+                return Err(LimitExceeded::Instructions(max).into());
+            }
+        }
+        if let Some(max) = self.limits.time {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            if self.instructions.is_multiple_of(TIME_CHECK_INTERVAL) && started.elapsed() > max {
+                return Err(LimitExceeded::Time(max).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the tape can hold `len` cells.
+    pub(super) fn check_tape(&self, len: usize) -> Result<(), RuntimeError> {
+        match self.limits.tape_size {
+            Some(max) if len > max => Err(LimitExceeded::TapeSize(max).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Grow the tape to hold at least `len` cells, if it's allowed to.
+    pub(super) fn reserve(&self, cells: &mut Vec<i64>, len: usize) -> Result<(), RuntimeError> {
+        if cells.len() < len {
+            self.check_tape(len)?;
+            let chunked = len
+                .div_ceil(TAPE_CHUNK_SIZE)
+                .saturating_mul(TAPE_CHUNK_SIZE)
+                .min(self.limits.tape_size.unwrap_or(usize::MAX));
+            cells
+                .try_reserve(chunked - cells.len())
+                .map_err(|_| RuntimeError::TapeOverflow(len))?;
+            cells.resize(chunked, 0);
+        }
+        Ok(())
+    }
+
+    /// Check that the call stack can hold `depth` calls.
+    pub(super) fn check_calls(&self, depth: usize) -> Result<(), RuntimeError> {
+        match self.limits.call_depth {
+            Some(max) if depth > max => Err(LimitExceeded::CallDepth(max).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the dereference stack can hold `depth` dereferences.
+    pub(super) fn check_refs(&self, depth: usize) -> Result<(), RuntimeError> {
+        match self.limits.deref_depth {
+            Some(max) if depth > max => Err(LimitExceeded::DerefDepth(max).into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,19 +1,36 @@
 //! # The Interpreter Module
 //!
 //! This module implements two interpreters for the virtual machine: one for each variant.
+//! The core variant also has a faster interpreter, which compiles programs to bytecode first.
 //! Both virtual machines are supplied with a `Device` object, which acts as a generic frontend
 //! of the machine to interact with the world. The `Device` object is responsible for
 //! supplying the input and handling the output of the program. For testing the compiler,
 //! assembler, and virtual machine, we use a `TestingDevice` object to supply sample input
 //! and capture the output to test against the predicted output.
+//!
+//! When a program fails, the interpreters and devices return a `RuntimeError`, which
+//! says what kind of error it was, and where the interpreter was when it happened.
+//! The core and standard interpreters can also record a trace of the instructions they execute.
 use crate::side_effects::{FFIBinding, Input, InputMode, Output, OutputMode};
 
 use log::{error, trace, warn};
 
+mod alloc;
+pub use self::alloc::*;
+mod checkpoint;
+pub use self::checkpoint::*;
 mod core;
 pub use self::core::*;
+mod error;
+pub use self::error::*;
+mod fast;
+pub use self::fast::*;
+mod limits;
+pub use self::limits::*;
 mod std;
 pub use self::std::*;
+mod trace;
+pub use self::trace::*;
 
 use ::std::{
     collections::{HashMap, VecDeque},
@@ -28,21 +45,61 @@ use ::std::{
 /// `get_char`, `put_char`, `get_int`, `put_int`, `get_float`, and `put_float` methods.
 pub trait Device {
     /// Get the next input (from a given input source).
-    fn get(&mut self, src: Input) -> Result<i64, String>;
+    fn get(&mut self, src: Input) -> Result<i64, RuntimeError>;
     /// Put the given value to the given output destination.
-    fn put(&mut self, val: i64, dst: Output) -> Result<(), String>;
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), RuntimeError>;
 
     /// Peek at the next value in the FFI buffer for the FFI function calls.
     /// Store the peeked value in the register.
-    fn peek(&mut self) -> Result<i64, String>;
+    fn peek(&mut self) -> Result<i64, RuntimeError>;
     /// Poke a value into the FFI buffer for the FFI function calls.
-    fn poke(&mut self, val: i64) -> Result<(), String>;
+    fn poke(&mut self, val: i64) -> Result<(), RuntimeError>;
+    /// The values waiting in the FFI buffer.
+    fn ffi_channel(&self) -> &VecDeque<i64>;
+    /// The FFI buffer, to restore its values from a checkpoint.
+    fn ffi_channel_mut(&mut self) -> &mut VecDeque<i64>;
 
     /// FFI call to the device. This will get the FFI binding for the device
     /// and call the function associated with the binding. If the tape is
     /// provided, the foreign function may mutate the tape. Otherwise all
     /// interaction with the FFI is done through the FFI channel.
-    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String>;
+    fn ffi_call(
+        &mut self,
+        ffi: &FFIBinding,
+        tape: Option<&mut Vec<i64>>,
+    ) -> Result<(), RuntimeError>;
+}
+
+/// An interpreter which can be stepped through one instruction at a time,
+/// and whose state can be inspected between instructions. This is used by the
+/// debugger to drive both variants of the interpreter.
+pub trait Debuggable {
+    /// The type of program the interpreter runs.
+    type Program;
+
+    /// Execute the current instruction of the program.
+    fn step(&mut self, code: &Self::Program) -> Result<(), RuntimeError>;
+    /// Has the interpreter finished running the program?
+    fn is_done(&self) -> bool;
+    /// The number of instructions in the program.
+    fn program_len(code: &Self::Program) -> usize;
+    /// The instruction at the given index in the program, formatted as text.
+    fn instruction(code: &Self::Program, i: usize) -> Option<String>;
+    /// If the instruction at the given index is a comment, get its text.
+    fn comment(code: &Self::Program, i: usize) -> Option<&str>;
+
+    /// The index of the next instruction to execute.
+    fn instruction_pointer(&self) -> usize;
+    /// The contents of the register.
+    fn register(&self) -> &[i64];
+    /// The cells of the turing tape.
+    fn tape(&self) -> &[i64];
+    /// The current pointer on the turing tape.
+    fn pointer(&self) -> usize;
+    /// The stack of pointers saved by dereferences, to be restored by `Refer`.
+    fn deref_stack(&self) -> &[usize];
+    /// The stack of instruction pointers to return to from each called function.
+    fn call_stack(&self) -> &[usize];
 }
 
 /// A device used for testing the compiler. This simply keeps a buffer
@@ -83,30 +140,30 @@ impl TestingDevice {
         }
     }
 
-    fn put_char(&mut self, ch: char) -> Result<(), String> {
+    fn put_char(&mut self, ch: char) -> Result<(), RuntimeError> {
         self.output.push((ch as u64 as i64, Output::stdout_char()));
         Ok(())
     }
 
-    fn put_int(&mut self, val: i64) -> Result<(), String> {
+    fn put_int(&mut self, val: i64) -> Result<(), RuntimeError> {
         for ch in val.to_string().chars() {
             self.put_char(ch)?
         }
         Ok(())
     }
 
-    fn put_float(&mut self, val: f64) -> Result<(), String> {
+    fn put_float(&mut self, val: f64) -> Result<(), RuntimeError> {
         for ch in format!("{val:?}").chars() {
             self.put_char(ch)?
         }
         Ok(())
     }
 
-    fn get_char(&mut self) -> Result<char, String> {
+    fn get_char(&mut self) -> Result<char, RuntimeError> {
         self.get(Input::stdin_char()).map(|n| n as u8 as char)
     }
 
-    fn get_int(&mut self) -> Result<i64, String> {
+    fn get_int(&mut self) -> Result<i64, RuntimeError> {
         let mut result: i64 = 0;
         loop {
             if self.input.is_empty() {
@@ -141,7 +198,7 @@ impl TestingDevice {
         Ok(result)
     }
 
-    fn get_float(&mut self) -> Result<f64, String> {
+    fn get_float(&mut self) -> Result<f64, RuntimeError> {
         let whole_part = self.get_int()? as f64;
 
         if self.input.is_empty() {
@@ -190,14 +247,14 @@ impl TestingDevice {
 
 /// Make the testing device work with the interpreter.
 impl Device for TestingDevice {
-    fn get(&mut self, src: Input) -> Result<i64, String> {
+    fn get(&mut self, src: Input) -> Result<i64, RuntimeError> {
         match src.mode {
             InputMode::StdinChar => {
                 if let Some(n) = self.input.pop_front() {
                     Ok(n)
                 } else {
                     error!("Tried to get character from empty input buffer");
-                    Err("input is empty".to_string())
+                    Err(RuntimeError::DeviceError("input is empty".to_string()))
                 }
             }
             InputMode::StdinInt => self.get_int(),
@@ -209,7 +266,7 @@ impl Device for TestingDevice {
         }
     }
 
-    fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), RuntimeError> {
         match dst.mode {
             OutputMode::StdoutChar => {
                 self.output.push((val, dst));
@@ -224,28 +281,40 @@ impl Device for TestingDevice {
         }
     }
 
-    fn peek(&mut self) -> Result<i64, String> {
+    fn peek(&mut self) -> Result<i64, RuntimeError> {
         if let Some(n) = self.ffi_channel.pop_front() {
             Ok(n)
         } else {
             error!("Tried to peek from empty ffi channel");
-            Err("ffi channel is empty".to_string())
+            Err(RuntimeError::EmptyFFIChannel)
         }
     }
 
-    fn poke(&mut self, val: i64) -> Result<(), String> {
+    fn poke(&mut self, val: i64) -> Result<(), RuntimeError> {
         self.ffi_channel.push_back(val);
         Ok(())
     }
 
-    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String> {
+    fn ffi_channel(&self) -> &VecDeque<i64> {
+        &self.ffi_channel
+    }
+
+    fn ffi_channel_mut(&mut self) -> &mut VecDeque<i64> {
+        &mut self.ffi_channel
+    }
+
+    fn ffi_call(
+        &mut self,
+        ffi: &FFIBinding,
+        tape: Option<&mut Vec<i64>>,
+    ) -> Result<(), RuntimeError> {
         if let Some(f) = self.ffi.get(ffi) {
             trace!("Calling FFI: {}", ffi);
             f(&mut self.ffi_channel, tape);
             Ok(())
         } else {
             error!("FFI call not found: {:?}", ffi);
-            Err(format!("ffi call not found: {:?}", ffi))
+            Err(RuntimeError::FFIUnbound(ffi.clone()))
         }
     }
 }
@@ -294,24 +363,30 @@ impl StandardDevice {
         self.ffi.insert(ffi, f);
     }
 
-    fn get_char(&mut self) -> Result<char, String> {
+    fn get_char(&mut self) -> Result<char, RuntimeError> {
         let mut buf = [0];
         if stdout().flush().is_err() {
             error!("Could not flush output, do you have a terminal?");
-            return Err("Could not flush output".to_string());
+            return Err(RuntimeError::DeviceError(
+                "Could not flush output".to_string(),
+            ));
         }
         if stdin().read(&mut buf).is_err() {
             error!("Could not flush output, do you have a terminal?");
-            return Err("Could not get user input".to_string());
+            return Err(RuntimeError::DeviceError(
+                "Could not get user input".to_string(),
+            ));
         }
         Ok(buf[0] as char)
     }
 
-    fn get_int(&mut self) -> Result<i64, String> {
+    fn get_int(&mut self) -> Result<i64, RuntimeError> {
         let mut buf = [0];
         if stdout().flush().is_err() {
             error!("Could not flush output, do you have a terminal?");
-            return Err("Could not flush output".to_string());
+            return Err(RuntimeError::DeviceError(
+                "Could not flush output".to_string(),
+            ));
         }
 
         while stdin().read(&mut buf).is_ok() && (buf[0] as char).is_whitespace() {}
@@ -337,15 +412,19 @@ impl StandardDevice {
         Ok(result)
     }
 
-    fn get_float(&mut self) -> Result<f64, String> {
+    fn get_float(&mut self) -> Result<f64, RuntimeError> {
         let mut buf = String::new();
         if stdout().flush().is_err() {
             error!("Could not flush output, do you have a terminal?");
-            return Err("Could not flush output".to_string());
+            return Err(RuntimeError::DeviceError(
+                "Could not flush output".to_string(),
+            ));
         }
         if stdin().read_line(&mut buf).is_err() {
             error!("Could not flush output, do you have a terminal?");
-            return Err("Could not get user input".to_string());
+            return Err(RuntimeError::DeviceError(
+                "Could not get user input".to_string(),
+            ));
         }
         Ok(buf.trim().parse::<f64>().unwrap_or_else(|s| {
             warn!("Could not parse float: {s:?}, defaulting to 0.0");
@@ -355,7 +434,7 @@ impl StandardDevice {
 }
 
 impl Device for StandardDevice {
-    fn get(&mut self, src: Input) -> Result<i64, String> {
+    fn get(&mut self, src: Input) -> Result<i64, RuntimeError> {
         Ok(match src.mode {
             InputMode::StdinChar => self.get_char()? as i64,
             InputMode::StdinInt => self.get_int()?,
@@ -371,7 +450,7 @@ impl Device for StandardDevice {
         })
     }
 
-    fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), RuntimeError> {
         // Print the character without a newline
         match dst.mode {
             OutputMode::StdoutChar => print!("{}", val as u8 as char),
@@ -388,34 +467,48 @@ impl Device for StandardDevice {
             }
         }
         if stdout().flush().is_err() {
-            Err(String::from("could not flush output"))
+            Err(RuntimeError::DeviceError(String::from(
+                "could not flush output",
+            )))
         } else {
             Ok(())
         }
     }
 
-    fn peek(&mut self) -> Result<i64, String> {
+    fn peek(&mut self) -> Result<i64, RuntimeError> {
         if let Some(n) = self.ffi_channel.pop_front() {
             Ok(n)
         } else {
             error!("Tried to peek from empty ffi channel");
-            Err("ffi channel is empty".to_string())
+            Err(RuntimeError::EmptyFFIChannel)
         }
     }
 
-    fn poke(&mut self, val: i64) -> Result<(), String> {
+    fn poke(&mut self, val: i64) -> Result<(), RuntimeError> {
         self.ffi_channel.push_back(val);
         Ok(())
     }
 
-    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String> {
+    fn ffi_channel(&self) -> &VecDeque<i64> {
+        &self.ffi_channel
+    }
+
+    fn ffi_channel_mut(&mut self) -> &mut VecDeque<i64> {
+        &mut self.ffi_channel
+    }
+
+    fn ffi_call(
+        &mut self,
+        ffi: &FFIBinding,
+        tape: Option<&mut Vec<i64>>,
+    ) -> Result<(), RuntimeError> {
         if let Some(f) = self.ffi.get(ffi) {
             trace!("Calling FFI: {}", ffi);
             f(&mut self.ffi_channel, tape);
             Ok(())
         } else {
             error!("FFI call not found: {:?}", ffi);
-            Err(format!("ffi call not found: {:?}", ffi))
+            Err(RuntimeError::FFIUnbound(ffi.clone()))
         }
     }
 }
@@ -3,7 +3,12 @@
 //! This module implements an interpreter for the Standard virtual machine
 //! variant.
 
-use crate::vm::{CoreOp, Device, StandardDevice, StandardOp, StandardProgram};
+use crate::vm::{
+    Allocator, Checkpoint, Checkpointer, CoreOp, Debuggable, Device, Limits, RuntimeError,
+    StandardDevice, StandardOp, StandardProgram, Tracer,
+};
+
+use super::limits::Meter;
 
 /// A function to reinterpret the bits of an integer as a float.
 pub fn as_float(n: i64) -> f64 {
@@ -34,6 +39,8 @@ where
     register: Vec<i64>,
     /// The turing tape (composed of integer cells)
     cells: Vec<i64>,
+    /// The allocator which manages the heap on the tape.
+    heap: Allocator,
     /// The addresses of defined functions. `functions[N]` is the
     /// instruction pointer for the Nth function's code.
     functions: Vec<usize>,
@@ -48,6 +55,12 @@ where
     i: usize,
     /// Is the interpreter finished interpreting?s
     done: bool,
+    /// The resources used by the program, checked against the interpreter's limits.
+    meter: Meter,
+    /// The tracer recording the instructions executed, if the program is traced.
+    tracer: Option<Tracer>,
+    /// The checkpointer saving the state of the interpreter periodically, if there is one.
+    checkpointer: Option<Checkpointer>,
 }
 
 impl<T> StandardInterpreter<T>
@@ -60,14 +73,101 @@ where
             pointer: 0,
             register: vec![0; 1024],
             cells: vec![],
+            heap: Allocator::default(),
             functions: vec![],
             calls: vec![],
             refs: vec![],
             i: 0,
             done: false,
+            meter: Meter::default(),
+            tracer: None,
+            checkpointer: None,
+        }
+    }
+
+    /// Limit the resources the interpreter can use to run a program.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.meter = Meter::new(limits);
+        self
+    }
+
+    /// Record the instructions the interpreter executes with the tracer, if there is one.
+    pub fn with_tracer(mut self, tracer: Option<Tracer>) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    /// Detach the tracer from the interpreter, to finish the trace.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Save checkpoints of the interpreter periodically with the checkpointer, if there is one.
+    pub fn with_checkpointer(mut self, checkpointer: Option<Checkpointer>) -> Self {
+        self.checkpointer = checkpointer;
+        self
+    }
+
+    /// Detach the checkpointer from the interpreter, to report any error saving checkpoints.
+    pub fn take_checkpointer(&mut self) -> Option<Checkpointer> {
+        self.checkpointer.take()
+    }
+
+    /// Record the cells written on the tape by the current instruction, if the program is traced.
+    fn trace_write(&mut self, addr: usize, n: usize) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.write(addr, &self.cells[addr..addr + n]);
         }
     }
 
+    /// Save the state of the interpreter, so it can carry on running the program later.
+    pub fn snapshot(&self, code: &StandardProgram) -> Checkpoint {
+        self.checkpoint(Checkpoint::hash_program(code))
+    }
+
+    /// Save the state of the interpreter running the program with the given hash.
+    fn checkpoint(&self, program: u64) -> Checkpoint {
+        let mut tape = self.cells.clone();
+        // The pointer can be moved past the end of the tape before the tape grows to
+        // hold it, so the tape is saved with the cells up to the pointer.
+        tape.resize(tape.len().max(self.pointer + 1), 0);
+        Checkpoint {
+            program,
+            instruction: self.i,
+            done: self.done,
+            pointer: self.pointer,
+            register: self.register.clone(),
+            tape,
+            heap: self.heap.clone(),
+            functions: self.functions.clone(),
+            call_stack: self.calls.clone(),
+            deref_stack: self.refs.clone(),
+            ffi_channel: self.device.ffi_channel().iter().copied().collect(),
+        }
+    }
+
+    /// Restore the state of the interpreter from a checkpoint of the same program.
+    /// The device and the limits of the interpreter are kept. If the checkpoint
+    /// wasn't saved from this program, or its state is invalid, the interpreter isn't changed.
+    pub fn restore(
+        &mut self,
+        checkpoint: Checkpoint,
+        code: &StandardProgram,
+    ) -> Result<(), String> {
+        checkpoint.validate(code)?;
+        self.i = checkpoint.instruction;
+        self.done = checkpoint.done;
+        self.pointer = checkpoint.pointer;
+        self.register = checkpoint.register;
+        self.cells = checkpoint.tape;
+        self.heap = checkpoint.heap;
+        self.functions = checkpoint.functions;
+        self.calls = checkpoint.call_stack;
+        self.refs = checkpoint.deref_stack;
+        *self.device.ffi_channel_mut() = checkpoint.ffi_channel.into();
+        Ok(())
+    }
+
     fn reg_scalar(&self) -> i64 {
         self.register[0]
     }
@@ -94,19 +194,21 @@ where
     }
 
     /// Dereference the current pointer on the tape.
-    fn deref(&mut self) {
-        // Add the old pointer to the dereference stack.
-        self.refs.push(self.pointer);
-        let cell = *self.get_cell();
+    fn deref(&mut self) -> Result<(), RuntimeError> {
+        self.meter.check_refs(self.refs.len() + 1)?;
+        let cell = *self.get_cell()?;
         if cell < 0 {
-            panic!("Dereferencing negative cell with value {cell:?}");
+            return Err(RuntimeError::DerefOutOfBounds(cell));
         }
+        // Add the old pointer to the dereference stack.
+        self.refs.push(self.pointer);
         // Set the pointer to the address on the tape.
-        self.pointer = *self.get_cell() as usize;
+        self.pointer = cell as usize;
+        Ok(())
     }
 
     /// Undo a dereference.
-    fn refer(&mut self) -> Result<(), String> {
+    fn refer(&mut self) -> Result<(), RuntimeError> {
         // Get the previous value of the pointer before
         // the last dereference instruction.
         if let Some(old) = self.refs.pop() {
@@ -114,12 +216,13 @@ where
             Ok(())
         } else {
             // There was no previous dereference, throw an error
-            Err(String::from("cannot Refer due to empty Deref stack"))
+            Err(RuntimeError::EmptyDerefStack)
         }
     }
 
     /// Call the Nth function defined in the program, where N is the value of the register.
-    fn call(&mut self, code: &StandardProgram) -> Result<(), String> {
+    fn call(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
+        self.meter.check_calls(self.calls.len() + 1)?;
         // If the function has been defined
         if self.functions.len() > self.reg_scalar() as usize {
             // Push the current instruction pointer to the call stack
@@ -141,7 +244,11 @@ where
                         count += 1;
                     }
                     Some(_) => {}
-                    None => return Err(format!("function {} not defined", self.reg_scalar())),
+                    None => {
+                        // Report the error at the call instruction.
+                        self.i = self.calls.pop().unwrap_or_default();
+                        return Err(RuntimeError::InvalidCall(self.reg_scalar()));
+                    }
                 }
                 // If `count` hasn't reached the function we want,
                 // keep going.
@@ -252,16 +359,13 @@ where
     }
 
     /// Get the current cell pointed to on the turing tape.
-    fn get_cell(&mut self) -> &mut i64 {
-        while self.pointer >= self.cells.len() {
-            self.cells.extend(vec![0; 1000]);
-        }
-
-        &mut self.cells[self.pointer]
+    fn get_cell(&mut self) -> Result<&mut i64, RuntimeError> {
+        self.meter.reserve(&mut self.cells, self.pointer + 1)?;
+        Ok(&mut self.cells[self.pointer])
     }
 
     /// Run a core program using this interpreter and its device.
-    pub fn run(mut self, code: &StandardProgram) -> Result<T, String> {
+    pub fn run(mut self, code: &StandardProgram) -> Result<T, RuntimeError> {
         while !self.done {
             self.step(code)?
         }
@@ -269,9 +373,37 @@ where
         Ok(self.device)
     }
 
-    /// Run a single step of the interpreter.
-    fn step(&mut self, code: &StandardProgram) -> Result<(), String> {
+    /// Run a single step of the interpreter. Errors are annotated with the
+    /// state of the interpreter when they happened.
+    pub fn step(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
+        let (i, depth) = (self.i, self.calls.len());
+        // The register holds the index of the function, if this is a call.
+        let function = self.register.first().copied().unwrap_or_default();
+        self.execute(code).map_err(|err| err.with_state(self))?;
+        if let (Some(tracer), Some(op)) = (&mut self.tracer, code.0.get(i)) {
+            let register = self.register.first().copied().unwrap_or_default();
+            tracer.record(i, op, self.pointer, register);
+            tracer.follow_calls(function, depth, self.calls.len());
+        }
+        if self.checkpointer.as_mut().is_some_and(Checkpointer::tick) {
+            self.save_checkpoint(code);
+        }
+        Ok(())
+    }
+
+    /// Save a checkpoint of the interpreter with its checkpointer.
+    fn save_checkpoint(&mut self, code: &StandardProgram) {
+        if let Some(mut checkpointer) = self.checkpointer.take() {
+            let checkpoint = self.checkpoint(checkpointer.program(code));
+            checkpointer.save(&checkpoint);
+            self.checkpointer = Some(checkpointer);
+        }
+    }
+
+    /// Execute the current instruction of the program.
+    fn execute(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
         if let Some(op) = self.fetch(code) {
+            self.meter.tick()?;
             match op {
                 StandardOp::CoreOp(core_op) => match core_op {
                     CoreOp::Comment(_) => {}
@@ -307,9 +439,7 @@ where
                     }
 
                     CoreOp::Load(n) => {
-                        while self.pointer + n >= self.cells.len() {
-                            self.cells.extend(vec![0; 1000]);
-                        }
+                        self.meter.reserve(&mut self.cells, self.pointer + n)?;
 
                         self.reg_mut_vector().clear();
 
@@ -320,14 +450,13 @@ where
                     }
 
                     CoreOp::Store(n) => {
-                        while self.pointer + n >= self.cells.len() {
-                            self.cells.extend(vec![0; 1000]);
-                        }
+                        self.meter.reserve(&mut self.cells, self.pointer + n)?;
 
                         for i in 0..*n {
                             let val = self.reg_vector()[i];
                             self.cells[self.pointer + i] = val;
                         }
+                        self.trace_write(self.pointer, *n);
                     }
                     // CoreOp::Load(n) => *self.get_cell() = self.reg_scalar(),
                     // CoreOp::Store(n) => self.register = *self.get_cell(),
@@ -336,10 +465,7 @@ where
                             self.pointer += *n as usize
                         } else {
                             if self.pointer < -*n as usize {
-                                return Err(format!(
-                                    "Instruction #{} tried to move the pointer to a negative index.",
-                                    self.i
-                                ));
+                                return Err(RuntimeError::NegativePointer(self.i));
                             }
                             self.pointer -= -*n as usize
                         }
@@ -351,7 +477,7 @@ where
                             self.reg_mut_vector()[i] += *offset as i64;
                         }
                     }
-                    CoreOp::Deref => self.deref(),
+                    CoreOp::Deref => self.deref()?,
                     CoreOp::Refer => self.refer()?,
 
                     CoreOp::Index(n) => {
@@ -387,7 +513,8 @@ where
                     }
                     CoreOp::LeftShift(n) => {
                         for i in 0..*n {
-                            self.reg_mut_vector()[i] <<= self.cells[self.pointer + i];
+                            self.reg_mut_vector()[i] = self.reg_vector()[i]
+                                .wrapping_shl(self.cells[self.pointer + i] as u32);
                         }
                     }
                     // CoreOp::LogicalRightShift => {
@@ -403,47 +530,50 @@ where
 
                     CoreOp::ArithmeticRightShift(n) => {
                         for i in 0..*n {
-                            self.reg_mut_vector()[i] >>= self.cells[self.pointer + i];
+                            self.reg_mut_vector()[i] = self.reg_vector()[i]
+                                .wrapping_shr(self.cells[self.pointer + i] as u32);
                         }
                     }
 
                     CoreOp::Add(n) => {
                         for i in 0..*n {
                             let val = self.cells[self.pointer + i];
-                            self.reg_mut_vector()[i] += val;
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_add(val);
                         }
                     }
                     CoreOp::Sub(n) => {
                         for i in 0..*n {
                             let val = self.cells[self.pointer + i];
-                            self.reg_mut_vector()[i] -= val;
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_sub(val);
                         }
                     }
                     CoreOp::Mul(n) => {
                         for i in 0..*n {
                             let val = self.cells[self.pointer + i];
-                            self.reg_mut_vector()[i] *= val;
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_mul(val);
                         }
                     }
                     CoreOp::Div(n) => {
                         for i in 0..*n {
                             let val = self.cells[self.pointer + i];
-                            if val != 0 {
-                                self.reg_mut_vector()[i] /= val;
+                            if val == 0 {
+                                return Err(RuntimeError::DivideByZero);
                             }
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_div(val);
                         }
                     }
                     CoreOp::Rem(n) => {
                         for i in 0..*n {
                             let val = self.cells[self.pointer + i];
-                            if val != 0 {
-                                self.reg_mut_vector()[i] %= val;
+                            if val == 0 {
+                                return Err(RuntimeError::DivideByZero);
                             }
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_rem(val);
                         }
                     }
                     CoreOp::Neg(n) => {
                         for i in 0..*n {
-                            self.reg_mut_vector()[i] *= -1;
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_neg();
                         }
                     }
 
@@ -469,12 +599,12 @@ where
 
                     CoreOp::Inc(n) => {
                         for i in 0..*n {
-                            self.reg_mut_vector()[i] += 1;
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_add(1);
                         }
                     }
                     CoreOp::Dec(n) => {
                         for i in 0..*n {
-                            self.reg_mut_vector()[i] -= 1;
+                            self.reg_mut_vector()[i] = self.reg_vector()[i].wrapping_sub(1);
                         }
                     }
 
@@ -484,6 +614,7 @@ where
                             self.reg_mut_vector()[i] = self.cells[self.pointer + i];
                             self.cells[self.pointer + i] = temp;
                         }
+                        self.trace_write(self.pointer, *n);
                     }
 
                     CoreOp::IsNonNegative(n) => {
@@ -603,19 +734,19 @@ where
                 }
 
                 StandardOp::Alloc => {
-                    // If the virtual machine doesn't have a thousand cells,
-                    // allocate some.
-                    if self.cells.len() < 30000 {
-                        self.cells.extend(vec![0; 30000]);
-                    }
-                    // Save the address of where the new cells will start.
-                    let result = self.cells.len() - 1;
-                    // Allocate new space at the end of the type.
-                    self.cells.extend(vec![0; self.reg_scalar() as usize]);
-                    // Store the address of the new space in the register.
-                    *self.reg_mut_scalar() = result as i64;
+                    // Allocate the cells on the heap, and store the address of the
+                    // first cell in the register.
+                    let size = self.reg_scalar();
+                    self.meter
+                        .check_tape(self.heap.required_len(size, self.cells.len()))?;
+                    let addr = self.heap.alloc(size, &mut self.cells)?;
+                    *self.reg_mut_scalar() = addr as i64;
+                    // The cells of the new block are zeroed.
+                    self.trace_write(addr, size.max(1) as usize);
+                }
+                StandardOp::Free => {
+                    self.heap.free(self.reg_scalar())?;
                 }
-                StandardOp::Free => {}
                 StandardOp::Call(binding) => {
                     self.device.ffi_call(binding, Some(&mut self.cells))?;
                 }
@@ -627,3 +758,57 @@ where
         Ok(())
     }
 }
+
+impl<T> Debuggable for StandardInterpreter<T>
+where
+    T: Device,
+{
+    type Program = StandardProgram;
+
+    fn step(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
+        StandardInterpreter::step(self, code)
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn program_len(code: &StandardProgram) -> usize {
+        code.0.len()
+    }
+
+    fn instruction(code: &StandardProgram, i: usize) -> Option<String> {
+        code.0.get(i).map(|op| op.to_string())
+    }
+
+    fn comment(code: &StandardProgram, i: usize) -> Option<&str> {
+        match code.0.get(i) {
+            Some(StandardOp::CoreOp(CoreOp::Comment(comment))) => Some(comment),
+            _ => None,
+        }
+    }
+
+    fn instruction_pointer(&self) -> usize {
+        self.i
+    }
+
+    fn register(&self) -> &[i64] {
+        &self.register
+    }
+
+    fn tape(&self) -> &[i64] {
+        &self.cells
+    }
+
+    fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    fn deref_stack(&self) -> &[usize] {
+        &self.refs
+    }
+
+    fn call_stack(&self) -> &[usize] {
+        &self.calls
+    }
+}
@@ -0,0 +1,316 @@
+//! # Trace Module
+//!
+//! This module implements traces of the core and standard interpreters.
+//! A `Tracer` attached to an interpreter records each instruction the
+//! interpreter executes: its index in the program, the instruction itself,
+//! the pointer and the register afterwards, and the cells it wrote on the tape.
+//! Comparing the traces of two runs of a program finds the first instruction
+//! where they went different ways.
+//!
+//! The tracer can be told to only record the first steps of a program, or
+//! only the instructions executed in some functions. Functions are identified
+//! by their virtual machine function index (the value in the register when
+//! they are called). Steps are always numbered from the start of the program,
+//! so traces recorded with the same filter can still be compared.
+//!
+//! ## Format
+//!
+//! A trace is written as JSON lines, one line for each instruction executed:
+//!
+//! ```text
+//! {"step":0,"ip":0,"op":"set [5]","pointer":0,"register":5,"writes":[]}
+//! {"step":1,"ip":1,"op":"store 1","pointer":0,"register":5,"writes":[[0,5]]}
+//! ```
+//!
+//! The register is the first cell of the register, and `writes` holds the
+//! address and the new value of each cell written. Cells written by foreign
+//! functions are not recorded, and neither is an instruction which fails.
+use ::std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Result as IoResult, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+/// An instruction executed by the interpreter, as recorded in a trace.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceStep {
+    /// The number of instructions executed before this one.
+    pub step: u64,
+    /// The index of the instruction in the program.
+    pub instruction: usize,
+    /// The instruction, formatted as text.
+    pub op: String,
+    /// The pointer on the turing tape after the instruction.
+    pub pointer: usize,
+    /// The first cell of the register after the instruction.
+    pub register: i64,
+    /// The address and the new value of each cell the instruction wrote on the tape.
+    pub writes: Vec<(usize, i64)>,
+}
+
+impl TraceStep {
+    /// Parse a step from a line of a trace.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut cursor = Cursor(line.trim());
+        let mut result = Self::default();
+        cursor.expect("{\"step\":")?;
+        result.step = cursor.number()?;
+        cursor.expect(",\"ip\":")?;
+        result.instruction = cursor.number()?;
+        cursor.expect(",\"op\":")?;
+        result.op = cursor.string()?;
+        cursor.expect(",\"pointer\":")?;
+        result.pointer = cursor.number()?;
+        cursor.expect(",\"register\":")?;
+        result.register = cursor.number()?;
+        cursor.expect(",\"writes\":[")?;
+        while !cursor.eat("]") {
+            if !result.writes.is_empty() {
+                cursor.expect(",")?;
+            }
+            cursor.expect("[")?;
+            let addr = cursor.number()?;
+            cursor.expect(",")?;
+            let value = cursor.number()?;
+            cursor.expect("]")?;
+            result.writes.push((addr, value));
+        }
+        cursor.expect("}")?;
+        if !cursor.0.is_empty() {
+            return Err(format!("unexpected `{}`", cursor.0));
+        }
+        Ok(result)
+    }
+}
+
+/// Serialize the step as a line of a trace.
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{{\"step\":{},\"ip\":{},\"op\":\"",
+            self.step, self.instruction
+        )?;
+        for c in self.op.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        write!(
+            f,
+            "\",\"pointer\":{},\"register\":{},\"writes\":[",
+            self.pointer, self.register
+        )?;
+        for (i, (addr, value)) in self.writes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "[{addr},{value}]")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// The text left to parse in a line of a trace.
+struct Cursor<'a>(&'a str);
+
+impl Cursor<'_> {
+    /// Skip the given text, if the line continues with it.
+    fn eat(&mut self, text: &str) -> bool {
+        match self.0.strip_prefix(text) {
+            Some(rest) => {
+                self.0 = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Skip the given text, which the line must continue with.
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(format!("expected `{text}`"))
+        }
+    }
+
+    /// Parse an integer.
+    fn number<T: FromStr>(&mut self) -> Result<T, String> {
+        let len = self
+            .0
+            .char_indices()
+            .find(|(i, c)| !(c.is_ascii_digit() || (*i == 0 && *c == '-')))
+            .map_or(self.0.len(), |(i, _)| i);
+        let (number, rest) = self.0.split_at(len);
+        self.0 = rest;
+        number
+            .parse()
+            .map_err(|_| format!("invalid number `{number}`"))
+    }
+
+    /// Parse a quoted string.
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut result = String::new();
+        let mut chars = self.0.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.0 = &self.0[i + 1..];
+                    return Ok(result);
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => result.push('\n'),
+                    Some((_, 't')) => result.push('\t'),
+                    Some((_, 'u')) => {
+                        let code = chars.by_ref().take(4).map(|(_, c)| c).collect::<String>();
+                        match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                            Some(c) => result.push(c),
+                            None => return Err(format!("invalid escape `\\u{code}`")),
+                        }
+                    }
+                    Some((_, c)) => result.push(c),
+                    None => break,
+                },
+                c => result.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+/// Parse a trace, one step per line.
+pub fn parse_trace(text: &str) -> Result<Vec<TraceStep>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            TraceStep::parse(line).map_err(|err| format!("line {}: {err} in trace", i + 1))
+        })
+        .collect()
+}
+
+/// Find the index of the first step where two traces differ.
+/// If one trace is a prefix of the other, they differ where the shorter one ends.
+pub fn diff_traces(a: &[TraceStep], b: &[TraceStep]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(i) => Some(i),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+/// Which instructions a tracer records.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only record the instructions executed in the functions with these indices.
+    /// If this is set, the instructions outside of any function aren't recorded.
+    pub functions: Option<RangeInclusive<usize>>,
+    /// Only record this many instructions from the start of the program.
+    pub steps: Option<u64>,
+}
+
+/// Records the instructions executed by an interpreter.
+pub struct Tracer {
+    /// Where the trace is written.
+    output: Box<dyn Write>,
+    /// Which instructions to record.
+    filter: TraceFilter,
+    /// The number of instructions executed so far.
+    steps: u64,
+    /// The function indices of the calls the program is currently in, outermost first.
+    /// The calls made before the tracer was attached have unknown indices.
+    frames: Vec<Option<usize>>,
+    /// The cells written by the current instruction.
+    writes: Vec<(usize, i64)>,
+    /// The first error writing the trace.
+    error: Option<::std::io::Error>,
+}
+
+impl Tracer {
+    /// Create a tracer which writes the trace to the given output.
+    pub fn new(output: impl Write + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+            filter: TraceFilter::default(),
+            steps: 0,
+            frames: vec![],
+            writes: vec![],
+            error: None,
+        }
+    }
+
+    /// Only record the instructions allowed by the filter.
+    pub fn with_filter(mut self, filter: TraceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Flush the trace to the output, and report any error writing it.
+    pub fn finish(mut self) -> IoResult<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.output.flush(),
+        }
+    }
+
+    /// Note that the current instruction wrote the given cells, starting at the address.
+    pub(super) fn write(&mut self, addr: usize, cells: &[i64]) {
+        self.writes
+            .extend(cells.iter().enumerate().map(|(i, cell)| (addr + i, *cell)));
+    }
+
+    /// Record an instruction after it's executed.
+    pub(super) fn record(
+        &mut self,
+        instruction: usize,
+        op: &impl Display,
+        pointer: usize,
+        register: i64,
+    ) {
+        let in_steps = self.filter.steps.is_none_or(|steps| self.steps < steps);
+        let in_functions = match (&self.filter.functions, self.frames.last()) {
+            (None, _) => true,
+            (Some(functions), Some(Some(function))) => functions.contains(function),
+            (Some(_), _) => false,
+        };
+        if in_steps && in_functions && self.error.is_none() {
+            let step = TraceStep {
+                step: self.steps,
+                instruction,
+                op: op.to_string(),
+                pointer,
+                register,
+                writes: ::std::mem::take(&mut self.writes),
+            };
+            if let Err(err) = writeln!(self.output, "{step}") {
+                self.error = Some(err);
+            }
+        }
+        self.writes.clear();
+        self.steps += 1;
+    }
+
+    /// Follow the calls and returns of the program, given the function index in the
+    /// register and the depth of the call stack before and after an instruction.
+    pub(super) fn follow_calls(&mut self, function: i64, depth: usize, new_depth: usize) {
+        if new_depth > depth {
+            self.frames.resize(depth, None);
+            self.frames.push(Some(function as usize));
+        } else {
+            self.frames.truncate(new_depth);
+        }
+    }
+}
@@ -65,6 +65,15 @@ pub use self::std::*;
 mod interpreter;
 pub use interpreter::*;
 
+mod source_map;
+pub use source_map::*;
+
+mod binary;
+pub use binary::*;
+
+mod optimize;
+pub use optimize::*;
+
 /// An error generated by the virtual machine.
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Error {
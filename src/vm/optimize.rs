@@ -0,0 +1,240 @@
+//! # Optimizer
+//!
+//! A peephole optimizer for virtual machine programs.
+//!
+//! The assembler emits virtual machine code without any cleanup, so the
+//! generated code is full of short sequences of instructions which cancel
+//! out or overwrite each other. This pass folds and eliminates them without
+//! changing what the program does.
+//!
+//! ## Optimization Levels
+//!
+//! - `0`: No optimizations.
+//! - `1`: Fold consecutive `Move` instructions, remove `Move(0)`, and remove
+//!   `Deref` instructions immediately followed by a `Refer`.
+//! - `2`: Also remove values written to the register which are overwritten
+//!   before they're used, `Load`s of cells which were just stored, `Store`s
+//!   of cells which were just loaded, and fold constants into `Set` instructions.
+//! - `3`: Also remove unreachable code after `Return` instructions, and
+//!   comments which aren't debug markers.
+//!
+//! Comments don't separate instructions, so instructions are combined across
+//! them. Debug markers are always kept, so the program's source map still
+//! points to the right code.
+use super::{CoreOp, CoreProgram, StandardOp, StandardProgram};
+use crate::lir::DebugInfo;
+
+/// The highest optimization level.
+pub const MAX_OPTIMIZATION_LEVEL: u8 = 3;
+
+impl CoreProgram {
+    /// Optimize the program at the given optimization level (from 0 to 3).
+    pub fn optimize(self, level: u8) -> Self {
+        Self(optimize(self.0, level))
+    }
+}
+
+impl StandardProgram {
+    /// Optimize the program at the given optimization level (from 0 to 3).
+    pub fn optimize(self, level: u8) -> Self {
+        Self(optimize(self.0, level))
+    }
+}
+
+/// An instruction of either variant of the virtual machine.
+trait Instruction: Clone {
+    /// Get the core instruction this is, if it is one.
+    fn as_core(&self) -> Option<&CoreOp>;
+    /// Create an instruction from a core instruction.
+    fn from_core(op: CoreOp) -> Self;
+    /// Does this instruction overwrite the whole register without reading it?
+    fn overwrites_register(&self) -> bool;
+
+    /// Is this a comment?
+    fn is_comment(&self) -> bool {
+        matches!(self.as_core(), Some(CoreOp::Comment(_)))
+    }
+
+    /// Is this a debug marker?
+    fn is_marker(&self) -> bool {
+        matches!(self.as_core(), Some(CoreOp::Comment(text)) if DebugInfo::parse_marker(text).is_some())
+    }
+}
+
+impl Instruction for CoreOp {
+    fn as_core(&self) -> Option<&CoreOp> {
+        Some(self)
+    }
+
+    fn from_core(op: CoreOp) -> Self {
+        op
+    }
+
+    fn overwrites_register(&self) -> bool {
+        matches!(self, CoreOp::Set(_) | CoreOp::Load(_))
+    }
+}
+
+impl Instruction for StandardOp {
+    fn as_core(&self) -> Option<&CoreOp> {
+        match self {
+            StandardOp::CoreOp(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn from_core(op: CoreOp) -> Self {
+        StandardOp::CoreOp(op)
+    }
+
+    fn overwrites_register(&self) -> bool {
+        match self {
+            StandardOp::CoreOp(op) => op.overwrites_register(),
+            StandardOp::Set(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Optimize a list of instructions at the given optimization level.
+fn optimize<T: Instruction>(mut code: Vec<T>, level: u8) -> Vec<T> {
+    if level == 0 {
+        return code;
+    }
+
+    if level >= 3 {
+        code.retain(|op| !op.is_comment() || op.is_marker());
+    }
+
+    // Every optimization removes at least one instruction,
+    // so we're done when the code stops getting shorter.
+    loop {
+        let len = code.len();
+        if level >= 3 {
+            code = remove_unreachable(code);
+        }
+        code = peephole(code, level);
+        if code.len() == len {
+            return code;
+        }
+    }
+}
+
+/// Combine adjacent instructions.
+fn peephole<T: Instruction>(code: Vec<T>, level: u8) -> Vec<T> {
+    let mut result = Vec::with_capacity(code.len());
+    for op in code {
+        push(&mut result, op, level);
+    }
+    result
+}
+
+/// Add an instruction to the end of the optimized code, combining it with
+/// the last instruction if possible.
+fn push<T: Instruction>(result: &mut Vec<T>, op: T, level: u8) {
+    if let Some(CoreOp::Move(0)) = op.as_core() {
+        return;
+    }
+
+    if let Some(i) = result.iter().rposition(|op| !op.is_comment()) {
+        if let Some(combined) = combine(&result[i], &op, level) {
+            result.remove(i);
+            // The combined instructions might combine with the ones before them.
+            for op in combined {
+                push(result, op, level);
+            }
+            return;
+        }
+    }
+    result.push(op);
+}
+
+/// Combine two adjacent instructions into fewer instructions which do the same thing.
+fn combine<T: Instruction>(prev: &T, next: &T, level: u8) -> Option<Vec<T>> {
+    match (prev.as_core(), next.as_core()) {
+        (Some(CoreOp::Move(a)), Some(CoreOp::Move(b))) => {
+            Some(vec![T::from_core(CoreOp::Move(a.checked_add(*b)?))])
+        }
+        (Some(CoreOp::Deref), Some(CoreOp::Refer)) => Some(vec![]),
+
+        _ if level < 2 => None,
+
+        _ if prev.overwrites_register() && next.overwrites_register() => Some(vec![next.clone()]),
+        // Storing the register twice in a row stores the same values.
+        (Some(CoreOp::Store(n)), Some(CoreOp::Store(m))) => {
+            Some(vec![T::from_core(CoreOp::Store(*n.max(m)))])
+        }
+        // The register already holds the values in the cells.
+        (Some(CoreOp::Store(n)), Some(CoreOp::Load(m)))
+        | (Some(CoreOp::Load(n)), Some(CoreOp::Store(m)))
+            if m <= n =>
+        {
+            Some(vec![prev.clone()])
+        }
+        (Some(CoreOp::Set(values)), Some(op)) => {
+            Some(vec![T::from_core(CoreOp::Set(fold(values, op)?))])
+        }
+        _ => None,
+    }
+}
+
+/// Apply an instruction to constant values in the register.
+fn fold(values: &[i64], op: &CoreOp) -> Option<Vec<i64>> {
+    let (n, f): (usize, fn(i64) -> Option<i64>) = match op {
+        CoreOp::Inc(n) => (*n, |x| x.checked_add(1)),
+        CoreOp::Dec(n) => (*n, |x| x.checked_sub(1)),
+        CoreOp::Neg(n) => (*n, |x| x.checked_neg()),
+        CoreOp::Not(n) => (*n, |x| Some(i64::from(x == 0))),
+        CoreOp::BitwiseNot(n) => (*n, |x| Some(!x)),
+        CoreOp::IsNonNegative(n) => (*n, |x| Some(i64::from(x >= 0))),
+        _ => return None,
+    };
+    if n > values.len() {
+        return None;
+    }
+
+    let mut result = values.to_vec();
+    for value in &mut result[..n] {
+        *value = f(*value)?;
+    }
+    Some(result)
+}
+
+/// Remove the instructions after a `Return` which can never run, up to the
+/// end of the block. Code which defines functions is always kept, because
+/// the definitions are still reachable.
+fn remove_unreachable<T: Instruction>(code: Vec<T>) -> Vec<T> {
+    let mut result = Vec::with_capacity(code.len());
+    let mut i = 0;
+    while i < code.len() {
+        result.push(code[i].clone());
+        i += 1;
+        if !matches!(code[i - 1].as_core(), Some(CoreOp::Return)) {
+            continue;
+        }
+
+        // Find the end of the block.
+        let mut depth = 0;
+        let mut end = i;
+        let mut defines_functions = false;
+        while end < code.len() {
+            match code[end].as_core() {
+                Some(CoreOp::Function) => {
+                    defines_functions = true;
+                    break;
+                }
+                Some(CoreOp::If | CoreOp::While) => depth += 1,
+                Some(CoreOp::Else | CoreOp::End) if depth == 0 => break,
+                Some(CoreOp::End) => depth -= 1,
+                _ => {}
+            }
+            end += 1;
+        }
+
+        if !defines_functions {
+            result.extend(code[i..end].iter().filter(|op| op.is_marker()).cloned());
+            i = end;
+        }
+    }
+    result
+}
@@ -0,0 +1,342 @@
+//! # Source Maps
+//!
+//! A source map records which span of the original source code each
+//! virtual machine instruction was compiled from. It is built from the
+//! debug markers the LIR compiler inserts as comments into the code
+//! (see `lir::DebugInfo`), so the program must have been compiled with
+//! `Compile::compile_with_debug_info` to have any spans.
+//!
+//! Instructions are indexed *without* their comments, in the order they
+//! appear in the program the map was built from. This matches the textual
+//! virtual machine code, which omits comments, so a source map can be saved
+//! next to a compiled program and used to report its runtime errors later.
+//! Use `without_comments` on a program before running it with a source map.
+//!
+//! ## Format
+//!
+//! A serialized source map is a text file starting with a version header,
+//! followed by one line for each span, and one line for each run of
+//! consecutive instructions belonging to the same span:
+//!
+//! ```text
+//! sage-source-map 1
+//! span <id> <line> <column> <offset> <length> <filename> <procedure>
+//! map <first instruction> <count> <span id>
+//! ```
+//!
+//! Fields are separated by tabs. Filenames and procedure names are quoted
+//! and escaped, and missing values are left empty.
+use super::CoreOp;
+use crate::{lir::DebugInfo, parse::SourceCodeLocation};
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::SimpleFiles,
+    term::{emit, termcolor::WriteColor, Config},
+};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Error as IoError, Result as IoResult},
+};
+
+/// The header of a serialized source map.
+const HEADER: &str = "sage-source-map 1";
+
+/// A span of source code which some instructions were compiled from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceSpan {
+    /// The location of the code.
+    pub location: SourceCodeLocation,
+    /// The name of the procedure containing the code.
+    /// This is `None` for code outside of any procedure.
+    pub procedure: Option<String>,
+}
+
+impl SourceSpan {
+    /// The name of the procedure containing the code, or `<main>`.
+    pub fn procedure_name(&self) -> &str {
+        self.procedure.as_deref().unwrap_or("<main>")
+    }
+}
+
+impl Display for SourceSpan {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{} at {}:{}:{}",
+            self.procedure_name(),
+            self.location.filename.as_deref().unwrap_or("<unknown>"),
+            self.location.line,
+            self.location.column
+        )
+    }
+}
+
+/// A map from virtual machine instructions to the spans of source code they were compiled from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// The spans of source code, indexed by the IDs of the debug markers in the code.
+    spans: Vec<SourceSpan>,
+    /// The span of each instruction, if it has one.
+    instructions: Vec<Option<usize>>,
+}
+
+impl SourceMap {
+    /// The maximum number of frames printed in a backtrace.
+    pub const MAX_BACKTRACE: usize = 32;
+
+    /// Build a source map from the instructions of a program. Standard
+    /// instructions are passed as `None`, since only the core instructions
+    /// affect which span an instruction belongs to.
+    pub(super) fn new<'a>(
+        ops: impl IntoIterator<Item = Option<&'a CoreOp>>,
+        debug_info: &DebugInfo,
+    ) -> Self {
+        let spans = debug_info
+            .scopes()
+            .iter()
+            .map(|scope| SourceSpan {
+                location: scope.location.clone(),
+                procedure: scope.procedure.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut instructions = vec![];
+        // The span of the code we're currently in.
+        let mut current = None;
+        // The blocks we're currently in, and whether they are functions along
+        // with the span to return to after the end of the function definition.
+        let mut blocks = vec![];
+        for op in ops {
+            match op {
+                Some(CoreOp::Comment(comment)) => {
+                    if let Some(id) = DebugInfo::parse_marker(comment) {
+                        if id < spans.len() {
+                            current = Some(id);
+                        }
+                    }
+                    continue;
+                }
+                Some(CoreOp::Function) => {
+                    instructions.push(current);
+                    blocks.push(Some(current));
+                    // The function's code has no span until its first marker.
+                    current = None;
+                    continue;
+                }
+                Some(CoreOp::If | CoreOp::While) => blocks.push(None),
+                Some(CoreOp::End) => {
+                    instructions.push(current);
+                    // After a function definition, we're back in the code which defined it.
+                    if let Some(Some(outer)) = blocks.pop() {
+                        current = outer;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            instructions.push(current);
+        }
+
+        Self {
+            spans,
+            instructions,
+        }
+    }
+
+    /// Get the spans of source code, indexed by the IDs of the debug markers in the code.
+    pub fn spans(&self) -> &[SourceSpan] {
+        &self.spans
+    }
+
+    /// Get the span of source code an instruction was compiled from.
+    pub fn get(&self, instruction: usize) -> Option<&SourceSpan> {
+        self.spans
+            .get(self.instructions.get(instruction).copied().flatten()?)
+    }
+
+    /// The number of instructions in the program.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Does the map have no instructions?
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Get the backtrace of the program, given its instruction pointer and call
+    /// stack (the instructions which made each of the calls, from outermost to innermost).
+    /// The innermost frame comes first. Frames without a known span are skipped.
+    pub fn backtrace(&self, instruction: usize, calls: &[usize]) -> Vec<&SourceSpan> {
+        std::iter::once(instruction)
+            .chain(calls.iter().rev().copied())
+            .filter_map(|i| self.get(i))
+            .collect()
+    }
+
+    /// Parse a serialized source map.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(format!("expected source map header `{HEADER}`")),
+        }
+
+        let mut result = Self::default();
+        for (i, line) in lines {
+            let error = |message: &str| format!("line {}: {message} in source map", i + 1);
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let number = |field: &str| {
+                field
+                    .parse::<usize>()
+                    .map_err(|_| error(&format!("invalid number `{field}`")))
+            };
+            let string = |field: &str| -> Result<Option<String>, String> {
+                if field.is_empty() {
+                    return Ok(None);
+                }
+                snailquote::unescape(field)
+                    .map(Some)
+                    .map_err(|_| error(&format!("invalid string `{field}`")))
+            };
+
+            match fields.as_slice() {
+                [] | [""] => {}
+                ["span", id, line, column, offset, length, filename, procedure] => {
+                    if number(id)? != result.spans.len() {
+                        return Err(error("out of order span"));
+                    }
+                    result.spans.push(SourceSpan {
+                        location: SourceCodeLocation {
+                            line: number(line)?,
+                            column: number(column)?,
+                            offset: number(offset)?,
+                            length: match *length {
+                                "" => None,
+                                length => Some(number(length)?),
+                            },
+                            filename: string(filename)?,
+                        },
+                        procedure: string(procedure)?,
+                    });
+                }
+                ["map", start, count, span] => {
+                    if number(start)? != result.instructions.len() {
+                        return Err(error("out of order instructions"));
+                    }
+                    let span = match *span {
+                        "" => None,
+                        span => Some(number(span)?),
+                    };
+                    if matches!(span, Some(id) if id >= result.spans.len()) {
+                        return Err(error("undefined span"));
+                    }
+                    result
+                        .instructions
+                        .extend(std::iter::repeat_n(span, number(count)?));
+                }
+                _ => return Err(error("invalid entry")),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Emit a diagnostic for a runtime error to a terminal, pointing at the source
+    /// code the error occurred in, followed by the backtrace.
+    ///
+    /// Only the innermost frame is shown with its code, which `source_of` looks up
+    /// from the frame's location. If it can't find the file, the diagnostic has no
+    /// snippet, but the backtrace is still complete.
+    pub fn emit_error(
+        writer: &mut dyn WriteColor,
+        message: &str,
+        backtrace: &[&SourceSpan],
+        source_of: impl Fn(&SourceCodeLocation) -> Option<String>,
+    ) -> IoResult<()> {
+        Self::emit_snippet(writer, message, backtrace.first().copied(), source_of)?;
+        write!(writer, "{}", Self::format_backtrace(backtrace))
+    }
+
+    /// Emit the diagnostic for a runtime error at a span of code, without the backtrace.
+    pub(crate) fn emit_snippet(
+        writer: &mut dyn WriteColor,
+        message: &str,
+        span: Option<&SourceSpan>,
+        source_of: impl Fn(&SourceCodeLocation) -> Option<String>,
+    ) -> IoResult<()> {
+        let mut files = SimpleFiles::new();
+        let mut diagnostic = Diagnostic::error().with_message(message);
+        if let Some(span) = span {
+            let location = &span.location;
+            if let Some(source) = source_of(location) {
+                // Point at the first line of the code.
+                let range = location.span_in(&source);
+                let file_id = files.add(
+                    location.filename.clone().unwrap_or("<unknown>".to_string()),
+                    source,
+                );
+                diagnostic = diagnostic.with_labels(vec![Label::primary(file_id, range)
+                    .with_message(format!("in {}", span.procedure_name()))]);
+            }
+        }
+
+        emit(writer, &Config::default(), &files, &diagnostic)
+            .map_err(|e| IoError::other(e.to_string()))
+    }
+
+    /// Format a backtrace, innermost frame first.
+    pub fn format_backtrace(backtrace: &[&SourceSpan]) -> String {
+        if backtrace.is_empty() {
+            return String::new();
+        }
+        let mut result = "backtrace:\n".to_string();
+        for (i, span) in backtrace.iter().enumerate().take(Self::MAX_BACKTRACE) {
+            result += &format!("  #{i} {span}\n");
+        }
+        if backtrace.len() > Self::MAX_BACKTRACE {
+            result += &format!(
+                "  ... {} more frames\n",
+                backtrace.len() - Self::MAX_BACKTRACE
+            );
+        }
+        result
+    }
+}
+
+/// Serialize the source map.
+impl Display for SourceMap {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        writeln!(f, "{HEADER}")?;
+        let string =
+            |s: &Option<String>| s.as_ref().map(|s| format!("{:?}", s)).unwrap_or_default();
+        for (id, span) in self.spans.iter().enumerate() {
+            let location = &span.location;
+            writeln!(
+                f,
+                "span\t{id}\t{}\t{}\t{}\t{}\t{}\t{}",
+                location.line,
+                location.column,
+                location.offset,
+                location.length.map(|n| n.to_string()).unwrap_or_default(),
+                string(&location.filename),
+                string(&span.procedure),
+            )?;
+        }
+
+        let mut start = 0;
+        while start < self.instructions.len() {
+            let span = self.instructions[start];
+            let count = self.instructions[start..]
+                .iter()
+                .take_while(|other| **other == span)
+                .count();
+            writeln!(
+                f,
+                "map\t{start}\t{count}\t{}",
+                span.map(|id| id.to_string()).unwrap_or_default()
+            )?;
+            start += count;
+        }
+        Ok(())
+    }
+}
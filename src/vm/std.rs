@@ -35,7 +35,8 @@
 //! This way, a developer can write a program in such a manner that user input
 //! cannot be confused with custom encoded instructions sent to and from the I/O device
 //! using `Put` and `Get`.
-use super::{CoreOp, CoreProgram, Error, VirtualMachineProgram};
+use super::{CoreOp, CoreProgram, Error, SourceMap, VirtualMachineProgram};
+use crate::lir::DebugInfo;
 use crate::side_effects::*;
 use core::fmt;
 use std::collections::HashMap;
@@ -122,6 +123,29 @@ impl StandardProgram {
         Self(flatten(self.0).0)
     }
 
+    /// Build the source map of this program from its debug markers. The source
+    /// map refers to the instructions of the program without its comments.
+    pub fn source_map(&self, debug_info: &DebugInfo) -> SourceMap {
+        SourceMap::new(
+            self.0.iter().map(|op| match op {
+                StandardOp::CoreOp(op) => Some(op),
+                _ => None,
+            }),
+            debug_info,
+        )
+    }
+
+    /// Remove the comments from this program, so that its instructions are
+    /// indexed the same way as in its source map.
+    pub fn without_comments(self) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .filter(|op| !matches!(op, StandardOp::CoreOp(CoreOp::Comment(_))))
+                .collect(),
+        )
+    }
+
     /// Get the code outside of any functions.
     pub fn get_main(&self) -> Vec<StandardOp> {
         flatten(self.0.clone()).2
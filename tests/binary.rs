@@ -0,0 +1,263 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{
+    lir::Compile,
+    parse::*,
+    side_effects::{Axis, Color, Direction, FFIBinding, Input, InputMode, Output, OutputMode},
+    vm::*,
+};
+use std::fs::{read_dir, read_to_string};
+
+use log::warn;
+
+/// Load a binary program, as the instructions of either variant.
+fn load(bytes: &[u8]) -> Result<Result<Vec<CoreOp>, Vec<StandardOp>>, String> {
+    from_bytes(bytes).map(|program| program.map(|core| core.0).map_err(|std| std.0))
+}
+
+/// Serialize a core program, and check that it loads back the same.
+fn round_trip_core(program: &CoreProgram) -> Vec<u8> {
+    let bytes = program.to_bytes();
+    assert_eq!(load(&bytes), Ok(Ok(program.clone().without_comments().0)));
+    bytes
+}
+
+/// Serialize a standard program, and check that it loads back the same.
+fn round_trip_std(program: &StandardProgram) -> Vec<u8> {
+    let bytes = program.to_bytes();
+    assert_eq!(load(&bytes), Ok(Err(program.clone().without_comments().0)));
+    bytes
+}
+
+#[test]
+fn test_binary_core_ops() {
+    let program = CoreProgram(vec![
+        CoreOp::Comment("skipped".to_string()),
+        CoreOp::Set(vec![0, -1, 1, i64::MIN, i64::MAX, 300]),
+        CoreOp::Function,
+        CoreOp::Move(-70),
+        CoreOp::Offset(-3, 2),
+        CoreOp::Offset(isize::MAX, usize::MAX),
+        CoreOp::Return,
+        CoreOp::Call,
+        CoreOp::While,
+        CoreOp::If,
+        CoreOp::Else,
+        CoreOp::End,
+        CoreOp::End,
+        CoreOp::Store(1),
+        CoreOp::Load(128),
+        CoreOp::Where,
+        CoreOp::Deref,
+        CoreOp::Refer,
+        CoreOp::Index(2),
+        CoreOp::BitwiseNand(1),
+        CoreOp::BitwiseAnd(1),
+        CoreOp::BitwiseOr(1),
+        CoreOp::BitwiseXor(1),
+        CoreOp::BitwiseNot(1),
+        CoreOp::LeftShift(1),
+        CoreOp::LogicalRightShift(1),
+        CoreOp::ArithmeticRightShift(1),
+        CoreOp::And(1),
+        CoreOp::Or(1),
+        CoreOp::Not(1),
+        CoreOp::Add(1),
+        CoreOp::Sub(1),
+        CoreOp::Mul(1),
+        CoreOp::Div(1),
+        CoreOp::Rem(1),
+        CoreOp::Neg(1),
+        CoreOp::Inc(1),
+        CoreOp::Dec(1),
+        CoreOp::Swap(1),
+        CoreOp::IsNonNegative(1),
+        CoreOp::Get(Input::stdin_int()),
+        CoreOp::Put(Output::stdout_char()),
+    ]);
+    let bytes = round_trip_core(&program);
+    assert!(bytes.starts_with(b"SGB"));
+    assert!(bytes.len() < program.to_string().len());
+
+    // Core programs are loaded as core programs, even when they could be standard ones.
+    assert_eq!(load(&CoreProgram(vec![]).to_bytes()), Ok(Ok(vec![])));
+    assert_eq!(load(&StandardProgram(vec![]).to_bytes()), Ok(Err(vec![])));
+}
+
+#[test]
+fn test_binary_std_ops() {
+    let putchar = FFIBinding::new("putchar".to_string(), 1, 0);
+    let pow = FFIBinding::new("pow".to_string(), 2, 1);
+    let program = StandardProgram(vec![
+        StandardOp::Set(vec![0.0, -0.5, 3.25, f64::INFINITY, f64::MIN_POSITIVE]),
+        StandardOp::CoreOp(CoreOp::Comment("skipped".to_string())),
+        StandardOp::CoreOp(CoreOp::Set(vec![-5])),
+        StandardOp::Call(putchar.clone()),
+        StandardOp::Call(pow.clone()),
+        StandardOp::Call(putchar.clone()),
+        StandardOp::Alloc,
+        StandardOp::Free,
+        StandardOp::ToInt(1),
+        StandardOp::ToFloat(1),
+        StandardOp::Add(2),
+        StandardOp::Sub(2),
+        StandardOp::Mul(2),
+        StandardOp::Div(2),
+        StandardOp::Rem(2),
+        StandardOp::Neg(2),
+        StandardOp::IsNonNegative(2),
+        StandardOp::Sin(1),
+        StandardOp::Cos(1),
+        StandardOp::Tan(1),
+        StandardOp::ASin(1),
+        StandardOp::ACos(1),
+        StandardOp::ATan(1),
+        StandardOp::Pow(1),
+        StandardOp::Peek,
+        StandardOp::Poke,
+    ]);
+    let bytes = round_trip_std(&program);
+
+    // Each binding is only stored once in the table of FFI bindings.
+    let count = |name: &[u8]| bytes.windows(name.len()).filter(|w| w == &name).count();
+    assert_eq!(count(b"putchar"), 1);
+    assert_eq!(count(b"pow"), 1);
+
+    // The bits of float constants are kept exactly.
+    let Ok(Err(loaded)) = load(&StandardProgram(vec![StandardOp::Set(vec![-0.0])]).to_bytes())
+    else {
+        panic!("expected a standard program");
+    };
+    match &loaded[..] {
+        [StandardOp::Set(values)] => assert!(values[0].is_sign_negative()),
+        ops => panic!("unexpected instructions {ops:?}"),
+    }
+}
+
+#[test]
+fn test_binary_io_modes() {
+    let inputs = [
+        Input::stdin_char(),
+        Input::new(InputMode::DigitalPin, 13),
+        Input::new(InputMode::DPad(Direction::Left), 0),
+        Input::new(InputMode::JoyStick(Direction::Down), 1),
+        Input::new(InputMode::Accelerometer(Axis::Z), 0),
+        Input::new(InputMode::Gyroscope(Axis::X), 0),
+        Input::new(InputMode::Magnetometer(Axis::Y), 0),
+        Input::new(InputMode::Speedometer(None), 0),
+        Input::new(InputMode::Speedometer(Some(Axis::Y)), 0),
+        Input::new(InputMode::Position(Axis::X), 1000),
+        Input::new(InputMode::Custom("sensor".to_string()), 2),
+    ];
+    let outputs = [
+        Output::stderr_float(),
+        Output::new(OutputMode::MoveCursorRight, 3),
+        Output::new(OutputMode::SetCursorChar(Color::Orange), 0),
+        Output::new(OutputMode::SetCursorPixel(Color::RGB(1, 128, 255)), 0),
+        Output::new(OutputMode::Custom("display".to_string()), 7),
+    ];
+    let program = CoreProgram(
+        inputs
+            .into_iter()
+            .map(CoreOp::Get)
+            .chain(outputs.into_iter().map(CoreOp::Put))
+            .collect(),
+    );
+    round_trip_core(&program);
+    round_trip_std(&program.into());
+}
+
+#[test]
+fn test_binary_errors() {
+    let program = StandardProgram(vec![
+        StandardOp::Call(FFIBinding::new("f".to_string(), 1, 1)),
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+    ]);
+    let bytes = program.to_bytes();
+    let with = |i: usize, byte: u8| {
+        let mut bytes = bytes.clone();
+        bytes[i] = byte;
+        load(&bytes)
+    };
+    // The header, the binding `f` with 1 input and 1 output cell,
+    // the number of instructions, the call, and the output.
+    assert_eq!(
+        bytes,
+        b"SGB\x01\x01\x01\x01f\x01\x01\x02\x55\x00\x26\x01\x00"
+    );
+
+    assert_eq!(load(b""), Err("not a binary sage program".to_string()));
+    assert_eq!(
+        load(b"#!/bin/sage"),
+        Err("not a binary sage program".to_string())
+    );
+    assert_eq!(
+        with(3, 9),
+        Err("unsupported binary program version 9".to_string())
+    );
+    assert_eq!(with(4, 2), Err("invalid program variant 2".to_string()));
+    assert_eq!(
+        with(5, 100),
+        Err("invalid length 100 at byte 5".to_string())
+    );
+    assert_eq!(
+        with(11, 63),
+        Err("invalid instruction tag 63 at byte 11".to_string())
+    );
+    assert_eq!(
+        with(12, 1),
+        Err("invalid FFI binding 1 at byte 11".to_string())
+    );
+    assert_eq!(with(14, 200), Err("invalid tag 200 at byte 14".to_string()));
+    // Standard instructions aren't allowed in core programs.
+    assert_eq!(
+        with(4, 0),
+        Err("invalid instruction tag 85 at byte 11".to_string())
+    );
+    assert_eq!(
+        load(&bytes[..bytes.len() - 1]),
+        Err("unexpected end of binary program".to_string())
+    );
+    assert_eq!(
+        load(&[&bytes[..], &[0]].concat()),
+        Err("unexpected bytes after the end of the program at byte 16".to_string())
+    );
+    assert_eq!(
+        load(b"SGB\x01\x00\x00\x01\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01"),
+        Err("number too large at byte 8".to_string())
+    );
+}
+
+#[test]
+fn test_binary_frontend_examples() {
+    with_large_stack(test_binary_frontend_examples_helper);
+}
+
+/// Check that every example loads back the same from its binary program.
+fn test_binary_frontend_examples_helper() {
+    for entry in read_dir("examples/frontend/").unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() || path.extension().and_then(|ext| ext.to_str()) != Some("sg") {
+            continue;
+        }
+        warn!("Starting test for `{path:?}`");
+
+        let frontend_src = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
+        let Ok(frontend_code) = parse_frontend(&frontend_src, path.to_str()) else {
+            continue;
+        };
+        match frontend_code.compile() {
+            Ok(Ok(asm_code)) => {
+                let vm_code = asm_code.assemble(CALL_STACK_SIZE).unwrap().flatten();
+                round_trip_core(&vm_code);
+            }
+            Ok(Err(asm_code)) => {
+                let vm_code = asm_code.assemble(CALL_STACK_SIZE).unwrap().flatten();
+                round_trip_std(&vm_code);
+            }
+            Err(_) => continue,
+        }
+    }
+}
@@ -1,3 +1,6 @@
+mod common;
+
+use common::{CALL_STACK_SIZE, with_large_stack};
 use sage::{lir::Compile, parse::*, targets::*};
 use std::{
     fs::{read_dir, read_to_string},
@@ -7,27 +10,135 @@ use std::{
 use log::warn;
 
 const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
-const CALL_STACK_SIZE: usize = 8192;
+/// The C compilers to test the generated code with.
+const COMPILERS: &[&str] = &["gcc", "clang"];
+/// The flags for compiling the generated code as standard C.
+const C_FLAGS: &[&str] = &["-std=c99", "-pedantic-errors"];
 
-#[test]
-fn test_c_target_frontend_examples() {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(1)
-        .stack_size(512 * 1024 * 1024)
-        .build_global()
+/// Is the given compiler installed?
+fn has_compiler(compiler: &str) -> bool {
+    std::process::Command::new(compiler)
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Compile C code with the given compiler, and run it with the given input.
+fn run_c_code(compiler: &str, name: &str, c_code: &str, input: &str) -> std::process::Output {
+    let c_code_path = format!("tmp_c_{compiler}_{name}.c");
+    let c_exe_path = format!("tmp_c_{compiler}_{name}.exe");
+    std::fs::write(&c_code_path, c_code).unwrap();
+
+    let c_compile_output = std::process::Command::new(compiler)
+        .args(C_FLAGS)
+        .arg(&c_code_path)
+        .arg("-o")
+        .arg(&c_exe_path)
+        .arg("-lm")
+        .output()
         .unwrap();
-    // Compiling most examples overflows the tiny stack for tests.
-    // So, we spawn a new thread with a larger stack size.
-    let child = std::thread::Builder::new()
-        .stack_size(512 * 1024 * 1024)
-        .spawn(test_c_target_frontend_examples_helper)
+    if !c_compile_output.status.success() {
+        panic!("Could not compile C code for `{name}` with {compiler}: {c_compile_output:?}");
+    }
+
+    let mut c_exe = match std::process::Command::new(format!("./{c_exe_path}"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn() {
+        Ok(v) => v,
+        Err(e) => panic!("Could not run C code for `{name}`: {e}")
+    };
+
+    // Programs which exit without reading their input (like those with
+    // runtime errors) close the pipe before we can write to it.
+    match c_exe.stdin.as_mut().unwrap().write_all(input.as_bytes()) {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+            panic!("Could not write to stdin of program `{name}`: {e}");
+        }
+        _ => {}
+    }
+    let output = c_exe.wait_with_output().unwrap();
+
+    // Remove the C code and executable.
+    std::fs::remove_file(&c_code_path).unwrap();
+    std::fs::remove_file(&c_exe_path).unwrap();
+    output
+}
+
+#[test]
+fn test_c_target_io() {
+    let c_code = match parse_vm(
+        "get stdin.int mul 0 put stdout.int set 32 put stdout.char
+        get stdin.float put stdout.float set 32 put stdout.char
+        set -9223372036854775807 dec put stdout.int set 32 put stdout.char
+        set 1 call",
+    )
+    .unwrap()
+    {
+        Ok(vm_code) => C::default().build_core(&vm_code.flatten()),
+        Err(vm_code) => C::default().build_std(&vm_code.flatten()),
+    }
+    .unwrap();
+    for compiler in COMPILERS {
+        if !has_compiler(compiler) {
+            warn!("Could not find the C compiler `{compiler}`. Skipping it.");
+            continue;
+        }
+        let output = run_c_code(compiler, "io", &c_code, " -42 -3.25");
+        assert!(!output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "-42 -3.2 -9223372036854775808 "
+        );
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "function 1 not defined\n"
+        );
+    }
+}
+
+#[test]
+fn test_c_target_divide_by_zero() {
+    for op in ["div", "rem"] {
+        let c_code = match parse_vm(format!("set 1 put stdout.int set 0 sav set 5 {op} put stdout.int"))
+            .unwrap()
+        {
+            Ok(vm_code) => C::default().build_core(&vm_code.flatten()),
+            Err(vm_code) => C::default().build_std(&vm_code.flatten()),
+        }
         .unwrap();
+        for compiler in COMPILERS {
+            if !has_compiler(compiler) {
+                warn!("Could not find the C compiler `{compiler}`. Skipping it.");
+                continue;
+            }
+            let output = run_c_code(compiler, op, &c_code, "");
+            assert!(!output.status.success());
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), "1");
+            assert_eq!(
+                String::from_utf8(output.stderr).unwrap(),
+                "division by zero\n"
+            );
+        }
+    }
+}
 
-    // Wait for the thread to finish.
-    child.join().unwrap();
+#[test]
+fn test_c_target_frontend_examples() {
+    with_large_stack(|| {
+        for compiler in COMPILERS {
+            if has_compiler(compiler) {
+                test_c_target_frontend_examples_helper(compiler);
+            } else {
+                warn!("Could not find the C compiler `{compiler}`. Skipping it.");
+            }
+        }
+    });
 }
 
-fn test_c_target_frontend_examples_helper() {
+fn test_c_target_frontend_examples_helper(compiler: &str) {
     let mut total_failures: i32 = 0;
     let mut total_attempts = 0;
 
@@ -76,8 +187,14 @@ fn test_c_target_frontend_examples_helper() {
 
             let frontend_src = read_to_string(&path)
                 .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
-            let frontend_code = parse_frontend(&frontend_src, path.to_str())
-                .unwrap_or_else(|_| panic!("Could not parse `{path:?}`"));
+            let frontend_code = match parse_frontend(&frontend_src, path.to_str()) {
+                Ok(frontend_code) => frontend_code,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not parse `{path:?}`: {e}"),
+                },
+            };
             drop(frontend_src);
             let asm_code = frontend_code.compile();
 
@@ -103,50 +220,25 @@ fn test_c_target_frontend_examples_helper() {
 
             let c_code = match vm_code {
                 Ok(vm_code) => {
-                    C.build_core(&vm_code.flatten()).unwrap()
+                    C::default().build_core(&vm_code.flatten()).unwrap()
                 }
                 Err(vm_code) => {
-                    C.build_std(&vm_code.flatten()).unwrap()
+                    C::default().build_std(&vm_code.flatten()).unwrap()
                 }
             };
 
-            // Write the C code to a file.
-            let c_code_path = format!("tmp_c_code_{file_name}.c");
-            std::fs::write(&c_code_path, c_code).unwrap();
-
-            // Compile the C code.
-            let c_exe_path = format!("tmp_c_code_{file_name}.exe");
-            let c_compile_output = std::process::Command::new("gcc")
-                .arg(&c_code_path)
-                .arg("-o")
-                .arg(&c_exe_path)
-                .arg("-lm")
-                .output()
-                .unwrap();
-
-            if !c_compile_output.status.success() {
-                panic!(
-                    "Could not compile C code for `{path:?}`: {c_compile_output:?}"
-                );
-            }
-
-            // Run the C code with the input, and confirm that the output matches the expected output.
-            let stdin = std::process::Stdio::piped();
-            let stdout = std::process::Stdio::piped();
-            let mut c_exe = match std::process::Command::new(&format!("./{c_exe_path}"))
-                .stdin(stdin)
-                .stdout(stdout)
-                .spawn() {
-                Ok(v) => v,
-                Err(e) => panic!("Could not run C code for `{path:?}`: {e}")
-            };
-                
-            if c_exe.stdin.as_mut().unwrap().write_all(INPUT.as_bytes()).is_err() {
-                panic!("Could not write to stdin of program `{path:?}`");
+            // Compile the C code, and run it with the input.
+            let c_exe_output = run_c_code(compiler, &format!("code_{file_name}"), &c_code, INPUT);
+            if correct_error.is_some() {
+                // Programs with runtime errors must exit unsuccessfully.
+                if c_exe_output.status.success() {
+                    warn!("Expected a runtime error for program `{path:?}`");
+                    total_failures += 1;
+                }
+                total_attempts += 1;
+                continue;
             }
-
-            // Get stdout from the C program.
-            let c_output = c_exe.wait_with_output().unwrap().stdout;
+            let c_output = c_exe_output.stdout;
             // Convert both to strings
             let correct_output = correct_output.iter().map(|byte| *byte as u8).collect::<Vec<_>>();
 
@@ -155,15 +247,11 @@ fn test_c_target_frontend_examples_helper() {
 
             if c_output != correct_output {
                 warn!(
-                    "Output did not match correct output for program `{path:?}`:\n{c_output}\n  !=   \n{correct_output}"
+                    "Output did not match correct output for program `{path:?}` with {compiler}:\n{c_output}\n  !=   \n{correct_output}"
                 );
                 total_failures += 1;
             }
             total_attempts += 1;
-
-            // Remove the C code and executable.
-            std::fs::remove_file(&c_code_path).unwrap();
-            std::fs::remove_file(&c_exe_path).unwrap();
         }
     }
 
@@ -171,7 +259,7 @@ fn test_c_target_frontend_examples_helper() {
     // And the frontend examples all print pointers differently. Same for floats.
     if total_failures as f64 / total_attempts as f64 > 0.3 {
         panic!(
-            "Too many failures in frontend examples: {total_failures} failures out of {total_attempts} attempts"
+            "Too many failures in frontend examples with {compiler}: {total_failures} failures out of {total_attempts} attempts"
         );
     }
 }
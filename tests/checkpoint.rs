@@ -0,0 +1,241 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{
+    lir::Compile,
+    parse::{parse_frontend, parse_vm},
+    side_effects::Output,
+    vm::*,
+};
+
+const HEAP_REUSE: &str = include_str!("../examples/frontend/heap-reuse.sg");
+
+/// Run a program for the given number of steps, and save its state.
+fn run_for(program: &StandardProgram, steps: usize) -> (Checkpoint, String) {
+    let mut interpreter = StandardInterpreter::new(TestingDevice::default());
+    for _ in 0..steps {
+        interpreter.step(program).unwrap();
+    }
+    let checkpoint = interpreter.snapshot(program);
+    // Running an empty program stops right away, and gives back the device.
+    let output = interpreter
+        .run(&StandardProgram(vec![]))
+        .unwrap()
+        .output_str();
+    (checkpoint, output)
+}
+
+/// Restore a program from a checkpoint, and run it to the end.
+fn resume(program: &StandardProgram, checkpoint: Checkpoint) -> String {
+    let mut interpreter = StandardInterpreter::new(TestingDevice::default());
+    interpreter.restore(checkpoint, program).unwrap();
+    interpreter.run(program).unwrap().output_str()
+}
+
+#[test]
+fn test_checkpoint_resume() {
+    with_large_stack(|| {
+        let program = match parse_frontend(HEAP_REUSE, Some("heap-reuse.sg"))
+            .unwrap()
+            .compile()
+            .unwrap()
+        {
+            Ok(core) => StandardProgram::from(core.assemble(CALL_STACK_SIZE).unwrap()),
+            Err(std) => std.assemble(CALL_STACK_SIZE).unwrap(),
+        };
+        let expected = StandardInterpreter::new(TestingDevice::default())
+            .run(&program)
+            .unwrap()
+            .output_str();
+
+        // Stop the program in the middle of the loop, after it has used the heap.
+        let (checkpoint, before) = run_for(&program, 200_000);
+        let text = checkpoint.to_string();
+        let restored = Checkpoint::parse(&text).unwrap();
+        assert_eq!(restored, checkpoint);
+        assert_eq!(before + &resume(&program, restored), expected);
+    });
+}
+
+#[test]
+fn test_checkpoint_ffi_channel() {
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![5])),
+        StandardOp::Poke,
+        StandardOp::CoreOp(CoreOp::Set(vec![6])),
+        StandardOp::Poke,
+        StandardOp::CoreOp(CoreOp::Set(vec![0])),
+        StandardOp::Peek,
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+        StandardOp::Peek,
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+    ]);
+    let (checkpoint, before) = run_for(&program, 5);
+    assert_eq!(before, "");
+    assert_eq!(checkpoint.ffi_channel, vec![5, 6]);
+    assert_eq!(checkpoint.instruction, 5);
+
+    let restored = Checkpoint::parse(&checkpoint.to_string()).unwrap();
+    assert_eq!(resume(&program, restored), "56");
+}
+
+#[test]
+fn test_checkpoint_tape() {
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![7, 0, -3])),
+        StandardOp::CoreOp(CoreOp::Move(2500)),
+        StandardOp::CoreOp(CoreOp::Store(3)),
+        StandardOp::CoreOp(CoreOp::Deref),
+    ]);
+    let (checkpoint, _) = run_for(&program, 4);
+    assert_eq!(checkpoint.deref_stack, vec![2500]);
+    assert_eq!(checkpoint.pointer, 7);
+    assert_eq!(&checkpoint.tape[2500..2503], &[7, 0, -3]);
+
+    // Only the nonzero cells are written out.
+    let text = checkpoint.to_string();
+    assert!(text.contains("cells\t2500\t7\n"));
+    assert!(text.contains("cells\t2502\t-3\n"));
+    assert_eq!(Checkpoint::parse(&text).unwrap(), checkpoint);
+}
+
+#[test]
+fn test_checkpoint_parse_errors() {
+    assert_eq!(
+        Checkpoint::parse("ip\t0"),
+        Err("expected checkpoint header `sage-checkpoint 2`".to_string())
+    );
+    assert_eq!(
+        Checkpoint::parse("sage-checkpoint 2\nip\tten"),
+        Err("line 2: invalid number `ten` in checkpoint".to_string())
+    );
+    assert_eq!(
+        Checkpoint::parse("sage-checkpoint 2\nregister\t1\tx"),
+        Err("line 2: invalid value `x` in checkpoint".to_string())
+    );
+    assert_eq!(
+        Checkpoint::parse("sage-checkpoint 2\ntape\t2\ncells\t1\t5\t6"),
+        Err("line 3: cells past the end of the tape in checkpoint".to_string())
+    );
+    assert_eq!(
+        Checkpoint::parse("sage-checkpoint 2\nstack\t1"),
+        Err("line 2: invalid entry in checkpoint".to_string())
+    );
+    assert_eq!(
+        Checkpoint::parse("sage-checkpoint 2\nheap\t30001\ntape\t100"),
+        Err("heap past the end of the tape in checkpoint".to_string())
+    );
+}
+
+#[test]
+fn test_checkpoint_heap_errors() {
+    let parse = |heap: &str| Checkpoint::parse(&format!("sage-checkpoint 2\n{heap}tape\t30020"));
+    assert!(parse("heap\t30010\nalloc\t30000\t4\nfree\t30004\t6\n").is_ok());
+    assert_eq!(
+        parse("free\t100000\t10\n"),
+        Err("heap range at 100000 past the end of the heap in checkpoint".to_string())
+    );
+    assert_eq!(
+        parse("heap\t30010\nalloc\t30005\t8\n"),
+        Err("heap range at 30005 past the end of the heap in checkpoint".to_string())
+    );
+    assert_eq!(
+        parse("heap\t30010\nalloc\t30000\t4\nfree\t30002\t2\n"),
+        Err("overlapping heap ranges at 30002 in checkpoint".to_string())
+    );
+    assert_eq!(
+        parse("heap\t30010\nalloc\t10\t4\n"),
+        Err("invalid heap range at 10 in checkpoint".to_string())
+    );
+    assert_eq!(
+        parse("heap\t30010\nfree\t30000\t0\n"),
+        Err("invalid heap range at 30000 in checkpoint".to_string())
+    );
+
+    // Restoring this would make the next allocation run past the end of the tape.
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![4])),
+        StandardOp::Alloc,
+    ]);
+    let (checkpoint, _) = run_for(&program, 0);
+    let text = checkpoint
+        .to_string()
+        .replace("heap\t0\n", "heap\t0\nfree\t100000\t10\n");
+    assert!(Checkpoint::parse(&text).is_err());
+}
+
+#[test]
+fn test_checkpoint_invalid_restore() {
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![1])),
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+        StandardOp::CoreOp(CoreOp::Set(vec![2])),
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+    ]);
+    let (checkpoint, _) = run_for(&program, 2);
+    let restore = |checkpoint: Checkpoint, program: &StandardProgram| {
+        StandardInterpreter::new(TestingDevice::default()).restore(checkpoint, program)
+    };
+    assert_eq!(restore(checkpoint.clone(), &program), Ok(()));
+
+    // A checkpoint can't be restored with another program.
+    let other = StandardProgram(vec![StandardOp::CoreOp(CoreOp::Put(Output::stdout_int()))]);
+    assert_eq!(
+        restore(checkpoint.clone(), &other),
+        Err("checkpoint was saved from a different program".to_string())
+    );
+
+    let text = checkpoint.to_string();
+    let edited = |from: &str, to: &str| Checkpoint::parse(&text.replace(from, to)).unwrap();
+    assert_eq!(
+        restore(edited("register\t1\n", "register\n"), &program),
+        Err("empty register in checkpoint".to_string())
+    );
+    assert_eq!(
+        restore(edited("ip\t2\n", "ip\t5\n"), &program),
+        Err("instruction pointer 5 past the end of the program in checkpoint".to_string())
+    );
+    assert_eq!(
+        restore(edited("calls\n", "calls\t4\n"), &program),
+        Err("address 4 past the end of the program in checkpoint".to_string())
+    );
+    assert_eq!(
+        restore(edited("refs\n", "refs\t1\n"), &program),
+        Err("pointer 1 past the end of the tape in checkpoint".to_string())
+    );
+}
+
+#[test]
+fn test_checkpoint_periodic() {
+    // Count down from 1000, printing each number.
+    let program = match parse_vm("set 1000 sav while put stdout.int res dec sav end").unwrap() {
+        Ok(core) => StandardProgram::from(core),
+        Err(std) => std,
+    };
+    let path = std::env::temp_dir().join(format!("sage-periodic-{}.ckpt", std::process::id()));
+    let mut interpreter =
+        StandardInterpreter::new(TestingDevice::default()).with_checkpointer(Some(
+            Checkpointer::new(&path, CheckpointInterval::Instructions(1000)),
+        ));
+    let mut steps = 0;
+    while !interpreter.is_done() {
+        interpreter.step(&program).unwrap();
+        steps += 1;
+    }
+    interpreter.take_checkpointer().unwrap().finish().unwrap();
+    let expected = interpreter
+        .run(&StandardProgram(vec![]))
+        .unwrap()
+        .output_str();
+
+    // The program finished, and the file holds the last checkpoint saved partway through.
+    let saved = Checkpoint::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut temporary = path.into_os_string();
+    temporary.push(".tmp");
+    assert!(!std::path::Path::new(&temporary).exists());
+    assert!(!saved.done);
+    let (checkpoint, before) = run_for(&program, steps / 1000 * 1000);
+    assert_eq!(saved, checkpoint);
+    assert_eq!(before + &resume(&program, saved), expected);
+}
@@ -0,0 +1,68 @@
+//! Helpers shared by the tests which compile and run small frontend programs.
+// Each test crate includes this module, but not every crate uses every helper.
+#![allow(dead_code)]
+
+use sage::{
+    lir::*,
+    parse::parse_frontend,
+    vm::{CoreInterpreter, FastInterpreter, StandardInterpreter, TestingDevice},
+};
+
+/// The size of the call stack for the programs compiled by the tests.
+pub const CALL_STACK_SIZE: usize = 8192;
+
+/// Compiling overflows the tiny stack for tests,
+/// so run the test in a new thread with a larger stack size.
+pub fn with_large_stack<T: Send + 'static>(test: impl FnOnce() -> T + Send + 'static) -> T {
+    let _ = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .stack_size(512 * 1024 * 1024)
+        .build_global();
+    std::thread::Builder::new()
+        .stack_size(512 * 1024 * 1024)
+        .spawn(test)
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+/// Compile a frontend program, and run it in every interpreter which supports it.
+pub fn run(code: &'static str) -> String {
+    with_large_stack(move || run_helper(code))
+}
+
+fn run_helper(code: &str) -> String {
+    let expr = parse_frontend(code, None).unwrap();
+    match expr.compile().unwrap() {
+        Ok(asm_code) => {
+            let vm_code = asm_code.assemble(CALL_STACK_SIZE).unwrap();
+            let core = CoreInterpreter::new(TestingDevice::default())
+                .run(&vm_code)
+                .unwrap();
+            let fast = FastInterpreter::new(TestingDevice::default())
+                .run(&vm_code)
+                .unwrap();
+            assert_eq!(core.output_str(), fast.output_str());
+            core.output_str()
+        }
+        Err(asm_code) => {
+            let vm_code = asm_code.assemble(CALL_STACK_SIZE).unwrap();
+            StandardInterpreter::new(TestingDevice::default())
+                .run(&vm_code)
+                .unwrap()
+                .output_str()
+        }
+    }
+}
+
+/// Type check a program, and get the error it fails with.
+pub fn type_error(code: &'static str) -> Error {
+    with_large_stack(move || {
+        let expr = parse_frontend(code, None).unwrap();
+        let mut err = expr.type_check(&Env::default()).unwrap_err();
+        while let Error::Annotated(inner, _) = err {
+            err = *inner;
+        }
+        err
+    })
+}
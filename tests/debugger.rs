@@ -0,0 +1,170 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{
+    debugger::{Debugger, Resume, Stop},
+    lir::Compile,
+    parse::parse_frontend,
+    vm::*,
+};
+
+const FACTORIAL: &str = "\
+def fact(n: Int): Int {
+    if n <= 1 {
+        return 1;
+    }
+    let result = n * fact(n - 1);
+    return result;
+}
+
+let x = 5;
+let y = fact(x);
+println(y);
+";
+
+const AVERAGE: &str = "\
+def average(a: Float, b: Float): Float {
+    let sum = a + b;
+    return sum / 2.0;
+}
+
+let is_done = False;
+let result = average(1.5, 2.5);
+println(result);
+";
+
+/// Run a script of debugger commands on a program, and return the debugger's output.
+fn debug_script(filename: &str, src: &str, script: &str) -> String {
+    let (program, debug_info) = parse_frontend(src, Some(filename))
+        .unwrap()
+        .compile_with_debug_info()
+        .unwrap();
+    let program = match program {
+        Ok(core_asm) => Ok(core_asm.assemble(CALL_STACK_SIZE).unwrap()),
+        Err(std_asm) => Err(std_asm.assemble(CALL_STACK_SIZE).unwrap()),
+    };
+
+    let mut output = vec![];
+    match &program {
+        Ok(vm_code) => Debugger::new(
+            CoreInterpreter::new(TestingDevice::new("")),
+            vm_code,
+            debug_info,
+        )
+        .with_source(filename, src)
+        .run(script.as_bytes(), &mut output)
+        .unwrap(),
+        Err(vm_code) => Debugger::new(
+            StandardInterpreter::new(TestingDevice::new("")),
+            vm_code,
+            debug_info,
+        )
+        .with_source(filename, src)
+        .run(script.as_bytes(), &mut output)
+        .unwrap(),
+    }
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_breakpoints() {
+    with_large_stack(|| {
+        let output = debug_script(
+            "fact.sg",
+            FACTORIAL,
+            "break 5\ncontinue\nprint n\ncontinue\nprint n\nbacktrace\ndelete 1\nbreak 6\ncontinue\nlocals\nbreakpoints\n",
+        );
+        assert!(output.contains("Breakpoint 1 at line 5"));
+        assert!(output.contains(
+            "Breakpoint 1, fact at fact.sg:5:5\n   5 |     let result = n * fact(n - 1);"
+        ));
+        assert!(output.contains("n: Int = 5"));
+        assert!(output.contains("n: Int = 4"));
+        assert!(output.contains("#0 fact at fact.sg:5:5"));
+        assert!(output.contains("#1 fact at fact.sg:5:5"));
+        assert!(output.contains("#2 <main> at fact.sg:10:1"));
+        assert!(output.contains("Deleted breakpoint 1"));
+        assert!(output.contains("Breakpoint 2, fact at fact.sg:6:5"));
+        assert!(output.contains("n: Int = 2\nresult: Int = 2"));
+        assert!(output.contains("2: line 6"));
+    });
+}
+
+#[test]
+fn test_stepping() {
+    with_large_stack(|| {
+        let output = debug_script(
+            "fact.sg",
+            FACTORIAL,
+            "step\nstep\nstep\nnext\nfinish\nnext\nprint y\n",
+        );
+        let locations = output
+            .lines()
+            .filter(|line| line.contains(" at fact.sg:"))
+            .map(|line| line.trim_start_matches("(sage) "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [
+                "<main> at fact.sg:9:1",
+                "<main> at fact.sg:10:1",
+                "fact at fact.sg:2:5",
+                "fact at fact.sg:5:5",
+                "<main> at fact.sg:11:1",
+            ]
+        );
+        assert!(output.contains("Returned from fact"));
+        assert!(output.contains("Program exited"));
+        assert!(output.contains("y: Int = 120"));
+    });
+}
+
+#[test]
+fn test_standard_interpreter() {
+    with_large_stack(|| {
+        let output = debug_script(
+            "average.sg",
+            AVERAGE,
+            "break 3\ncontinue\nlocals\nfinish\nnext\nprint result\nprint is_done\ncontinue\ncontinue\n",
+        );
+        assert!(output.contains("Breakpoint 1, average at average.sg:3:5"));
+        assert!(output.contains("a: Float = 1.5\nb: Float = 2.5\nsum: Float = 4.0"));
+        assert!(output.contains("result: Float = 2.0"));
+        assert!(output.contains("is_done: Bool = False"));
+        assert!(output.contains("Program exited"));
+        assert!(output.contains("The program is not running"));
+    });
+}
+
+#[test]
+fn test_debugger_api() {
+    with_large_stack(|| {
+        let (program, debug_info) = parse_frontend(FACTORIAL, Some("fact.sg"))
+            .unwrap()
+            .compile_with_debug_info()
+            .unwrap();
+        let vm_code = program.unwrap().assemble(CALL_STACK_SIZE).unwrap();
+
+        let mut debugger = Debugger::new(
+            CoreInterpreter::new(TestingDevice::new("")),
+            &vm_code,
+            debug_info,
+        );
+        assert_eq!(
+            debugger.add_breakpoint(None, 8),
+            Err("no code at line 8".to_string())
+        );
+        let id = debugger.add_breakpoint(Some("fact.sg"), 3).unwrap();
+        assert_eq!(debugger.resume(Resume::Continue), Stop::Breakpoint(id));
+        assert_eq!(debugger.location().unwrap().line, 3);
+        assert_eq!(debugger.get_var("n").unwrap().1, vec![1]);
+        assert_eq!(debugger.backtrace().len(), 6);
+        assert!(debugger.get_var("y").is_err());
+
+        assert!(debugger.remove_breakpoint(id));
+        assert_eq!(debugger.resume(Resume::Finish), Stop::Returned);
+        assert_eq!(debugger.backtrace().len(), 5);
+        assert_eq!(debugger.resume(Resume::Continue), Stop::Exited);
+        assert!(debugger.is_done());
+    });
+}
@@ -0,0 +1,136 @@
+use sage::{frontend, lir::*, parse::parse_frontend};
+
+/// Type check a program, and get the line of each error found.
+fn error_lines(code: &str) -> Vec<usize> {
+    let expr = parse_frontend(code, None).unwrap();
+    let err = expr.type_check(&Env::default()).unwrap_err();
+    err.into_errors()
+        .into_iter()
+        .map(|err| match err {
+            Error::Annotated(_, annotation) => annotation.location().unwrap().line,
+            err => panic!("error without a location: {err}"),
+        })
+        .collect()
+}
+
+#[test]
+fn test_errors_in_procedures() {
+    let code = "\
+def f(x: Int): Int {
+    return x + True;
+}
+
+def g(): Bool {
+    let y: Int = 'c';
+    return y == 1;
+}
+
+def h(): Int {
+    return 1;
+}
+
+h();
+";
+    assert_eq!(error_lines(code), vec![2, 6]);
+}
+
+#[test]
+fn test_errors_in_statements() {
+    let code = "\
+let a = 1;
+let b: Int = 'b';
+a + undefined;
+a = 'a';
+";
+    assert_eq!(error_lines(code), vec![2, 3, 4]);
+}
+
+#[test]
+fn test_errors_in_match_arms() {
+    let code = "\
+enum Shape { Circle, Square }
+let shape = Shape of Circle;
+let area = match shape {
+    of Circle => undefined_a,
+    of Square => undefined_b
+};
+";
+    let expr = parse_frontend(code, None).unwrap();
+    let errors = expr
+        .type_check(&Env::default())
+        .unwrap_err()
+        .into_errors()
+        .into_iter()
+        .map(|err| err.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec![
+            "symbol undefined_a not defined",
+            "symbol undefined_b not defined"
+        ]
+    );
+}
+
+#[test]
+fn test_single_error() {
+    // A program with one error still reports a single error.
+    let code = "let x: Int = True;\n";
+    let expr = parse_frontend(code, None).unwrap();
+    let err = expr.type_check(&Env::default()).unwrap_err();
+    assert!(matches!(err, Error::Annotated(..)), "{err:?}");
+    assert_eq!(err.into_errors().len(), 1);
+}
+
+#[test]
+fn test_combining_errors() {
+    assert!(Error::all([Ok(()), Ok(())]).is_ok());
+    let one = Error::SymbolNotDefined("a".to_string());
+    let two = Error::SymbolNotDefined("b".to_string());
+    let err = Error::all([Err(one.clone()), Ok(())]).unwrap_err();
+    assert_eq!(err.to_string(), "symbol a not defined");
+    // Nested errors are flattened, in order.
+    let err = Error::all([Err(Error::Many(vec![one.clone(), two])), Err(one)]).unwrap_err();
+    assert_eq!(err.into_errors().len(), 3);
+}
+
+#[test]
+fn test_syntax_errors() {
+    let code = "\
+def f(x: Int): Int {
+    return x +;
+}
+
+def g() {
+    let = 5;
+}
+
+const OK = 1;
+let x = (1 + ;
+";
+    let errors = match parse_frontend(code, Some("syntax.sg")) {
+        Err(frontend::Error::Many(errors)) => errors,
+        other => panic!("expected several syntax errors, got {other:?}"),
+    };
+    let positions = errors
+        .iter()
+        .map(|err| match err {
+            frontend::Error::Syntax(message) => message.lines().next().unwrap().trim().to_string(),
+            err => panic!("expected a syntax error, got {err:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        positions,
+        vec![
+            "--> syntax.sg:2:15",
+            "--> syntax.sg:6:9",
+            "--> syntax.sg:10:14"
+        ]
+    );
+
+    // A single syntax error isn't wrapped.
+    assert!(matches!(
+        parse_frontend("let = 5;", None),
+        Err(frontend::Error::Syntax(_))
+    ));
+}
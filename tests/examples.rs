@@ -1,4 +1,7 @@
-use sage::{lir::Compile, parse::*, vm::*};
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{frontend::stdlib, lir::Compile, parse::*, vm::*};
 use std::{
     fs::{read_dir, read_to_string},
     path::PathBuf,
@@ -7,24 +10,10 @@ use std::{
 use log::warn;
 
 const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
-const CALL_STACK_SIZE: usize = 8192;
 
 #[test]
 fn test_frontend_examples() {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(1)
-        .stack_size(512 * 1024 * 1024)
-        .build_global()
-        .unwrap();
-    // Compiling most examples overflows the tiny stack for tests.
-    // So, we spawn a new thread with a larger stack size.
-    let child = std::thread::Builder::new()
-        .stack_size(512 * 1024 * 1024)
-        .spawn(test_frontend_examples_helper)
-        .unwrap();
-
-    // Wait for the thread to finish.
-    child.join().unwrap();
+    with_large_stack(test_frontend_examples_helper);
 }
 
 fn test_frontend_examples_helper() {
@@ -84,8 +73,14 @@ fn test_frontend_examples_helper() {
 
             let frontend_src = read_to_string(&path)
                 .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
-            let frontend_code = parse_frontend(&frontend_src, path.to_str())
-                .unwrap_or_else(|_| panic!("Could not parse `{path:?}`"));
+            let frontend_code = match parse_frontend(&frontend_src, path.to_str()) {
+                Ok(frontend_code) => frontend_code,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not parse `{path:?}`: {e}"),
+                },
+            };
             drop(frontend_src);
             let asm_code = frontend_code.compile();
 
@@ -110,12 +105,16 @@ fn test_frontend_examples_helper() {
             .unwrap();
 
             let device = match vm_code {
-                Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT))
-                    .run(&vm_code)
-                    .unwrap_or_else(|_| panic!("Could not interpret code in `{path:?}`")),
-                Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT))
-                    .run(&vm_code)
-                    .unwrap_or_else(|_| panic!("Could not interpret code in `{path:?}`")),
+                Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+                Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+            };
+            let device = match device {
+                Ok(device) => device,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not interpret code in `{path:?}`: {e}"),
+                },
             };
 
             let output_text = device.output_str();
@@ -131,16 +130,15 @@ fn test_frontend_examples_helper() {
 }
 
 #[test]
-fn test_lir_examples() {
-    // Compiling most examples overflows the tiny stack for tests.
-    // So, we spawn a new thread with a larger stack size.
-    let child = std::thread::Builder::new()
-        .stack_size(64 * 1024 * 1024)
-        .spawn(test_lir_examples_helper)
-        .unwrap();
+fn test_std_version() {
+    // The version of the bundled library must match the version it reports to programs.
+    let root = stdlib::source(format!("{}/mod.sg", stdlib::ROOT)).unwrap();
+    assert!(root.contains(&format!("const VERSION = \"{}\";", stdlib::VERSION)));
+}
 
-    // Wait for the thread to finish.
-    child.join().unwrap();
+#[test]
+fn test_lir_examples() {
+    with_large_stack(test_lir_examples_helper);
 }
 
 fn test_lir_examples_helper() {
@@ -216,12 +214,16 @@ fn test_lir_examples_helper() {
             .unwrap();
 
             let device = match vm_code {
-                Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT))
-                    .run(&vm_code)
-                    .unwrap_or_else(|_| panic!("Could not interpret code in `{path:?}`")),
-                Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT))
-                    .run(&vm_code)
-                    .unwrap_or_else(|_| panic!("Could not interpret code in `{path:?}`")),
+                Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+                Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+            };
+            let device = match device {
+                Ok(device) => device,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not interpret code in `{path:?}`: {e}"),
+                },
             };
 
             let output_text = device.output_str();
@@ -238,15 +240,7 @@ fn test_lir_examples_helper() {
 
 #[test]
 fn test_asm_examples() {
-    // Compiling most examples overflows the tiny stack for tests.
-    // So, we spawn a new thread with a larger stack size.
-    let child = std::thread::Builder::new()
-        .stack_size(64 * 1024 * 1024)
-        .spawn(test_asm_examples_helper)
-        .unwrap();
-
-    // Wait for the thread to finish.
-    child.join().unwrap();
+    with_large_stack(test_asm_examples_helper);
 }
 
 fn test_asm_examples_helper() {
@@ -320,12 +314,16 @@ fn test_asm_examples_helper() {
             let vm_code = vm_code.unwrap();
 
             let device = match vm_code {
-                Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT))
-                    .run(&vm_code)
-                    .unwrap_or_else(|_| panic!("Could not interpret code in `{path:?}`")),
-                Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT))
-                    .run(&vm_code)
-                    .unwrap_or_else(|_| panic!("Could not interpret code in `{path:?}`")),
+                Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+                Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+            };
+            let device = match device {
+                Ok(device) => device,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not interpret code in `{path:?}`: {e}"),
+                },
             };
 
             let output_text = device.output_str();
@@ -0,0 +1,192 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{lir::Compile, parse::*, side_effects::Output, vm::*};
+use std::fs::{read_dir, read_to_string};
+
+use log::warn;
+
+const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
+
+/// Run a program in both core interpreters, and check that they agree.
+/// Only the kinds of errors are compared, because the interpreters lay out the tape differently.
+fn run_both(program: &CoreProgram) -> Result<Vec<i64>, RuntimeError> {
+    let expected = CoreInterpreter::new(TestingDevice::new(INPUT))
+        .run(program)
+        .map(|device| device.output_vals())
+        .map_err(|err| err.kind().clone());
+    let result = FastInterpreter::new(TestingDevice::new(INPUT))
+        .run(program)
+        .map(|device| device.output_vals())
+        .map_err(|err| err.kind().clone());
+    assert_eq!(result, expected);
+    result
+}
+
+#[test]
+fn test_fast_control_flow() {
+    let program = CoreProgram(vec![
+        // Count down from 3, printing whether each number is even.
+        CoreOp::Set(vec![3]),
+        CoreOp::Store(1),
+        CoreOp::While,
+        CoreOp::Comment("loop body".to_string()),
+        CoreOp::Put(Output::stdout_char()),
+        CoreOp::Move(1),
+        CoreOp::Set(vec![2]),
+        CoreOp::Store(1),
+        CoreOp::Move(-1),
+        CoreOp::Load(1),
+        CoreOp::Move(1),
+        CoreOp::Rem(1),
+        CoreOp::Move(-1),
+        CoreOp::If,
+        CoreOp::Set(vec![0]),
+        CoreOp::Else,
+        CoreOp::Set(vec![1]),
+        CoreOp::End,
+        CoreOp::Put(Output::stdout_char()),
+        CoreOp::Load(1),
+        CoreOp::Dec(1),
+        CoreOp::Store(1),
+        CoreOp::End,
+    ]);
+    assert_eq!(run_both(&program), Ok(vec![3, 0, 2, 1, 1, 0]));
+}
+
+#[test]
+fn test_fast_functions() {
+    let program = CoreProgram(vec![
+        // Function 0 prints the register plus one.
+        CoreOp::Function,
+        CoreOp::Load(1),
+        CoreOp::Inc(1),
+        CoreOp::Put(Output::stdout_char()),
+        // Function 1 is nested, and calls function 0.
+        CoreOp::Function,
+        CoreOp::Set(vec![0]),
+        CoreOp::Call,
+        CoreOp::Return,
+        CoreOp::End,
+        CoreOp::Return,
+        CoreOp::End,
+        CoreOp::Set(vec![41]),
+        CoreOp::Store(1),
+        CoreOp::Set(vec![1]),
+        CoreOp::Call,
+        CoreOp::Set(vec![0]),
+        CoreOp::Call,
+        CoreOp::Set(vec![2]),
+        CoreOp::Call,
+    ]);
+    assert_eq!(run_both(&program), Err(RuntimeError::InvalidCall(2)));
+
+    let program = CoreProgram(program.0[..program.0.len() - 2].to_vec());
+    assert_eq!(run_both(&program), Ok(vec![42, 42]));
+}
+
+#[test]
+fn test_fast_fused_instructions() {
+    let program = CoreProgram(vec![
+        CoreOp::Set(vec![5, 6]),
+        CoreOp::Move(2),
+        CoreOp::Move(1),
+        CoreOp::Store(2),
+        CoreOp::Set(vec![3]),
+        CoreOp::Move(-3),
+        CoreOp::Store(1),
+        CoreOp::Deref,
+        CoreOp::Move(1),
+        CoreOp::Load(1),
+        CoreOp::Offset(10, 1),
+        CoreOp::Store(1),
+        CoreOp::Refer,
+        CoreOp::Move(4),
+        CoreOp::Load(1),
+        CoreOp::Put(Output::stdout_char()),
+        CoreOp::Move(-1),
+        CoreOp::Load(1),
+        CoreOp::Put(Output::stdout_char()),
+        // Moving below zero is an error, even if the next move would fix it.
+        CoreOp::Move(-10),
+        CoreOp::Move(7),
+    ]);
+    let result = run_both(&program);
+    assert_eq!(result, Err(RuntimeError::NegativePointer(19)));
+}
+
+#[test]
+fn test_fast_unmatched_blocks() {
+    for program in [
+        vec![CoreOp::If, CoreOp::Set(vec![1])],
+        vec![CoreOp::End],
+        vec![CoreOp::While, CoreOp::Else, CoreOp::End],
+    ] {
+        assert!(Bytecode::new(&CoreProgram(program)).is_err());
+    }
+}
+
+#[test]
+fn test_fast_debuggable() {
+    let program = CoreProgram(vec![
+        CoreOp::Function,
+        CoreOp::Move(1),
+        CoreOp::Refer,
+        CoreOp::Return,
+        CoreOp::End,
+        CoreOp::Set(vec![0]),
+        CoreOp::Call,
+    ]);
+    let bytecode = Bytecode::new(&program).unwrap();
+
+    let mut core = CoreInterpreter::new(TestingDevice::default());
+    let mut fast = FastInterpreter::new(TestingDevice::default());
+    let core_result = loop {
+        if let Err(e) = core.step(&program) {
+            break e;
+        }
+    };
+    let fast_result = loop {
+        if let Err(e) = fast.step(&bytecode) {
+            break e;
+        }
+    };
+    // Both interpreters stop at the same instruction, with the same call stack.
+    assert_eq!(fast_result.kind(), core_result.kind());
+    assert_eq!(fast_result.instruction(), Some(2));
+    assert_eq!(core_result.instruction(), Some(2));
+    assert_eq!(fast.instruction_pointer(), 2);
+    assert_eq!(core.instruction_pointer(), 2);
+    assert_eq!(fast.call_stack(), &[6]);
+    assert_eq!(core.call_stack(), &[6]);
+    assert_eq!(fast.pointer(), 1);
+}
+
+#[test]
+fn test_fast_frontend_examples() {
+    with_large_stack(test_fast_frontend_examples_helper);
+}
+
+/// Check that every example which compiles to the core variant runs the same in both interpreters.
+fn test_fast_frontend_examples_helper() {
+    for entry in read_dir("examples/frontend/").unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() || path.extension().and_then(|ext| ext.to_str()) != Some("sg") {
+            continue;
+        }
+        warn!("Starting test for `{path:?}`");
+
+        let frontend_src = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
+        let Ok(frontend_code) = parse_frontend(&frontend_src, path.to_str()) else {
+            continue;
+        };
+        let Ok(Ok(asm_code)) = frontend_code.compile() else {
+            continue;
+        };
+        let vm_code = asm_code.assemble(CALL_STACK_SIZE).unwrap();
+        for level in 0..=MAX_OPTIMIZATION_LEVEL {
+            let _ = run_both(&vm_code.clone().optimize(level));
+        }
+    }
+}
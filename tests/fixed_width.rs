@@ -0,0 +1,146 @@
+mod common;
+
+use common::{run, type_error};
+use sage::lir::Error;
+
+#[test]
+fn test_wrapping_arithmetic() {
+    let code = "\
+let a = 255 as U8;
+println(a + 1);
+let b = 127 as I8;
+println(b + 1);
+println(-(-128 as I8));
+println((7 as U32) - 8);
+println((40000 as U16) * 2);
+println((1000000 as I32) * 1000000);
+println((-128 as I8) / (-1 as I8));
+";
+    assert_eq!(
+        run(code),
+        "0\n-128\n-128\n4294967295\n14464\n-727379968\n-128\n"
+    );
+}
+
+#[test]
+fn test_bitwise() {
+    let code = "\
+println(~(0 as U16));
+println(~(0 as I16));
+println((0xF0 as U8) ^ 0xFF);
+println((0x0F as U8) | (0xF0 as U8));
+println((0xFF as U8) & 0x3C);
+println((0xFF as U8) ~& 0xFF);
+";
+    assert_eq!(run(code), "65535\n-1\n15\n255\n60\n0\n");
+}
+
+#[test]
+fn test_u64() {
+    let code = "\
+let max = (0 as U64) - 1;
+println(max);
+println(max / 10);
+println(max % 10);
+println(max / max);
+println(max / (9223372036854775807 as U64));
+println(max > 1);
+println((1 as U64) < max);
+println(max >= (9223372036854775807 as U64));
+println((9223372036854775807 as U64) + 1);
+";
+    assert_eq!(
+        run(code),
+        "18446744073709551615\n1844674407370955161\n5\n1\n2\ntrue\ntrue\ntrue\n9223372036854775808\n"
+    );
+}
+
+#[test]
+fn test_i64_comparisons() {
+    // The difference of these integers overflows, so they must not be compared by subtracting.
+    let code = "\
+let max = 9223372036854775807 as I64;
+let min = (0 - 9223372036854775807 as I64) - 1;
+println(max > min);
+println(min < max);
+println(max <= (0 - 1 as I64));
+println(min >= 1);
+";
+    assert_eq!(run(code), "true\ntrue\nfalse\nfalse\n");
+}
+
+#[test]
+fn test_casts() {
+    // Casting a value which is only known at run time truncates it.
+    let code = "\
+let n = 300;
+println(n as U8);
+println((-1 as I8) as U8);
+println((200 as U8) as I8);
+println(((0 as U64) - 1) as Int);
+println(2.5 as I16);
+println((3 as U8) as Float);
+println('A' as U8);
+let arr = [10, 20, 30];
+println(arr[2 as U8]);
+";
+    assert_eq!(run(code), "44\n255\n-56\n-1\n2\n3.0\n65\n30\n");
+}
+
+#[test]
+fn test_checked_conversions() {
+    let code = "\
+use std::num::{to_u8, to_i8, to_u64, to_i64};
+let n = 300;
+println(to_u8(n).is_some());
+println(to_u8(n - 45).unwrap());
+println(to_i8(n - 428).unwrap());
+println(to_i8(n - 429).is_some());
+println(to_u64(n - 301).is_some());
+println(to_u64(n).unwrap() * 2);
+println(to_i64(-n).unwrap());
+";
+    assert_eq!(run(code), "false\n255\n-128\nfalse\nfalse\n600\n-300\n");
+}
+
+#[test]
+fn test_constant_range() {
+    assert!(matches!(
+        type_error("let x = 256 as U8;"),
+        Error::IntOutOfRange(..)
+    ));
+    assert!(matches!(
+        type_error("let x = -1 as U64;"),
+        Error::IntOutOfRange(..)
+    ));
+    assert!(matches!(
+        type_error("let x = 128 as I8;"),
+        Error::IntOutOfRange(..)
+    ));
+    assert!(matches!(
+        type_error("let x = -129 as I8;"),
+        Error::IntOutOfRange(..)
+    ));
+    assert_eq!(run("println(-128 as I8);"), "-128\n");
+}
+
+#[test]
+fn test_mixed_types() {
+    for code in [
+        "let x = (1 as U8) + (1 as I8);",
+        "let x = (1 as U8) + 1.0;",
+        "let x = (1 as U32) < (1 as U64);",
+        "let x: U8 = 1;",
+    ] {
+        let err = type_error(code);
+        assert!(
+            matches!(
+                err,
+                Error::InvalidBinaryOp(..)
+                    | Error::InvalidBinaryOpTypes(..)
+                    | Error::MismatchedTypes { .. }
+            ),
+            "unexpected error for `{code}`: {err}"
+        );
+    }
+}
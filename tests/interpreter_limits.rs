@@ -0,0 +1,177 @@
+use sage::{side_effects::Output, vm::*};
+use std::time::Duration;
+
+/// Run a program one step at a time, and get the limit it exceeded if it failed.
+fn run<I: Debuggable>(interpreter: &mut I, code: &I::Program) -> Result<(), Option<LimitExceeded>> {
+    while !interpreter.is_done() {
+        if let Err(err) = interpreter.step(code) {
+            return Err(match err.kind() {
+                RuntimeError::LimitExceeded(limit) => Some(*limit),
+                _ => None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Run a core program in every interpreter with the given limits, and check the result.
+fn check(program: &[CoreOp], limits: Limits, expected: Result<(), LimitExceeded>) {
+    let expected = expected.map_err(Some);
+    let core = CoreProgram(program.to_vec());
+    let std = StandardProgram(program.iter().cloned().map(StandardOp::CoreOp).collect());
+
+    let mut interpreter = CoreInterpreter::new(TestingDevice::default()).with_limits(limits);
+    assert_eq!(run(&mut interpreter, &core), expected);
+    let mut interpreter = StandardInterpreter::new(TestingDevice::default()).with_limits(limits);
+    assert_eq!(run(&mut interpreter, &std), expected);
+    let mut interpreter = FastInterpreter::new(TestingDevice::default()).with_limits(limits);
+    assert_eq!(
+        run(&mut interpreter, &Bytecode::new(&core).unwrap()),
+        expected
+    );
+}
+
+/// A program which loops forever.
+fn infinite_loop() -> Vec<CoreOp> {
+    vec![CoreOp::Set(vec![1]), CoreOp::While, CoreOp::End]
+}
+
+#[test]
+fn test_no_limits() {
+    let program = CoreProgram(vec![
+        CoreOp::Set(vec![5]),
+        CoreOp::Move(10000),
+        CoreOp::Store(1),
+        CoreOp::Put(Output::stdout_char()),
+    ]);
+    let device = CoreInterpreter::new(TestingDevice::default())
+        .with_limits(Limits::NONE)
+        .run(&program)
+        .unwrap();
+    assert_eq!(device.output_vals(), vec![5]);
+    check(&program.0, Limits::default(), Ok(()));
+}
+
+#[test]
+fn test_instruction_limit() {
+    let limits = Limits {
+        instructions: Some(100),
+        ..Limits::NONE
+    };
+    check(
+        &infinite_loop(),
+        limits,
+        Err(LimitExceeded::Instructions(100)),
+    );
+
+    // The limit is the number of instructions executed, not the length of the program.
+    let program = vec![CoreOp::Inc(1); 100];
+    check(&program, limits, Ok(()));
+    let program = vec![CoreOp::Inc(1); 101];
+    check(&program, limits, Err(LimitExceeded::Instructions(100)));
+
+    // The error message says which limit was exceeded.
+    let result = CoreInterpreter::new(TestingDevice::default())
+        .with_limits(limits)
+        .run(&CoreProgram(infinite_loop()));
+    assert_eq!(
+        result.map(|_| ()).map_err(|err| err.to_string()),
+        Err("exceeded the limit of 100 instructions".to_string())
+    );
+}
+
+#[test]
+fn test_tape_size_limit() {
+    let limits = Limits {
+        tape_size: Some(1000),
+        ..Limits::NONE
+    };
+    let program = vec![CoreOp::Move(999), CoreOp::Store(1)];
+    check(&program, limits, Ok(()));
+    let program = vec![CoreOp::Move(999), CoreOp::Store(2)];
+    check(&program, limits, Err(LimitExceeded::TapeSize(1000)));
+    let program = vec![CoreOp::Move(1000), CoreOp::Deref];
+    check(&program, limits, Err(LimitExceeded::TapeSize(1000)));
+
+    // A runaway allocation fails before the tape grows.
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![i64::MAX / 2])),
+        StandardOp::Alloc,
+    ]);
+    let mut interpreter = StandardInterpreter::new(TestingDevice::default()).with_limits(Limits {
+        tape_size: Some(100_000),
+        ..Limits::NONE
+    });
+    assert_eq!(
+        run(&mut interpreter, &program),
+        Err(Some(LimitExceeded::TapeSize(100_000)))
+    );
+    assert!(interpreter.tape().len() <= 100_000);
+
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![1000])),
+        StandardOp::Alloc,
+    ]);
+    let mut interpreter = StandardInterpreter::new(TestingDevice::default()).with_limits(Limits {
+        tape_size: Some(100_000),
+        ..Limits::NONE
+    });
+    assert_eq!(run(&mut interpreter, &program), Ok(()));
+}
+
+#[test]
+fn test_call_depth_limit() {
+    let recursive = |depth: i64| {
+        vec![
+            // Function 0 calls itself until the register is zero.
+            CoreOp::Function,
+            CoreOp::Load(1),
+            CoreOp::If,
+            CoreOp::Dec(1),
+            CoreOp::Store(1),
+            CoreOp::Set(vec![0]),
+            CoreOp::Call,
+            CoreOp::End,
+            CoreOp::Return,
+            CoreOp::End,
+            CoreOp::Set(vec![depth]),
+            CoreOp::Store(1),
+            CoreOp::Set(vec![0]),
+            CoreOp::Call,
+        ]
+    };
+    let limits = Limits {
+        call_depth: Some(10),
+        ..Limits::NONE
+    };
+    check(&recursive(9), limits, Ok(()));
+    check(&recursive(10), limits, Err(LimitExceeded::CallDepth(10)));
+}
+
+#[test]
+fn test_deref_depth_limit() {
+    let derefs = |depth: usize| {
+        let mut program = vec![CoreOp::Deref; depth];
+        program.extend(vec![CoreOp::Refer; depth]);
+        program
+    };
+    let limits = Limits {
+        deref_depth: Some(10),
+        ..Limits::NONE
+    };
+    check(&derefs(10), limits, Ok(()));
+    check(&derefs(11), limits, Err(LimitExceeded::DerefDepth(10)));
+}
+
+#[test]
+fn test_time_limit() {
+    let limits = Limits {
+        time: Some(Duration::from_millis(50)),
+        ..Limits::NONE
+    };
+    check(
+        &infinite_loop(),
+        limits,
+        Err(LimitExceeded::Time(Duration::from_millis(50))),
+    );
+}
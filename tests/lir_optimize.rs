@@ -0,0 +1,286 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{lir::*, parse::*, vm::*};
+use std::fs::{read_dir, read_to_string};
+
+use log::warn;
+
+const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
+
+fn int(n: i64) -> Expr {
+    Expr::ConstExpr(ConstExpr::Int(n))
+}
+
+fn bool(b: bool) -> Expr {
+    Expr::ConstExpr(ConstExpr::Bool(b))
+}
+
+fn print(expr: Expr) -> Expr {
+    expr.unop(Put::Display)
+}
+
+fn optimize(expr: Expr, optimizations: Optimizations) -> Expr {
+    with_large_stack(move || expr.optimize(optimizations))
+        .unwrap_or_else(|e| panic!("Could not optimize: {e}"))
+}
+
+/// Check that two expressions are the same.
+///
+/// Declarations aren't compared by `Expr`'s `PartialEq`, so this compares their debug output.
+fn assert_same(result: &Expr, expected: &Expr) {
+    assert_eq!(format!("{result:?}"), format!("{expected:?}"));
+}
+
+#[test]
+fn test_lir_fold_constants() {
+    let fold = Optimizations {
+        fold_constants: true,
+        ..Optimizations::NONE
+    };
+    let expr = int(2).add(int(3).mul(int(4)));
+    assert_eq!(optimize(expr.clone(), Optimizations::NONE), expr);
+    assert_eq!(optimize(expr, fold), int(14));
+
+    assert_eq!(optimize(int(7).rem(int(3)).neg(), fold), int(-1));
+    assert_eq!(optimize(int(2).pow(int(10)), fold), int(1024));
+    assert_eq!(
+        optimize(int(1).lt(int(2)).and(bool(true).not().not()), fold),
+        bool(true)
+    );
+    assert_eq!(optimize(int(6).bitxor(int(3)), fold), int(5));
+
+    // Operations which would fail or overflow at runtime are left alone.
+    for expr in [
+        int(1).div(int(0)),
+        int(i64::MAX).add(int(1)),
+        int(i64::MIN).neg(),
+        int(2).pow(int(-1)),
+    ] {
+        assert_eq!(optimize(expr.clone(), fold), expr);
+    }
+
+    // Comparisons of floats are compiled differently than they're evaluated.
+    let expr = Expr::ConstExpr(ConstExpr::Float(1.0)).lt(Expr::ConstExpr(ConstExpr::Float(2.0)));
+    assert_eq!(optimize(expr.clone(), fold), expr);
+}
+
+#[test]
+fn test_lir_remove_dead_branches() {
+    let dead_branches = Optimizations {
+        remove_dead_branches: true,
+        ..Optimizations::NONE
+    };
+    let expr = bool(true).if_then(print(int(1)), print(int(2)));
+    assert_eq!(optimize(expr.clone(), Optimizations::NONE), expr);
+    assert_eq!(optimize(expr, dead_branches), print(int(1)));
+
+    assert_eq!(
+        optimize(bool(false).while_loop(print(int(1))), dead_branches),
+        Expr::ConstExpr(ConstExpr::None)
+    );
+    assert_eq!(
+        optimize(
+            Expr::When(
+                ConstExpr::Bool(false),
+                Box::new(int(1)),
+                Box::new(bool(true))
+            ),
+            dead_branches
+        ),
+        bool(true)
+    );
+
+    let expr = Expr::Match(
+        Box::new(int(3)),
+        vec![
+            (Pattern::ConstExpr(ConstExpr::Int(1)), print(int(10))),
+            (Pattern::ConstExpr(ConstExpr::Int(3)), print(int(30))),
+            (Pattern::Wildcard, print(int(0))),
+        ],
+    );
+    assert_eq!(optimize(expr, dead_branches), print(int(30)));
+
+    let expr = Expr::Match(
+        Box::new(int(3)),
+        vec![
+            (Pattern::ConstExpr(ConstExpr::Int(1)), print(int(10))),
+            (
+                Pattern::Symbol(Mutability::Immutable, "n".to_string()),
+                print(Expr::var("n")),
+            ),
+        ],
+    );
+    assert_same(
+        &optimize(expr, dead_branches),
+        &Expr::let_var(
+            "n",
+            Mutability::Immutable,
+            None,
+            int(3),
+            print(Expr::var("n")),
+        ),
+    );
+
+    // A condition which isn't a literal can't select a branch.
+    let expr = Expr::let_var(
+        "c",
+        Mutability::Immutable,
+        None,
+        bool(true),
+        Expr::var("c").if_then(print(int(1)), print(int(2))),
+    );
+    assert_same(&optimize(expr.clone(), dead_branches), &expr);
+}
+
+#[test]
+fn test_lir_remove_unused_variables() {
+    let unused_vars = Optimizations {
+        remove_unused_variables: true,
+        ..Optimizations::NONE
+    };
+    let expr = Expr::let_vars(
+        vec![
+            ("a", Mutability::Immutable, None, int(1)),
+            ("b", Mutability::Immutable, None, int(2)),
+            ("c", Mutability::Mutable, None, print(int(3))),
+        ],
+        print(Expr::var("b")),
+    );
+    assert_same(&optimize(expr.clone(), Optimizations::NONE), &expr);
+    // The initializer of `c` has side effects, so it must still run.
+    assert_same(
+        &optimize(expr, unused_vars),
+        &Expr::let_vars(
+            vec![
+                ("b", Mutability::Immutable, None, int(2)),
+                ("c", Mutability::Mutable, None, print(int(3))),
+            ],
+            print(Expr::var("b")),
+        ),
+    );
+
+    let expr = Expr::let_var("a", Mutability::Immutable, None, int(1), print(int(2)));
+    assert_eq!(optimize(expr, unused_vars), print(int(2)));
+}
+
+#[test]
+fn test_lir_inline_procedures() {
+    let square = Procedure::new(
+        Some("square".to_string()),
+        vec![("x".to_string(), Mutability::Immutable, Type::Int)],
+        Type::Int,
+        Expr::var("x").mul(Expr::var("x")),
+    );
+    let expr = Expr::let_proc(
+        "square",
+        square,
+        Expr::let_var(
+            "a",
+            Mutability::Immutable,
+            None,
+            int(5),
+            Expr::Many(vec![
+                print(Expr::var("square").app(vec![int(3)])),
+                print(Expr::var("square").app(vec![Expr::var("a")])),
+                print(Expr::var("square").app(vec![Expr::var("a").add(int(1))])),
+            ]),
+        ),
+    );
+
+    // The procedure and the variable are declared together.
+    let body = |expr: Expr| match expr {
+        Expr::Declare(_, body) => *body,
+        expr => panic!("expected a declaration, found {expr}"),
+    };
+    // Calls with other arguments are left alone.
+    let not_inlined = print(Expr::var("square").app(vec![Expr::var("a").add(int(1))]));
+    let inline = Optimizations {
+        inline_procedures: true,
+        ..Optimizations::NONE
+    };
+    assert_eq!(
+        body(optimize(expr.clone(), inline)),
+        Expr::Many(vec![
+            print(int(3).mul(int(3))),
+            print(
+                Expr::var("a")
+                    .as_type(Type::Int)
+                    .mul(Expr::var("a").as_type(Type::Int)),
+            ),
+            not_inlined.clone(),
+        ])
+    );
+
+    // Inlining a call with literal arguments lets it be folded.
+    assert_eq!(
+        body(optimize(expr, Optimizations::ALL)),
+        Expr::Many(vec![
+            print(int(9)),
+            print(
+                Expr::var("a")
+                    .as_type(Type::Int)
+                    .mul(Expr::var("a").as_type(Type::Int)),
+            ),
+            not_inlined.clone(),
+        ])
+    );
+}
+
+#[test]
+fn test_lir_optimize_type_errors() {
+    // The branch which is removed still has to type check.
+    let expr = bool(true).if_then(int(1), int(2).add(bool(false)));
+    assert!(with_large_stack(move || expr.optimize(Optimizations::ALL)).is_err());
+}
+
+#[test]
+fn test_lir_optimize_frontend_examples() {
+    with_large_stack(test_lir_optimize_frontend_examples_helper);
+}
+
+/// Compile a program and run it in the interpreter, and get its output or the kind of its error.
+fn run(lir_code: Expr) -> Option<Result<Vec<i64>, RuntimeError>> {
+    let vm_code = match lir_code.compile().ok()? {
+        Ok(core_asm_code) => core_asm_code.assemble(CALL_STACK_SIZE).map(Ok),
+        Err(std_asm_code) => std_asm_code.assemble(CALL_STACK_SIZE).map(Err),
+    }
+    .unwrap();
+    Some(
+        match vm_code {
+            Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+            Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT)).run(&vm_code),
+        }
+        .map(|device| device.output_vals())
+        .map_err(|err| err.kind().clone()),
+    )
+}
+
+/// Check that optimizing every example doesn't change the output of the interpreter.
+fn test_lir_optimize_frontend_examples_helper() {
+    for entry in read_dir("examples/frontend/").unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() || path.extension().and_then(|ext| ext.to_str()) != Some("sg") {
+            continue;
+        }
+        warn!("Starting test for `{path:?}`");
+
+        let frontend_src = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
+        // Programs which don't compile have nothing to optimize.
+        let Ok(frontend_code) = parse_frontend(&frontend_src, path.to_str()) else {
+            continue;
+        };
+        let Some(expected) = run(frontend_code.clone()) else {
+            continue;
+        };
+        let optimized = frontend_code
+            .optimize(Optimizations::ALL)
+            .unwrap_or_else(|e| panic!("Could not optimize `{path:?}`: {e}"));
+        assert_eq!(
+            run(optimized),
+            Some(expected),
+            "Optimizing `{path:?}` changed its output"
+        );
+    }
+}
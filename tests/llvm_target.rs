@@ -0,0 +1,369 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use log::warn;
+use sage::{
+    lir::Compile,
+    parse::*,
+    side_effects::{FFIBinding, Output},
+    targets::*,
+    vm::{CoreOp, StandardOp, StandardProgram},
+};
+use std::{
+    fs::{read_dir, read_to_string},
+    io::Write,
+    path::PathBuf,
+    process::Command,
+};
+
+const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
+
+/// A foreign function for the tests, which adds the two cells on the top of the channel.
+const FFI_ADD: &str = "
+#include <stdint.h>
+extern int64_t *ffi_ptr;
+void __add(void) {
+    int64_t b = *(ffi_ptr--);
+    int64_t a = *(ffi_ptr--);
+    *(++ffi_ptr) = a + b;
+}
+";
+
+/// Is the given program installed?
+fn has_compiler(compiler: &str) -> bool {
+    Command::new(compiler)
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Are the LLVM tools and the C compiler needed to build LLVM IR installed?
+fn has_llvm() -> bool {
+    for compiler in ["opt", "llc", "gcc"] {
+        if !has_compiler(compiler) {
+            warn!("Could not find `{compiler}`. Skipping the LLVM target tests.");
+            return false;
+        }
+    }
+    true
+}
+
+/// The flags to use opaque pointers with the installed version of LLVM.
+/// LLVM 14 only parses opaque pointers when they're enabled, and later versions use them by default.
+fn opaque_pointers() -> Vec<&'static str> {
+    let output = Command::new("llc").arg("--version").output().unwrap();
+    if String::from_utf8_lossy(&output.stdout).contains("LLVM version 14.") {
+        vec!["-opaque-pointers"]
+    } else {
+        vec![]
+    }
+}
+
+/// Optimize, compile, and link LLVM IR with any C sources, and run it with the given input.
+fn run_llvm_code(
+    name: &str,
+    llvm_code: &str,
+    c_sources: &[&str],
+    input: &str,
+) -> std::process::Output {
+    let ll_path = format!("tmp_llvm_{name}.ll");
+    let opt_path = format!("tmp_llvm_{name}.opt.ll");
+    let asm_path = format!("tmp_llvm_{name}.s");
+    let exe_path = format!("tmp_llvm_{name}.exe");
+    std::fs::write(&ll_path, llvm_code).unwrap();
+    let mut c_paths = vec![];
+    for (i, src) in c_sources.iter().enumerate() {
+        let path = format!("tmp_llvm_{name}_{i}.c");
+        std::fs::write(&path, src).unwrap();
+        c_paths.push(path);
+    }
+
+    let flags = opaque_pointers();
+    for (program, args) in [
+        (
+            "opt",
+            [&flags[..], &["-O2", "-S", &ll_path, "-o", &opt_path]].concat(),
+        ),
+        (
+            "llc",
+            [
+                &flags[..],
+                &["-relocation-model=pic", &opt_path, "-o", &asm_path],
+            ]
+            .concat(),
+        ),
+        (
+            "gcc",
+            [
+                &[asm_path.as_str()],
+                &c_paths.iter().map(String::as_str).collect::<Vec<_>>()[..],
+                &["-o", &exe_path, "-lm"],
+            ]
+            .concat(),
+        ),
+    ] {
+        let output = Command::new(program).args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "Could not build LLVM code for `{name}`: {output:?}"
+        );
+    }
+
+    let mut exe = Command::new(format!("./{exe_path}"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Programs which exit without reading their input close the pipe before we can write to it.
+    let _ = exe.stdin.as_mut().unwrap().write_all(input.as_bytes());
+    let output = exe.wait_with_output().unwrap();
+    for path in [ll_path, opt_path, asm_path, exe_path]
+        .into_iter()
+        .chain(c_paths)
+    {
+        std::fs::remove_file(path).unwrap();
+    }
+    output
+}
+
+/// Compile virtual machine code to LLVM IR, and run it with the given input.
+fn run_vm_code(name: &str, vm_code: &str, input: &str) -> std::process::Output {
+    let llvm_code = match parse_vm(vm_code).unwrap() {
+        Ok(vm_code) => Llvm::default().build_core(&vm_code.flatten()),
+        Err(vm_code) => Llvm::default().build_std(&vm_code.flatten()),
+    }
+    .unwrap();
+    run_llvm_code(name, &llvm_code, &[], input)
+}
+
+#[test]
+fn test_llvm_target_io() {
+    if !has_llvm() {
+        return;
+    }
+    let output = run_vm_code(
+        "io",
+        "get stdin.int mul 0 put stdout.int set 32 put stdout.char
+        get stdin.float put stdout.float set 32 put stdout.char
+        get stdin.char put stdout.char get stdin.char put stdout.int",
+        " -42 -3.25x",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "-42 -3.2 x0");
+}
+
+#[test]
+fn test_llvm_target_arithmetic() {
+    if !has_llvm() {
+        return;
+    }
+    let output = run_vm_code(
+        "arithmetic",
+        "set -1 sav set -9223372036854775807 dec div put stdout.int set 32 put stdout.char
+        set 3 sav set -7 rem put stdout.int set 32 put stdout.char
+        set 65 sav set 1 lsh put stdout.int set 32 put stdout.char
+        set 1 sav set -8 arsh put stdout.int set 32 put stdout.char
+        set 2 sav set 5 bitwise-nand put stdout.int",
+        "",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "-9223372036854775808 -1 2 -4 -1"
+    );
+}
+
+#[test]
+fn test_llvm_target_floats() {
+    if !has_llvm() {
+        return;
+    }
+    let output = run_vm_code(
+        "floats",
+        "set-f 10.0 sav set-f 2.0 pow put stdout.float set 32 put stdout.char
+        set-f 2.0 sav set-f -7.5 rem-f put stdout.float set 32 put stdout.char
+        set-f 0.0 sav set-f 1.0 div-f put stdout.float set 32 put stdout.char
+        set-f 0.5 sin sav set-f 0.5 cos mul-f put stdout.float set 32 put stdout.char
+        set-f -2.75 to-int put stdout.int set 32 put stdout.char
+        set-f 10000000000.0 sav mul-f sav mul-f to-int put stdout.int",
+        "",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1024.0 -1.5 inf 0.4 -2 9223372036854775807"
+    );
+}
+
+#[test]
+fn test_llvm_target_ffi() {
+    if !has_llvm() {
+        return;
+    }
+    // The virtual machine code syntax can't call foreign functions, so build the program directly.
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![2])),
+        StandardOp::Poke,
+        StandardOp::CoreOp(CoreOp::Set(vec![3])),
+        StandardOp::Poke,
+        StandardOp::Call(FFIBinding::new("add".to_string(), 2, 1)),
+        StandardOp::Peek,
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+    ]);
+    let llvm_code = Llvm::default().build_std(&program).unwrap();
+    let output = run_llvm_code("ffi", &llvm_code, &[FFI_ADD], "");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "5");
+}
+
+#[test]
+fn test_llvm_target_runtime_errors() {
+    if !has_llvm() {
+        return;
+    }
+    let output = run_vm_code("call", "set 1 put stdout.int set 5 call", "");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "function 5 not defined\n"
+    );
+
+    let output = run_vm_code("free", "set 4 alloc sav free res free", "");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("double free of address "), "{stderr}");
+
+    let output = run_vm_code("alloc", "set -3 alloc", "");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "cannot allocate a negative number of cells: -3\n"
+    );
+
+    for op in ["div", "rem"] {
+        let code = format!("set 1 put stdout.int set 0 sav set 5 {op} put stdout.int");
+        let output = run_vm_code(op, &code, "");
+        assert!(!output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "1");
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "division by zero\n"
+        );
+    }
+}
+
+#[test]
+fn test_llvm_target_frontend_examples() {
+    if !has_llvm() {
+        return;
+    }
+    with_large_stack(test_llvm_target_frontend_examples_helper);
+}
+
+fn test_llvm_target_frontend_examples_helper() {
+    let mut total_failures: i32 = 0;
+    let mut total_attempts = 0;
+
+    for entry in read_dir("examples/frontend/").unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+        warn!("Starting test for `{path:?}`");
+        if path.is_file()
+            && matches!(
+                path.extension().map(|p| p
+                    .to_str()
+                    .expect("Couldn't get file extension of example code")
+                    .as_bytes()),
+                Some(b"sg") | None
+            )
+        {
+            let file_name = path
+                .file_name()
+                .unwrap_or_else(|| panic!("Could not get file name of path `{path:?}`"))
+                .to_str()
+                .unwrap_or_else(|| panic!("Could not get file name of path `{path:?}`"))
+                .to_string();
+            let correct_output_path = PathBuf::from("examples/test-output")
+                .join(file_name.clone())
+                .with_extension("txt");
+            let correct_error_path = PathBuf::from("examples/test-output")
+                .join(file_name.clone())
+                .with_extension("error.txt");
+            let correct_error = match read_to_string(&correct_error_path) {
+                Ok(contents) => Some(contents.replace("\r\n", "\n")),
+                Err(_) => None,
+            };
+            let correct_output = match read_to_string(&correct_output_path) {
+                Ok(contents) => contents.replace("\r\n", "\n"),
+                Err(_) if correct_error.is_none() => {
+                    warn!("Could not read output text file `{correct_output_path:?}` to compare against. Skipping this test.");
+                    continue;
+                }
+                Err(_) => String::new(),
+            };
+
+            let frontend_src = read_to_string(&path)
+                .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
+            let frontend_code = match parse_frontend(&frontend_src, path.to_str()) {
+                Ok(frontend_code) => frontend_code,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not parse `{path:?}`: {e}"),
+                },
+            };
+            drop(frontend_src);
+            let asm_code = match frontend_code.compile() {
+                Ok(asm_code) => asm_code,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not assemble code in `{path:?}`: {e}"),
+                },
+            };
+
+            let vm_code = match asm_code {
+                Ok(core_asm_code) => core_asm_code.assemble(CALL_STACK_SIZE).map(Ok),
+                Err(std_asm_code) => std_asm_code.assemble(CALL_STACK_SIZE).map(Err),
+            }
+            .unwrap();
+
+            let llvm_code = match vm_code {
+                Ok(vm_code) => Llvm::default().build_core(&vm_code.flatten()).unwrap(),
+                Err(vm_code) => Llvm::default().build_std(&vm_code.flatten()).unwrap(),
+            };
+
+            // Run the program with the input, and confirm that the output matches the expected output.
+            let exe_output = run_llvm_code(&format!("code_{file_name}"), &llvm_code, &[], INPUT);
+            if correct_error.is_some() {
+                // Programs with runtime errors must exit unsuccessfully.
+                if exe_output.status.success() {
+                    warn!("Expected a runtime error for program `{path:?}`");
+                    total_failures += 1;
+                }
+                total_attempts += 1;
+                continue;
+            }
+
+            let output = String::from_utf8_lossy(&exe_output.stdout);
+            if output != correct_output {
+                warn!(
+                    "Output did not match correct output for program `{path:?}`:\n{output}\n  !=   \n{correct_output}"
+                );
+                total_failures += 1;
+            }
+            total_attempts += 1;
+        }
+    }
+
+    // We just use a threshold of 30% failure, because the frontend examples print out pointers
+    // And the frontend examples all print pointers differently. Same for floats.
+    if total_failures as f64 / total_attempts as f64 > 0.3 {
+        panic!(
+            "Too many failures in frontend examples: {total_failures} failures out of {total_attempts} attempts"
+        );
+    }
+}
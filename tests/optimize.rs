@@ -0,0 +1,163 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{lir::Compile, parse::*, side_effects::Output, vm::*};
+use std::fs::{read_dir, read_to_string};
+
+use log::warn;
+
+const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
+
+#[test]
+fn test_optimize_moves() {
+    let program = CoreProgram(vec![
+        CoreOp::Move(3),
+        CoreOp::Comment("moving back".to_string()),
+        CoreOp::Move(-1),
+        CoreOp::Move(-2),
+        CoreOp::Deref,
+        CoreOp::Refer,
+        CoreOp::Move(5),
+        CoreOp::Deref,
+        CoreOp::Move(1),
+        CoreOp::Move(-1),
+        CoreOp::Refer,
+        CoreOp::Move(-4),
+    ]);
+    assert_eq!(program.clone().optimize(0).0, program.0);
+    assert_eq!(
+        program.optimize(1).0,
+        vec![CoreOp::Comment("moving back".to_string()), CoreOp::Move(1),]
+    );
+}
+
+#[test]
+fn test_optimize_register() {
+    let program = CoreProgram(vec![
+        CoreOp::Set(vec![1]),
+        CoreOp::Set(vec![5]),
+        CoreOp::Inc(1),
+        CoreOp::Neg(1),
+        CoreOp::Store(1),
+        CoreOp::Load(1),
+        CoreOp::Store(1),
+        CoreOp::Move(1),
+        CoreOp::Load(2),
+        CoreOp::Store(1),
+        CoreOp::Put(Output::stdout_int()),
+        CoreOp::Set(vec![i64::MAX]),
+        CoreOp::Inc(1),
+    ]);
+    // The first level doesn't touch the register.
+    assert_eq!(program.clone().optimize(1).0, program.0);
+    assert_eq!(
+        program.optimize(2).0,
+        vec![
+            CoreOp::Set(vec![-6]),
+            CoreOp::Store(1),
+            CoreOp::Move(1),
+            CoreOp::Load(2),
+            CoreOp::Put(Output::stdout_int()),
+            // Folding this would overflow.
+            CoreOp::Set(vec![i64::MAX]),
+            CoreOp::Inc(1),
+        ]
+    );
+}
+
+#[test]
+fn test_optimize_unreachable() {
+    let program = CoreProgram(vec![
+        CoreOp::Function,
+        CoreOp::Comment("the function".to_string()),
+        CoreOp::If,
+        CoreOp::Return,
+        CoreOp::Set(vec![1]),
+        CoreOp::Comment("@debug 0".to_string()),
+        CoreOp::While,
+        CoreOp::Dec(1),
+        CoreOp::End,
+        CoreOp::Else,
+        CoreOp::Return,
+        CoreOp::Set(vec![2]),
+        CoreOp::End,
+        CoreOp::Return,
+        CoreOp::Function,
+        CoreOp::Return,
+        CoreOp::End,
+        CoreOp::End,
+    ]);
+    assert_eq!(
+        program.optimize(3).0,
+        vec![
+            CoreOp::Function,
+            CoreOp::If,
+            CoreOp::Return,
+            CoreOp::Comment("@debug 0".to_string()),
+            CoreOp::Else,
+            CoreOp::Return,
+            CoreOp::End,
+            CoreOp::Return,
+            // The nested function definition is still reachable.
+            CoreOp::Function,
+            CoreOp::Return,
+            CoreOp::End,
+            CoreOp::End,
+        ]
+    );
+}
+
+#[test]
+fn test_optimize_frontend_examples() {
+    with_large_stack(test_optimize_frontend_examples_helper);
+}
+
+/// Run a program in the interpreter, and get its output or the kind of its error.
+/// The state of the interpreter isn't compared, because optimizing moves the instructions.
+fn run(vm_code: &Result<CoreProgram, StandardProgram>) -> Result<Vec<i64>, RuntimeError> {
+    match vm_code {
+        Ok(vm_code) => CoreInterpreter::new(TestingDevice::new(INPUT)).run(vm_code),
+        Err(vm_code) => StandardInterpreter::new(TestingDevice::new(INPUT)).run(vm_code),
+    }
+    .map(|device| device.output_vals())
+    .map_err(|err| err.kind().clone())
+}
+
+/// Check that optimizing every example doesn't change the output of the interpreter.
+fn test_optimize_frontend_examples_helper() {
+    for entry in read_dir("examples/frontend/").unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() || path.extension().and_then(|ext| ext.to_str()) != Some("sg") {
+            continue;
+        }
+        warn!("Starting test for `{path:?}`");
+
+        let frontend_src = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
+        // Programs which don't compile have nothing to optimize.
+        let Ok(frontend_code) = parse_frontend(&frontend_src, path.to_str()) else {
+            continue;
+        };
+        let Ok(asm_code) = frontend_code.compile() else {
+            continue;
+        };
+        let vm_code = match asm_code {
+            Ok(core_asm_code) => core_asm_code.assemble(CALL_STACK_SIZE).map(Ok),
+            Err(std_asm_code) => std_asm_code.assemble(CALL_STACK_SIZE).map(Err),
+        }
+        .unwrap();
+
+        let expected = run(&vm_code);
+        for level in 1..=MAX_OPTIMIZATION_LEVEL {
+            let optimized = match vm_code.clone() {
+                Ok(vm_code) => Ok(vm_code.optimize(level)),
+                Err(vm_code) => Err(vm_code.optimize(level)),
+            };
+            assert_eq!(
+                run(&optimized),
+                expected,
+                "Optimizing `{path:?}` at level {level} changed its output"
+            );
+        }
+    }
+}
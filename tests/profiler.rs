@@ -0,0 +1,194 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{
+    lir::{Compile, DebugInfo},
+    parse::parse_frontend,
+    profiler::Profiler,
+    vm::*,
+};
+
+const FACTORIAL: &str = "\
+def fact(n: Int): Int {
+    if n <= 1 {
+        return 1;
+    }
+    return n * square(1) * fact(n - 1);
+}
+
+def square(n: Int): Int {
+    return n * n;
+}
+
+println(fact(5));
+";
+
+/// Compile the factorial program to core virtual machine code, with its debug information.
+fn compile_factorial() -> (CoreProgram, DebugInfo) {
+    let (program, debug_info) = parse_frontend(FACTORIAL, Some("fact.sg"))
+        .unwrap()
+        .compile_with_debug_info()
+        .unwrap();
+    let program = program
+        .expect("factorial should compile to core code")
+        .assemble(CALL_STACK_SIZE)
+        .unwrap();
+    (program, debug_info)
+}
+
+/// Profile a program to the end, and return the profiler and the interpreter.
+fn profile<I: Debuggable>(
+    mut interpreter: I,
+    code: &I::Program,
+    debug_info: &DebugInfo,
+) -> (Profiler, I) {
+    let mut profiler = Profiler::new(debug_info);
+    profiler.run(&mut interpreter, code).unwrap();
+    (profiler, interpreter)
+}
+
+#[test]
+fn test_profiler_procedures() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile_factorial();
+        // There's a procedure for each function index in the program.
+        let functions = program
+            .0
+            .iter()
+            .filter(|op| matches!(op, CoreOp::Function))
+            .count();
+        assert_eq!(debug_info.procedures().len(), functions);
+
+        let names = debug_info
+            .procedures()
+            .iter()
+            .map(|procedure| procedure.name())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"fact"));
+        assert!(names.contains(&"square"));
+        for procedure in debug_info.procedures() {
+            assert!(!procedure.mangled_name.is_empty());
+        }
+    });
+}
+
+#[test]
+fn test_profiler_counts() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile_factorial();
+        let code = program.without_comments();
+        let (profiler, interpreter) = profile(
+            CoreInterpreter::new(TestingDevice::default()),
+            &code,
+            &debug_info,
+        );
+        let device = interpreter.run(&CoreProgram(vec![])).unwrap();
+        assert_eq!(device.output_str(), "120\n");
+
+        // The functions account for every instruction executed.
+        let functions = profiler.functions();
+        let total = functions.iter().map(|(_, count)| count).sum::<u64>();
+        assert_eq!(total, profiler.total());
+        assert!(functions.iter().any(|(function, _)| function.is_none()));
+        let names = functions
+            .iter()
+            .filter_map(|(function, _)| *function)
+            .map(|index| profiler.function_name(index))
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"fact".to_string()));
+        assert!(names.contains(&"square".to_string()));
+
+        // `fact` is called 5 times, and calls `square` 4 times.
+        let instructions = profiler.instructions::<CoreInterpreter<TestingDevice>>(&code);
+        assert!(instructions.contains(&("call".to_string(), 9)));
+        assert!(instructions.contains(&("ret".to_string(), 9)));
+        let total = instructions.iter().map(|(_, count)| count).sum::<u64>();
+        assert_eq!(total, profiler.total());
+
+        let report = profiler.report::<CoreInterpreter<TestingDevice>>(&code);
+        assert!(report.starts_with(&format!("{} instructions executed\n", profiler.total())));
+        assert!(report.contains("  <main>\n"));
+        assert!(report.contains("  call\n"));
+    });
+}
+
+#[test]
+fn test_profiler_collapsed_stacks() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile_factorial();
+        let code = program.without_comments();
+        let (profiler, _) = profile(
+            CoreInterpreter::new(TestingDevice::default()),
+            &code,
+            &debug_info,
+        );
+
+        let stacks = profiler.collapsed_stacks();
+        let mut total = 0;
+        for line in stacks.lines() {
+            let (stack, count) = line.rsplit_once(' ').unwrap();
+            assert!(stack.starts_with("<main>"));
+            total += count.parse::<u64>().unwrap();
+        }
+        assert_eq!(total, profiler.total());
+        assert!(stacks.contains("\n<main>;fact;fact;fact;fact;fact "));
+        assert!(stacks.contains("\n<main>;fact;fact;fact;fact;square "));
+        assert!(!stacks.contains("fact;fact;fact;fact;fact;fact"));
+    });
+}
+
+#[test]
+fn test_profiler_interpreters() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile_factorial();
+        let code = program.without_comments();
+        let (core, _) = profile(
+            CoreInterpreter::new(TestingDevice::default()),
+            &code,
+            &debug_info,
+        );
+
+        // The standard interpreter executes the same instructions.
+        let std_code = StandardProgram::from(code.clone());
+        let (std, _) = profile(
+            StandardInterpreter::new(TestingDevice::default()),
+            &std_code,
+            &debug_info,
+        );
+        assert_eq!(std.total(), core.total());
+        assert_eq!(std.collapsed_stacks(), core.collapsed_stacks());
+
+        // The fast interpreter fuses instructions, but calls the same functions.
+        let bytecode = Bytecode::new(&code).unwrap();
+        let (fast, _) = profile(
+            FastInterpreter::new(TestingDevice::default()),
+            &bytecode,
+            &debug_info,
+        );
+        assert!(fast.total() <= core.total());
+        let stacks = |profiler: &Profiler| {
+            profiler
+                .collapsed_stacks()
+                .lines()
+                .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(stacks(&fast), stacks(&core));
+    });
+}
+
+#[test]
+fn test_profiler_unknown_functions() {
+    with_large_stack(|| {
+        let (program, _) = compile_factorial();
+        let code = program.without_comments();
+        // Without debug information, the functions are named by their indices.
+        let (profiler, _) = profile(
+            CoreInterpreter::new(TestingDevice::default()),
+            &code,
+            &DebugInfo::default(),
+        );
+        assert_eq!(profiler.function_name(0), "function #0");
+        assert!(profiler.collapsed_stacks().contains(";function #"));
+    });
+}
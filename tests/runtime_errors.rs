@@ -0,0 +1,214 @@
+use sage::{
+    lir::Compile,
+    parse::parse_frontend,
+    side_effects::{FFIBinding, Input, Output},
+    vm::*,
+};
+
+mod common;
+
+/// Run a core program in every core interpreter, and check that they fail with the same kind of error.
+fn check_core(program: Vec<CoreOp>, expected: RuntimeError) -> Vec<RuntimeError> {
+    let program = CoreProgram(program);
+    let errors = vec![
+        CoreInterpreter::new(TestingDevice::default())
+            .run(&program)
+            .unwrap_err(),
+        FastInterpreter::new(TestingDevice::default())
+            .run(&program)
+            .unwrap_err(),
+    ];
+    for err in &errors {
+        assert_eq!(err.kind(), &expected);
+    }
+    errors
+}
+
+/// Run a standard program, and get the error it fails with.
+fn run_std(program: Vec<StandardOp>) -> RuntimeError {
+    StandardInterpreter::new(TestingDevice::default())
+        .run(&StandardProgram(program))
+        .unwrap_err()
+}
+
+#[test]
+fn test_divide_by_zero() {
+    for op in [CoreOp::Div(1), CoreOp::Rem(1)] {
+        let errors = check_core(
+            vec![
+                CoreOp::Set(vec![0]),
+                CoreOp::Store(1),
+                CoreOp::Set(vec![5]),
+                op.clone(),
+            ],
+            RuntimeError::DivideByZero,
+        );
+        for err in errors {
+            assert_eq!(err.to_string(), "division by zero");
+            assert_eq!(err.instruction(), Some(3));
+        }
+    }
+
+    // Only the cells which are divisors are checked.
+    let program = CoreProgram(vec![
+        CoreOp::Set(vec![3]),
+        CoreOp::Store(1),
+        CoreOp::Set(vec![7]),
+        CoreOp::Div(1),
+        CoreOp::Put(Output::stdout_int()),
+    ]);
+    let device = FastInterpreter::new(TestingDevice::default())
+        .run(&program)
+        .unwrap();
+    assert_eq!(device.output_str(), "2");
+}
+
+#[test]
+fn test_invalid_call() {
+    let errors = check_core(
+        vec![CoreOp::Set(vec![3]), CoreOp::Call],
+        RuntimeError::InvalidCall(3),
+    );
+    for err in errors {
+        assert_eq!(err.to_string(), "function 3 not defined");
+        assert_eq!(err.instruction(), Some(1));
+        // The failed call isn't left on the call stack.
+        assert_eq!(err.state().unwrap().call_stack, Vec::<usize>::new());
+    }
+}
+
+#[test]
+fn test_deref_errors() {
+    check_core(
+        vec![CoreOp::Set(vec![-4]), CoreOp::Store(1), CoreOp::Deref],
+        RuntimeError::DerefOutOfBounds(-4),
+    );
+    check_core(vec![CoreOp::Refer], RuntimeError::EmptyDerefStack);
+    check_core(
+        vec![CoreOp::Move(1), CoreOp::Move(-2)],
+        RuntimeError::NegativePointer(1),
+    );
+}
+
+#[test]
+fn test_state_snapshot() {
+    let errors = check_core(
+        vec![
+            CoreOp::Set(vec![7]),
+            CoreOp::Move(2),
+            CoreOp::Store(1),
+            CoreOp::Deref,
+            CoreOp::Refer,
+            CoreOp::Refer,
+        ],
+        RuntimeError::EmptyDerefStack,
+    );
+    for err in errors {
+        let state = err.state().unwrap();
+        assert_eq!(state.instruction, 5);
+        assert_eq!(state.pointer, 2);
+        assert_eq!(state.register, vec![7]);
+        assert_eq!(state.tape[2], 7);
+        assert!(state.deref_stack.is_empty());
+    }
+
+    // Annotating an error twice keeps the first state.
+    let mut interpreter = CoreInterpreter::new(TestingDevice::default());
+    let err = RuntimeError::DivideByZero.with_state(&interpreter);
+    interpreter
+        .step(&CoreProgram(vec![CoreOp::Move(3)]))
+        .unwrap();
+    assert_eq!(err.clone().with_state(&interpreter), err);
+    assert_eq!(err.state().unwrap().pointer, 0);
+}
+
+#[test]
+fn test_heap_errors() {
+    let err = run_std(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![-1])),
+        StandardOp::Alloc,
+    ]);
+    assert_eq!(err.kind(), &RuntimeError::NegativeAlloc(-1));
+
+    let err = run_std(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![4])),
+        StandardOp::Alloc,
+        StandardOp::CoreOp(CoreOp::Store(1)),
+        StandardOp::Free,
+        StandardOp::CoreOp(CoreOp::Load(1)),
+        StandardOp::Free,
+    ]);
+    assert!(matches!(err.kind(), RuntimeError::DoubleFree(_)));
+    assert_eq!(err.instruction(), Some(5));
+
+    let err = run_std(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![12345])),
+        StandardOp::Free,
+    ]);
+    assert_eq!(err.kind(), &RuntimeError::InvalidFree(12345));
+}
+
+#[test]
+fn test_frontend_double_free() {
+    const CODE: &str = r#"
+        use std::mem::{allocate, deallocate};
+        let a = allocate<Int>(4);
+        deallocate<Int>(a);
+        deallocate<Int>(a);
+    "#;
+
+    // Programs compiled for the standard variant use the interpreter's allocator.
+    let err = common::with_large_stack(|| {
+        let program = match parse_frontend(CODE, None).unwrap().compile().unwrap() {
+            Ok(_) => panic!("expected a standard program"),
+            Err(std) => std.assemble(8192).unwrap(),
+        };
+        StandardInterpreter::new(TestingDevice::default())
+            .run(&program)
+            .unwrap_err()
+    });
+    assert!(matches!(err.kind(), RuntimeError::DoubleFree(_)));
+
+    // Programs for core-only targets use the fallback allocator, which panics and never returns.
+    let err = common::with_large_stack(|| {
+        let program = match parse_frontend(CODE, None).unwrap().compile_core_only() {
+            Ok(Ok(core)) => core.assemble(8192).unwrap(),
+            _ => panic!("expected a core program"),
+        };
+        let limits = Limits {
+            instructions: Some(1_000_000),
+            ..Limits::NONE
+        };
+        CoreInterpreter::new(TestingDevice::default())
+            .with_limits(limits)
+            .run(&program)
+            .unwrap_err()
+    });
+    assert!(matches!(
+        err.kind(),
+        RuntimeError::LimitExceeded(LimitExceeded::Instructions(_))
+    ));
+}
+
+#[test]
+fn test_device_errors() {
+    let err = run_std(vec![StandardOp::Peek]);
+    assert_eq!(err.kind(), &RuntimeError::EmptyFFIChannel);
+    assert_eq!(err.to_string(), "ffi channel is empty");
+
+    let ffi = FFIBinding::new("missing".to_string(), 1, 1);
+    let err = run_std(vec![StandardOp::Call(ffi.clone())]);
+    assert_eq!(err.kind(), &RuntimeError::FFIUnbound(ffi));
+
+    let err = run_std(vec![StandardOp::CoreOp(CoreOp::Get(Input::stdin_char()))]);
+    assert!(matches!(err.kind(), RuntimeError::DeviceError(_)));
+    assert_eq!(err.instruction(), Some(0));
+}
+
+#[test]
+fn test_invalid_program() {
+    let err = FastInterpreter::new(TestingDevice::default())
+        .run(&CoreProgram(vec![CoreOp::If]))
+        .unwrap_err();
+    assert!(matches!(err, RuntimeError::InvalidProgram(_)));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{run, with_large_stack};
+use sage::{lir::*, parse::parse_frontend};
+
+#[test]
+fn test_int_shifts() {
+    let code = "\
+let x = 5;
+println(x << 3);
+println(-40 >> 3);
+println(-1 >>> 60);
+println(1 << 64, \" \", 1 << 65);
+println(1 + 1 << 2 + 1, \" \", 1 << 2 < 5);
+let mut y = 3;
+y <<= 4;
+println(y);
+y >>= 2;
+println(y);
+y = -y;
+y >>>= 62;
+println(y);
+";
+    assert_eq!(run(code), "40\n-5\n15\n1 2\n16 true\n48\n12\n3\n");
+}
+
+#[test]
+fn test_fixed_int_shifts() {
+    let code = "\
+println((0x81 as U8) << 1);
+println((0x81 as U8) >> 1);
+println((-128 as I8) >> 3);
+println((-128 as I8) >>> 3);
+println((64 as I8) << 1);
+let max = (0 as U64) - 1;
+println(max >> 60);
+println((-1 as I64) >> 60);
+let mut hash = 1 as U32;
+hash <<= 31;
+println(hash);
+hash >>= 30;
+println(hash);
+";
+    assert_eq!(run(code), "2\n64\n-16\n16\n-128\n15\n-1\n2147483648\n2\n");
+}
+
+#[test]
+fn test_constant_shifts() {
+    let mut env = Env::default();
+    let mut shift = |op: Shift, a: i64, b: i64| {
+        op.eval(&ConstExpr::Int(a), &ConstExpr::Int(b), &mut env)
+            .unwrap()
+    };
+    assert_eq!(shift(Shift::Left, 3, 2), ConstExpr::Int(12));
+    assert_eq!(shift(Shift::Right, -8, 1), ConstExpr::Int(-4));
+    assert_eq!(shift(Shift::LogicalRight, -1, 63), ConstExpr::Int(1));
+    assert_eq!(Shift::LogicalRight.to_string(), ">>>");
+}
+
+#[test]
+fn test_shift_types() {
+    with_large_stack(|| {
+        for code in [
+            "let x = 1.0 << 1;",
+            "let x = 1 >> 'a';",
+            "let x = True >>> 1;",
+        ] {
+            let expr = parse_frontend(code, None).unwrap();
+            assert!(
+                expr.type_check(&Env::default()).is_err(),
+                "`{code}` type checked"
+            );
+        }
+    })
+}
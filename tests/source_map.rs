@@ -0,0 +1,228 @@
+mod common;
+
+use codespan_reporting::term::termcolor::NoColor;
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{
+    lir::{Compile, DebugInfo},
+    parse::{parse_frontend, parse_vm},
+    targets::{CompiledTarget, C},
+    vm::*,
+};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+const DOUBLE_FREE: &str = "\
+def release(ptr: &mut Int, depth: Int) {
+    if depth > 0 {
+        release(ptr, depth - 1);
+    } else {
+        free(ptr);
+    }
+}
+
+let ptr = alloc(4) as &mut Int;
+release(ptr, 2);
+release(ptr, 1);
+";
+
+const DOUBLE_FREE_ERROR: &str = "\
+error: double free of address 30000
+  ┌─ double-free.sg:5:9
+  │
+5 │         free(ptr);
+  │         ^^^^^^^^^ in release
+
+backtrace:
+  #0 release at double-free.sg:5:9
+  #1 release at double-free.sg:3:9
+  #2 <main> at double-free.sg:11:1
+";
+
+const DIVIDE_BY_ZERO: &str = "\
+def divide(a: Int, b: Int): Int {
+    return a / b;
+}
+
+let zero = alloc(1) as &mut Int;
+println(divide(7, *zero));
+";
+
+/// Compile a standard program to flattened virtual machine code with its debug information.
+fn compile(filename: &str, src: &str) -> (StandardProgram, DebugInfo) {
+    let (program, debug_info) = parse_frontend(src, Some(filename))
+        .unwrap()
+        .compile_with_debug_info()
+        .unwrap();
+    let program = match program {
+        Ok(_) => panic!("expected a program using the standard variant"),
+        Err(std_asm) => std_asm.assemble(CALL_STACK_SIZE).unwrap().flatten(),
+    };
+    (program, debug_info)
+}
+
+/// Compile C code with gcc, and run it without any input.
+fn run_c_code(name: &str, c_code: &str) -> std::process::Output {
+    let c_code_path = format!("tmp_source_map_{name}.c");
+    let c_exe_path = format!("tmp_source_map_{name}.exe");
+    std::fs::write(&c_code_path, c_code).unwrap();
+    let c_compile_output = Command::new("gcc")
+        .args([&c_code_path, "-o", &c_exe_path, "-lm"])
+        .output()
+        .unwrap();
+    assert!(
+        c_compile_output.status.success(),
+        "Could not compile C code: {c_compile_output:?}"
+    );
+
+    let mut c_exe = Command::new(format!("./{c_exe_path}"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _ = c_exe.stdin.as_mut().unwrap().write_all(b"");
+    let output = c_exe.wait_with_output().unwrap();
+    std::fs::remove_file(c_code_path).unwrap();
+    std::fs::remove_file(c_exe_path).unwrap();
+    output
+}
+
+/// Run a program until it fails, and render the runtime error with the source map.
+fn run_until_error(program: &StandardProgram, source_map: &SourceMap, src: &str) -> String {
+    let mut interpreter = StandardInterpreter::new(TestingDevice::new(""));
+    let err = loop {
+        assert!(
+            !interpreter.is_done(),
+            "the program finished without an error"
+        );
+        if let Err(err) = interpreter.step(program) {
+            break err;
+        }
+    };
+
+    // The error remembers where the interpreter was when it happened.
+    let state = err.state().expect("the error has no interpreter state");
+    let backtrace = source_map.backtrace(state.instruction, &state.call_stack);
+    let mut writer = NoColor::new(vec![]);
+    SourceMap::emit_error(&mut writer, &err.to_string(), &backtrace, |_| {
+        Some(src.to_string())
+    })
+    .unwrap();
+    String::from_utf8(writer.into_inner()).unwrap()
+}
+
+#[test]
+fn test_source_map_spans() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile("double-free.sg", DOUBLE_FREE);
+        let source_map = program.source_map(&debug_info);
+        let program = program.without_comments();
+        assert_eq!(source_map.len(), program.0.len());
+
+        // Each statement of the program was compiled to some instructions.
+        let spans = (0..source_map.len())
+            .filter_map(|i| source_map.get(i))
+            .collect::<Vec<_>>();
+        for line in [3, 5, 9, 10, 11] {
+            assert!(
+                spans.iter().any(|span| span.location.line == line),
+                "no instructions for line {line}"
+            );
+        }
+        // The code in `release` is attributed to it, and the rest to the main program.
+        for span in spans {
+            match span.location.line {
+                1..=7 => assert_eq!(span.procedure_name(), "release"),
+                _ => assert_eq!(span.procedure_name(), "<main>"),
+            }
+        }
+    });
+}
+
+#[test]
+fn test_source_map_serialization() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile("double-free.sg", DOUBLE_FREE);
+        let source_map = program.source_map(&debug_info);
+        let text = source_map.to_string();
+        assert!(text.starts_with("sage-source-map 1\n"));
+        assert_eq!(SourceMap::parse(&text).unwrap(), source_map);
+
+        assert!(SourceMap::parse("span\t0\t1\t1\t0\t\t\t").is_err());
+        assert!(SourceMap::parse("sage-source-map 1\nmap\t0\t4\t0\n").is_err());
+        assert!(SourceMap::parse("sage-source-map 1\nmap\t0\t4\t\n").is_ok());
+
+        // The saved program and source map still report errors at the original source.
+        let saved = match parse_vm(program.without_comments().to_string()).unwrap() {
+            Ok(_) => panic!("expected a program using the standard variant"),
+            Err(saved) => saved,
+        };
+        let source_map = SourceMap::parse(&text).unwrap();
+        assert_eq!(
+            run_until_error(&saved, &source_map, DOUBLE_FREE),
+            DOUBLE_FREE_ERROR
+        );
+    });
+}
+
+#[test]
+fn test_interpreter_backtrace() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile("double-free.sg", DOUBLE_FREE);
+        let source_map = program.source_map(&debug_info);
+        let program = program.without_comments();
+        assert_eq!(
+            run_until_error(&program, &source_map, DOUBLE_FREE),
+            DOUBLE_FREE_ERROR
+        );
+
+        // Without a source map, the error is reported without a location.
+        let output = run_until_error(&program, &SourceMap::default(), DOUBLE_FREE);
+        assert_eq!(output, "error: double free of address 30000\n\n");
+    });
+}
+
+#[test]
+fn test_c_target_backtrace() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile("double-free.sg", DOUBLE_FREE);
+        let source_map = program.source_map(&debug_info);
+        let c_code = C::default()
+            .with_source_map(&source_map, |_| Some(DOUBLE_FREE.to_string()))
+            .build_std(&program)
+            .unwrap();
+        let output = run_c_code("double_free", &c_code);
+
+        // The C target allocates memory on the real heap, so the address differs.
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let (message, rest) = stderr.split_once('\n').unwrap();
+        assert!(message.starts_with("error: double free of address "));
+        assert_eq!(rest, DOUBLE_FREE_ERROR.split_once('\n').unwrap().1);
+    });
+}
+
+#[test]
+fn test_c_target_divide_by_zero() {
+    with_large_stack(|| {
+        let (program, debug_info) = compile("divide.sg", DIVIDE_BY_ZERO);
+        let source_map = program.source_map(&debug_info);
+        let c_code = C::default()
+            .with_source_map(&source_map, |_| Some(DIVIDE_BY_ZERO.to_string()))
+            .build_std(&program)
+            .unwrap();
+        let output = run_c_code("divide_by_zero", &c_code);
+
+        // The division is reported at the same place as in the interpreter.
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("error: division by zero\n  ┌─ divide.sg:2:"));
+        let program = program.without_comments();
+        assert_eq!(
+            stderr,
+            run_until_error(&program, &source_map, DIVIDE_BY_ZERO)
+        );
+    });
+}
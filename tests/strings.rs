@@ -0,0 +1,141 @@
+mod common;
+
+use common::{run, type_error};
+use sage::lir::Error;
+
+#[test]
+fn test_str_casts() {
+    let code = r#"
+let s = &"hello world" as Str;
+println(s, " ", s.length, " ", sizeof<Str>());
+let chars = ['a', 'b', 'c'];
+let t = &chars as Str;
+println(t, " ", t.length);
+let p = &"pointer" as &Char;
+println(p as Str);
+let u = {data=&"sliced" as &Char, length=5} as Str;
+println(u, " ", (&"" as Str).length);
+println((u, 1));
+"#;
+    assert_eq!(
+        run(code),
+        "hello world 11 2\nabc 3\npointer\nslice 0\n(\"slice\", 1)\n"
+    );
+}
+
+#[test]
+fn test_str_type_errors() {
+    for code in [
+        "let x = &5 as Str;",
+        "let a = [1, 2]; let x = &a as Str;",
+        "let x = (&\"a\" as Str) as Int;",
+        "let x: Str = &\"a\" as &Char;",
+    ] {
+        let err = type_error(code);
+        assert!(
+            matches!(err, Error::InvalidAs(..) | Error::MismatchedTypes { .. }),
+            "unexpected error for `{code}`: {err}"
+        );
+    }
+}
+
+#[test]
+fn test_str_methods() {
+    let code = r#"
+mod std;
+let s = &"  one two three  " as Str;
+let t = s.trim();
+println("[", t, "] ", t.len(), " ", t.is_empty(), " ", t.at(4));
+println(t.slice(4, 7), " ", t.slice(0, 0).is_empty());
+println(t.starts_with(&"one" as Str), " ", t.starts_with(&"one two three four" as Str));
+println(t.ends_with(&"three" as Str), " ", t.ends_with(&"two" as Str));
+println(t.find(&"two" as Str).unwrap(), " ", t.find(&"four" as Str).is_some());
+println(t.find_char('e').unwrap(), " ", t.contains(&"" as Str));
+println((&"abc" as Str).compare(&"abd" as Str) < 0, " ", (&"ab" as Str).compare(&"a" as Str) > 0);
+println((&"abc" as Str).equals(t.slice(0, 3)), " ", (&"one" as Str).equals(t.slice(0, 3)));
+println((&"   " as Str).trim().len());
+"#;
+    assert_eq!(
+        run(code),
+        "[one two three] 13 false t\ntwo true\ntrue false\ntrue false\n4 false\n2 true\ntrue true\nfalse true\n0\n"
+    );
+}
+
+#[test]
+fn test_string_growth() {
+    let code = r#"
+use std::string::String;
+let mut s = String.with_capacity(1);
+for let mut i=0; i<20; i+=1 {
+    s.push_slice(&"ab" as Str);
+}
+println(s.len(), " ", s.slice(38, 40));
+let mut t = (&"x = " as Str).concat(&"y" as Str);
+t.push_int(-15);
+t.push(';');
+t.println();
+let u = t.as_slice().to_string();
+let x = String.from_slice(&"x" as Str);
+println(u.equals(&t), " ", x.compare(&t) < 0);
+t.append(&u);
+t.println();
+"#;
+    assert_eq!(
+        run(code),
+        "40 ab\nx = y-15;\ntrue true\nx = y-15;x = y-15;\n"
+    );
+}
+
+#[test]
+fn test_parse_int() {
+    let code = r#"
+mod std;
+let inputs = [&"0" as Str, &"-17" as Str, &"+42" as Str,
+    &"9223372036854775807" as Str, &"-9223372036854775808" as Str,
+    &"9223372036854775808" as Str, &"-" as Str, &"" as Str, &"12a" as Str];
+for let mut i=0; i<9; i+=1 {
+    match inputs[i].parse_int() {
+        of Some(n) => println(n),
+        of Nothing => println("none")
+    }
+}
+"#;
+    assert_eq!(
+        run(code),
+        "0\n-17\n42\n9223372036854775807\n-9223372036854775808\nnone\nnone\nnone\nnone\n"
+    );
+}
+
+#[test]
+fn test_parse_float() {
+    let inputs = [
+        "0",
+        "-0.5",
+        "3.25",
+        "0.1",
+        "+2.",
+        ".75",
+        "123456789.123456789",
+        "0.000000000000000001",
+        "9007199254740993",
+        "1e5",
+        ".",
+        "",
+    ];
+    let mut code = String::from("mod std;\n");
+    for input in inputs {
+        code += &format!(
+            "match (&\"{input}\" as Str).parse_float() {{ of Some(x) => println(x), of Nothing => println(\"none\") }}\n"
+        );
+    }
+    let code: &'static str = Box::leak(code.into_boxed_str());
+    // The floats are parsed exactly like Rust parses them.
+    let expected: String = inputs
+        .iter()
+        .map(|input| match input.parse::<f64>() {
+            Ok(x) if !input.contains('e') && *input != "." => format!("{x:?}\n"),
+            _ => "none\n".to_string(),
+        })
+        .collect();
+    assert_eq!(run(code), expected);
+}
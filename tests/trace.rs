@@ -0,0 +1,252 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use sage::{lir::Compile, parse::parse_frontend, side_effects::Output, vm::*};
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+const FACTORIAL: &str = "\
+def fact(n: Int): Int {
+    if n <= 1 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+
+println(fact(5));
+";
+
+/// A buffer shared with a tracer, to read the trace it writes.
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run a core program with a tracer, and return its trace.
+fn trace_core(program: &CoreProgram, filter: TraceFilter) -> Vec<TraceStep> {
+    let buffer = Buffer::default();
+    let tracer = Tracer::new(buffer.clone()).with_filter(filter);
+    let mut interpreter = CoreInterpreter::new(TestingDevice::default()).with_tracer(Some(tracer));
+    while !interpreter.is_done() {
+        interpreter.step(program).unwrap();
+    }
+    interpreter.take_tracer().unwrap().finish().unwrap();
+    let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+    parse_trace(&text).unwrap()
+}
+
+/// Compile the factorial program to core virtual machine code, and find the index of `fact`.
+fn compile_factorial() -> (CoreProgram, usize) {
+    let (program, debug_info) = parse_frontend(FACTORIAL, Some("fact.sg"))
+        .unwrap()
+        .compile_with_debug_info()
+        .unwrap();
+    let program = program
+        .expect("factorial should compile to core code")
+        .assemble(CALL_STACK_SIZE)
+        .unwrap()
+        .without_comments();
+    let fact = debug_info
+        .procedures()
+        .iter()
+        .position(|procedure| procedure.name() == "fact")
+        .unwrap();
+    (program, fact)
+}
+
+#[test]
+fn test_trace_steps() {
+    let program = CoreProgram(vec![
+        CoreOp::Set(vec![5, 6]),
+        CoreOp::Move(2),
+        CoreOp::Store(2),
+        CoreOp::Inc(1),
+        CoreOp::Swap(1),
+        CoreOp::Put(Output::stdout_int()),
+    ]);
+    let trace = trace_core(&program, TraceFilter::default());
+    assert_eq!(trace.len(), 6);
+    assert_eq!(
+        trace[2],
+        TraceStep {
+            step: 2,
+            instruction: 2,
+            op: "store 2".to_string(),
+            pointer: 2,
+            register: 5,
+            writes: vec![(2, 5), (3, 6)],
+        }
+    );
+    assert_eq!(trace[3].register, 6);
+    assert!(trace[3].writes.is_empty());
+    // Swapping writes the register to the tape, and the old cell to the register.
+    assert_eq!(trace[4].register, 5);
+    assert_eq!(trace[4].writes, vec![(2, 6)]);
+    assert_eq!(trace[5].op, CoreOp::Put(Output::stdout_int()).to_string());
+}
+
+#[test]
+fn test_trace_standard_interpreter() {
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![3])),
+        StandardOp::Alloc,
+        StandardOp::CoreOp(CoreOp::Store(1)),
+    ]);
+    let buffer = Buffer::default();
+    let mut interpreter = StandardInterpreter::new(TestingDevice::default())
+        .with_tracer(Some(Tracer::new(buffer.clone())));
+    while !interpreter.is_done() {
+        interpreter.step(&program).unwrap();
+    }
+    assert_eq!(interpreter.take_tracer().unwrap().steps(), 3);
+    let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+    let trace = parse_trace(&text).unwrap();
+
+    // The cells of a new block on the heap are zeroed.
+    let addr = trace[1].register as usize;
+    assert_eq!(trace[1].op, "alloc");
+    assert_eq!(
+        trace[1].writes,
+        vec![(addr, 0), (addr + 1, 0), (addr + 2, 0)]
+    );
+    assert_eq!(trace[2].writes, vec![(0, addr as i64)]);
+}
+
+#[test]
+fn test_trace_filters() {
+    with_large_stack(|| {
+        let (program, fact) = compile_factorial();
+        let full = trace_core(&program, TraceFilter::default());
+        assert!(full
+            .iter()
+            .enumerate()
+            .all(|(i, step)| step.step == i as u64));
+
+        // The first steps of the program.
+        let first = trace_core(
+            &program,
+            TraceFilter {
+                steps: Some(10),
+                ..TraceFilter::default()
+            },
+        );
+        assert_eq!(first, full[..10]);
+
+        // Only the steps inside `fact`, numbered from the start of the program.
+        let in_fact = trace_core(
+            &program,
+            TraceFilter {
+                functions: Some(fact..=fact),
+                ..TraceFilter::default()
+            },
+        );
+        assert!(!in_fact.is_empty() && in_fact.len() < full.len());
+        for step in &in_fact {
+            assert_eq!(step, &full[step.step as usize]);
+        }
+        // `fact` is called 5 times, and calls itself 4 times.
+        let count = |op: &str| in_fact.iter().filter(|step| step.op == op).count();
+        assert_eq!(count("ret"), 5);
+        assert_eq!(count("call"), 4);
+
+        // There are no functions with other indices.
+        let none = trace_core(
+            &program,
+            TraceFilter {
+                functions: Some(fact + 1..=fact + 10),
+                ..TraceFilter::default()
+            },
+        );
+        assert!(none.is_empty());
+    });
+}
+
+#[test]
+fn test_trace_serialization() {
+    let step = TraceStep {
+        step: 12,
+        instruction: 3,
+        op: "put \"out\\put\"\n\u{1}".to_string(),
+        pointer: 7,
+        register: -42,
+        writes: vec![(7, -1), (8, 9)],
+    };
+    let line = step.to_string();
+    assert_eq!(
+        line,
+        r#"{"step":12,"ip":3,"op":"put \"out\\put\"\n\u0001","pointer":7,"register":-42,"writes":[[7,-1],[8,9]]}"#
+    );
+    assert_eq!(TraceStep::parse(&line), Ok(step));
+
+    assert_eq!(
+        parse_trace("\n{\"step\":1}\n"),
+        Err("line 2: expected `,\"ip\":` in trace".to_string())
+    );
+    assert_eq!(
+        parse_trace(r#"{"step":x,"ip":0,"op":"","pointer":0,"register":0,"writes":[]}"#),
+        Err("line 1: invalid number `` in trace".to_string())
+    );
+    assert_eq!(
+        parse_trace(r#"{"step":0,"ip":0,"op":"a","pointer":0,"register":0,"writes":[]} x"#),
+        Err("line 1: unexpected ` x` in trace".to_string())
+    );
+}
+
+#[test]
+fn test_diff_traces() {
+    with_large_stack(|| {
+        let (program, _) = compile_factorial();
+        let trace = trace_core(&program, TraceFilter::default());
+        assert_eq!(diff_traces(&trace, &trace), None);
+        assert_eq!(diff_traces(&trace[..20], &trace), Some(20));
+        assert_eq!(diff_traces(&trace, &trace[..20]), Some(20));
+
+        // Optimizing the program changes where its instructions are.
+        let optimized = trace_core(
+            &program.clone().optimize(2).without_comments(),
+            TraceFilter::default(),
+        );
+        let i = diff_traces(&trace, &optimized).unwrap();
+        assert_eq!(trace[..i], optimized[..i]);
+        assert_ne!(trace.get(i), optimized.get(i));
+    });
+}
+
+#[test]
+fn test_trace_view_command() {
+    let dir = std::env::temp_dir().join(format!("sage-trace-view-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let sage = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_sage"))
+            .current_dir(&dir)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // Write a trace with the CLI, and show it with the `trace-view` command.
+    std::fs::write(dir.join("fact.sg"), FACTORIAL).unwrap();
+    let output = sage(&["fact.sg", "--trace", "fact.jsonl", "--trace-steps", "5"]);
+    assert!(output.status.success(), "{output:?}");
+    let output = sage(&["trace-view", "fact.jsonl"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 5);
+
+    // The command is listed in the help.
+    let output = sage(&["--help"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("trace-view"));
+
+    // A file with the same name as the command can still be compiled.
+    std::fs::write(dir.join("trace-view"), "println(1);").unwrap();
+    let output = sage(&["--", "trace-view"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -134,3 +134,42 @@ fn test_pointers() {
 
     assert_eq!(device.output_vals(), vec![b, a]);
 }
+
+#[test]
+fn test_stack_grows_after_alloc() {
+    // Use almost all of the tape before the heap, then allocate a block.
+    let mut program = vec![
+        StandardOp::CoreOp(CoreOp::Move(HEAP_START as isize - 10)),
+        StandardOp::CoreOp(CoreOp::Set(vec![4])),
+        StandardOp::CoreOp(CoreOp::Store(1)),
+        StandardOp::Alloc,
+        StandardOp::CoreOp(CoreOp::Store(1)),
+        StandardOp::CoreOp(CoreOp::Deref),
+        StandardOp::CoreOp(CoreOp::Set(vec![7])),
+        StandardOp::CoreOp(CoreOp::Store(1)),
+        StandardOp::CoreOp(CoreOp::Refer),
+        StandardOp::CoreOp(CoreOp::Set(vec![9])),
+    ];
+    // Grow the stack past where the heap used to start.
+    for _ in 0..20 {
+        program.push(StandardOp::CoreOp(CoreOp::Move(1)));
+        program.push(StandardOp::CoreOp(CoreOp::Store(1)));
+    }
+    program.extend([
+        StandardOp::CoreOp(CoreOp::Move(-20)),
+        StandardOp::CoreOp(CoreOp::Deref),
+        StandardOp::CoreOp(CoreOp::Load(1)),
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+        StandardOp::CoreOp(CoreOp::Refer),
+        StandardOp::CoreOp(CoreOp::Load(1)),
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+    ]);
+
+    let i = StandardInterpreter::new(TestingDevice::default());
+    let device = i.run(&StandardProgram(program)).unwrap();
+
+    // The block is left intact, with room for the stack to keep growing.
+    let output = device.output_str();
+    assert_eq!(&output[..1], "7");
+    assert!(output[1..].parse::<usize>().unwrap() >= HEAP_START - 9 + STACK_GAP);
+}
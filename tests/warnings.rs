@@ -0,0 +1,181 @@
+mod common;
+
+use common::with_large_stack;
+use sage::{frontend, lir::*, parse::parse_frontend};
+use std::{collections::BTreeSet, fs::read_dir, fs::read_to_string};
+
+/// Find the warnings for every lint in a program, as the lint and the line of each warning.
+fn warnings(code: &str) -> Vec<(Lint, usize)> {
+    parse_frontend(code, None)
+        .unwrap()
+        .lint(&Lint::ALL.into())
+        .into_iter()
+        .map(|warning| (warning.lint, warning.location.unwrap().line))
+        .collect()
+}
+
+const PROGRAM: &str = "\
+def f(x: Int): Int {
+    let mut unused = 1;
+    let _ignored = 2;
+    let mut counter = 0;
+    counter += x;
+    let x = counter;
+    return x;
+    return 0;
+}
+
+when False {
+    println(\"never\");
+}
+println(f(5));
+";
+
+#[test]
+fn test_lint_names() {
+    assert_eq!(Lint::named("unused_mut"), Some(vec![Lint::UnusedMut]));
+    assert_eq!(Lint::named(ALL_LINTS), Some(Lint::ALL.to_vec()));
+    assert_eq!(Lint::named("unused"), None);
+    for lint in Lint::ALL {
+        assert_eq!(Lint::named(&lint.to_string()), Some(vec![lint]));
+    }
+    assert!(!Lint::defaults().contains(&Lint::Shadowing));
+    assert_eq!(Lint::defaults().len(), Lint::ALL.len() - 1);
+}
+
+#[test]
+fn test_warnings() {
+    assert_eq!(
+        warnings(PROGRAM),
+        vec![
+            (Lint::UnusedVariables, 2),
+            (Lint::UnusedMut, 2),
+            (Lint::Shadowing, 6),
+            (Lint::UnreachableCode, 8),
+            (Lint::DeadWhenBranches, 12),
+        ]
+    );
+
+    // Only the enabled lints are checked.
+    let expr = parse_frontend(PROGRAM, Some("program.sg")).unwrap();
+    let found = expr
+        .type_check_with_warnings(&Env::default(), &BTreeSet::from([Lint::UnreachableCode]))
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(
+        found[0].to_string(),
+        "program.sg:8:5: warning[unreachable_code]: unreachable code after a return"
+    );
+}
+
+#[test]
+fn test_when_branches_for_variants() {
+    // A branch which is only taken for one of the variants isn't dead.
+    let code = "\
+when CORE_ONLY {
+    println(1);
+} else {
+    println(2);
+}
+const DEBUG = False;
+def debug(x: Int) {
+    when DEBUG {
+        println(x);
+    }
+}
+debug(5);
+";
+    assert_eq!(warnings(code), vec![(Lint::DeadWhenBranches, 9)]);
+}
+
+#[test]
+fn test_allow_attributes() {
+    let allowed = PROGRAM
+        .replace("def f", "@allow(unused_variables, unused_mut)\ndef f")
+        .replace(
+            "    return x;",
+            "    @allow(shadowing) let x = x;\n    return x;",
+        )
+        .replace("    return 0;", "    @allow(unreachable_code) return 0;")
+        .replace("when False", "@allow(warnings)\nwhen False");
+    assert_eq!(warnings(&allowed), vec![(Lint::Shadowing, 7)]);
+
+    // Methods can be allowed one at a time, or all together.
+    let code = "\
+struct Point { x: Int }
+impl Point {
+    def get(self: &Point): Int {
+        let unused = 1;
+        return self.x;
+    }
+    @allow(unused_variables)
+    def set(self: &mut Point, x: Int) {
+        let unused = 1;
+        self.x = x;
+    }
+}
+@allow(unused_variables)
+impl Point {
+    def zero(): Point {
+        let unused = 1;
+        return {x=0};
+    }
+}
+let p = Point.zero();
+println(p.get());
+";
+    assert_eq!(warnings(code), vec![(Lint::UnusedVariables, 4)]);
+
+    assert!(matches!(
+        parse_frontend("@allow(unused) let x = 1;", None),
+        Err(frontend::Error::UnknownLint(name, _)) if name == "unused"
+    ));
+}
+
+#[test]
+fn test_examples_warnings() {
+    with_large_stack(|| {
+        for entry in read_dir("examples/frontend/").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("sg") {
+                continue;
+            }
+            let code = read_to_string(&path).unwrap();
+            let Ok(expr) = parse_frontend(&code, path.to_str()) else {
+                continue;
+            };
+            // The standard library never warns about its own code.
+            // Blocks inside of expressions don't record their filename.
+            for warning in expr.lint(&Lint::ALL.into()) {
+                let location = warning.location.as_ref().expect("warnings have locations");
+                let filename = location.filename.as_deref();
+                assert!(filename.is_none() || filename == path.to_str(), "{warning}");
+            }
+        }
+    });
+}
+
+#[test]
+fn test_deny_warnings_exit_status() {
+    let path = std::env::temp_dir().join(format!("sage-deny-warnings-{}.sg", std::process::id()));
+    std::fs::write(&path, PROGRAM).unwrap();
+    let sage = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_sage"))
+            .arg(&path)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = sage(&[]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "5\n");
+
+    // Denied warnings and other errors fail the build.
+    let output = sage(&["--deny-warnings"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(output.stdout.is_empty());
+    let output = sage(&["-s", "low-ir"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,340 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use log::warn;
+use sage::{
+    lir::Compile,
+    parse::*,
+    side_effects::{FFIBinding, Output},
+    targets::*,
+    vm::{CoreOp, StandardOp, StandardProgram},
+};
+use std::{
+    fs::{read_dir, read_to_string},
+    io::Write,
+    path::PathBuf,
+};
+
+const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
+
+/// A host for the WebAssembly modules which runs them with Node.js.
+/// It reads the module's input from stdin, and formats floats like the interpreter.
+const HOST: &str = r#"
+const fs = require("fs");
+const input = fs.readFileSync(0);
+let pos = 0;
+let out = "";
+let memory;
+
+const peek = () => (pos < input.length ? input[pos] : -1);
+const isSpace = (c) => c === 32 || (c >= 9 && c <= 13);
+function readNumber(pattern) {
+    while (isSpace(peek())) pos++;
+    const match = input.subarray(pos, pos + 64).toString().match(pattern);
+    if (!match) return "0";
+    pos += match[0].length;
+    return match[0];
+}
+function fmtFloat(x) {
+    if (Number.isNaN(x)) return "NaN";
+    if (!Number.isFinite(x)) return x > 0 ? "inf" : "-inf";
+    const a = Math.abs(x);
+    if (a !== 0 && (a >= 1e16 || a < 1e-4)) return x.toExponential().replace("e+", "e");
+    const s = String(x);
+    return s.includes(".") ? s : s + ".0";
+}
+function flush() {
+    fs.writeSync(1, out);
+    out = "";
+}
+const write = (fd) => (s) => {
+    if (fd === 1) out += s;
+    else { flush(); fs.writeSync(fd, s); }
+};
+const outputs = {};
+for (const [name, fd] of [["stdout", 1], ["stderr", 2]]) {
+    outputs[name + ".char"] = (c) => write(fd)(String.fromCharCode(Number(c)));
+    outputs[name + ".int"] = (n) => write(fd)(n.toString());
+    outputs[name + ".float"] = (x) => write(fd)(fmtFloat(x));
+}
+const imports = {
+    sage: {
+        error(ptr, len) {
+            flush();
+            fs.writeSync(2, Buffer.from(memory.buffer, ptr, len).toString() + "\n");
+            process.exit(1);
+        },
+        "stdin.char": () => BigInt(pos < input.length ? input[pos++] : 0),
+        "stdin.int": () => BigInt(readNumber(/^[+-]?\d+/)),
+        "stdin.float": () => parseFloat(readNumber(/^[+-]?(\d+\.?\d*|\.\d+)([eE][+-]?\d+)?/)),
+        ...outputs,
+        ffi(ptr, len, top) {
+            const name = Buffer.from(memory.buffer, ptr, len).toString();
+            const channel = new BigInt64Array(memory.buffer);
+            if (name === "add") {
+                const b = channel[top / 8], a = channel[top / 8 - 1];
+                channel[top / 8 - 1] = a + b;
+                return top - 8;
+            }
+            throw new Error(`unbound foreign function ${name}`);
+        },
+    },
+    math: {
+        sin: Math.sin, cos: Math.cos, tan: Math.tan,
+        asin: Math.asin, acos: Math.acos, atan: Math.atan,
+        pow: Math.pow, fmod: (x, y) => x % y,
+    },
+};
+const wasmModule = new WebAssembly.Module(fs.readFileSync(process.argv[2]));
+const instance = new WebAssembly.Instance(wasmModule, imports);
+memory = instance.exports.memory;
+instance.exports.main();
+flush();
+"#;
+
+/// Convert virtual machine code to a WebAssembly module, and run it with Node.js.
+fn run_vm_code(name: &str, vm_code: &str, input: &str) -> std::process::Output {
+    let wat_code = match parse_vm(vm_code).unwrap() {
+        Ok(vm_code) => Wasm::default().build_core(&vm_code.flatten()),
+        Err(vm_code) => Wasm::default().build_std(&vm_code.flatten()),
+    }
+    .unwrap();
+    run_wat_code(name, &wat_code, input)
+}
+
+/// Convert a module in the WebAssembly text format to binary, and run it with Node.js.
+fn run_wat_code(name: &str, wat_code: &str, input: &str) -> std::process::Output {
+    let wasm = wat::parse_str(wat_code)
+        .unwrap_or_else(|e| panic!("Could not convert wasm code for `{name}`: {e}"));
+
+    let host_path = format!("tmp_wasm_{name}.cjs");
+    let wasm_path = format!("tmp_wasm_{name}.wasm");
+    std::fs::write(&host_path, HOST).unwrap();
+    std::fs::write(&wasm_path, wasm).unwrap();
+
+    let mut exe = std::process::Command::new("node")
+        .args([&host_path, &wasm_path])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _ = exe.stdin.as_mut().unwrap().write_all(input.as_bytes());
+    let output = exe.wait_with_output().unwrap();
+    for path in [host_path, wasm_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+    output
+}
+
+#[test]
+fn test_wasm_target_io() {
+    let output = run_vm_code(
+        "io",
+        "get stdin.int mul 0 put stdout.int set 32 put stdout.char
+        get stdin.float put stdout.float set 32 put stdout.char
+        get stdin.char put stdout.char get stdin.char put stdout.int",
+        " -42 -3.25x",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "-42 -3.25 x0");
+}
+
+#[test]
+fn test_wasm_target_floats() {
+    let output = run_vm_code(
+        "floats",
+        "set-f 10.0 sav set-f 2.0 pow put stdout.float set 32 put stdout.char
+        set-f 2.0 sav set-f -7.5 rem-f put stdout.float set 32 put stdout.char
+        set-f 0.0 sav set-f 1.0 div-f put stdout.float set 32 put stdout.char
+        set-f -2.75 to-int put stdout.int",
+        "",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1024.0 -1.5 inf -2"
+    );
+}
+
+#[test]
+fn test_wasm_target_pointers() {
+    // Pointers are cell indices, and the heap allocates at the same addresses as the interpreter.
+    let output = run_vm_code(
+        "pointers",
+        "where put stdout.int set 32 put stdout.char
+        mov 1 set 4 alloc sav put stdout.int set 32 put stdout.char
+        set 2 alloc put stdout.int set 32 put stdout.char
+        res free set 1 alloc put stdout.int set 32 put stdout.char
+        set 5 alloc put stdout.int",
+        "",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0 30000 30004 30000 30006"
+    );
+}
+
+#[test]
+fn test_wasm_target_ffi() {
+    // The virtual machine code syntax can't call foreign functions, so build the program directly.
+    let program = StandardProgram(vec![
+        StandardOp::CoreOp(CoreOp::Set(vec![2])),
+        StandardOp::Poke,
+        StandardOp::CoreOp(CoreOp::Set(vec![3])),
+        StandardOp::Poke,
+        StandardOp::Call(FFIBinding::new("add".to_string(), 2, 1)),
+        StandardOp::Peek,
+        StandardOp::CoreOp(CoreOp::Put(Output::stdout_int())),
+    ]);
+    let output = run_wat_code("ffi", &Wasm::default().build_std(&program).unwrap(), "");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "5");
+}
+
+#[test]
+fn test_wasm_target_runtime_errors() {
+    let output = run_vm_code("call", "set 1 put stdout.int set 5 call", "");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "function 5 not defined\n"
+    );
+
+    let output = run_vm_code("free", "set 4 alloc sav free res free", "");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "double free of address 30000\n"
+    );
+
+    let output = run_vm_code("alloc", "set -3 alloc", "");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "cannot allocate a negative number of cells: -3\n"
+    );
+
+    for op in ["div", "rem"] {
+        let code = format!("set 1 put stdout.int set 0 sav set 5 {op} put stdout.int");
+        let output = run_vm_code(op, &code, "");
+        assert!(!output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "1");
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "division by zero\n"
+        );
+    }
+}
+
+#[test]
+fn test_wasm_target_frontend_examples() {
+    with_large_stack(test_wasm_target_frontend_examples_helper);
+}
+
+fn test_wasm_target_frontend_examples_helper() {
+    let mut total_failures: i32 = 0;
+    let mut total_attempts = 0;
+
+    for entry in read_dir("examples/frontend/").unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+        warn!("Starting test for `{path:?}`");
+        if path.is_file()
+            && matches!(
+                path.extension().map(|p| p
+                    .to_str()
+                    .expect("Couldn't get file extension of example code")
+                    .as_bytes()),
+                Some(b"sg") | None
+            )
+        {
+            let file_name = path
+                .file_name()
+                .unwrap_or_else(|| panic!("Could not get file name of path `{path:?}`"))
+                .to_str()
+                .unwrap_or_else(|| panic!("Could not get file name of path `{path:?}`"))
+                .to_string();
+            let correct_output_path = PathBuf::from("examples/test-output")
+                .join(file_name.clone())
+                .with_extension("txt");
+            let correct_error_path = PathBuf::from("examples/test-output")
+                .join(file_name.clone())
+                .with_extension("error.txt");
+            let correct_error = match read_to_string(&correct_error_path) {
+                Ok(contents) => Some(contents.replace("\r\n", "\n")),
+                Err(_) => None,
+            };
+            let correct_output = match read_to_string(&correct_output_path) {
+                Ok(contents) => contents.replace("\r\n", "\n"),
+                Err(_) if correct_error.is_none() => {
+                    warn!("Could not read output text file `{correct_output_path:?}` to compare against. Skipping this test.");
+                    continue;
+                }
+                Err(_) => String::new(),
+            };
+
+            let frontend_src = read_to_string(&path)
+                .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
+            let frontend_code = match parse_frontend(&frontend_src, path.to_str()) {
+                Ok(frontend_code) => frontend_code,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not parse `{path:?}`: {e}"),
+                },
+            };
+            drop(frontend_src);
+            let asm_code = match frontend_code.compile() {
+                Ok(asm_code) => asm_code,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not assemble code in `{path:?}`: {e}"),
+                },
+            };
+
+            let vm_code = match asm_code {
+                Ok(core_asm_code) => core_asm_code.assemble(CALL_STACK_SIZE).map(Ok),
+                Err(std_asm_code) => std_asm_code.assemble(CALL_STACK_SIZE).map(Err),
+            }
+            .unwrap();
+
+            let wat_code = match vm_code {
+                Ok(vm_code) => Wasm::default().build_core(&vm_code.flatten()).unwrap(),
+                Err(vm_code) => Wasm::default().build_std(&vm_code.flatten()).unwrap(),
+            };
+
+            // Run the program with the input, and confirm that the output matches the expected output.
+            let exe_output = run_wat_code(&format!("code_{file_name}"), &wat_code, INPUT);
+            if let Some(correct_error) = correct_error {
+                // Programs with runtime errors must exit unsuccessfully with the same message.
+                let stderr = String::from_utf8_lossy(&exe_output.stderr);
+                if exe_output.status.success() || stderr.trim_end() != correct_error {
+                    warn!("Expected runtime error {correct_error:?} for program `{path:?}`, got {stderr:?}");
+                    total_failures += 1;
+                }
+                total_attempts += 1;
+                continue;
+            }
+
+            let output = String::from_utf8_lossy(&exe_output.stdout);
+            if output != correct_output {
+                warn!(
+                    "Output did not match correct output for program `{path:?}`:\n{output}\n  !=   \n{correct_output}"
+                );
+                total_failures += 1;
+            }
+            total_attempts += 1;
+        }
+    }
+
+    // Pointers have the same values as in the interpreter, so there should be very few failures.
+    if total_failures as f64 / total_attempts as f64 > 0.1 {
+        panic!(
+            "Too many failures in frontend examples: {total_failures} failures out of {total_attempts} attempts"
+        );
+    }
+}
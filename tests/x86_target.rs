@@ -0,0 +1,292 @@
+mod common;
+
+use common::{with_large_stack, CALL_STACK_SIZE};
+use log::warn;
+use sage::{lir::Compile, parse::*, targets::*};
+use std::{
+    fs::{read_dir, read_to_string},
+    io::Write,
+    path::PathBuf,
+};
+
+const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
+
+/// Assemble, link, and run virtual machine code on the x86 target with the given input.
+fn run_vm_code(name: &str, vm_code: &str, input: &str) -> std::process::Output {
+    let asm_code = match parse_vm(vm_code).unwrap() {
+        Ok(vm_code) => X86::default().build_core(&vm_code.flatten()),
+        Err(vm_code) => X86::default().build_std(&vm_code.flatten()),
+    }
+    .unwrap();
+
+    let asm_code_path = format!("tmp_x86_{name}.s");
+    let obj_path = format!("tmp_x86_{name}.o");
+    let exe_path = format!("tmp_x86_{name}.exe");
+    std::fs::write(&asm_code_path, asm_code).unwrap();
+    for (program, args) in [
+        ("as", [&asm_code_path, "-o", &obj_path]),
+        ("ld", [&obj_path, "-o", &exe_path]),
+    ] {
+        let output = std::process::Command::new(program)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "Could not build x86 code for `{name}`: {output:?}"
+        );
+    }
+
+    let mut exe = std::process::Command::new(format!("./{exe_path}"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _ = exe.stdin.as_mut().unwrap().write_all(input.as_bytes());
+    let output = exe.wait_with_output().unwrap();
+    for path in [asm_code_path, obj_path, exe_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+    output
+}
+
+#[test]
+fn test_x86_target_io() {
+    let output = run_vm_code(
+        "io",
+        "get stdin.int mul 0 put stdout.int set 32 put stdout.char
+        get stdin.float put stdout.float set 32 put stdout.char
+        get stdin.char put stdout.char get stdin.char put stdout.int",
+        " -42 -3.25x",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "-42 -3.25 x0");
+}
+
+#[test]
+fn test_x86_target_floats() {
+    let output = run_vm_code(
+        "floats",
+        "set-f 10.0 sav set-f 2.0 pow put stdout.float set 32 put stdout.char
+        set-f 2.0 sav set-f -7.5 rem-f put stdout.float set 32 put stdout.char
+        set-f 0.0 sav set-f 1.0 div-f put stdout.float set 32 put stdout.char
+        set-f 0.5 sin sav set-f 0.5 cos mul-f put stdout.float set 32 put stdout.char
+        set-f 0.00001 put stdout.float set 32 put stdout.char
+        set-f 123456789012345678.0 put stdout.float",
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1024.0 -1.5 inf 0.420735492403948 1e-5 1.23456789012346e17"
+    );
+}
+
+#[test]
+fn test_x86_target_runtime_errors() {
+    let output = run_vm_code("call", "set 1 put stdout.int set 5 call", "");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "function 5 not defined\n"
+    );
+
+    let output = run_vm_code("free", "set 4 alloc sav free res free", "");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("double free of address "), "{stderr}");
+
+    for op in ["div", "rem"] {
+        let code = format!("set 1 put stdout.int set 0 sav set 5 {op} put stdout.int");
+        let output = run_vm_code(op, &code, "");
+        assert!(!output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "1");
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "division by zero\n"
+        );
+    }
+}
+
+#[test]
+fn test_x86_target_frontend_examples() {
+    with_large_stack(test_x86_target_frontend_examples_helper);
+}
+
+fn test_x86_target_frontend_examples_helper() {
+    let mut total_failures: i32 = 0;
+    let mut total_attempts = 0;
+
+    for entry in read_dir("examples/frontend/").unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+        warn!("Starting test for `{path:?}`");
+        if path.is_file()
+            && matches!(
+                path.extension().map(|p| p
+                    .to_str()
+                    .expect("Couldn't get file extension of example code")
+                    .as_bytes()),
+                Some(b"sg") | None
+            )
+        {
+            let file_name = path
+                .file_name()
+                .unwrap_or_else(|| panic!("Could not get file name of path `{path:?}`"))
+                .to_str()
+                .unwrap_or_else(|| panic!("Could not get file name of path `{path:?}`"))
+                .to_string();
+            let correct_output_path = PathBuf::from("examples/test-output")
+                .join(file_name.clone())
+                .with_extension("txt");
+            let correct_error_path = PathBuf::from("examples/test-output")
+                .join(file_name.clone())
+                .with_extension("error.txt");
+            let correct_error = match read_to_string(&correct_error_path) {
+                Ok(contents) => Some(contents.replace("\r\n", "\n")),
+                Err(_) => None,
+            };
+            let correct_output_text = match read_to_string(&correct_output_path) {
+                Ok(contents) => contents.replace("\r\n", "\n"),
+                Err(_) if correct_error.is_none() => {
+                    warn!("Could not read output text file `{correct_output_path:?}` to compare against. Skipping this test.");
+                    continue;
+                }
+                Err(_) => String::new(),
+            };
+            let correct_output = correct_output_text
+                .as_bytes()
+                .iter()
+                .map(|byte| *byte as i64)
+                .collect::<Vec<_>>();
+
+            let frontend_src = read_to_string(&path)
+                .unwrap_or_else(|_| panic!("Could not read contents of file `{path:?}`"));
+            let frontend_code = match parse_frontend(&frontend_src, path.to_str()) {
+                Ok(frontend_code) => frontend_code,
+                Err(e) => match correct_error {
+                    Some(correct_error) if e.to_string() == correct_error => continue,
+                    Some(correct_error) => panic!("{:?} != {correct_error:?}, error did not match correct error for program {path:?}", e.to_string()),
+                    None => panic!("Could not parse `{path:?}`: {e}"),
+                },
+            };
+            drop(frontend_src);
+            let asm_code = frontend_code.compile();
+
+            if let Err(ref e) = asm_code {
+                if let Some(correct_error) = correct_error {
+                    let text = e.to_string();
+                    if text != correct_error {
+                        panic!("{text:?} != {correct_error:?}, error did not match correct error for program {path:?}")
+                    } else {
+                        continue;
+                    }
+                } else {
+                    panic!("Could not assemble code in `{path:?}`: {e}")
+                }
+            }
+            let asm_code = asm_code.unwrap();
+
+            let vm_code = match asm_code {
+                Ok(core_asm_code) => core_asm_code.assemble(CALL_STACK_SIZE).map(Ok),
+                Err(std_asm_code) => std_asm_code.assemble(CALL_STACK_SIZE).map(Err),
+            }
+            .unwrap();
+
+            let asm_code = match vm_code {
+                Ok(vm_code) => X86::default().build_core(&vm_code.flatten()).unwrap(),
+                Err(vm_code) => X86::default().build_std(&vm_code.flatten()).unwrap(),
+            };
+
+            // Write the assembly code to a file.
+            let asm_code_path = format!("tmp_x86_code_{file_name}.s");
+            std::fs::write(&asm_code_path, asm_code).unwrap();
+
+            // Assemble and link the code.
+            let obj_path = format!("tmp_x86_code_{file_name}.o");
+            let exe_path = format!("tmp_x86_code_{file_name}.exe");
+            let as_output = std::process::Command::new("as")
+                .arg(&asm_code_path)
+                .arg("-o")
+                .arg(&obj_path)
+                .output()
+                .unwrap();
+            if !as_output.status.success() {
+                panic!("Could not assemble x86 code for `{path:?}`: {as_output:?}");
+            }
+            let ld_output = std::process::Command::new("ld")
+                .arg(&obj_path)
+                .arg("-o")
+                .arg(&exe_path)
+                .output()
+                .unwrap();
+            if !ld_output.status.success() {
+                panic!("Could not link x86 code for `{path:?}`: {ld_output:?}");
+            }
+
+            // Run the program with the input, and confirm that the output matches the expected output.
+            let stdin = std::process::Stdio::piped();
+            let stdout = std::process::Stdio::piped();
+            let mut exe = match std::process::Command::new(&format!("./{exe_path}"))
+                .stdin(stdin)
+                .stdout(stdout)
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+            {
+                Ok(v) => v,
+                Err(e) => panic!("Could not run x86 code for `{path:?}`: {e}"),
+            };
+
+            // Programs which exit without reading their input (like those with
+            // runtime errors) close the pipe before we can write to it.
+            match exe.stdin.as_mut().unwrap().write_all(INPUT.as_bytes()) {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+                    panic!("Could not write to stdin of program `{path:?}`: {e}");
+                }
+                _ => {}
+            }
+
+            // Get stdout from the program.
+            let exe_output = exe.wait_with_output().unwrap();
+            std::fs::remove_file(&asm_code_path).unwrap();
+            std::fs::remove_file(&obj_path).unwrap();
+            std::fs::remove_file(&exe_path).unwrap();
+            if correct_error.is_some() {
+                // Programs with runtime errors must exit unsuccessfully.
+                if exe_output.status.success() {
+                    warn!("Expected a runtime error for program `{path:?}`");
+                    total_failures += 1;
+                }
+                total_attempts += 1;
+                continue;
+            }
+            let output = exe_output.stdout;
+            // Convert both to strings
+            let correct_output = correct_output
+                .iter()
+                .map(|byte| *byte as u8)
+                .collect::<Vec<_>>();
+
+            let output = String::from_utf8_lossy(&output);
+            let correct_output = String::from_utf8(correct_output).unwrap();
+
+            if output != correct_output {
+                warn!(
+                    "Output did not match correct output for program `{path:?}`:\n{output}\n  !=   \n{correct_output}"
+                );
+                total_failures += 1;
+            }
+            total_attempts += 1;
+        }
+    }
+
+    // We just use a threshold of 30% failure, because the frontend examples print out pointers
+    // And the frontend examples all print pointers differently. Same for floats.
+    if total_failures as f64 / total_attempts as f64 > 0.3 {
+        panic!(
+            "Too many failures in frontend examples: {total_failures} failures out of {total_attempts} attempts"
+        );
+    }
+}